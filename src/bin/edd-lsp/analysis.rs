@@ -0,0 +1,221 @@
+//! Everything the server knows about an open document, and answering questions with it
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use edd::{
+    diagnostic::Diagnostic,
+    parse::{
+        bundled_source, locate, location::Location, parse_source_with_names, source::Disk,
+        ParseError,
+    },
+    ttype::{
+        type_checker::{check_program_with_info, CheckInfo},
+        Type, TypeError,
+    },
+};
+use serde_json::{json, Value};
+
+use crate::convert;
+
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    info: CheckInfo,
+    /// Where each global was declared, by its full name
+    globals: HashMap<Rc<str>, Location>,
+    /// Full names of the globals the names usable in the document refer to
+    names: HashMap<Rc<str>, Rc<str>>,
+}
+
+/// Parses and checks `text` as the contents of `path`, looking for what it includes in
+/// `include_paths` as the compiler would
+///
+/// If it does not parse, what was known from `previous` is kept so hovering and completion still
+/// work while the user is typing.
+pub fn analyse(
+    path: &Path,
+    text: &str,
+    include_paths: &[PathBuf],
+    previous: Option<Analysis>,
+) -> Analysis {
+    let (program, names) = parse_source_with_names(path, text.to_owned(), include_paths, &Disk);
+    let program = match program {
+        Ok(program) => program,
+        Err(errors) => {
+            let previous = previous.unwrap_or_default();
+            return Analysis {
+                diagnostics: errors.iter().map(ParseError::diagnostic).collect(),
+                ..previous
+            };
+        }
+    };
+    let globals = program
+        .0
+        .iter()
+        .map(|(name, decl)| (name.clone(), decl.location().clone()))
+        .collect();
+
+    let (checked, info) = check_program_with_info(program);
+    let diagnostics = match checked {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors.iter().map(TypeError::diagnostic).collect(),
+    };
+
+    Analysis {
+        diagnostics,
+        info,
+        globals,
+        names,
+    }
+}
+
+/// The name the cursor is on or just after, if any, along with what qualifies it
+///
+/// On `area` in `shapes::area` this is all of `shapes::area`, but on `shapes` it is only
+/// `shapes`.
+fn name_at(line: &str, col: u16) -> Option<&str> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let ident_start = |before: &str| {
+        before
+            .rfind(|c| !is_ident(c))
+            .map_or(0, |i| i + before[i..].chars().next().unwrap().len_utf8())
+    };
+    let at = line
+        .char_indices()
+        .nth(col.saturating_sub(1) as usize)
+        .map_or(line.len(), |(i, _)| i);
+    let mut start = ident_start(&line[..at]);
+    let end = line[at..]
+        .find(|c| !is_ident(c))
+        .map_or(line.len(), |i| at + i);
+    if start == end {
+        return None;
+    }
+    while let Some(before) = line[..start].strip_suffix("::") {
+        match ident_start(before) {
+            s if s == before.len() => break,
+            s => start = s,
+        }
+    }
+    let name = &line[start..end];
+    (!name.starts_with(|c: char| c.is_ascii_digit())).then_some(name)
+}
+
+/// The file named by the `include` or `import` on `line`, if the cursor is in its string
+fn included_at(line: &str, col: u16) -> Option<&str> {
+    let start = line.find("include").or_else(|| line.find("import"))?;
+    let open = start + line[start..].find('"')? + 1;
+    let close = open + line[open..].find('"')?;
+    let at = line
+        .char_indices()
+        .nth(col.saturating_sub(1) as usize)
+        .map_or(line.len(), |(i, _)| i);
+    (open <= at && at <= close).then_some(&line[open..close])
+}
+
+impl Analysis {
+    pub fn hover(&self, path: &Path, text: &str, pos: (u16, u16)) -> Option<Value> {
+        // the innermost expression is the one with the smallest span
+        let (loc, t) = self
+            .info
+            .types
+            .iter()
+            .filter(|(loc, t)| {
+                &*loc.source_file == path
+                    && convert::contains(loc, pos)
+                    && !matches!(t, Type::Opaque | Type::Unknown(_))
+            })
+            .min_by_key(|(loc, _)| {
+                (
+                    loc.line_end - loc.line_start,
+                    loc.col_end.wrapping_sub(loc.col_start),
+                )
+            })?;
+
+        Some(json!({
+            "contents": { "kind": "markdown", "value": format!("```edd\n{t}\n```") },
+            "range": convert::range(loc, (path, text)),
+        }))
+    }
+
+    /// Where what is at `(line, col)` was declared, or the file named there by an include or
+    /// import
+    ///
+    /// Bundled libraries, like `<std>`, are not files an editor could open, so nothing is given
+    /// for them or for what is declared in them.
+    pub fn definition(
+        &self,
+        path: &Path,
+        text: &str,
+        include_paths: &[PathBuf],
+        (line, col): (u16, u16),
+    ) -> Option<Value> {
+        let line_text = text.lines().nth(line as usize - 1)?;
+
+        if let Some(included) = included_at(line_text, col) {
+            let target = locate(path, included, include_paths, &Disk)
+                .filter(|target| bundled_source(target).is_none())?;
+            return Some(json!({
+                "uri": convert::path_to_uri(&target),
+                "range": {
+                    "start": { "line": 0, "character": 0 },
+                    "end": { "line": 0, "character": 0 },
+                },
+            }));
+        }
+
+        let name = name_at(line_text, col)?;
+        // variants are declared along with their enum, as in `shapes::Shape::Circle`
+        let full = self
+            .names
+            .get(name)
+            .or_else(|| self.names.get(name.rsplit_once("::")?.0))?;
+        let loc = self
+            .globals
+            .get(full)
+            .filter(|loc| bundled_source(&loc.source_file).is_none())?;
+        Some(convert::location(loc, (path, text)))
+    }
+
+    pub fn completion(&self, path: &Path, pos: (u16, u16)) -> Value {
+        // scopes are recorded innermost first, as blocks are finished before their parents
+        let scope = self
+            .info
+            .scopes
+            .iter()
+            .find(|(loc, _)| &*loc.source_file == path && convert::contains(loc, pos))
+            .map_or(&self.info.globals, |(_, scope)| scope);
+
+        // globals are in scope by their full names, which are not always those usable here, and
+        // those not exported by their module are not usable here at all
+        let mut usable: HashMap<&str, Vec<Rc<str>>> = HashMap::new();
+        for (name, full) in &self.names {
+            usable.entry(full).or_default().push(name.clone());
+        }
+        let labels = |name: &Rc<str>| match self.globals.contains_key(name) {
+            true => usable.get(&**name).cloned().unwrap_or_default(),
+            false => vec![name.clone()],
+        };
+
+        let mut items: Vec<_> = scope
+            .symbols()
+            .flat_map(|(name, t)| {
+                let kind = if let Type::Function(_, _) = t { 3 } else { 6 };
+                labels(name).into_iter().map(
+                    move |label| json!({ "label": &*label, "kind": kind, "detail": t.to_string() }),
+                )
+            })
+            .collect();
+        items.extend(scope.types().flat_map(|(name, t)| {
+            let kind = if let Type::Enum(_) = t { 13 } else { 22 };
+            labels(name)
+                .into_iter()
+                .map(move |label| json!({ "label": &*label, "kind": kind }))
+        }));
+        Value::Array(items)
+    }
+}
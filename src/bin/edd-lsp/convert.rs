@@ -0,0 +1,139 @@
+//! Converting between the compiler's locations and diagnostics and their LSP counterparts
+//!
+//! LSP counts lines and columns from zero and columns in UTF-16 code units, where `Location`
+//! counts both from one and columns in characters.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use edd::{
+    diagnostic::Diagnostic,
+    parse::{bundled_source, location::Location},
+};
+use serde_json::{json, Value};
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        match (b, tail) {
+            (b'%', [h, l, tail @ ..]) => {
+                let hex = std::str::from_utf8(&[*h, *l]).ok()?.to_owned();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+                rest = tail;
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.to_string_lossy().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(b as char)
+            }
+            b => uri.push_str(&format!("%{b:02X}")),
+        }
+    }
+    uri
+}
+
+/// The text of the file `loc` is in, preferring `open` if it is that file
+fn source_of(loc: &Location, open: (&Path, &str)) -> Option<String> {
+    if &*loc.source_file == open.0 {
+        Some(open.1.to_owned())
+    } else if let Some(source) = bundled_source(&loc.source_file) {
+        Some(source.to_owned())
+    } else {
+        fs::read_to_string(&loc.source_file).ok()
+    }
+}
+
+fn position(source: Option<&str>, line: u16, col: u16) -> Value {
+    let line = line.saturating_sub(1) as usize;
+    let chars = col.saturating_sub(1) as usize;
+    let character = match source.and_then(|s| s.lines().nth(line)) {
+        Some(text) => {
+            text.chars().take(chars).map(char::len_utf16).sum::<usize>()
+                + chars.saturating_sub(text.chars().count())
+        }
+        None => chars,
+    };
+    json!({ "line": line, "character": character })
+}
+
+pub fn range(loc: &Location, open: (&Path, &str)) -> Value {
+    let source = source_of(loc, open);
+    json!({
+        "start": position(source.as_deref(), loc.line_start, loc.col_start),
+        "end": position(source.as_deref(), loc.line_end, loc.col_end),
+    })
+}
+
+pub fn location(loc: &Location, open: (&Path, &str)) -> Value {
+    json!({
+        "uri": path_to_uri(&loc.source_file),
+        "range": range(loc, open),
+    })
+}
+
+/// The line and column, as counted by `Location`, of an LSP position in `source`
+pub fn from_position(source: &str, pos: &Value) -> Option<(u16, u16)> {
+    let line = pos["line"].as_u64()? as usize;
+    let character = pos["character"].as_u64()? as usize;
+    let text = source.lines().nth(line).unwrap_or_default();
+    let mut units = 0;
+    let chars = text
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= character
+        })
+        .count();
+    Some(((line + 1).try_into().ok()?, (chars + 1).try_into().ok()?))
+}
+
+pub fn contains(loc: &Location, (line, col): (u16, u16)) -> bool {
+    (loc.line_start, loc.col_start) <= (line, col) && (line, col) < (loc.line_end, loc.col_end)
+}
+
+/// The diagnostic in LSP form, put at `fallback` if it does not point at anything
+pub fn diagnostic(diag: &Diagnostic, fallback: &Location, open: (&Path, &str)) -> Value {
+    let primary = diag.labels.iter().find(|l| l.primary);
+    let loc = primary.map_or(fallback, |l| &l.loc);
+
+    let mut message = diag.message.clone();
+    for note in &diag.notes {
+        message.push_str("\nnote: ");
+        message.push_str(note);
+    }
+    let related: Vec<_> = diag
+        .labels
+        .iter()
+        .filter(|l| !l.primary)
+        .map(|l| {
+            json!({
+                "location": location(&l.loc, open),
+                "message": l.message.as_deref().unwrap_or_default(),
+            })
+        })
+        .collect();
+
+    json!({
+        "range": range(loc, open),
+        "severity": 1,
+        "code": diag.code,
+        "source": "edd",
+        "message": message,
+        "relatedInformation": related,
+    })
+}
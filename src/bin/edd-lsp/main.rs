@@ -0,0 +1,244 @@
+//! A language server for edd, speaking LSP over stdin and stdout
+//!
+//! Documents are parsed and type checked in full whenever they change, and the diagnostics,
+//! types and scopes found are used to answer requests until the next change.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+};
+
+use clap::Parser;
+use edd::parse::{edd_path, location::Location};
+use serde_json::{json, Value};
+
+use analysis::{analyse, Analysis};
+
+mod analysis;
+mod convert;
+mod rpc;
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(short = 'I', long = "include-path", value_name = "DIR")]
+    /// Directory to search for libraries and included files, before those in EDD_PATH
+    include_paths: Vec<PathBuf>,
+}
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+struct Document {
+    path: PathBuf,
+    text: String,
+    analysis: Analysis,
+    /// Other files diagnostics were published to, which need clearing when these change
+    others: Vec<String>,
+}
+
+struct Server<W> {
+    output: W,
+    documents: HashMap<String, Document>,
+    /// Directories searched for libraries and included files, as with `edd -I`
+    include_paths: Vec<PathBuf>,
+    shutting_down: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, msg: Value) -> io::Result<()> {
+        rpc::write_message(&mut self.output, &msg)
+    }
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn handle(&mut self, msg: Value) -> io::Result<()> {
+        let method = msg["method"].as_str().unwrap_or_default().to_owned();
+        let params = &msg["params"];
+        let Some(id) = msg.get("id").cloned() else {
+            return self.handle_notification(&method, params);
+        };
+
+        let result = match &*method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "edd-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.with_position(params, |doc, pos| {
+                doc.analysis.hover(&doc.path, &doc.text, pos)
+            }),
+            "textDocument/definition" => {
+                let include_paths = self.include_paths.clone();
+                self.with_position(params, |doc, pos| {
+                    doc.analysis
+                        .definition(&doc.path, &doc.text, &include_paths, pos)
+                })
+            }
+            "textDocument/completion" => self.with_position(params, |doc, pos| {
+                Some(doc.analysis.completion(&doc.path, pos))
+            }),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        };
+
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        self.send(response)
+    }
+
+    /// Answers a request about a position in an open document, with `null` if `f` has nothing
+    fn with_position(
+        &self,
+        params: &Value,
+        f: impl FnOnce(&Document, (u16, u16)) -> Option<Value>,
+    ) -> Result<Value, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let Some(doc) = self.documents.get(uri) else {
+            return Err((INVALID_PARAMS, format!("`{uri}` is not open")));
+        };
+        let Some(pos) = convert::from_position(&doc.text, &params["position"]) else {
+            return Err((INVALID_PARAMS, "invalid position".to_owned()));
+        };
+        Ok(f(doc, pos).unwrap_or(Value::Null))
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.update(uri, text.to_owned())
+            }
+            "textDocument/didChange" => {
+                // only full syncing is offered, so the last change is the whole document
+                let changes = params["contentChanges"].as_array();
+                match changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    Some(text) => self.update(uri, text.to_owned()),
+                    None => Ok(()),
+                }
+            }
+            // included files may have changed on disk
+            "textDocument/didSave" => match self.documents.get(uri) {
+                Some(doc) => {
+                    let text = doc.text.clone();
+                    self.update(uri, text)
+                }
+                None => Ok(()),
+            },
+            "textDocument/didClose" => {
+                if let Some(doc) = self.documents.remove(uri) {
+                    for other in doc.others.into_iter().chain([uri.to_owned()]) {
+                        self.publish(&other, Vec::new())?;
+                    }
+                }
+                Ok(())
+            }
+            "exit" => process::exit(if self.shutting_down { 0 } else { 1 }),
+            // `initialized`, `$/cancelRequest` and the like need nothing doing
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> io::Result<()> {
+        let Some(path) = convert::uri_to_path(uri) else {
+            return Ok(());
+        };
+        let previous = self.documents.remove(uri);
+        let include_paths = &self.include_paths;
+        let (analysis, old_others) = match previous {
+            Some(doc) => {
+                let analysis = analyse(&path, &text, include_paths, Some(doc.analysis));
+                (analysis, doc.others)
+            }
+            None => (analyse(&path, &text, include_paths, None), Vec::new()),
+        };
+
+        // diagnostics are published per file, and errors in included files go to those files
+        let fallback = Location {
+            line_start: 1,
+            col_start: 1,
+            line_end: 1,
+            col_end: 1,
+            ..Location::new(path.as_path().into())
+        };
+        let mut by_file: HashMap<String, Vec<Value>> = HashMap::new();
+        by_file.insert(uri.to_owned(), Vec::new());
+        for diag in &analysis.diagnostics {
+            let file = diag
+                .labels
+                .iter()
+                .find(|l| l.primary)
+                .map_or(uri.to_owned(), |l| convert::path_to_uri(&l.loc.source_file));
+            let diag = convert::diagnostic(diag, &fallback, (&path, &text));
+            by_file.entry(file).or_default().push(diag);
+        }
+        for other in old_others {
+            by_file.entry(other).or_default();
+        }
+
+        let others = by_file
+            .iter()
+            .filter(|(u, diags)| *u != uri && !diags.is_empty())
+            .map(|(u, _)| u.clone())
+            .collect();
+        for (file, diags) in by_file {
+            self.publish(&file, diags)?;
+        }
+        self.documents.insert(
+            uri.to_owned(),
+            Document {
+                path,
+                text,
+                analysis,
+                others,
+            },
+        );
+        Ok(())
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Value>) -> io::Result<()> {
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+}
+
+fn main() -> io::Result<()> {
+    let Args { mut include_paths } = Args::parse();
+    include_paths.extend(edd_path());
+
+    let mut input = io::stdin().lock();
+    let mut server = Server {
+        output: io::stdout().lock(),
+        documents: HashMap::new(),
+        include_paths,
+        shutting_down: false,
+    };
+
+    while let Some(msg) = rpc::read_message(&mut input)? {
+        server.handle(msg)?;
+    }
+    // the client went away without saying `exit`
+    process::exit(if server.shutting_down { 0 } else { 1 })
+}
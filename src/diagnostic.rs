@@ -0,0 +1,330 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    path::Path,
+    rc::Rc,
+};
+
+use serde_json::{json, Value};
+
+use crate::parse::{
+    bundled_source,
+    location::Location,
+    source::{Disk, SourceProvider},
+};
+
+/// A span of source code to point at, with an optional message written next to it
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub loc: Location,
+    pub message: Option<Box<str>>,
+    /// Primary labels point at the cause of the diagnostic, secondary ones give context
+    pub primary: bool,
+}
+
+impl Label {
+    pub fn primary(loc: Location) -> Self {
+        Label {
+            loc,
+            message: None,
+            primary: true,
+        }
+    }
+    pub fn secondary(loc: Location, message: impl Into<Box<str>>) -> Self {
+        Label {
+            loc,
+            message: Some(message.into()),
+            primary: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Stable identifier for the kind of error, like `E0100`
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<Box<str>>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+    pub fn with_note(mut self, note: impl Into<Box<str>>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+    fn primary_location(&self) -> Option<&Location> {
+        self.labels.iter().find(|l| l.primary).map(|l| &l.loc)
+    }
+
+    /// Serialises the diagnostic as a single line JSON object, for tools to consume
+    ///
+    /// The location of the primary label is repeated at the top level, and is `null` if there
+    /// is none.
+    pub fn to_json(&self) -> String {
+        let labels: Vec<_> = self
+            .labels
+            .iter()
+            .map(|label| {
+                let mut json = json_location(Some(&label.loc));
+                json["primary"] = label.primary.into();
+                json["message"] = label.message.as_deref().into();
+                json
+            })
+            .collect();
+        let mut json = json_location(self.primary_location());
+        json["level"] = "error".into();
+        json["code"] = self.code.into();
+        json["message"] = self.message.as_str().into();
+        json["labels"] = labels.into();
+        json["notes"] = self.notes.iter().map(|note| &**note).collect();
+        json.to_string()
+    }
+}
+
+fn json_location(loc: Option<&Location>) -> Value {
+    match loc {
+        Some(loc) => json!({
+            "file": loc.source_file.to_string_lossy(),
+            "line_start": loc.line_start,
+            "col_start": loc.col_start,
+            "line_end": loc.line_end,
+            "col_end": loc.col_end,
+        }),
+        None => json!({
+            "file": null,
+            "line_start": null,
+            "col_start": null,
+            "line_end": null,
+            "col_end": null,
+        }),
+    }
+}
+
+/// The plain one line form, without any source code
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.primary_location() {
+            Some(loc) => write!(f, "{loc}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+mod style {
+    pub const RESET: &str = "\x1b[0m";
+    pub const BOLD: &str = "\x1b[1m";
+    pub const RED: &str = "\x1b[1;31m";
+    pub const BLUE: &str = "\x1b[1;34m";
+}
+
+const TAB_WIDTH: usize = 4;
+
+/// Renders diagnostics with the source lines they point at, reading (and caching) source files
+/// through `provider` as they are needed, or taking those of bundled libraries from the compiler
+#[derive(Debug)]
+pub struct Emitter {
+    colour: bool,
+    provider: Box<dyn SourceProvider>,
+    sources: HashMap<Rc<Path>, Option<Rc<str>>>,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Emitter::new(false, Disk)
+    }
+}
+
+impl Emitter {
+    /// Reads source files through `provider`, which should be the one they were compiled from
+    pub fn new(colour: bool, provider: impl SourceProvider + 'static) -> Self {
+        Emitter {
+            colour,
+            provider: Box::new(provider),
+            sources: HashMap::new(),
+        }
+    }
+
+    fn source(&mut self, path: &Rc<Path>) -> Option<Rc<str>> {
+        let provider = &self.provider;
+        self.sources
+            .entry(path.clone())
+            .or_insert_with(|| match bundled_source(path) {
+                Some(source) => Some(source.into()),
+                None => provider.read(path).ok().map(Into::into),
+            })
+            .clone()
+    }
+
+    fn paint<'a>(&self, style: &'static str, s: &'a str) -> Painted<'a> {
+        Painted {
+            style: if self.colour { style } else { "" },
+            reset: if self.colour { style::RESET } else { "" },
+            s,
+        }
+    }
+
+    pub fn render(&mut self, diag: &Diagnostic) -> String {
+        use fmt::Write;
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            self.paint(style::RED, &format!("error[{}]", diag.code)),
+            self.paint(style::BOLD, &format!(": {}", diag.message)),
+        );
+
+        // labels on the same line are shown under one copy of it, starting with the file the
+        // primary label is in
+        let primary_file = diag.primary_location().map(|l| l.source_file.clone());
+        let mut labels: Vec<&Label> = diag.labels.iter().collect();
+        labels.sort_by(|a, b| {
+            let key = |l: &Label| {
+                (
+                    Some(&l.loc.source_file) != primary_file.as_ref(),
+                    l.loc.source_file.clone(),
+                    l.loc.line_start,
+                    l.loc.col_start,
+                )
+            };
+            key(a).cmp(&key(b))
+        });
+        let mut lines: Vec<(&Rc<Path>, u16, Vec<&Label>)> = Vec::new();
+        for label in labels {
+            match lines.last_mut() {
+                Some((f, l, ls)) if **f == label.loc.source_file && *l == label.loc.line_start => {
+                    ls.push(label)
+                }
+                _ => lines.push((&label.loc.source_file, label.loc.line_start, vec![label])),
+            }
+        }
+
+        let gutter = lines
+            .iter()
+            .map(|(_, l, _)| l.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let pad = " ".repeat(gutter);
+
+        let mut last: Option<(&Rc<Path>, u16)> = None;
+        for (file, line_no, labels) in &lines {
+            match last {
+                Some((last_file, last_line)) if last_file == *file => {
+                    if *line_no > last_line + 1 {
+                        let _ = writeln!(out, "{}", self.paint(style::BLUE, "..."));
+                    }
+                }
+                _ => {
+                    let loc = match diag.primary_location() {
+                        Some(loc) if loc.source_file == **file => loc,
+                        _ => &labels[0].loc,
+                    };
+                    let _ = writeln!(
+                        out,
+                        "{pad}{} {}:{}:{}",
+                        self.paint(style::BLUE, "-->"),
+                        file.display(),
+                        loc.line_start,
+                        loc.col_start
+                    );
+                    let _ = writeln!(out, "{pad} {}", self.paint(style::BLUE, "|"));
+                }
+            }
+            last = Some((file, *line_no));
+            let Some(source) = self.source(file) else {
+                continue;
+            };
+            let Some(line) = line_no
+                .checked_sub(1)
+                .and_then(|n| source.lines().nth(n as usize))
+            else {
+                continue;
+            };
+            let line = line.trim_end();
+            let number = format!("{line_no:>gutter$}");
+            let _ = writeln!(
+                out,
+                "{} {}",
+                self.paint(style::BLUE, &format!("{number} |")),
+                expand_tabs(line)
+            );
+            for label in labels {
+                let start = display_width(line, (label.loc.col_start as usize).saturating_sub(1));
+                let end = if label.loc.line_end == label.loc.line_start {
+                    display_width(line, (label.loc.col_end as usize).saturating_sub(1))
+                } else {
+                    // spans running over several lines are underlined to the end of the first one
+                    display_width(line, line.chars().count())
+                };
+                let (marker, marker_style) = if label.primary {
+                    ("^", style::RED)
+                } else {
+                    ("-", style::BLUE)
+                };
+                let mut underline = marker.repeat(end.saturating_sub(start).max(1));
+                if let Some(msg) = &label.message {
+                    underline.push(' ');
+                    underline.push_str(msg);
+                }
+                let _ = writeln!(
+                    out,
+                    "{pad} {} {}{}",
+                    self.paint(style::BLUE, "|"),
+                    " ".repeat(start),
+                    self.paint(marker_style, &underline)
+                );
+            }
+        }
+
+        if !diag.notes.is_empty() && !lines.is_empty() {
+            let _ = writeln!(out, "{pad} {}", self.paint(style::BLUE, "|"));
+        }
+        for note in &diag.notes {
+            let _ = writeln!(
+                out,
+                "{pad} {} {}: {note}",
+                self.paint(style::BLUE, "="),
+                self.paint(style::BOLD, "note")
+            );
+        }
+        out
+    }
+}
+
+struct Painted<'a> {
+    style: &'static str,
+    reset: &'static str,
+    s: &'a str,
+}
+
+impl Display for Painted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.style, self.s, self.reset)
+    }
+}
+
+fn expand_tabs(line: &str) -> String {
+    line.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+/// How many columns the first `chars` characters of `line` take up once tabs are expanded
+fn display_width(line: &str, chars: usize) -> usize {
+    line.chars()
+        .take(chars)
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum::<usize>()
+        + chars.saturating_sub(line.chars().count())
+}
@@ -1,15 +1,18 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc};
 
-use crate::ttype::{
-    ast::{Expr, PlaceExpr, Statement},
-    Type,
+use crate::{
+    parse::location::Location,
+    ttype::{
+        ast::{Expr, Iterable, Pattern, PlaceExpr, Statement},
+        Type,
+    },
 };
 
 use self::state::FlattenState;
 
 use super::{
     static_eval::static_eval, ticker::StaticNamer, Binop, Const, FlatType, Function, Global, Ident,
-    Line, StaticDecl, Temp, Unop,
+    Line, PanicReason, StaticDecl, Temp, Unop,
 };
 
 mod state;
@@ -19,17 +22,27 @@ pub fn flatten_function(
     body: Expr,
     statics: &mut Vec<StaticDecl>,
     fns: &mut HashMap<Global, Function>,
+    links: &HashMap<Rc<str>, Global>,
 ) {
     let (fn_name, mut function) = fns.remove_entry(&fn_name).unwrap();
 
     let ret_type = function.ret_type.clone();
-    let mut state = FlattenState::new(fn_name.inner(), &mut function, statics, fns);
+    let mut state = FlattenState::new(fn_name.inner(), &mut function, statics, fns, links);
     let place = state.new_temp("ret_val", ret_type.clone());
     flatten_expr(body, ret_type, place.clone(), &mut state);
     function.lines.push(Line::Ret(place));
 
     fns.insert(fn_name, function);
 }
+/// Functions as values are closures, a pointer to the code and a pointer to the values it
+/// captured, which the code takes as its first argument
+fn closure_type(args: Vec<FlatType>, ret: FlatType) -> FlatType {
+    let args = [FlatType::Ptr(None)].into_iter().chain(args).collect();
+    FlatType::Struct(Box::new([
+        FlatType::FnPtr(args, Box::new(ret)),
+        FlatType::Ptr(None),
+    ]))
+}
 fn flatten_type_maybe(t: Type) -> Option<FlatType> {
     Some(match t {
         Type::Unknown(_) => unimplemented!(),
@@ -44,9 +57,9 @@ fn flatten_type_maybe(t: Type) -> Option<FlatType> {
         Type::U32 => FlatType::U32,
         Type::I32 => FlatType::I32,
         Type::Float => FlatType::Float,
-        Type::Function(args, ret) => FlatType::FnPtr(
+        Type::Function(args, ret) => closure_type(
             args.into_vec().into_iter().map(flatten_type).collect(),
-            Box::new(flatten_type(*ret)),
+            flatten_type(*ret),
         ),
         Type::Pointer(t) => FlatType::Ptr(flatten_type_maybe(*t).map(Box::new)),
         Type::ArrayPointer(t) => FlatType::Ptr(flatten_type_maybe(*t).map(Box::new)),
@@ -61,7 +74,20 @@ fn flatten_type_maybe(t: Type) -> Option<FlatType> {
                 .map(|(_, t)| flatten_type(t))
                 .collect(),
         ),
-        Type::Option(opt) => FlatType::Struct(Box::new([flatten_type(*opt), FlatType::Bool])),
+        // a null pointer is already an invalid pointer
+        Type::Option(opt) if matches!(*opt, Type::Pointer(_)) => flatten_type(*opt),
+        // whether there is a value followed by the value
+        Type::Option(opt) => FlatType::Struct(Box::new([FlatType::Bool, flatten_type(*opt)])),
+        // the tag followed by the payload of every variant
+        Type::Enum(variants) => FlatType::Struct(
+            std::iter::once(FlatType::U8)
+                .chain(variants.into_vec().into_iter().map(|(_, payload)| {
+                    FlatType::Struct(payload.into_vec().into_iter().map(flatten_type).collect())
+                }))
+                .collect(),
+        ),
+        Type::Named(_) => unreachable!("named types are resolved during type checking"),
+        Type::Distinct(distinct) => flatten_type(distinct.1),
     })
 }
 #[inline]
@@ -69,10 +95,297 @@ pub fn flatten_type(t: Type) -> FlatType {
     flatten_type_maybe(t).expect("non-opaque")
 }
 
+fn int_const(t: &FlatType, num: i128) -> Const {
+    match t {
+        FlatType::U8 => Const::ConstU8(num as u8),
+        FlatType::I8 => Const::ConstI8(num as i8),
+        FlatType::U16 => Const::ConstU16(num as u16),
+        FlatType::I16 => Const::ConstI16(num as i16),
+        FlatType::U32 => Const::ConstU32(num as u32),
+        FlatType::I32 => Const::ConstI32(num as i32),
+        t => unreachable!("{t}"),
+    }
+}
+
+/// Position of `field` in the struct type `t`
+fn field_index(t: &Type, field: &str) -> u16 {
+    match t {
+        Type::Struct(fields) => fields.iter().position(|(n, _)| &**n == field).unwrap() as u16,
+        t => unreachable!("field of non-struct type {t}"),
+    }
+}
+/// Tag of `variant` in the enum type `t`
+fn variant_index(t: &Type, variant: &str) -> u8 {
+    match t {
+        Type::Enum(variants) => variants.iter().position(|(n, _)| &**n == variant).unwrap() as u8,
+        t => unreachable!("variant of non-enum type {t}"),
+    }
+}
+fn field_type(t: &FlatType, n: u16) -> FlatType {
+    match t {
+        FlatType::Struct(fields) => fields[n as usize].clone(),
+        FlatType::Arr(t, _) => (**t).clone(),
+        t => unreachable!("field of non-aggregate type {t}"),
+    }
+}
+
+/// Panics with the location `loc` unless comparing the index `a` to `b` with `cmp` holds
+fn flatten_bounds_check(loc: &Location, cmp: Binop, a: Temp, b: Temp, state: &mut FlattenState) {
+    let in_bounds = state.new_temp("in_bounds", FlatType::Bool);
+    state.add_code(Line::SetBinop(in_bounds.clone(), FlatType::U16, cmp, a, b));
+
+    let safe_l = state.new_label();
+    let error_l = state.new_label();
+
+    state.add_code(Line::If(in_bounds, safe_l.clone(), error_l.clone()));
+    state.add_code(Line::Label(error_l));
+    state.add_code(Line::Panic(loc.clone(), PanicReason::IndexOutOfBounds));
+    state.add_code(Line::Label(safe_l));
+}
+
+/// Writes `a` and `b` combined with `binop` to `place`, first panicking at the location if the
+/// paired overflow check says the result does not fit in `t`
+fn flatten_arith(
+    binop: Binop,
+    overflow_check: Option<(&Location, Binop)>,
+    a: Expr,
+    b: Expr,
+    t: FlatType,
+    place: Temp,
+    state: &mut FlattenState,
+) {
+    let ta = state.new_temp("arith_arg1", t.clone());
+    flatten_expr(a, t.clone(), ta.clone(), state);
+    let tb = state.new_temp("arith_arg2", t.clone());
+    flatten_expr(b, t.clone(), tb.clone(), state);
+
+    if let (Some((loc, overflows)), false) = (overflow_check, t == FlatType::Float) {
+        flatten_overflow_check(loc, overflows, &t, &ta, &tb, state);
+    }
+
+    state.add_code(Line::SetBinop(place, t, binop, ta, tb));
+}
+
+/// Panics with the location `loc` if the overflow check `overflows` says combining `a` and `b`
+/// does not fit in `t`
+fn flatten_overflow_check(
+    loc: &Location,
+    overflows: Binop,
+    t: &FlatType,
+    a: &Temp,
+    b: &Temp,
+    state: &mut FlattenState,
+) {
+    let overflowed = state.new_temp("overflowed", FlatType::Bool);
+    state.add_code(Line::SetBinop(
+        overflowed.clone(),
+        t.clone(),
+        overflows,
+        a.clone(),
+        b.clone(),
+    ));
+
+    let safe_l = state.new_label();
+    let error_l = state.new_label();
+
+    state.add_code(Line::If(overflowed, error_l.clone(), safe_l.clone()));
+    state.add_code(Line::Label(error_l));
+    state.add_code(Line::Panic(loc.clone(), PanicReason::Overflow));
+    state.add_code(Line::Label(safe_l));
+}
+
+/// Writes `a` divided by `b` using `binop` to `place`, panicking at the location if `b` is zero
+fn flatten_division(
+    loc: &Location,
+    binop: Binop,
+    a: Expr,
+    b: Expr,
+    t: FlatType,
+    place: Temp,
+    state: &mut FlattenState,
+) {
+    let ta = state.new_temp("div_arg1", t.clone());
+    flatten_expr(a, t.clone(), ta.clone(), state);
+    let tb = state.new_temp("div_arg2", t.clone());
+    flatten_expr(b, t.clone(), tb.clone(), state);
+
+    let zero_t = state.new_temp("zero", t.clone());
+    state.add_code(Line::SetConst(zero_t.clone(), t.clone(), Const::ConstZero));
+    let is_zero = state.new_temp("zero_cond", FlatType::Bool);
+    state.add_code(Line::SetBinop(
+        is_zero.clone(),
+        t.clone(),
+        Binop::Eq,
+        tb.clone(),
+        zero_t,
+    ));
+
+    let safe_l = state.new_label();
+    let error_l = state.new_label();
+
+    state.add_code(Line::If(is_zero, error_l.clone(), safe_l.clone()));
+    state.add_code(Line::Label(error_l));
+    state.add_code(Line::Panic(loc.clone(), PanicReason::DivisionByZero));
+    state.add_code(Line::Label(safe_l));
+    state.add_code(Line::SetBinop(place, t, binop, ta, tb));
+}
+
+/// Writes a pointer to the first element of the array `e` evaluates to, which is only copied if it
+/// does not already live somewhere
+fn flatten_array_ptr(e: Expr, arr_t: FlatType, state: &mut FlattenState) -> Temp {
+    let ptr_t = FlatType::Ptr(Some(Box::new(field_type(&arr_t, 0))));
+    let ptr = state.new_temp("arr_ptr", ptr_t.clone());
+    let arr = match e {
+        Expr::Ident(_, name) => state.ident_from_identifier(name),
+        e @ (Expr::ConstString(_, _) | Expr::Concat(_, _, _)) => {
+            let global = state.new_global("string");
+            let mut namer = StaticNamer::new(&global.0);
+            static_eval(global.clone(), arr_t, e, &mut namer, state.statics).unwrap();
+            global.into()
+        }
+        e => {
+            let arr = state.new_temp("arr", arr_t.clone());
+            flatten_expr(e, arr_t, arr.clone(), state);
+            arr.into()
+        }
+    };
+    state.add_code(Line::SetAddrOf(ptr.clone(), ptr_t, arr));
+    ptr
+}
+
+/// Gives a pointer to the element of the slice `slice_e` at `index_e`, which is checked to be in
+/// bounds, and the type of the element
+fn flatten_element_ptr(
+    loc: &Location,
+    slice_e: Expr,
+    index_e: Expr,
+    slice_t: FlatType,
+    state: &mut FlattenState,
+) -> (Temp, FlatType) {
+    let slice = state.new_temp("sliced", slice_t.clone());
+    flatten_expr(slice_e, slice_t.clone(), slice.clone(), state);
+    let index = state.new_temp("index", FlatType::U16);
+    flatten_expr(index_e, FlatType::U16, index.clone(), state);
+
+    let ptr_t = field_type(&slice_t, 0);
+    let FlatType::Ptr(Some(elem_t)) = &ptr_t else {
+        unreachable!("slice without element type");
+    };
+    let elem_t = (**elem_t).clone();
+    let ptr = state.new_temp("ptr", ptr_t.clone());
+    state.add_code(Line::ReadField(ptr.clone(), slice_t.clone(), slice.clone(), 0));
+    let len = state.new_temp("len", FlatType::U16);
+    state.add_code(Line::ReadField(len.clone(), slice_t, slice, 1));
+    flatten_bounds_check(loc, Binop::Lt, index.clone(), len, state);
+
+    let elem_ptr = state.new_temp("elem_ptr", ptr_t);
+    state.add_code(Line::SetElementAddr(elem_ptr.clone(), elem_t.clone(), ptr, index));
+    (elem_ptr, elem_t)
+}
+
+/// Writes the closure of the function `f` with the environment `env` to `place`
+fn flatten_closure(place: Temp, closure_t: FlatType, f: Temp, env: Temp, state: &mut FlattenState) {
+    state.add_code(Line::SetConst(
+        place.clone(),
+        closure_t.clone(),
+        Const::ConstZero,
+    ));
+    state.add_code(Line::WriteField(place.clone(), closure_t.clone(), 0, f));
+    state.add_code(Line::WriteField(place, closure_t, 1, env));
+}
+
+/// Gives a function that calls the function `g` while taking an environment like closures do,
+/// which is made the first time it is needed
+fn flatten_thunk(
+    g: Global,
+    args: Box<[FlatType]>,
+    ret: FlatType,
+    state: &mut FlattenState,
+) -> Global {
+    let thunk = Global(format!("{}$closure", g.inner()).into());
+    if state.fns.contains_key(&thunk) {
+        return thunk;
+    }
+    // temporaries are the hole, the environment, the arguments and then the return value
+    let arg_temps: Box<[_]> = (2..args.len() + 2).map(Temp).collect();
+    let ret_temp = Temp(args.len() + 2);
+    let local_names = ["_", "env"]
+        .into_iter()
+        .map(Into::into)
+        .chain((0..args.len()).map(|n| format!("arg{n}").into()))
+        .chain(["__ret_val".into()])
+        .collect();
+    let function = Function {
+        arg_types: [FlatType::Ptr(None)]
+            .into_iter()
+            .chain(args.into_vec())
+            .collect(),
+        ret_type: ret.clone(),
+        lines: vec![
+            Line::SetCall(ret_temp.clone(), ret, g.into(), arg_temps),
+            Line::Ret(ret_temp),
+        ],
+        local_names,
+    };
+    state.fns.insert(thunk.clone(), function);
+    thunk
+}
+
+/// Writes the slice of `len` elements from `ptr` to `place`
+fn flatten_slice(place: Temp, slice_t: FlatType, ptr: Temp, len: Temp, state: &mut FlattenState) {
+    state.add_code(Line::SetConst(
+        place.clone(),
+        slice_t.clone(),
+        Const::ConstZero,
+    ));
+    state.add_code(Line::WriteField(place.clone(), slice_t.clone(), 0, ptr));
+    state.add_code(Line::WriteField(place, slice_t, 1, len));
+}
+
+/// Writes whether the optional `opt` has a value to `cond`
+fn flatten_is_some(cond: Temp, opt_t: &FlatType, opt: Temp, state: &mut FlattenState) {
+    match opt_t {
+        FlatType::Ptr(_) => {
+            let null = state.new_temp("null", opt_t.clone());
+            state.add_code(Line::SetConst(
+                null.clone(),
+                opt_t.clone(),
+                Const::ConstZero,
+            ));
+            state.add_code(Line::SetBinop(cond, opt_t.clone(), Binop::Neq, opt, null));
+        }
+        _ => state.add_code(Line::ReadField(cond, opt_t.clone(), opt, 0)),
+    }
+}
+/// Writes the value inside the optional `opt`, which must not be null, to `place`
+fn flatten_opt_value(place: Temp, opt_t: &FlatType, opt: Temp, state: &mut FlattenState) {
+    match opt_t {
+        FlatType::Ptr(_) => state.add_code(Line::SetTo(place, opt_t.clone(), opt)),
+        _ => state.add_code(Line::ReadField(place, opt_t.clone(), opt, 1)),
+    }
+}
+
 fn flatten_expr(expr: Expr, t: FlatType, place: Temp, state: &mut FlattenState) {
     match expr {
+        Expr::Instance(_, _, _) => unreachable!("instances are named once their types are known"),
         Expr::Ident(_, name) => match state.ident_from_identifier(name) {
-            Ident::Global(g) => state.add_code(Line::ReadGlobal(place, t, g)),
+            Ident::Global(g) => match state.get_type(g.clone()) {
+                // a function used as a value
+                Some(FlatType::FnPtr(args, ret)) => {
+                    let thunk = flatten_thunk(g, args, *ret, state);
+                    let fn_t = field_type(&t, 0);
+                    let f = state.new_temp("fn", fn_t.clone());
+                    state.add_code(Line::ReadGlobal(f.clone(), fn_t, thunk));
+                    let env = state.new_temp("env", FlatType::Ptr(None));
+                    state.add_code(Line::SetConst(
+                        env.clone(),
+                        FlatType::Ptr(None),
+                        Const::ConstZero,
+                    ));
+                    flatten_closure(place, t, f, env, state);
+                }
+                _ => state.add_code(Line::ReadGlobal(place, t, g)),
+            },
             Ident::Temp(temp) => state.add_code(Line::SetTo(place, t, temp)),
         },
         Expr::ConstBoolean(_, b) => {
@@ -100,15 +413,7 @@ fn flatten_expr(expr: Expr, t: FlatType, place: Temp, state: &mut FlattenState)
             state.add_code(Line::SetConst(place, t, Const::ConstFloat(num)));
         }
         Expr::ConstCompInteger(_, num) => {
-            let c = match t {
-                FlatType::U8 => Const::ConstU8(num as u8),
-                FlatType::I8 => Const::ConstI8(num as i8),
-                FlatType::U16 => Const::ConstU16(num as u16),
-                FlatType::I16 => Const::ConstI16(num as i16),
-                FlatType::U32 => Const::ConstU32(num as u32),
-                FlatType::I32 => Const::ConstI32(num as i32),
-                t => unreachable!("{t}"),
-            };
+            let c = int_const(&t, num);
             state.add_code(Line::SetConst(place, t, c));
         }
         Expr::ConstUnit(_) | Expr::ConstNull(_) => {
@@ -134,80 +439,404 @@ fn flatten_expr(expr: Expr, t: FlatType, place: Temp, state: &mut FlattenState)
             static_eval(global.clone(), t.clone(), e, &mut namer, state.statics).unwrap();
             state.add_code(Line::ReadGlobal(place, t, global));
         }
-        Expr::Array(_, _) => todo!(),
-        Expr::StructConstructor(_, _) => todo!(),
-        Expr::Cast(_, e, from_t, to_t) => {
-            let from_t = flatten_type(*from_t);
-            let to_t = flatten_type(*to_t);
+        Expr::Array(_, es) => {
+            let elem_t = field_type(&t, 0);
+            // evaluate every element before touching `place`, since the elements might read it
+            let elems: Vec<_> = es
+                .into_vec()
+                .into_iter()
+                .map(|e| {
+                    let elem = state.new_temp("elem", elem_t.clone());
+                    flatten_expr(e, elem_t.clone(), elem.clone(), state);
+                    elem
+                })
+                .collect();
 
-            match (from_t, to_t) {
-                (t1, t2) if t1 == t2 => flatten_expr(*e, t1, place, state),
-                (FlatType::Arr(t, _sz), FlatType::Struct(st))
-                | (FlatType::Struct(st), FlatType::Arr(t, _sz)) => {
-                    if *st != [FlatType::Ptr(Some(t)), FlatType::U16] {
-                        unimplemented!()
-                    }
+            state.add_code(Line::SetConst(place.clone(), t.clone(), Const::ConstZero));
+            for (n, elem) in elems.into_iter().enumerate() {
+                state.add_code(Line::WriteField(place.clone(), t.clone(), n as u16, elem));
+            }
+        }
+        Expr::Index(loc, e, index_e, coll_t) => {
+            let coll_t = flatten_type(*coll_t);
+            // arrays are read through a pointer to them, so they are not copied
+            if let &FlatType::Arr(_, len) = &coll_t {
+                let ptr = flatten_array_ptr(*e, coll_t, state);
+                let index = state.new_temp("index", FlatType::U16);
+                flatten_expr(*index_e, FlatType::U16, index.clone(), state);
+                let len_place = state.new_temp("len", FlatType::U16);
+                state.add_code(Line::SetConst(
+                    len_place.clone(),
+                    FlatType::U16,
+                    Const::ConstU16(len),
+                ));
+                flatten_bounds_check(&loc, Binop::Lt, index.clone(), len_place, state);
+                state.add_code(Line::ReadIndex(place, t, ptr, index));
+                return;
+            }
+            let coll = state.new_temp("indexed", coll_t.clone());
+            flatten_expr(*e, coll_t.clone(), coll.clone(), state);
+            let index = state.new_temp("index", FlatType::U16);
+            flatten_expr(*index_e, FlatType::U16, index.clone(), state);
 
-                    flatten_expr(*e, FlatType::Struct(st), place, state);
-                    // TODO: write size to slice
+            match &coll_t {
+                // slices are a pointer and a length
+                FlatType::Struct(_) => {
+                    let ptr_t = field_type(&coll_t, 0);
+                    let ptr = state.new_temp("ptr", ptr_t);
+                    state.add_code(Line::ReadField(
+                        ptr.clone(),
+                        coll_t.clone(),
+                        coll.clone(),
+                        0,
+                    ));
+                    let len = state.new_temp("len", FlatType::U16);
+                    state.add_code(Line::ReadField(len.clone(), coll_t, coll, 1));
+                    flatten_bounds_check(&loc, Binop::Lt, index.clone(), len, state);
+                    state.add_code(Line::ReadIndex(place, t, ptr, index));
                 }
-                _ => todo!(),
+                // array pointers do not know how many elements they point to
+                FlatType::Ptr(_) => state.add_code(Line::ReadIndex(place, t, coll, index)),
+                t => unreachable!("index into non-array type {t}"),
             }
         }
-        // TODO: check for overflow
-        Expr::Add(_, a, b) => {
-            let ta = state.new_temp("add_arg1", t.clone());
-            flatten_expr(*a, t.clone(), ta.clone(), state);
-            let tb = state.new_temp("add_arg2", t.clone());
-            flatten_expr(*b, t.clone(), tb.clone(), state);
+        Expr::Slice(loc, e, start_e, end_e, coll_t) => {
+            let coll_t = flatten_type(*coll_t);
+            let (ptr, len) = match &coll_t {
+                &FlatType::Arr(_, len) => {
+                    let ptr = flatten_array_ptr(*e, coll_t.clone(), state);
+                    let len_place = state.new_temp("len", FlatType::U16);
+                    state.add_code(Line::SetConst(
+                        len_place.clone(),
+                        FlatType::U16,
+                        Const::ConstU16(len),
+                    ));
+                    (ptr, Some(len_place))
+                }
+                FlatType::Struct(_) => {
+                    let coll = state.new_temp("sliced", coll_t.clone());
+                    flatten_expr(*e, coll_t.clone(), coll.clone(), state);
+                    let ptr = state.new_temp("ptr", field_type(&coll_t, 0));
+                    state.add_code(Line::ReadField(
+                        ptr.clone(),
+                        coll_t.clone(),
+                        coll.clone(),
+                        0,
+                    ));
+                    let len = state.new_temp("len", FlatType::U16);
+                    state.add_code(Line::ReadField(len.clone(), coll_t, coll, 1));
+                    (ptr, Some(len))
+                }
+                FlatType::Ptr(_) => {
+                    let ptr = state.new_temp("sliced", coll_t.clone());
+                    flatten_expr(*e, coll_t, ptr.clone(), state);
+                    (ptr, None)
+                }
+                t => unreachable!("slice of non-array type {t}"),
+            };
+            let start = state.new_temp("start", FlatType::U16);
+            flatten_expr(*start_e, FlatType::U16, start.clone(), state);
+            let end = state.new_temp("end", FlatType::U16);
+            flatten_expr(*end_e, FlatType::U16, end.clone(), state);
 
-            state.add_code(Line::SetBinop(place, t, Binop::Add, ta, tb));
-        }
-        Expr::Sub(_, a, b) => {
-            let ta = state.new_temp("sub_arg1", t.clone());
-            flatten_expr(*a, t.clone(), ta.clone(), state);
-            let tb = state.new_temp("sub_arg2", t.clone());
-            flatten_expr(*b, t.clone(), tb.clone(), state);
+            flatten_bounds_check(&loc, Binop::Lte, start.clone(), end.clone(), state);
+            // array pointers do not know how many elements they point to
+            if let Some(len) = len {
+                flatten_bounds_check(&loc, Binop::Lte, end.clone(), len, state);
+            }
 
-            state.add_code(Line::SetBinop(place, t, Binop::Sub, ta, tb));
+            let ptr_t = field_type(&t, 0);
+            let FlatType::Ptr(Some(elem_t)) = &ptr_t else {
+                unreachable!("slice without element type");
+            };
+            let new_ptr = state.new_temp("slice_ptr", ptr_t.clone());
+            state.add_code(Line::SetElementAddr(
+                new_ptr.clone(),
+                (**elem_t).clone(),
+                ptr,
+                start.clone(),
+            ));
+            let new_len = state.new_temp("slice_len", FlatType::U16);
+            state.add_code(Line::SetBinop(
+                new_len.clone(),
+                FlatType::U16,
+                Binop::Sub,
+                end,
+                start,
+            ));
+            flatten_slice(place, t, new_ptr, new_len, state);
         }
-        Expr::Mul(_, a, b) => {
-            let ta = state.new_temp("mul_arg1", t.clone());
-            flatten_expr(*a, t.clone(), ta.clone(), state);
-            let tb = state.new_temp("mul_arg2", t.clone());
-            flatten_expr(*b, t.clone(), tb.clone(), state);
+        Expr::StructConstructor(_, fields) => {
+            // evaluate every field before touching `place`, since the fields might read it
+            let fields: Vec<_> = fields
+                .into_vec()
+                .into_iter()
+                .enumerate()
+                .map(|(n, (_, e))| {
+                    let field_t = field_type(&t, n as u16);
+                    let field = state.new_temp("field", field_t.clone());
+                    flatten_expr(e, field_t, field.clone(), state);
+                    field
+                })
+                .collect();
 
-            state.add_code(Line::SetBinop(place, t, Binop::Mul, ta, tb));
+            state.add_code(Line::SetConst(place.clone(), t.clone(), Const::ConstZero));
+            for (n, field) in fields.into_iter().enumerate() {
+                state.add_code(Line::WriteField(place.clone(), t.clone(), n as u16, field));
+            }
         }
-        Expr::Div(loc, a, b) => {
-            let ta = state.new_temp("div_arg1", t.clone());
-            flatten_expr(*a, t.clone(), ta.clone(), state);
-            let tb = state.new_temp("div_arg2", t.clone());
-            flatten_expr(*b, t.clone(), tb.clone(), state);
+        Expr::FieldAccess(_, e, field, strct_t) => {
+            // the only field of arrays and slices is their length
+            let n = match &*strct_t {
+                Type::Array(_, _) | Type::Slice(_) => 1,
+                strct_t => field_index(strct_t, &field),
+            };
+            let strct_t = flatten_type(*strct_t);
+            let strct = state.new_temp("strct", strct_t.clone());
+            flatten_expr(*e, strct_t.clone(), strct.clone(), state);
+            match strct_t {
+                FlatType::Arr(_, len) => {
+                    state.add_code(Line::SetConst(place, t, Const::ConstU16(len)))
+                }
+                strct_t => state.add_code(Line::ReadField(place, strct_t, strct, n)),
+            }
+        }
+        Expr::VariantConstructor(_, variant, payload, enum_t) => {
+            let tag = variant_index(&enum_t, &variant);
+            let payload_t = field_type(&t, 1 + tag as u16);
+            let fields: Vec<_> = payload
+                .into_vec()
+                .into_iter()
+                .enumerate()
+                .map(|(n, e)| {
+                    let field_t = field_type(&payload_t, n as u16);
+                    let field = state.new_temp("payload", field_t.clone());
+                    flatten_expr(e, field_t, field.clone(), state);
+                    field
+                })
+                .collect();
 
-            let t = t;
+            let payload = state.new_temp("payload", payload_t.clone());
+            state.add_code(Line::SetConst(
+                payload.clone(),
+                payload_t.clone(),
+                Const::ConstZero,
+            ));
+            for (n, field) in fields.into_iter().enumerate() {
+                state.add_code(Line::WriteField(
+                    payload.clone(),
+                    payload_t.clone(),
+                    n as u16,
+                    field,
+                ));
+            }
+            let tag_place = state.new_temp("tag", FlatType::U8);
+            state.add_code(Line::SetConst(
+                tag_place.clone(),
+                FlatType::U8,
+                Const::ConstU8(tag),
+            ));
 
-            let zero_t = state.new_temp("zero", t.clone());
-            state.add_code(Line::SetConst(zero_t.clone(), t.clone(), Const::ConstZero));
-            let is_zero = state.new_temp("zero_cond", FlatType::Bool);
-            state.add_code(Line::SetBinop(
-                is_zero.clone(),
-                t.clone(),
-                Binop::Eq,
-                tb.clone(),
-                zero_t,
+            state.add_code(Line::SetConst(place.clone(), t.clone(), Const::ConstZero));
+            state.add_code(Line::WriteField(place.clone(), t.clone(), 0, tag_place));
+            state.add_code(Line::WriteField(place, t, 1 + tag as u16, payload));
+        }
+        Expr::Match(_, e, enum_t, arms) => {
+            let flat_enum_t = flatten_type((*enum_t).clone());
+            let scrutinee = state.new_temp("match", flat_enum_t.clone());
+            flatten_expr(*e, flat_enum_t.clone(), scrutinee.clone(), state);
+            let tag = state.new_temp("tag", FlatType::U8);
+            state.add_code(Line::ReadField(
+                tag.clone(),
+                flat_enum_t.clone(),
+                scrutinee.clone(),
+                0,
             ));
+            let l_end = state.new_label();
 
-            let safe_l = state.new_label();
-            let error_l = state.new_label();
+            let arm_count = arms.len();
+            for (i, (pattern, arm)) in arms.into_vec().into_iter().enumerate() {
+                let Pattern::Variant(_, variant, bindings) = pattern else {
+                    flatten_expr(arm, t.clone(), place.clone(), state);
+                    continue;
+                };
+                let variant = variant_index(&enum_t, &variant);
 
-            state.add_code(Line::If(is_zero, error_l.clone(), safe_l.clone()));
-            state.add_code(Line::Label(error_l));
-            state.add_code(Line::Panic(
-                format!(":{}:{}: divended was zero", loc.line_start, loc.col_start).into(),
+                // the type checker made sure the match is exhaustive,
+                // so the last arm does not need to check the tag
+                let l_next = if i + 1 < arm_count {
+                    let expected = state.new_temp("variant", FlatType::U8);
+                    state.add_code(Line::SetConst(
+                        expected.clone(),
+                        FlatType::U8,
+                        Const::ConstU8(variant),
+                    ));
+                    let cond = state.new_temp("is_variant", FlatType::Bool);
+                    state.add_code(Line::SetBinop(
+                        cond.clone(),
+                        FlatType::U8,
+                        Binop::Eq,
+                        tag.clone(),
+                        expected,
+                    ));
+                    let l_arm = state.new_label();
+                    let l_next = state.new_label();
+                    state.add_code(Line::If(cond, l_arm.clone(), l_next.clone()));
+                    state.add_code(Line::Label(l_arm));
+                    Some(l_next)
+                } else {
+                    None
+                };
+
+                let payload_t = field_type(&flat_enum_t, 1 + variant as u16);
+                if bindings.iter().any(Option::is_some) {
+                    let payload = state.new_temp("payload", payload_t.clone());
+                    state.add_code(Line::ReadField(
+                        payload.clone(),
+                        flat_enum_t.clone(),
+                        scrutinee.clone(),
+                        1 + variant as u16,
+                    ));
+                    for (n, binding) in bindings.into_vec().into_iter().enumerate() {
+                        let Some(binding) = binding else { continue };
+                        let binding_t = field_type(&payload_t, n as u16);
+                        let var = state.new_temp_from_identifier(binding, binding_t);
+                        state.add_code(Line::ReadField(
+                            var,
+                            payload_t.clone(),
+                            payload.clone(),
+                            n as u16,
+                        ));
+                    }
+                }
+                flatten_expr(arm, t.clone(), place.clone(), state);
+
+                if let Some(l_next) = l_next {
+                    state.add_code(Line::Goto(l_end.clone()));
+                    state.add_code(Line::Label(l_next));
+                }
+            }
+            state.add_code(Line::Label(l_end));
+        }
+        Expr::Cast(_, e, from_t, to_t) if matches!(&*to_t, Type::Option(inner) if **inner == *from_t) =>
+        {
+            let from_t = flatten_type(*from_t);
+            if t == from_t {
+                // nullable pointers need no tag
+                flatten_expr(*e, t, place, state);
+                return;
+            }
+            let val = state.new_temp("val", from_t.clone());
+            flatten_expr(*e, from_t, val.clone(), state);
+            let tag = state.new_temp("is_some", FlatType::Bool);
+            state.add_code(Line::SetConst(
+                tag.clone(),
+                FlatType::Bool,
+                Const::ConstBoolean(true),
             ));
-            state.add_code(Line::Label(safe_l));
-            state.add_code(Line::SetBinop(place, t, Binop::Div, ta, tb));
+            state.add_code(Line::SetConst(place.clone(), t.clone(), Const::ConstZero));
+            state.add_code(Line::WriteField(place.clone(), t.clone(), 0, tag));
+            state.add_code(Line::WriteField(place, t, 1, val));
+        }
+        Expr::Cast(_, e, from_t, to_t) => {
+            let from_t = flatten_type(*from_t);
+            let to_t = flatten_type(*to_t);
+
+            match (from_t, to_t) {
+                (t1, t2) if t1 == t2 => flatten_expr(*e, t1, place, state),
+                // arrays can only become slices
+                (arr_t @ FlatType::Arr(_, _), slice_t) => {
+                    let &FlatType::Arr(_, len) = &arr_t else {
+                        unreachable!()
+                    };
+                    let ptr = flatten_array_ptr(*e, arr_t, state);
+                    let len_place = state.new_temp("len", FlatType::U16);
+                    state.add_code(Line::SetConst(
+                        len_place.clone(),
+                        FlatType::U16,
+                        Const::ConstU16(len),
+                    ));
+                    flatten_slice(place, slice_t, ptr, len_place, state);
+                }
+                (from_t, FlatType::Bool) => {
+                    let val = state.new_temp("val", from_t.clone());
+                    flatten_expr(*e, from_t.clone(), val.clone(), state);
+                    let zero = state.new_temp("zero", from_t.clone());
+                    state.add_code(Line::SetConst(
+                        zero.clone(),
+                        from_t.clone(),
+                        Const::ConstZero,
+                    ));
+                    state.add_code(Line::SetBinop(place, from_t, Binop::Neq, val, zero));
+                }
+                (from_t, to_t) => {
+                    let val = state.new_temp("val", from_t.clone());
+                    flatten_expr(*e, from_t.clone(), val.clone(), state);
+                    state.add_code(Line::SetUnop(place, to_t, Unop::Convert(from_t), val));
+                }
+            }
+        }
+        Expr::Add(loc, a, b) => flatten_arith(
+            Binop::Add,
+            Some((&loc, Binop::AddOverflows)),
+            *a,
+            *b,
+            t,
+            place,
+            state,
+        ),
+        Expr::Sub(loc, a, b) => flatten_arith(
+            Binop::Sub,
+            Some((&loc, Binop::SubOverflows)),
+            *a,
+            *b,
+            t,
+            place,
+            state,
+        ),
+        Expr::Mul(loc, a, b) => flatten_arith(
+            Binop::Mul,
+            Some((&loc, Binop::MulOverflows)),
+            *a,
+            *b,
+            t,
+            place,
+            state,
+        ),
+        Expr::WrappingAdd(_, a, b) => flatten_arith(Binop::Add, None, *a, *b, t, place, state),
+        Expr::WrappingSub(_, a, b) => flatten_arith(Binop::Sub, None, *a, *b, t, place, state),
+        Expr::WrappingMul(_, a, b) => flatten_arith(Binop::Mul, None, *a, *b, t, place, state),
+        Expr::SaturatingAdd(_, a, b) => flatten_arith(Binop::SatAdd, None, *a, *b, t, place, state),
+        Expr::SaturatingSub(_, a, b) => flatten_arith(Binop::SatSub, None, *a, *b, t, place, state),
+        Expr::SaturatingMul(_, a, b) => flatten_arith(Binop::SatMul, None, *a, *b, t, place, state),
+        Expr::BitAnd(_, a, b) => flatten_arith(Binop::And, None, *a, *b, t, place, state),
+        Expr::BitOr(_, a, b) => flatten_arith(Binop::Or, None, *a, *b, t, place, state),
+        Expr::BitXor(_, a, b) => flatten_arith(Binop::Xor, None, *a, *b, t, place, state),
+        Expr::Shl(_, a, b) => flatten_arith(Binop::Shl, None, *a, *b, t, place, state),
+        Expr::Shr(_, a, b) => flatten_arith(Binop::Shr, None, *a, *b, t, place, state),
+        Expr::Div(loc, a, b) => flatten_division(&loc, Binop::Div, *a, *b, t, place, state),
+        Expr::Rem(loc, a, b) => flatten_division(&loc, Binop::Rem, *a, *b, t, place, state),
+        Expr::And(_, a, b) => {
+            let l_rhs = state.new_label();
+            let l_end = state.new_label();
+
+            // the right hand side is only evaluated if the left one is true
+            flatten_expr(*a, FlatType::Bool, place.clone(), state);
+            state.add_code(Line::If(place.clone(), l_rhs.clone(), l_end.clone()));
+            state.add_code(Line::Label(l_rhs));
+            flatten_expr(*b, FlatType::Bool, place, state);
+            state.add_code(Line::Label(l_end));
+        }
+        Expr::Or(_, a, b) => {
+            let l_rhs = state.new_label();
+            let l_end = state.new_label();
+
+            // the right hand side is only evaluated if the left one is false
+            flatten_expr(*a, FlatType::Bool, place.clone(), state);
+            state.add_code(Line::If(place.clone(), l_end.clone(), l_rhs.clone()));
+            state.add_code(Line::Label(l_rhs));
+            flatten_expr(*b, FlatType::Bool, place, state);
+            state.add_code(Line::Label(l_end));
         }
         Expr::Block(_, bl) => flatten_block(bl, t, place, state),
         Expr::Not(_, e) => {
@@ -215,9 +844,21 @@ fn flatten_expr(expr: Expr, t: FlatType, place: Temp, state: &mut FlattenState)
             flatten_expr(*e, t.clone(), arg_place.clone(), state);
             state.add_code(Line::SetUnop(place, t, Unop::Not, arg_place));
         }
-        Expr::Neg(_, e) => {
+        Expr::Neg(loc, e) => {
             let arg_place = state.new_temp("neg_arg", t.clone());
             flatten_expr(*e, t.clone(), arg_place.clone(), state);
+            // negating overflows exactly when subtracting from zero does
+            let zero = match t {
+                FlatType::I8 => Some(Const::ConstI8(0)),
+                FlatType::I16 => Some(Const::ConstI16(0)),
+                FlatType::I32 => Some(Const::ConstI32(0)),
+                _ => None,
+            };
+            if let Some(zero) = zero {
+                let zero_t = state.new_temp("zero", t.clone());
+                state.add_code(Line::SetConst(zero_t.clone(), t.clone(), zero));
+                flatten_overflow_check(&loc, Binop::SubOverflows, &t, &zero_t, &arg_place, state);
+            }
             state.add_code(Line::SetUnop(place, t, Unop::Neg, arg_place));
         }
         Expr::Deref(_, e) => {
@@ -226,32 +867,128 @@ fn flatten_expr(expr: Expr, t: FlatType, place: Temp, state: &mut FlattenState)
             flatten_expr(*e, ptr_t.clone(), ptr_place.clone(), state);
             state.add_code(Line::SetUnop(place, t, Unop::Deref, ptr_place));
         }
-        Expr::Lambda(_, args, ret, body) => {
+        Expr::Lambda(loc, args, ret, body, captures) => {
             let lambda_g = state.new_global("lambda");
+            // the environment comes first, and the captured values are bound to their old names
+            let env_name: Rc<str> = "#env".into();
+            let env_t = Type::Struct(captures.clone());
+            let mut stmnts: Vec<_> = captures
+                .iter()
+                .map(|(name, capture_t)| {
+                    let env = Expr::Deref(
+                        loc.clone(),
+                        Box::new(Expr::Ident(loc.clone(), env_name.clone())),
+                    );
+                    let value = Expr::FieldAccess(
+                        loc.clone(),
+                        Box::new(env),
+                        name.clone(),
+                        Box::new(env_t.clone()),
+                    );
+                    Statement::Let(
+                        loc.clone(),
+                        name.clone(),
+                        Box::new(capture_t.clone()),
+                        value,
+                    )
+                })
+                .collect();
+            stmnts.push(Statement::Express(
+                loc.clone(),
+                Box::new(ret.clone()),
+                *body,
+            ));
+            let body = Expr::Block(loc, stmnts.into_boxed_slice());
+            let env_arg = (env_name, Type::Pointer(Box::new(env_t.clone())));
+            let args = [env_arg].into_iter().chain(args.into_vec()).collect();
+
             let f = Function::init(args, ret);
             state.fns.insert(lambda_g.clone(), f);
-            flatten_function(lambda_g.clone(), *body, state.statics, state.fns);
-            state.add_code(Line::ReadGlobal(place, t, lambda_g));
-        }
-        Expr::Call(_, f_name, args) => {
-            let f_name = state.ident_from_identifier(f_name);
-            let t_args = match state.get_type(f_name.clone()) {
-                Some(FlatType::FnPtr(args, _)) => args,
-                t => unreachable!("{} {t:?}", f_name.display()),
+            flatten_function(
+                lambda_g.clone(),
+                body,
+                state.statics,
+                state.fns,
+                state.links,
+            );
+
+            let fn_t = field_type(&t, 0);
+            let f = state.new_temp("fn", fn_t.clone());
+            state.add_code(Line::ReadGlobal(f.clone(), fn_t, lambda_g));
+            let env_ptr = state.new_temp("env_ptr", FlatType::Ptr(None));
+            if captures.is_empty() {
+                state.add_code(Line::SetConst(
+                    env_ptr.clone(),
+                    FlatType::Ptr(None),
+                    Const::ConstZero,
+                ));
+            } else {
+                // every closure gets an environment of its own, which can outlive this call
+                let env_t = flatten_type(env_t);
+                let env = state.new_temp("env", env_t.clone());
+                state.add_code(Line::SetConst(env.clone(), env_t.clone(), Const::ConstZero));
+                for (n, (name, _)) in captures.into_vec().into_iter().enumerate() {
+                    let Ident::Temp(value) = state.ident_from_identifier(name) else {
+                        unreachable!("only locals are captured");
+                    };
+                    state.add_code(Line::WriteField(
+                        env.clone(),
+                        env_t.clone(),
+                        n as u16,
+                        value,
+                    ));
+                }
+                state.add_code(Line::SetAlloc(env_ptr.clone(), env_t, env));
+            }
+            flatten_closure(place, t, f, env_ptr, state);
+        }
+        Expr::Call(_, callee, args, callee_t) => {
+            // functions called by name are called directly
+            let direct = match &*callee {
+                Expr::Ident(_, name) => match state.ident_from_identifier(name.clone()) {
+                    Ident::Global(g) => match state.get_type(g.clone()) {
+                        Some(FlatType::FnPtr(args, _)) => Some((g, args)),
+                        _ => None,
+                    },
+                    Ident::Temp(_) => None,
+                },
+                _ => None,
+            };
+            let (f, t_args, env) = match direct {
+                Some((g, t_args)) => (Ident::Global(g), t_args.into_vec(), None),
+                // anything else is a closure, which has to be taken apart
+                None => {
+                    let closure_t = flatten_type(*callee_t);
+                    let closure = state.new_temp("closure", closure_t.clone());
+                    flatten_expr(*callee, closure_t.clone(), closure.clone(), state);
+                    let fn_t = field_type(&closure_t, 0);
+                    let FlatType::FnPtr(args, _) = &fn_t else {
+                        unreachable!("closure without a function");
+                    };
+                    let t_args = args[1..].to_vec();
+                    let f = state.new_temp("fn", fn_t.clone());
+                    state.add_code(Line::ReadField(
+                        f.clone(),
+                        closure_t.clone(),
+                        closure.clone(),
+                        0,
+                    ));
+                    let env = state.new_temp("env", FlatType::Ptr(None));
+                    state.add_code(Line::ReadField(env.clone(), closure_t, closure, 1));
+                    (f.into(), t_args, Some(env))
+                }
             };
 
-            let args = args
-                .into_vec()
+            let args = env
                 .into_iter()
-                .zip(t_args.into_vec())
-                .map(|(a, t)| {
+                .chain(args.into_vec().into_iter().zip(t_args).map(|(a, t)| {
                     let place = state.new_temp("arg", t.clone());
                     flatten_expr(a, t, place.clone(), state);
                     place
-                })
+                }))
                 .collect();
 
-            state.add_code(Line::SetCall(place, t, f_name, args));
+            state.add_code(Line::SetCall(place, t, f, args));
         }
         Expr::If(_, cond, e_true, e_false) => {
             let cond_place = state.new_temp("condition", FlatType::Bool);
@@ -268,6 +1005,55 @@ fn flatten_expr(expr: Expr, t: FlatType, place: Temp, state: &mut FlattenState)
             flatten_expr(*e_false, t, place, state);
             state.add_code(Line::Label(l_end));
         }
+        Expr::IfLet(_, n, opt, inner_t, e_some, e_null) => {
+            let opt_t = flatten_type(Type::Option(inner_t.clone()));
+            let opt_place = state.new_temp("opt", opt_t.clone());
+            flatten_expr(*opt, opt_t.clone(), opt_place.clone(), state);
+            let cond_place = state.new_temp("is_some", FlatType::Bool);
+            flatten_is_some(cond_place.clone(), &opt_t, opt_place.clone(), state);
+            let l_some = state.new_label();
+            let l_null = state.new_label();
+            let l_end = state.new_label();
+
+            state.add_code(Line::If(cond_place, l_some.clone(), l_null.clone()));
+            state.add_code(Line::Label(l_some));
+            let var = state.new_temp_from_identifier(n, flatten_type(*inner_t));
+            flatten_opt_value(var, &opt_t, opt_place, state);
+            flatten_expr(*e_some, t.clone(), place.clone(), state);
+            state.add_code(Line::Goto(l_end.clone()));
+            state.add_code(Line::Label(l_null));
+            flatten_expr(*e_null, t, place, state);
+            state.add_code(Line::Label(l_end));
+        }
+        Expr::Unwrap(loc, opt, inner_t) => {
+            let opt_t = flatten_type(Type::Option(inner_t));
+            let opt_place = state.new_temp("opt", opt_t.clone());
+            flatten_expr(*opt, opt_t.clone(), opt_place.clone(), state);
+            let is_some = state.new_temp("is_some", FlatType::Bool);
+            flatten_is_some(is_some.clone(), &opt_t, opt_place.clone(), state);
+
+            let safe_l = state.new_label();
+            let error_l = state.new_label();
+
+            state.add_code(Line::If(is_some, safe_l.clone(), error_l.clone()));
+            state.add_code(Line::Label(error_l));
+            state.add_code(Line::Panic(loc.clone(), PanicReason::UnwrappedNull));
+            state.add_code(Line::Label(safe_l));
+            flatten_opt_value(place, &opt_t, opt_place, state);
+        }
+        Expr::Loop(_, label, body) => {
+            let l_start = state.new_label();
+            let l_end = state.new_label();
+
+            state.add_code(Line::Label(l_start.clone()));
+            let hole = state.temp_hole();
+            state.push_loop(label, l_start.clone(), l_end.clone(), place, t);
+            flatten_expr(*body, FlatType::Unit, hole, state);
+            state.pop_loop();
+
+            state.add_code(Line::Goto(l_start));
+            state.add_code(Line::Label(l_end));
+        }
         Expr::Eq(_, l, r, op_t) => {
             let op_t = flatten_type(*op_t);
             let tl = state.new_temp("eq_arg1", op_t.clone());
@@ -325,6 +1111,55 @@ fn flatten_expr(expr: Expr, t: FlatType, place: Temp, state: &mut FlattenState)
     }
 }
 
+/// Part of an aggregate that is being written to
+enum PathStep<I> {
+    Field(u16),
+    Index(I),
+}
+
+/// Writes `val` into the part at the end of `path` of the aggregate in `agg`,
+/// with each step of the path being paired with the type of the aggregate it steps into
+fn flatten_part_write(
+    agg: Temp,
+    path: &[(PathStep<Temp>, FlatType)],
+    val: Temp,
+    state: &mut FlattenState<'_>,
+) {
+    let ((step, agg_t), path) = path.split_first().unwrap();
+    let write = |agg, val| match step {
+        PathStep::Field(n) => Line::WriteField(agg, agg_t.clone(), *n, val),
+        PathStep::Index(index) => Line::SetIndex(agg, agg_t.clone(), index.clone(), val),
+    };
+    if path.is_empty() {
+        state.add_code(write(agg, val));
+    } else {
+        let inner = match step {
+            PathStep::Field(n) => {
+                let inner = state.new_temp("field", field_type(agg_t, *n));
+                state.add_code(Line::ReadField(
+                    inner.clone(),
+                    agg_t.clone(),
+                    agg.clone(),
+                    *n,
+                ));
+                inner
+            }
+            PathStep::Index(index) => {
+                let inner = state.new_temp("elem", field_type(agg_t, 0));
+                state.add_code(Line::ReadElement(
+                    inner.clone(),
+                    agg_t.clone(),
+                    agg.clone(),
+                    index.clone(),
+                ));
+                inner
+            }
+        };
+        flatten_part_write(inner.clone(), path, val, state);
+        state.add_code(write(agg, inner));
+    }
+}
+
 fn flatten_block(
     bl: Box<[Statement]>,
     block_t: FlatType,
@@ -347,8 +1182,10 @@ fn flatten_block(
             }
             Statement::Let(_, n, t, e) | Statement::Var(_, n, t, e) => {
                 let t = flatten_type(*t);
-                let place = state.new_temp_from_identifier(n, t.clone());
-                flatten_expr(e, t, place, state);
+                // the binding only comes into scope after its value, which may use a shadowed `n`
+                let place = state.new_temp(&n, t.clone());
+                flatten_expr(e, t, place.clone(), state);
+                state.bind_identifier(n, place);
             }
             Statement::Rebind(_, PlaceExpr::Ident(_, n), e) => {
                 let ident = state.ident_from_identifier(n.clone());
@@ -376,11 +1213,244 @@ fn flatten_block(
                 flatten_expr(e, t.clone(), place_val.clone(), state);
                 state.add_code(Line::WriteTo(place_ptr, t, place_val))
             }
-            Statement::Rebind(_, PlaceExpr::FieldAccess(_, _strct_e, _ident), _e) => {
-                todo!()
+            Statement::Rebind(_, PlaceExpr::SliceElement(loc, slice_e, index_e, slice_t), e) => {
+                let slice_t = flatten_type(*slice_t);
+                let (elem_ptr, elem_t) =
+                    flatten_element_ptr(&loc, *slice_e, *index_e, slice_t, state);
+                let val = state.new_temp("val", elem_t.clone());
+                flatten_expr(e, elem_t.clone(), val.clone(), state);
+                state.add_code(Line::WriteTo(elem_ptr, elem_t, val))
+            }
+            Statement::Rebind(_, pl @ (PlaceExpr::FieldAccess(..) | PlaceExpr::Index(..)), e) => {
+                // steps from the outermost aggregate in, down to the part being written
+                let mut path = Vec::new();
+                let mut root = pl;
+                let root = loop {
+                    root = match root {
+                        PlaceExpr::FieldAccess(_, inner, field, strct_t) => {
+                            let n = field_index(&strct_t, &field);
+                            path.push((PathStep::Field(n), flatten_type(*strct_t)));
+                            *inner
+                        }
+                        PlaceExpr::Index(loc, inner, index_e, arr_t) => {
+                            path.push((PathStep::Index((loc, index_e)), flatten_type(*arr_t)));
+                            *inner
+                        }
+                        root => break root,
+                    };
+                };
+                path.reverse();
+                let path: Vec<_> = path
+                    .into_iter()
+                    .map(|(step, agg_t)| {
+                        let step = match step {
+                            PathStep::Field(n) => PathStep::Field(n),
+                            PathStep::Index((loc, index_e)) => {
+                                let &FlatType::Arr(_, len) = &agg_t else {
+                                    unreachable!("only arrays can be indexed into in place");
+                                };
+                                let index = state.new_temp("index", FlatType::U16);
+                                flatten_expr(*index_e, FlatType::U16, index.clone(), state);
+                                let len_place = state.new_temp("len", FlatType::U16);
+                                state.add_code(Line::SetConst(
+                                    len_place.clone(),
+                                    FlatType::U16,
+                                    Const::ConstU16(len),
+                                ));
+                                flatten_bounds_check(
+                                    &loc,
+                                    Binop::Lt,
+                                    index.clone(),
+                                    len_place,
+                                    state,
+                                );
+                                PathStep::Index(index)
+                            }
+                        };
+                        (step, agg_t)
+                    })
+                    .collect();
+                let root_t = path[0].1.clone();
+                let val_t = match path.last().unwrap() {
+                    (PathStep::Field(n), t) => field_type(t, *n),
+                    (PathStep::Index(_), t) => field_type(t, 0),
+                };
+
+                match root {
+                    PlaceExpr::Ident(_, n) => match state.ident_from_identifier(n.clone()) {
+                        Ident::Temp(agg) => {
+                            let val = state.new_temp("val", val_t.clone());
+                            flatten_expr(e, val_t, val.clone(), state);
+                            flatten_part_write(agg, &path, val, state);
+                        }
+                        Ident::Global(g) => {
+                            let val = state.new_temp("val", val_t.clone());
+                            flatten_expr(e, val_t, val.clone(), state);
+                            let agg = state.new_temp(&n, root_t.clone());
+                            state.add_code(Line::ReadGlobal(
+                                agg.clone(),
+                                root_t.clone(),
+                                g.clone(),
+                            ));
+                            flatten_part_write(agg.clone(), &path, val, state);
+                            state.add_code(Line::WriteGlobal(g, root_t, agg));
+                        }
+                    },
+                    root @ (PlaceExpr::Deref(..) | PlaceExpr::SliceElement(..)) => {
+                        // the pointer is only evaluated once for both reading and writing
+                        let ptr = match root {
+                            PlaceExpr::Deref(_, ptr_e, _) => {
+                                let ptr_t = FlatType::Ptr(Some(Box::new(root_t.clone())));
+                                let ptr = state.new_temp("deref_ptr", ptr_t.clone());
+                                flatten_expr(*ptr_e, ptr_t, ptr.clone(), state);
+                                ptr
+                            }
+                            PlaceExpr::SliceElement(loc, slice_e, index_e, slice_t) => {
+                                let slice_t = flatten_type(*slice_t);
+                                flatten_element_ptr(&loc, *slice_e, *index_e, slice_t, state).0
+                            }
+                            _ => unreachable!(),
+                        };
+                        let val = state.new_temp("val", val_t.clone());
+                        flatten_expr(e, val_t, val.clone(), state);
+                        let agg = state.new_temp("agg", root_t.clone());
+                        state.add_code(Line::SetUnop(
+                            agg.clone(),
+                            root_t.clone(),
+                            Unop::Deref,
+                            ptr.clone(),
+                        ));
+                        flatten_part_write(agg.clone(), &path, val, state);
+                        state.add_code(Line::WriteTo(ptr, root_t, agg));
+                    }
+                    PlaceExpr::FieldAccess(_, _, _, _) | PlaceExpr::Index(_, _, _, _) => {
+                        unreachable!()
+                    }
+                }
+            }
+            Statement::While(_, label, cond, body) => {
+                let l_start = state.new_label();
+                let l_body = state.new_label();
+                let l_end = state.new_label();
+
+                state.add_code(Line::Label(l_start.clone()));
+                let cond_place = state.new_temp("while_cond", FlatType::Bool);
+                flatten_expr(cond, FlatType::Bool, cond_place.clone(), state);
+                state.add_code(Line::If(cond_place, l_body.clone(), l_end.clone()));
+                state.add_code(Line::Label(l_body));
+
+                let hole = state.temp_hole();
+                state.push_loop(
+                    label,
+                    l_start.clone(),
+                    l_end.clone(),
+                    hole.clone(),
+                    FlatType::Unit,
+                );
+                flatten_expr(body, FlatType::Unit, hole, state);
+                state.pop_loop();
+
+                state.add_code(Line::Goto(l_start));
+                state.add_code(Line::Label(l_end));
+            }
+            Statement::Break(_, label, e) => {
+                let the_loop = state.lookup_loop(label.as_deref());
+                let l_end = the_loop.break_l.clone();
+                if let Some(e) = e {
+                    let (t, place) = (the_loop.t.clone(), the_loop.place.clone());
+                    flatten_expr(e, t, place, state);
+                }
+                state.add_code(Line::Goto(l_end));
+            }
+            Statement::Continue(_, label) => {
+                let l_start = state.lookup_loop(label.as_deref()).continue_l.clone();
+                state.add_code(Line::Goto(l_start));
             }
-            Statement::Rebind(_, PlaceExpr::Index(_, _arr_e, _ind_e), _e) => {
-                todo!()
+            Statement::For(_, label, n, elem_t, iterable, body) => {
+                let elem_t = flatten_type(*elem_t);
+                let l_start = state.new_label();
+                let l_body = state.new_label();
+                let l_next = state.new_label();
+                let l_end = state.new_label();
+
+                // ranges count with the loop variable itself, slices with an index into them
+                let (index_t, index, end, ptr) = match iterable {
+                    Iterable::Range(start, end) => {
+                        let index = state.new_temp("for_index", elem_t.clone());
+                        flatten_expr(start, elem_t.clone(), index.clone(), state);
+                        let end_place = state.new_temp("for_end", elem_t.clone());
+                        flatten_expr(end, elem_t.clone(), end_place.clone(), state);
+                        (elem_t.clone(), index, end_place, None)
+                    }
+                    Iterable::Slice(e) => {
+                        let ptr_t = FlatType::Ptr(Some(Box::new(elem_t.clone())));
+                        let slice_t = FlatType::Struct(Box::new([ptr_t.clone(), FlatType::U16]));
+                        let slice = state.new_temp("for_slice", slice_t.clone());
+                        flatten_expr(e, slice_t.clone(), slice.clone(), state);
+
+                        let ptr = state.new_temp("for_ptr", ptr_t);
+                        state.add_code(Line::ReadField(
+                            ptr.clone(),
+                            slice_t.clone(),
+                            slice.clone(),
+                            0,
+                        ));
+                        let len = state.new_temp("for_len", FlatType::U16);
+                        state.add_code(Line::ReadField(len.clone(), slice_t, slice, 1));
+                        let index = state.new_temp("for_index", FlatType::U16);
+                        state.add_code(Line::SetConst(
+                            index.clone(),
+                            FlatType::U16,
+                            Const::ConstU16(0),
+                        ));
+                        (FlatType::U16, index, len, Some(ptr))
+                    }
+                };
+
+                state.add_code(Line::Label(l_start.clone()));
+                let cond = state.new_temp("for_cond", FlatType::Bool);
+                state.add_code(Line::SetBinop(
+                    cond.clone(),
+                    index_t.clone(),
+                    Binop::Lt,
+                    index.clone(),
+                    end,
+                ));
+                state.add_code(Line::If(cond, l_body.clone(), l_end.clone()));
+                state.add_code(Line::Label(l_body));
+
+                let var = state.new_temp_from_identifier(n, elem_t.clone());
+                match ptr {
+                    Some(ptr) => state.add_code(Line::ReadIndex(var, elem_t, ptr, index.clone())),
+                    None => state.add_code(Line::SetTo(var, elem_t, index.clone())),
+                }
+                let hole = state.temp_hole();
+                state.push_loop(
+                    label,
+                    l_next.clone(),
+                    l_end.clone(),
+                    hole.clone(),
+                    FlatType::Unit,
+                );
+                flatten_expr(body, FlatType::Unit, hole, state);
+                state.pop_loop();
+
+                state.add_code(Line::Label(l_next));
+                let one = state.new_temp("one", index_t.clone());
+                state.add_code(Line::SetConst(
+                    one.clone(),
+                    index_t.clone(),
+                    int_const(&index_t, 1),
+                ));
+                state.add_code(Line::SetBinop(
+                    index.clone(),
+                    index_t,
+                    Binop::Add,
+                    index,
+                    one,
+                ));
+                state.add_code(Line::Goto(l_start));
+                state.add_code(Line::Label(l_end));
             }
             Statement::Return(_, e) => {
                 // use block `place` because the function will not continue after this
@@ -4,14 +4,28 @@ use crate::flat::{
     ticker::Ticker, FlatType, Function, Global, Ident, Label, Line, StaticDecl, Temp,
 };
 
+/// Jump targets of a loop being flattened
+#[derive(Debug, Clone)]
+pub struct LoopLabels {
+    pub label: Option<Rc<str>>,
+    pub continue_l: Label,
+    pub break_l: Label,
+    /// Where `break` values are written to
+    pub place: Temp,
+    pub t: FlatType,
+}
+
 pub struct FlattenState<'a> {
     fn_name: &'a str,
     symtab: HashMap<Rc<str>, Temp>,
     pub statics: &'a mut Vec<StaticDecl>,
     pub fns: &'a mut HashMap<Global, Function>,
+    /// Symbols of externals declared in modules, by the name they were declared with
+    pub links: &'a HashMap<Rc<str>, Global>,
     local_types: Vec<Option<FlatType>>,
     label_ticker: Ticker,
     global_ticker: Ticker,
+    loops: Vec<LoopLabels>,
     function: &'a mut Function,
 }
 impl<'a> FlattenState<'a> {
@@ -20,6 +34,7 @@ impl<'a> FlattenState<'a> {
         function: &'a mut Function,
         statics: &'a mut Vec<StaticDecl>,
         fns: &'a mut HashMap<Global, Function>,
+        links: &'a HashMap<Rc<str>, Global>,
     ) -> Self {
         Self {
             local_types: iter::once(None)
@@ -36,8 +51,10 @@ impl<'a> FlattenState<'a> {
             function,
             statics,
             fns,
+            links,
             label_ticker: Ticker::new(),
             global_ticker: Ticker::new(),
+            loops: Vec::new(),
         }
     }
     pub fn add_code(&mut self, line: Line) {
@@ -47,14 +64,20 @@ impl<'a> FlattenState<'a> {
         if let Some(local) = self.symtab.get(&identifier) {
             return local.clone().into();
         }
+        if let Some(symbol) = self.links.get(&identifier) {
+            return symbol.clone().into();
+        }
 
         Global(identifier).into()
     }
     pub fn new_temp_from_identifier(&mut self, identifier: Rc<str>, t: FlatType) -> Temp {
         let temp = self.new_temp(&identifier, t);
-        self.symtab.insert(identifier, temp.clone());
+        self.bind_identifier(identifier, temp.clone());
         temp
     }
+    pub fn bind_identifier(&mut self, identifier: Rc<str>, temp: Temp) {
+        self.symtab.insert(identifier, temp);
+    }
     pub const fn temp_hole(&self) -> Temp {
         Temp(0)
     }
@@ -71,6 +94,37 @@ impl<'a> FlattenState<'a> {
     pub fn new_label(&mut self) -> Label {
         Label(self.label_ticker.tick())
     }
+    pub fn push_loop(
+        &mut self,
+        label: Option<Rc<str>>,
+        continue_l: Label,
+        break_l: Label,
+        place: Temp,
+        t: FlatType,
+    ) {
+        self.loops.push(LoopLabels {
+            label,
+            continue_l,
+            break_l,
+            place,
+            t,
+        });
+    }
+    pub fn pop_loop(&mut self) {
+        self.loops.pop();
+    }
+    /// Finds the loop with the given label, or the innermost one if there is no label
+    pub fn lookup_loop(&self, label: Option<&str>) -> &LoopLabels {
+        match label {
+            None => self.loops.last(),
+            Some(label) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|l| l.label.as_deref() == Some(label)),
+        }
+        .expect("type checker ensures we are in a loop")
+    }
     pub fn new_global(&mut self, purpose: &str) -> Global {
         let n = self.global_ticker.tick();
         Global(format!("#g@{}${purpose}{n}", self.fn_name).into())
@@ -103,7 +157,8 @@ impl<'a> FlattenState<'a> {
                         | StaticDecl::SetArray(n, t, _)
                         | StaticDecl::SetString(n, t, _)
                         | StaticDecl::External(n, t)
-                        | StaticDecl::SetPtr(n, t, _) => {
+                        | StaticDecl::SetPtr(n, t, _)
+                        | StaticDecl::SetSlice(n, t, _, _) => {
                             if n == &g {
                                 return Some(t.clone());
                             }
@@ -7,7 +7,7 @@ use crate::ttype::Type;
 
 use super::{
     flat_codegen::flatten_type, Binop, Const, FlatType, Function, Global, Ident, Label, Line,
-    Program, StaticDecl, Temp, Unop,
+    PanicReason, Program, StaticDecl, Temp, Unop,
 };
 
 impl Function {
@@ -29,6 +29,13 @@ impl Function {
             lines: Vec::new(),
         }
     }
+    /// The temporaries the arguments are passed in, with their types
+    pub(crate) fn params(&self) -> impl Iterator<Item = (Temp, &FlatType)> {
+        self.arg_types
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (Temp(i + 1), t))
+    }
 }
 
 impl Display for Global {
@@ -148,6 +155,9 @@ impl Display for Program {
                 }
                 StaticDecl::SetString(dest, t, val) => writeln!(f, "static {dest}: {t} = {val:?}")?,
                 StaticDecl::SetPtr(dest, t, val) => writeln!(f, "static {dest}: {t} = {val}")?,
+                StaticDecl::SetSlice(dest, t, arr, len) => {
+                    writeln!(f, "static {dest}: {t} = {arr}[0..{len}]")?
+                }
                 StaticDecl::External(dest, t) => writeln!(f, "external {dest}: {t}")?,
             }
         }
@@ -227,6 +237,19 @@ impl Display for DisplayLine<'_> {
                 dest.display_with(locals),
                 src.display_with(locals)
             ),
+            Line::SetAlloc(dest, t, src) => write!(
+                f,
+                "{} = {t} new {}",
+                dest.display_with(locals),
+                src.display_with(locals)
+            ),
+            Line::SetElementAddr(dest, t, ptr, index) => write!(
+                f,
+                "{} = {t} &{}[{}]",
+                dest.display_with(locals),
+                ptr.display_with(locals),
+                index.display_with(locals)
+            ),
             Line::SetCall(dest, t, name, args) => {
                 write!(
                     f,
@@ -264,13 +287,81 @@ impl Display for DisplayLine<'_> {
                 dest_ptr.display_with(locals),
                 src.display_with(locals)
             ),
-            Line::SetIndex(dest, t, src) => write!(
+            Line::SetIndex(dest, t, index, src) => write!(
+                f,
+                "{}[{}] = {t} {}",
+                dest.display_with(locals),
+                index.display_with(locals),
+                src.display_with(locals)
+            ),
+            Line::ReadField(dest, t, src, n) => write!(
+                f,
+                "{} = {t} {}.{n}",
+                dest.display_with(locals),
+                src.display_with(locals)
+            ),
+            Line::WriteField(dest, t, n, src) => write!(
                 f,
-                "{}[??] = {t} {}",
+                "{}.{n} = {t} {}",
                 dest.display_with(locals),
                 src.display_with(locals)
             ),
-            Line::Panic(msg) => write!(f, "panic({msg})"),
+            Line::ReadIndex(dest, t, ptr, index) => write!(
+                f,
+                "{} = {t} {}[{}]",
+                dest.display_with(locals),
+                ptr.display_with(locals),
+                index.display_with(locals)
+            ),
+            Line::ReadElement(dest, t, arr, index) => write!(
+                f,
+                "{} = {t} {}[{}]",
+                dest.display_with(locals),
+                arr.display_with(locals),
+                index.display_with(locals)
+            ),
+            Line::Panic(loc, reason) => {
+                write!(f, "panic(:{}:{}: {reason})", loc.line_start, loc.col_start)
+            }
+        }
+    }
+}
+impl Display for PanicReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PanicReason::IndexOutOfBounds => write!(f, "index out of bounds"),
+            PanicReason::Overflow => write!(f, "arithmetic overflow"),
+            PanicReason::DivisionByZero => write!(f, "divended was zero"),
+            PanicReason::UnwrappedNull => write!(f, "unwrapped null"),
+        }
+    }
+}
+impl Const {
+    /// This number converted to the number type `t`, wrapping when it does not fit and cutting
+    /// floats toward zero
+    pub(crate) fn convert(self, t: &FlatType) -> Const {
+        let n: i64 = match self {
+            Const::ConstBoolean(b) => b.into(),
+            Const::ConstI8(n) => n.into(),
+            Const::ConstU8(n) => n.into(),
+            Const::ConstI16(n) => n.into(),
+            Const::ConstU16(n) => n.into(),
+            Const::ConstI32(n) => n.into(),
+            Const::ConstU32(n) => n.into(),
+            Const::ConstFloat(_) if *t == FlatType::Float => return self,
+            Const::ConstFloat(f) => f as i64,
+            Const::ConstZero => 0,
+        };
+        match t {
+            FlatType::Bool => Const::ConstBoolean(n != 0),
+            FlatType::I8 => Const::ConstI8(n as i8),
+            FlatType::U8 => Const::ConstU8(n as u8),
+            FlatType::I16 => Const::ConstI16(n as i16),
+            FlatType::U16 => Const::ConstU16(n as u16),
+            FlatType::I32 => Const::ConstI32(n as i32),
+            FlatType::U32 => Const::ConstU32(n as u32),
+            FlatType::Float => Const::ConstFloat(n as f64),
+            t => unreachable!("conversion to {t}, which is not a number"),
         }
     }
 }
@@ -296,6 +387,18 @@ impl Display for Binop {
             Binop::Sub => write!(f, "-"),
             Binop::Mul => write!(f, "*"),
             Binop::Div => write!(f, "/"),
+            Binop::Rem => write!(f, "%"),
+            Binop::SatAdd => write!(f, "+|"),
+            Binop::SatSub => write!(f, "-|"),
+            Binop::SatMul => write!(f, "*|"),
+            Binop::AddOverflows => write!(f, "+?"),
+            Binop::SubOverflows => write!(f, "-?"),
+            Binop::MulOverflows => write!(f, "*?"),
+            Binop::And => write!(f, "&"),
+            Binop::Or => write!(f, "|"),
+            Binop::Xor => write!(f, "^"),
+            Binop::Shl => write!(f, "<<"),
+            Binop::Shr => write!(f, ">>"),
             Binop::Eq => write!(f, "=="),
             Binop::Neq => write!(f, "!="),
             Binop::Lt => write!(f, "<"),
@@ -311,6 +414,7 @@ impl Display for Unop {
             Unop::Not => write!(f, "!"),
             Unop::Neg => write!(f, "-"),
             Unop::Deref => write!(f, "*"),
+            Unop::Convert(from) => write!(f, "({from}) "),
         }
     }
 }
@@ -1,6 +1,9 @@
 use std::{collections::HashMap, rc::Rc};
 
-use crate::ttype::ast::{Decl, Program as TypedProgram};
+use crate::{
+    parse::location::Location,
+    ttype::ast::{Decl, Expr, Program as TypedProgram},
+};
 
 mod flat_codegen;
 mod impls;
@@ -18,6 +21,7 @@ pub fn flatten(program: TypedProgram) -> Program {
     let mut fns = HashMap::new();
     let mut decl_exprs = Vec::new();
     let mut external_symbols = Vec::new();
+    let mut links = HashMap::new();
     for (name, decl) in program.0.into_vec() {
         match decl {
             Decl::Fn(_, args, b) => {
@@ -36,22 +40,56 @@ pub fn flatten(program: TypedProgram) -> Program {
                         .collect(),
                     Box::new(flatten_type(*ret)),
                 );
-                external_symbols.push(StaticDecl::External(Global(name), t));
+                add_external(name, t, &mut external_symbols, &mut links);
             }
             Decl::ExternStatic(_, t) => {
-                external_symbols.push(StaticDecl::External(Global(name), flatten_type(*t)));
+                add_external(name, flatten_type(*t), &mut external_symbols, &mut links);
+            }
+        }
+    }
+    for (_, _, expr) in &mut decl_exprs {
+        if let Expr::Ident(_, name) = expr {
+            if let Some(symbol) = links.get(name) {
+                *name = symbol.inner().clone();
             }
         }
     }
     let mut statics = compute_statics(decl_exprs, external_symbols);
 
     for (name, body) in fn_exprs {
-        flatten_function(name, body, &mut statics, &mut fns);
+        flatten_function(name, body, &mut statics, &mut fns, &links);
     }
 
     Program { fns, statics }
 }
 
+/// Declares the symbol an external declaration refers to, which is its name without the module it
+/// was declared in, unless it already has been
+///
+/// Declarations in modules are recorded in `links`, so uses of them can be made to refer to the
+/// symbol.
+fn add_external(
+    name: Rc<str>,
+    t: FlatType,
+    external_symbols: &mut Vec<StaticDecl>,
+    links: &mut HashMap<Rc<str>, Global>,
+) {
+    let symbol = match name.rsplit_once("::") {
+        Some((_, symbol)) => {
+            let symbol = Global(symbol.into());
+            links.insert(name, symbol.clone());
+            symbol
+        }
+        None => Global(name),
+    };
+    let declared = external_symbols
+        .iter()
+        .any(|s| matches!(s, StaticDecl::External(g, _) if *g == symbol));
+    if !declared {
+        external_symbols.push(StaticDecl::External(symbol, t));
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Label(u64);
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -125,8 +163,11 @@ pub enum StaticDecl {
     SetConst(Global, FlatType, Const),
     SetAlias(Global, FlatType, Global),
     SetArray(Global, FlatType, Box<[Const]>),
+    /// the bytes of the string, type is that of the byte array
     SetString(Global, FlatType, Box<str>),
     SetPtr(Global, FlatType, Global),
+    /// a slice of the whole array in the second `Global`, which has `u16` elements
+    SetSlice(Global, FlatType, Global, u16),
     External(Global, FlatType),
 }
 
@@ -140,9 +181,27 @@ pub enum Line {
     SetCall(Temp, FlatType, Ident, Box<[Temp]>),
     /// first `Temp` must contain a pointer
     WriteTo(Temp, FlatType, Temp),
-    // TODO: merge with `WriteTo` using a offset where `Temp(0)` represents no offset
-    SetIndex(Temp, FlatType, Temp),
+    /// writes the last `Temp` into the element at the index in the second `Temp` of the array
+    /// in the first `Temp`, type is that of the array
+    SetIndex(Temp, FlatType, Temp, Temp),
+    /// reads field `n` of the struct or array in the second `Temp`, type is that of the aggregate
+    ReadField(Temp, FlatType, Temp, u16),
+    /// writes the last `Temp` into field `n` of the struct or array in the first `Temp`,
+    /// type is that of the aggregate
+    WriteField(Temp, FlatType, u16, Temp),
+    /// reads the element at the index in the last `Temp` through the pointer in the second,
+    /// type is that of the element
+    ReadIndex(Temp, FlatType, Temp, Temp),
+    /// reads the element at the index in the last `Temp` of the array in the second `Temp`,
+    /// type is that of the array
+    ReadElement(Temp, FlatType, Temp, Temp),
     SetAddrOf(Temp, FlatType, Ident),
+    /// sets the first `Temp` to a pointer to the element at the index in the last `Temp` through
+    /// the pointer in the second, type is that of the element
+    SetElementAddr(Temp, FlatType, Temp, Temp),
+    /// sets the first `Temp` to a pointer to newly allocated memory holding the value in the last
+    /// `Temp`, which lives as long as the program does, type is that of the value
+    SetAlloc(Temp, FlatType, Temp),
 
     ReadGlobal(Temp, FlatType, Global),
     WriteGlobal(Global, FlatType, Temp),
@@ -153,7 +212,28 @@ pub enum Line {
     Goto(Label),
     Ret(Temp),
 
-    Panic(Box<str>),
+    Panic(Location, PanicReason),
+}
+
+/// Why a program panicked, each kind of check that can fail at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PanicReason {
+    IndexOutOfBounds,
+    Overflow,
+    DivisionByZero,
+    UnwrappedNull,
+}
+
+impl PanicReason {
+    /// Stable code identifying the kind of panic, like those of compile errors
+    pub fn code(self) -> &'static str {
+        match self {
+            PanicReason::IndexOutOfBounds => "E0200",
+            PanicReason::Overflow => "E0201",
+            PanicReason::DivisionByZero => "E0202",
+            PanicReason::UnwrappedNull => "E0203",
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -171,10 +251,29 @@ pub enum Const {
 }
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Binop {
+    /// Wrapping addition
     Add,
+    /// Wrapping subtraction
     Sub,
+    /// Wrapping multiplication
     Mul,
     Div,
+    Rem,
+    SatAdd,
+    SatSub,
+    SatMul,
+    /// Whether the addition overflows, dest is a bool
+    AddOverflows,
+    /// Whether the subtraction overflows, dest is a bool
+    SubOverflows,
+    /// Whether the multiplication overflows, dest is a bool
+    MulOverflows,
+    And,
+    Or,
+    Xor,
+    Shl,
+    /// Arithmetic shift for signed types, logical for unsigned
+    Shr,
     Eq,
     Neq,
     Lt,
@@ -182,9 +281,12 @@ pub enum Binop {
     Gt,
     Gte,
 }
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Unop {
     Not,
     Neg,
     Deref,
+    /// Converts from the number type given to that of the line, wrapping when it does not fit and
+    /// extending the sign of signed types
+    Convert(FlatType),
 }
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::flat::{Binop, Const, Ident, Line, Program, StaticDecl, Temp, Unop};
+use crate::flat::{Binop, Const, FlatType, Ident, Line, Program, StaticDecl, Temp, Unop};
 #[derive(Debug, Clone)]
 enum Value {
     Const(Const),
@@ -22,6 +22,22 @@ impl Symtab {
     fn get<I: Into<Ident>>(&self, i: I) -> &Value {
         &self.inner[&i.into()]
     }
+    /// Values of `temps` are unknown after they might have been changed through a pointer
+    fn forget_all(&mut self, temps: &[Temp]) {
+        for t in temps {
+            self.invalidate_aliases(t.clone());
+            self.set(t.clone(), Value::RuntimeDependant);
+        }
+    }
+    /// Anything aliasing `i` still holds the old value after `i` gets changed in place
+    fn invalidate_aliases<I: Into<Ident>>(&mut self, i: I) {
+        let i = i.into();
+        for v in self.inner.values_mut() {
+            if matches!(v, Value::Alias(a) if *a == i) {
+                *v = Value::RuntimeDependant;
+            }
+        }
+    }
 }
 
 // TODO: either remove units or set them to `$0`
@@ -44,6 +60,7 @@ pub fn const_prop_pass(mut program: Program) -> Program {
             StaticDecl::SetArray(g, _, _) => stab.set(g.clone(), Value::RuntimeDependant),
             StaticDecl::SetString(g, _, _) => stab.set(g.clone(), Value::RuntimeDependant),
             StaticDecl::SetPtr(g, _, _) => stab.set(g.clone(), Value::RuntimeDependant),
+            StaticDecl::SetSlice(g, _, _, _) => stab.set(g.clone(), Value::RuntimeDependant),
             StaticDecl::External(g, _) => stab.set(g.clone(), Value::RuntimeDependant),
         }
     }
@@ -62,6 +79,8 @@ pub fn const_prop_pass(mut program: Program) -> Program {
         // FIXME: handle IF and GOTO properly!!!!!!!!!!!!!!!!!!!!!
         // FIXME: do not inline mutable globals as their values could be different at runtime
         let mut jumping = None;
+        // temporaries that have had their address taken and could be changed through it
+        let mut referenced = Vec::new();
         for line in &mut f.lines {
             if let Some(lbl) = &jumping {
                 match line {
@@ -89,6 +108,7 @@ pub fn const_prop_pass(mut program: Program) -> Program {
                 Line::SetBinop(dest, ty, binop, s1, s2) => {
                     match (stab.get(s1.clone()), stab.get(s2.clone())) {
                         (&Value::Const(c1), &Value::Const(c2)) => {
+                            let (c1, c2) = (zero_of_type(c1, ty), zero_of_type(c2, ty));
                             let c = apply_binop(*binop, c1, c2);
                             stab.set(dest.clone(), Value::Const(c));
                             *line = Line::SetConst(dest.clone(), ty.clone(), c);
@@ -112,9 +132,9 @@ pub fn const_prop_pass(mut program: Program) -> Program {
                 Line::SetUnop(dest, ty, unop, s) => {
                     match stab.get(s.clone()) {
                         &Value::Const(c) => {
-                            let c = apply_unop(*unop, c);
+                            let c = apply_unop(unop, c, ty);
                             stab.set(dest.clone(), Value::Const(c));
-                            *line = Line::SetConst(dest.clone(), ty.clone(), apply_unop(*unop, c));
+                            *line = Line::SetConst(dest.clone(), ty.clone(), c);
                         }
                         Value::Alias(Ident::Temp(t)) => {
                             *s = t.clone();
@@ -123,8 +143,12 @@ pub fn const_prop_pass(mut program: Program) -> Program {
                         _ => stab.set(dest.clone(), Value::RuntimeDependant),
                     }
                 }
-                Line::SetCall(t, _, _, _) => stab.set(t.clone(), Value::RuntimeDependant),
+                Line::SetCall(t, _, _, _) => {
+                    stab.forget_all(&referenced);
+                    stab.set(t.clone(), Value::RuntimeDependant);
+                }
                 Line::WriteTo(s1, _, s2) => {
+                    stab.forget_all(&referenced);
                     match (stab.get(s1.clone()), stab.get(s2.clone())) {
                         (Value::Alias(Ident::Temp(t1)), Value::Alias(Ident::Temp(t2))) => {
                             *s1 = t1.clone();
@@ -139,8 +163,46 @@ pub fn const_prop_pass(mut program: Program) -> Program {
                         _ => (),
                     }
                 }
-                Line::SetIndex(_, _, _) => todo!(),
-                Line::SetAddrOf(t, _, _) => stab.set(t.clone(), Value::RuntimeDependant),
+                Line::SetIndex(dest, _, s1, s2) => {
+                    if let Value::Alias(Ident::Temp(t)) = stab.get(s1.clone()) {
+                        *s1 = t.clone();
+                    }
+                    if let Value::Alias(Ident::Temp(t)) = stab.get(s2.clone()) {
+                        *s2 = t.clone();
+                    }
+                    stab.invalidate_aliases(dest.clone());
+                    stab.set(dest.clone(), Value::RuntimeDependant);
+                }
+                Line::ReadField(dest, _, s, _) | Line::SetAlloc(dest, _, s) => {
+                    if let Value::Alias(Ident::Temp(t)) = stab.get(s.clone()) {
+                        *s = t.clone();
+                    }
+                    stab.set(dest.clone(), Value::RuntimeDependant);
+                }
+                Line::WriteField(dest, _, _, s) => {
+                    if let Value::Alias(Ident::Temp(t)) = stab.get(s.clone()) {
+                        *s = t.clone();
+                    }
+                    stab.invalidate_aliases(dest.clone());
+                    stab.set(dest.clone(), Value::RuntimeDependant);
+                }
+                Line::ReadIndex(dest, _, s1, s2)
+                | Line::ReadElement(dest, _, s1, s2)
+                | Line::SetElementAddr(dest, _, s1, s2) => {
+                    if let Value::Alias(Ident::Temp(t)) = stab.get(s1.clone()) {
+                        *s1 = t.clone();
+                    }
+                    if let Value::Alias(Ident::Temp(t)) = stab.get(s2.clone()) {
+                        *s2 = t.clone();
+                    }
+                    stab.set(dest.clone(), Value::RuntimeDependant);
+                }
+                Line::SetAddrOf(t, _, i) => {
+                    if let Ident::Temp(t2) = i {
+                        referenced.push(t2.clone());
+                    }
+                    stab.set(t.clone(), Value::RuntimeDependant);
+                }
                 Line::ReadGlobal(t, ty, g) => {
                     let t = t.clone();
                     match stab.get(g.clone()) {
@@ -176,7 +238,7 @@ pub fn const_prop_pass(mut program: Program) -> Program {
                 // TODO: don't just stop in panic as soon as any control flow pops up
                 Line::Label(_) => break,
                 Line::Ret(_) => break,
-                Line::Panic(_) => break,
+                Line::Panic(..) => break,
             }
         }
     }
@@ -184,11 +246,25 @@ pub fn const_prop_pass(mut program: Program) -> Program {
     program
 }
 
-fn apply_unop(unop: Unop, c: Const) -> Const {
+/// Turns the null constant into the zero of the number type `t`, so it can be operated on
+fn zero_of_type(c: Const, t: &FlatType) -> Const {
+    match (c, t) {
+        (Const::ConstZero, FlatType::I8) => Const::ConstI8(0),
+        (Const::ConstZero, FlatType::U8) => Const::ConstU8(0),
+        (Const::ConstZero, FlatType::I16) => Const::ConstI16(0),
+        (Const::ConstZero, FlatType::U16) => Const::ConstU16(0),
+        (Const::ConstZero, FlatType::I32) => Const::ConstI32(0),
+        (Const::ConstZero, FlatType::U32) => Const::ConstU32(0),
+        (Const::ConstZero, FlatType::Float) => Const::ConstFloat(0.),
+        (c, _) => c,
+    }
+}
+
+fn apply_unop(unop: &Unop, c: Const, ty: &FlatType) -> Const {
     match (unop, c) {
-        (Unop::Neg, Const::ConstI8(i)) => Const::ConstI8(-i),
-        (Unop::Neg, Const::ConstI16(i)) => Const::ConstI16(-i),
-        (Unop::Neg, Const::ConstI32(i)) => Const::ConstI32(-i),
+        (Unop::Neg, Const::ConstI8(i)) => Const::ConstI8(i.wrapping_neg()),
+        (Unop::Neg, Const::ConstI16(i)) => Const::ConstI16(i.wrapping_neg()),
+        (Unop::Neg, Const::ConstI32(i)) => Const::ConstI32(i.wrapping_neg()),
         (Unop::Neg, Const::ConstFloat(f)) => Const::ConstFloat(-f),
         (Unop::Neg, _) => unreachable!(),
         (Unop::Not, Const::ConstI8(i)) => Const::ConstI8(!i),
@@ -199,34 +275,59 @@ fn apply_unop(unop: Unop, c: Const) -> Const {
         (Unop::Not, Const::ConstU32(i)) => Const::ConstU32(!i),
         (Unop::Not, Const::ConstBoolean(b)) => Const::ConstBoolean(!b),
         (Unop::Not, _) => unreachable!(),
+        (Unop::Convert(_), c) => c.convert(ty),
         (Unop::Deref, _) => unreachable!("constant deref is not yet implemented"),
     }
 }
 
 fn apply_binop(binop: Binop, c1: Const, c2: Const) -> Const {
     match (binop, c1, c2) {
-        (Binop::Add, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1 + i2),
-        (Binop::Add, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1 + i2),
-        (Binop::Add, Const::ConstI16(i1), Const::ConstI16(i2)) => Const::ConstI16(i1 + i2),
-        (Binop::Add, Const::ConstU16(i1), Const::ConstU16(i2)) => Const::ConstU16(i1 + i2),
-        (Binop::Add, Const::ConstI32(i1), Const::ConstI32(i2)) => Const::ConstI32(i1 + i2),
-        (Binop::Add, Const::ConstU32(i1), Const::ConstU32(i2)) => Const::ConstU32(i1 + i2),
+        (Binop::Add, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1.wrapping_add(i2)),
+        (Binop::Add, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1.wrapping_add(i2)),
+        (Binop::Add, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstI16(i1.wrapping_add(i2))
+        }
+        (Binop::Add, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstU16(i1.wrapping_add(i2))
+        }
+        (Binop::Add, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstI32(i1.wrapping_add(i2))
+        }
+        (Binop::Add, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstU32(i1.wrapping_add(i2))
+        }
         (Binop::Add, Const::ConstFloat(f1), Const::ConstFloat(f2)) => Const::ConstFloat(f1 + f2),
         (Binop::Add, _, _) => unreachable!(),
-        (Binop::Sub, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1 - i2),
-        (Binop::Sub, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1 - i2),
-        (Binop::Sub, Const::ConstI16(i1), Const::ConstI16(i2)) => Const::ConstI16(i1 - i2),
-        (Binop::Sub, Const::ConstU16(i1), Const::ConstU16(i2)) => Const::ConstU16(i1 - i2),
-        (Binop::Sub, Const::ConstI32(i1), Const::ConstI32(i2)) => Const::ConstI32(i1 - i2),
-        (Binop::Sub, Const::ConstU32(i1), Const::ConstU32(i2)) => Const::ConstU32(i1 - i2),
+        (Binop::Sub, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1.wrapping_sub(i2)),
+        (Binop::Sub, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1.wrapping_sub(i2)),
+        (Binop::Sub, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstI16(i1.wrapping_sub(i2))
+        }
+        (Binop::Sub, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstU16(i1.wrapping_sub(i2))
+        }
+        (Binop::Sub, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstI32(i1.wrapping_sub(i2))
+        }
+        (Binop::Sub, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstU32(i1.wrapping_sub(i2))
+        }
         (Binop::Sub, Const::ConstFloat(f1), Const::ConstFloat(f2)) => Const::ConstFloat(f1 - f2),
         (Binop::Sub, _, _) => unreachable!(),
-        (Binop::Mul, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1 * i2),
-        (Binop::Mul, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1 * i2),
-        (Binop::Mul, Const::ConstI16(i1), Const::ConstI16(i2)) => Const::ConstI16(i1 * i2),
-        (Binop::Mul, Const::ConstU16(i1), Const::ConstU16(i2)) => Const::ConstU16(i1 * i2),
-        (Binop::Mul, Const::ConstI32(i1), Const::ConstI32(i2)) => Const::ConstI32(i1 * i2),
-        (Binop::Mul, Const::ConstU32(i1), Const::ConstU32(i2)) => Const::ConstU32(i1 * i2),
+        (Binop::Mul, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1.wrapping_mul(i2)),
+        (Binop::Mul, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1.wrapping_mul(i2)),
+        (Binop::Mul, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstI16(i1.wrapping_mul(i2))
+        }
+        (Binop::Mul, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstU16(i1.wrapping_mul(i2))
+        }
+        (Binop::Mul, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstI32(i1.wrapping_mul(i2))
+        }
+        (Binop::Mul, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstU32(i1.wrapping_mul(i2))
+        }
         (Binop::Mul, Const::ConstFloat(f1), Const::ConstFloat(f2)) => Const::ConstFloat(f1 * f2),
         (Binop::Mul, _, _) => unreachable!(),
         (Binop::Div, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1 / i2),
@@ -237,6 +338,208 @@ fn apply_binop(binop: Binop, c1: Const, c2: Const) -> Const {
         (Binop::Div, Const::ConstU32(i1), Const::ConstU32(i2)) => Const::ConstU32(i1 / i2),
         (Binop::Div, Const::ConstFloat(f1), Const::ConstFloat(f2)) => Const::ConstFloat(f1 / f2),
         (Binop::Div, _, _) => unreachable!(),
+        (Binop::Rem, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1 % i2),
+        (Binop::Rem, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1 % i2),
+        (Binop::Rem, Const::ConstI16(i1), Const::ConstI16(i2)) => Const::ConstI16(i1 % i2),
+        (Binop::Rem, Const::ConstU16(i1), Const::ConstU16(i2)) => Const::ConstU16(i1 % i2),
+        (Binop::Rem, Const::ConstI32(i1), Const::ConstI32(i2)) => Const::ConstI32(i1 % i2),
+        (Binop::Rem, Const::ConstU32(i1), Const::ConstU32(i2)) => Const::ConstU32(i1 % i2),
+        (Binop::Rem, Const::ConstFloat(f1), Const::ConstFloat(f2)) => Const::ConstFloat(f1 % f2),
+        (Binop::Rem, _, _) => unreachable!(),
+        (Binop::SatAdd, Const::ConstI8(i1), Const::ConstI8(i2)) => {
+            Const::ConstI8(i1.saturating_add(i2))
+        }
+        (Binop::SatAdd, Const::ConstU8(i1), Const::ConstU8(i2)) => {
+            Const::ConstU8(i1.saturating_add(i2))
+        }
+        (Binop::SatAdd, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstI16(i1.saturating_add(i2))
+        }
+        (Binop::SatAdd, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstU16(i1.saturating_add(i2))
+        }
+        (Binop::SatAdd, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstI32(i1.saturating_add(i2))
+        }
+        (Binop::SatAdd, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstU32(i1.saturating_add(i2))
+        }
+        (Binop::SatAdd, Const::ConstFloat(f1), Const::ConstFloat(f2)) => Const::ConstFloat(f1 + f2),
+        (Binop::SatAdd, _, _) => unreachable!(),
+        (Binop::SatSub, Const::ConstI8(i1), Const::ConstI8(i2)) => {
+            Const::ConstI8(i1.saturating_sub(i2))
+        }
+        (Binop::SatSub, Const::ConstU8(i1), Const::ConstU8(i2)) => {
+            Const::ConstU8(i1.saturating_sub(i2))
+        }
+        (Binop::SatSub, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstI16(i1.saturating_sub(i2))
+        }
+        (Binop::SatSub, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstU16(i1.saturating_sub(i2))
+        }
+        (Binop::SatSub, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstI32(i1.saturating_sub(i2))
+        }
+        (Binop::SatSub, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstU32(i1.saturating_sub(i2))
+        }
+        (Binop::SatSub, Const::ConstFloat(f1), Const::ConstFloat(f2)) => Const::ConstFloat(f1 - f2),
+        (Binop::SatSub, _, _) => unreachable!(),
+        (Binop::SatMul, Const::ConstI8(i1), Const::ConstI8(i2)) => {
+            Const::ConstI8(i1.saturating_mul(i2))
+        }
+        (Binop::SatMul, Const::ConstU8(i1), Const::ConstU8(i2)) => {
+            Const::ConstU8(i1.saturating_mul(i2))
+        }
+        (Binop::SatMul, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstI16(i1.saturating_mul(i2))
+        }
+        (Binop::SatMul, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstU16(i1.saturating_mul(i2))
+        }
+        (Binop::SatMul, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstI32(i1.saturating_mul(i2))
+        }
+        (Binop::SatMul, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstU32(i1.saturating_mul(i2))
+        }
+        (Binop::SatMul, Const::ConstFloat(f1), Const::ConstFloat(f2)) => Const::ConstFloat(f1 * f2),
+        (Binop::SatMul, _, _) => unreachable!(),
+        (Binop::AddOverflows, Const::ConstI8(i1), Const::ConstI8(i2)) => {
+            Const::ConstBoolean(i1.checked_add(i2).is_none())
+        }
+        (Binop::AddOverflows, Const::ConstU8(i1), Const::ConstU8(i2)) => {
+            Const::ConstBoolean(i1.checked_add(i2).is_none())
+        }
+        (Binop::AddOverflows, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstBoolean(i1.checked_add(i2).is_none())
+        }
+        (Binop::AddOverflows, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstBoolean(i1.checked_add(i2).is_none())
+        }
+        (Binop::AddOverflows, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstBoolean(i1.checked_add(i2).is_none())
+        }
+        (Binop::AddOverflows, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstBoolean(i1.checked_add(i2).is_none())
+        }
+        (Binop::AddOverflows, Const::ConstFloat(_), Const::ConstFloat(_)) => {
+            Const::ConstBoolean(false)
+        }
+        (Binop::AddOverflows, _, _) => unreachable!(),
+        (Binop::SubOverflows, Const::ConstI8(i1), Const::ConstI8(i2)) => {
+            Const::ConstBoolean(i1.checked_sub(i2).is_none())
+        }
+        (Binop::SubOverflows, Const::ConstU8(i1), Const::ConstU8(i2)) => {
+            Const::ConstBoolean(i1.checked_sub(i2).is_none())
+        }
+        (Binop::SubOverflows, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstBoolean(i1.checked_sub(i2).is_none())
+        }
+        (Binop::SubOverflows, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstBoolean(i1.checked_sub(i2).is_none())
+        }
+        (Binop::SubOverflows, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstBoolean(i1.checked_sub(i2).is_none())
+        }
+        (Binop::SubOverflows, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstBoolean(i1.checked_sub(i2).is_none())
+        }
+        (Binop::SubOverflows, Const::ConstFloat(_), Const::ConstFloat(_)) => {
+            Const::ConstBoolean(false)
+        }
+        (Binop::SubOverflows, _, _) => unreachable!(),
+        (Binop::MulOverflows, Const::ConstI8(i1), Const::ConstI8(i2)) => {
+            Const::ConstBoolean(i1.checked_mul(i2).is_none())
+        }
+        (Binop::MulOverflows, Const::ConstU8(i1), Const::ConstU8(i2)) => {
+            Const::ConstBoolean(i1.checked_mul(i2).is_none())
+        }
+        (Binop::MulOverflows, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstBoolean(i1.checked_mul(i2).is_none())
+        }
+        (Binop::MulOverflows, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstBoolean(i1.checked_mul(i2).is_none())
+        }
+        (Binop::MulOverflows, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstBoolean(i1.checked_mul(i2).is_none())
+        }
+        (Binop::MulOverflows, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstBoolean(i1.checked_mul(i2).is_none())
+        }
+        (Binop::MulOverflows, Const::ConstFloat(_), Const::ConstFloat(_)) => {
+            Const::ConstBoolean(false)
+        }
+        (Binop::MulOverflows, _, _) => unreachable!(),
+        (Binop::And, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1 & i2),
+        (Binop::And, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1 & i2),
+        (Binop::And, Const::ConstI16(i1), Const::ConstI16(i2)) => Const::ConstI16(i1 & i2),
+        (Binop::And, Const::ConstU16(i1), Const::ConstU16(i2)) => Const::ConstU16(i1 & i2),
+        (Binop::And, Const::ConstI32(i1), Const::ConstI32(i2)) => Const::ConstI32(i1 & i2),
+        (Binop::And, Const::ConstU32(i1), Const::ConstU32(i2)) => Const::ConstU32(i1 & i2),
+        (Binop::And, Const::ConstBoolean(b1), Const::ConstBoolean(b2)) => {
+            Const::ConstBoolean(b1 & b2)
+        }
+        (Binop::And, _, _) => unreachable!(),
+        (Binop::Or, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1 | i2),
+        (Binop::Or, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1 | i2),
+        (Binop::Or, Const::ConstI16(i1), Const::ConstI16(i2)) => Const::ConstI16(i1 | i2),
+        (Binop::Or, Const::ConstU16(i1), Const::ConstU16(i2)) => Const::ConstU16(i1 | i2),
+        (Binop::Or, Const::ConstI32(i1), Const::ConstI32(i2)) => Const::ConstI32(i1 | i2),
+        (Binop::Or, Const::ConstU32(i1), Const::ConstU32(i2)) => Const::ConstU32(i1 | i2),
+        (Binop::Or, Const::ConstBoolean(b1), Const::ConstBoolean(b2)) => {
+            Const::ConstBoolean(b1 | b2)
+        }
+        (Binop::Or, _, _) => unreachable!(),
+        (Binop::Xor, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstI8(i1 ^ i2),
+        (Binop::Xor, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstU8(i1 ^ i2),
+        (Binop::Xor, Const::ConstI16(i1), Const::ConstI16(i2)) => Const::ConstI16(i1 ^ i2),
+        (Binop::Xor, Const::ConstU16(i1), Const::ConstU16(i2)) => Const::ConstU16(i1 ^ i2),
+        (Binop::Xor, Const::ConstI32(i1), Const::ConstI32(i2)) => Const::ConstI32(i1 ^ i2),
+        (Binop::Xor, Const::ConstU32(i1), Const::ConstU32(i2)) => Const::ConstU32(i1 ^ i2),
+        (Binop::Xor, Const::ConstBoolean(b1), Const::ConstBoolean(b2)) => {
+            Const::ConstBoolean(b1 ^ b2)
+        }
+        (Binop::Xor, _, _) => unreachable!(),
+        (Binop::Shl, Const::ConstI8(i1), Const::ConstI8(i2)) => {
+            Const::ConstI8(i1.checked_shl(i2 as u32).unwrap_or(0))
+        }
+        (Binop::Shl, Const::ConstU8(i1), Const::ConstU8(i2)) => {
+            Const::ConstU8(i1.checked_shl(i2 as u32).unwrap_or(0))
+        }
+        (Binop::Shl, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstI16(i1.checked_shl(i2 as u32).unwrap_or(0))
+        }
+        (Binop::Shl, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstU16(i1.checked_shl(i2 as u32).unwrap_or(0))
+        }
+        (Binop::Shl, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstI32(i1.checked_shl(i2 as u32).unwrap_or(0))
+        }
+        (Binop::Shl, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstU32(i1.checked_shl(i2).unwrap_or(0))
+        }
+        (Binop::Shl, _, _) => unreachable!(),
+        (Binop::Shr, Const::ConstI8(i1), Const::ConstI8(i2)) => {
+            Const::ConstI8(i1.checked_shr(i2 as u32).unwrap_or(i1 >> (i8::BITS - 1)))
+        }
+        (Binop::Shr, Const::ConstU8(i1), Const::ConstU8(i2)) => {
+            Const::ConstU8(i1.checked_shr(i2 as u32).unwrap_or(0))
+        }
+        (Binop::Shr, Const::ConstI16(i1), Const::ConstI16(i2)) => {
+            Const::ConstI16(i1.checked_shr(i2 as u32).unwrap_or(i1 >> (i16::BITS - 1)))
+        }
+        (Binop::Shr, Const::ConstU16(i1), Const::ConstU16(i2)) => {
+            Const::ConstU16(i1.checked_shr(i2 as u32).unwrap_or(0))
+        }
+        (Binop::Shr, Const::ConstI32(i1), Const::ConstI32(i2)) => {
+            Const::ConstI32(i1.checked_shr(i2 as u32).unwrap_or(i1 >> (i32::BITS - 1)))
+        }
+        (Binop::Shr, Const::ConstU32(i1), Const::ConstU32(i2)) => {
+            Const::ConstU32(i1.checked_shr(i2).unwrap_or(0))
+        }
+        (Binop::Shr, _, _) => unreachable!(),
         (Binop::Eq, Const::ConstI8(i1), Const::ConstI8(i2)) => Const::ConstBoolean(i1 == i2),
         (Binop::Eq, Const::ConstU8(i1), Const::ConstU8(i2)) => Const::ConstBoolean(i1 == i2),
         (Binop::Eq, Const::ConstI16(i1), Const::ConstI16(i2)) => Const::ConstBoolean(i1 == i2),
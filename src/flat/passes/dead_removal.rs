@@ -57,6 +57,7 @@ fn remove_unused(program: &mut Program, mut symtab: Symtab) {
             StaticDecl::SetArray(name, _, _) |
             StaticDecl::SetString(name, _, _) |
             StaticDecl::SetPtr(name, _, _) |
+            StaticDecl::SetSlice(name, _, _, _) |
             StaticDecl::External(name, _)
             if !used.contains(name) => dead_decls.push(i),
             _ => (),
@@ -81,7 +82,8 @@ fn mark_statics(statics: &[StaticDecl], symtab: &mut Symtab) {
     for decl in statics {
         match decl {
             StaticDecl::SetAlias(g, _, g2) |
-            StaticDecl::SetPtr(g, _, g2) => symtab.reference_from(g, g2),
+            StaticDecl::SetPtr(g, _, g2) |
+            StaticDecl::SetSlice(g, _, g2, _) => symtab.reference_from(g, g2),
             StaticDecl::SetConst(_g, _, _) |
             StaticDecl::SetArray(_g, _, _) |
             StaticDecl::SetString(_g, _, _) |
@@ -111,8 +113,14 @@ fn mark_fns(fns: &HashMap<Global, Function>, symtab: &mut Symtab) {
                 Line::If(_, _, _) |
                 Line::Goto(_) |
                 Line::Ret(_) |
-                Line::Panic(_) |
-                Line::SetIndex(_, _, _) => ()
+                Line::Panic(..) |
+                Line::SetIndex(_, _, _, _) |
+                Line::ReadField(_, _, _, _) |
+                Line::WriteField(_, _, _, _) |
+                Line::ReadIndex(_, _, _, _) |
+                Line::ReadElement(_, _, _, _) |
+                Line::SetElementAddr(_, _, _, _) |
+                Line::SetAlloc(_, _, _) => ()
             }
         }
     }
@@ -120,27 +128,38 @@ fn mark_fns(fns: &HashMap<Global, Function>, symtab: &mut Symtab) {
 
 fn remove_unused_locals(program: &mut Program) {
     for f in program.fns.values_mut() {
-        let mut queue = vec![0];
+        // parameters are kept even when unused, since callers pass them by position
+        let mut queue: Vec<_> = (0..=f.arg_types.len()).collect();
         let mut references = Vec::new();
         let mut upper = 0;
         for line in &f.lines {
             match line {
                 Line::Goto(_) |
-                Line::Panic(_) |
+                Line::Panic(..) |
                 Line::Label(_) => (),
                 &Line::ReadGlobal(Temp(i), _, _) |
                 &Line::SetAddrOf(Temp(i), _, Ident::Global(_)) |
                 &Line::SetConst(Temp(i), _, _) => upper = i.max(upper),
-                &Line::SetBinop(Temp(f), _, _, Temp(i), Temp(j)) => {
+                &Line::SetBinop(Temp(f), _, _, Temp(i), Temp(j)) |
+                &Line::SetIndex(Temp(f), _, Temp(i), Temp(j)) |
+                &Line::ReadIndex(Temp(f), _, Temp(i), Temp(j)) |
+                &Line::ReadElement(Temp(f), _, Temp(i), Temp(j)) |
+                &Line::SetElementAddr(Temp(f), _, Temp(i), Temp(j)) => {
                     set_reference_from(&mut references, f, i, &mut upper);
                     set_reference_from(&mut references, f, j, &mut upper);
                 }
                 &Line::SetUnop(Temp(f), _, _, Temp(i)) |
-                &Line::WriteTo(Temp(f), _, Temp(i)) |
-                &Line::SetIndex(Temp(f), _, Temp(i)) |
+                &Line::ReadField(Temp(f), _, Temp(i), _) |
+                &Line::WriteField(Temp(f), _, _, Temp(i)) |
                 &Line::SetAddrOf(Temp(f), _, Ident::Temp(Temp(i))) |
+                &Line::SetAlloc(Temp(f), _, Temp(i)) |
                 &Line::SetTo(Temp(f), _, Temp(i)) => set_reference_from(&mut references, f, i, &mut upper),
                 &Line::WriteGlobal(ref _g, _, Temp(i)) => queue.push(i),
+                // writing through a pointer is a side effect
+                &Line::WriteTo(Temp(p), _, Temp(i)) => {
+                    queue.push(p);
+                    queue.push(i);
+                }
                 &Line::If(Temp(i), _, _) |
                 &Line::Ret(Temp(i)) => queue.push(i),
                 Line::SetCall(Temp(t), _, i, ts) => {
@@ -184,14 +203,20 @@ fn remove_unused_locals(program: &mut Program) {
                 Line::If(t, _, _) |
                 Line::Ret(t) => rename_temp(t, &dead, &mut dead_lines, line_index),
                 Line::SetUnop(t1, _, _, t2) |
+                Line::ReadField(t1, _, t2, _) |
+                Line::WriteField(t1, _, _, t2) |
                 Line::WriteTo(t1, _, t2) |
-                Line::SetIndex(t1, _, t2) |
                 Line::SetAddrOf(t1, _, Ident::Temp(t2)) |
+                Line::SetAlloc(t1, _, t2) |
                 Line::SetTo(t1, _, t2) => {
                     rename_temp(t1, &dead, &mut dead_lines, line_index);
                     rename_temp(t2, &dead, &mut dead_lines, line_index);
                 }
-                Line::SetBinop(t1, _, _, t2, t3) => {
+                Line::SetBinop(t1, _, _, t2, t3) |
+                Line::SetIndex(t1, _, t2, t3) |
+                Line::ReadIndex(t1, _, t2, t3) |
+                Line::ReadElement(t1, _, t2, t3) |
+                Line::SetElementAddr(t1, _, t2, t3) => {
                     rename_temp(t1, &dead, &mut dead_lines, line_index);
                     rename_temp(t2, &dead, &mut dead_lines, line_index);
                     rename_temp(t3, &dead, &mut dead_lines, line_index);
@@ -208,7 +233,7 @@ fn remove_unused_locals(program: &mut Program) {
                 }
                 Line::Label(_) |
                 Line::Goto(_) |
-                Line::Panic(_) => ()
+                Line::Panic(..) => ()
             }
         }
 
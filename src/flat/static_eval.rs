@@ -1,7 +1,7 @@
 use std::{collections::HashSet, rc::Rc};
 
 use crate::ttype::{
-    ast::{Expr, PlaceExpr, Statement},
+    ast::{Expr, Iterable, Pattern, PlaceExpr, Statement},
     Type,
 };
 
@@ -11,27 +11,26 @@ pub fn compute_statics(
     static_exprs: Vec<(Rc<str>, Type, Expr)>,
     statics: Vec<StaticDecl>,
 ) -> Vec<StaticDecl> {
-    let mut calculate_order: Vec<(_, _, _, HashSet<_>)> = Vec::new();
     let mut static_namer = StaticNamer::new("#s");
 
-    for (name, t, expr) in static_exprs {
-        let mut deps = HashSet::new();
-        expr_symbol_deps(&expr, &mut deps, &HashSet::new());
-
-        let mut insert_at = 0;
-        for (i, (other_name, _, _, deps)) in calculate_order.iter().enumerate() {
-            if deps.contains(other_name) {
-                insert_at = i + 1;
-                if deps.contains(&name) {
-                    todo!("return cyclic dependency error");
-                }
-            }
-        }
-        calculate_order.insert(insert_at, (name, t, expr, deps));
-    }
+    let mut to_calculate: Vec<_> = static_exprs
+        .into_iter()
+        .map(|(name, t, expr)| {
+            let mut deps = HashSet::new();
+            expr_symbol_deps(&expr, &mut deps, &HashSet::new());
+            (name, t, expr, deps)
+        })
+        .collect();
 
     let mut out = statics;
-    for (name, t, expr, _) in calculate_order {
+    while !to_calculate.is_empty() {
+        // one that needs none of the statics left to calculate
+        let Some(next) = to_calculate.iter().position(|(_, _, _, deps)| {
+            !to_calculate.iter().any(|(other_name, _, _, _)| deps.contains(other_name))
+        }) else {
+            todo!("return cyclic dependency error");
+        };
+        let (name, t, expr, _) = to_calculate.remove(next);
         static_eval(
             Global(name),
             flatten_type(t),
@@ -53,7 +52,8 @@ fn lookup_in_out<'a>(out: &'a [StaticDecl], name: &Global) -> &'a StaticDecl {
             | StaticDecl::SetArray(g, _, _)
             | StaticDecl::SetString(g, _, _)
             | StaticDecl::External(g, _)
-            | StaticDecl::SetPtr(g, _, _)) => {
+            | StaticDecl::SetPtr(g, _, _)
+            | StaticDecl::SetSlice(g, _, _, _)) => {
                 if g == name {
                     return sd;
                 }
@@ -63,6 +63,16 @@ fn lookup_in_out<'a>(out: &'a [StaticDecl], name: &Global) -> &'a StaticDecl {
     unreachable!()
 }
 
+/// Follows aliases and slices to the declaration holding the value
+fn resolve<'a>(out: &'a [StaticDecl], decl: &'a StaticDecl) -> &'a StaticDecl {
+    match decl {
+        StaticDecl::SetAlias(_, _, g) | StaticDecl::SetSlice(_, _, g, _) => {
+            resolve(out, lookup_in_out(out, g))
+        }
+        decl => decl,
+    }
+}
+
 pub fn static_eval(
     place: Global,
     t: FlatType,
@@ -75,6 +85,7 @@ pub fn static_eval(
             out.push(StaticDecl::SetAlias(place, t, Global(alias)));
             Ok(())
         }
+        Expr::Instance(_, _, _) => unreachable!("instances are named once their types are known"),
         Expr::ConstBoolean(_, b) => {
             out.push(StaticDecl::SetConst(place, t, Const::ConstBoolean(b)));
             Ok(())
@@ -164,20 +175,106 @@ pub fn static_eval(
             Ok(())
         }
         Expr::Ref(_, Ok(_)) => todo!(),
-        Expr::Array(_, _) => todo!(),
+        Expr::Array(_, es) => {
+            let FlatType::Arr(elem_t, _) = &t else {
+                unreachable!("array of non-array type {t}");
+            };
+            let mut consts = Vec::with_capacity(es.len());
+            for e in es.into_vec() {
+                let mut elem_out = Vec::new();
+                static_eval(place.clone(), (**elem_t).clone(), e, namer, &mut elem_out)?;
+                match &*elem_out {
+                    [StaticDecl::SetConst(_, _, c)] => consts.push(*c),
+                    _ => return Err("static array elements must be constants".into()),
+                }
+            }
+            out.push(StaticDecl::SetArray(place, t, consts.into_boxed_slice()));
+            Ok(())
+        }
+        Expr::Index(_, e, index_e, coll_t) => {
+            // only the element is kept, not the array it is in
+            let mut coll_out = Vec::new();
+            static_eval(place.clone(), flatten_type(*coll_t), *e, namer, &mut coll_out)?;
+            let coll = coll_out.pop().unwrap();
+            out.extend(coll_out);
+            let mut index_out = Vec::new();
+            static_eval(place.clone(), FlatType::U16, *index_e, namer, &mut index_out)?;
+            let index = match resolve(out, &index_out[0]) {
+                StaticDecl::SetConst(_, _, Const::ConstU16(i)) => *i as usize,
+                _ => return Err("static indices must be constants".into()),
+            };
+            let elem = match resolve(out, &coll) {
+                StaticDecl::SetArray(_, _, consts) => consts.get(index).copied(),
+                StaticDecl::SetString(_, _, s) => s.as_bytes().get(index).copied().map(Const::ConstU8),
+                _ => return Err("only static arrays and strings can be indexed into".into()),
+            };
+            let c = elem.ok_or("static index is out of bounds")?;
+            out.push(StaticDecl::SetConst(place, t, c));
+            Ok(())
+        }
+        Expr::Slice(_, _, _, _, _) => todo!(),
         Expr::StructConstructor(_, _) => todo!(),
-        Expr::Cast(_, _, _, _) => todo!(),
+        Expr::FieldAccess(_, _, _, _) => todo!(),
+        Expr::VariantConstructor(_, _, _, _) => todo!(),
+        Expr::Match(_, _, _, _) => todo!(),
+        Expr::Cast(_, e, from_t, _) => match flatten_type(*from_t) {
+            from_t if from_t == t => static_eval(place, t, *e, namer, out),
+            // slices of a static array point to a static of their own
+            arr_t @ FlatType::Arr(_, len) => {
+                let arr = namer.new_global("slice_arr");
+                static_eval(arr.clone(), arr_t, *e, namer, out)?;
+                out.push(StaticDecl::SetSlice(place, t, arr, len));
+                Ok(())
+            }
+            from_t @ (FlatType::Bool
+            | FlatType::U8
+            | FlatType::I8
+            | FlatType::U16
+            | FlatType::I16
+            | FlatType::U32
+            | FlatType::I32
+            | FlatType::Float) => {
+                let mut val_out = Vec::new();
+                static_eval(place.clone(), from_t, *e, namer, &mut val_out)?;
+                match &*val_out {
+                    [StaticDecl::SetConst(_, _, c)] => {
+                        let c = c.convert(&t);
+                        out.push(StaticDecl::SetConst(place, t, c));
+                        Ok(())
+                    }
+                    _ => Err("static numbers can only be cast from constants".into()),
+                }
+            }
+            _ => todo!(),
+        },
         Expr::Add(_, _, _) => todo!(),
         Expr::Sub(_, _, _) => todo!(),
         Expr::Mul(_, _, _) => todo!(),
+        Expr::WrappingAdd(_, _, _) => todo!(),
+        Expr::WrappingSub(_, _, _) => todo!(),
+        Expr::WrappingMul(_, _, _) => todo!(),
+        Expr::SaturatingAdd(_, _, _) => todo!(),
+        Expr::SaturatingSub(_, _, _) => todo!(),
+        Expr::SaturatingMul(_, _, _) => todo!(),
+        Expr::BitAnd(_, _, _) => todo!(),
+        Expr::BitOr(_, _, _) => todo!(),
+        Expr::BitXor(_, _, _) => todo!(),
+        Expr::Shl(_, _, _) => todo!(),
+        Expr::Shr(_, _, _) => todo!(),
         Expr::Div(_, _, _) => todo!(),
+        Expr::Rem(_, _, _) => todo!(),
+        Expr::And(_, _, _) => todo!(),
+        Expr::Or(_, _, _) => todo!(),
         Expr::Not(_, _) => todo!(),
         Expr::Neg(_, _) => todo!(),
         Expr::Deref(_, _) => todo!(),
         Expr::Block(_, _) => todo!(),
-        Expr::Lambda(_, _, _, _) => todo!(),
-        Expr::Call(_, _, _) => todo!(),
+        Expr::Lambda(_, _, _, _, _) => todo!(),
+        Expr::Call(_, _, _, _) => todo!(),
         Expr::If(_, _, _, _) => todo!(),
+        Expr::IfLet(_, _, _, _, _, _) => todo!(),
+        Expr::Unwrap(_, _, _) => todo!(),
+        Expr::Loop(_, _, _) => todo!(),
         Expr::Eq(_, _, _, _) => todo!(),
         Expr::Neq(_, _, _, _) => todo!(),
         Expr::Lt(_, _, _, _) => todo!(),
@@ -197,6 +294,7 @@ fn add_dep(name: &Rc<str>, deps: &mut HashSet<Rc<str>>, overshadowed: &HashSet<R
 fn expr_symbol_deps(expr: &Expr, deps: &mut HashSet<Rc<str>>, overshadowed: &HashSet<Rc<str>>) {
     match expr {
         Expr::Ident(_, name) => add_dep(name, deps, overshadowed),
+        Expr::Instance(_, _, _) => unreachable!("instances are named once their types are known"),
         Expr::ConstBoolean(_, _) |
         Expr::ConstI8(_, _) |
         Expr::ConstU8(_, _) |
@@ -209,12 +307,15 @@ fn expr_symbol_deps(expr: &Expr, deps: &mut HashSet<Rc<str>>, overshadowed: &Has
         Expr::ConstUnit(_) |
         Expr::ConstString(_, _) |
         // TODO: don't ignore expr of lambda, if it gets run
-        Expr::Lambda(_, _, _, _) |
+        Expr::Lambda(_, _, _, _, _) |
         Expr::ConstNull(_) => (),
         Expr::Ref(_, Err(e)) |
         Expr::Cast(_, e, _, _) |
         Expr::Not(_, e) |
         Expr::Neg(_, e) |
+        Expr::Loop(_, _, e) |
+        Expr::FieldAccess(_, e, _, _) |
+        Expr::Unwrap(_, e, _) |
         Expr::Deref(_, e) => expr_symbol_deps(e, deps, overshadowed),
         Expr::Block(_, stmnts) => {
             let overshadowed = &mut overshadowed.clone();
@@ -228,26 +329,59 @@ fn expr_symbol_deps(expr: &Expr, deps: &mut HashSet<Rc<str>>, overshadowed: &Has
                 expr_symbol_deps(e, deps, overshadowed);
             }
         }
-        Expr::Array(_, es) => {
+        Expr::Array(_, es) | Expr::VariantConstructor(_, _, es, _) => {
             for e in es.iter() {
                 expr_symbol_deps(e, deps, overshadowed);
             }
         }
-        Expr::Call(_, name, es) => {
-            add_dep(name, deps, overshadowed);
+        Expr::Match(_, e, _, arms) => {
+            expr_symbol_deps(e, deps, overshadowed);
+            for (pattern, arm) in arms.iter() {
+                let overshadowed = &mut overshadowed.clone();
+                if let Pattern::Variant(_, _, bindings) = pattern {
+                    overshadowed.extend(bindings.iter().flatten().cloned());
+                }
+                expr_symbol_deps(arm, deps, overshadowed);
+            }
+        }
+        Expr::Call(_, callee, es, _) => {
+            expr_symbol_deps(callee, deps, overshadowed);
             for e in es.iter() {
                 expr_symbol_deps(e, deps, overshadowed);
             }
         }
+        Expr::Slice(_, e, e2, e3, _) |
         Expr::If(_, e, e2, e3) => {
             expr_symbol_deps(e, deps, overshadowed);
             expr_symbol_deps(e2, deps, overshadowed);
             expr_symbol_deps(e3, deps, overshadowed)
         }
+        Expr::IfLet(_, n, e, _, e2, e3) => {
+            expr_symbol_deps(e, deps, overshadowed);
+            let inner_overshadowed = &mut overshadowed.clone();
+            inner_overshadowed.insert(n.clone());
+            expr_symbol_deps(e2, deps, inner_overshadowed);
+            expr_symbol_deps(e3, deps, overshadowed)
+        }
+        Expr::Index(_, e1, e2, _) |
         Expr::Add(_, e1, e2) |
         Expr::Sub(_, e1, e2) |
         Expr::Mul(_, e1, e2) |
+        Expr::WrappingAdd(_, e1, e2) |
+        Expr::WrappingSub(_, e1, e2) |
+        Expr::WrappingMul(_, e1, e2) |
+        Expr::SaturatingAdd(_, e1, e2) |
+        Expr::SaturatingSub(_, e1, e2) |
+        Expr::SaturatingMul(_, e1, e2) |
+        Expr::BitAnd(_, e1, e2) |
+        Expr::BitOr(_, e1, e2) |
+        Expr::BitXor(_, e1, e2) |
+        Expr::Shl(_, e1, e2) |
+        Expr::Shr(_, e1, e2) |
         Expr::Div(_, e1, e2) |
+        Expr::Rem(_, e1, e2) |
+        Expr::And(_, e1, e2) |
+        Expr::Or(_, e1, e2) |
         Expr::Concat(_, e1, e2) |
         Expr::Eq(_, e1, e2, _) |
         Expr::Neq(_, e1, e2, _) |
@@ -268,10 +402,17 @@ fn pl_expr_symbol_deps(
 ) {
     match pl_expr {
         PlaceExpr::Ident(_, name) => add_dep(name, deps, overshadowed),
-        PlaceExpr::Deref(_, e, _) | PlaceExpr::FieldAccess(_, e, _) => {
+        PlaceExpr::Deref(_, e, _) => {
             expr_symbol_deps(e, deps, overshadowed);
         }
-        PlaceExpr::Index(_, e1, e2) => {
+        PlaceExpr::FieldAccess(_, pl_expr, _, _) => {
+            pl_expr_symbol_deps(pl_expr, deps, overshadowed);
+        }
+        PlaceExpr::Index(_, pl_expr, e, _) => {
+            pl_expr_symbol_deps(pl_expr, deps, overshadowed);
+            expr_symbol_deps(e, deps, overshadowed);
+        }
+        PlaceExpr::SliceElement(_, e1, e2, _) => {
             expr_symbol_deps(e1, deps, overshadowed);
             expr_symbol_deps(e2, deps, overshadowed);
         }
@@ -295,5 +436,22 @@ fn statement_symbol_deps(
         Statement::Express(_, _, e) | Statement::Return(_, e) => {
             expr_symbol_deps(e, deps, overshadowed);
         }
+        Statement::While(_, _, c, b) => {
+            expr_symbol_deps(c, deps, overshadowed);
+            expr_symbol_deps(b, deps, overshadowed);
+        }
+        Statement::Break(_, _, Some(e)) => expr_symbol_deps(e, deps, overshadowed),
+        Statement::Break(_, _, None) | Statement::Continue(_, _) => (),
+        Statement::For(_, _, n, _, i, b) => {
+            match i {
+                Iterable::Range(start, end) => {
+                    expr_symbol_deps(start, deps, overshadowed);
+                    expr_symbol_deps(end, deps, overshadowed);
+                }
+                Iterable::Slice(e) => expr_symbol_deps(e, deps, overshadowed),
+            }
+            overshadowed.insert(n.clone());
+            expr_symbol_deps(b, deps, overshadowed);
+        }
     }
 }
@@ -1,11 +1,20 @@
-use std::{fmt::Display, path::Path};
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
 
+use diagnostic::Diagnostic;
 use flat::{flatten, Program};
-use ttype::type_checker::check_program;
+use ttype::{type_checker::check_program, TypeError};
 
-use self::parse::parse_file;
+use self::parse::{
+    edd_path, parse_file, parse_source,
+    source::{Disk, SourceProvider},
+    ParseError,
+};
 
 pub mod flat;
+pub mod diagnostic;
 pub mod parse;
 pub mod rt;
 pub mod ttype;
@@ -24,14 +33,82 @@ fn get_only_one<R, I: Iterator<Item = R>>(mut iter: I) -> R {
 pub struct CompileOptions {
     parsed_hook: Option<fn(&self::parse::ast::Program)>,
     checked_hook: Option<fn(&self::ttype::ast::Program)>,
+    /// Directories searched for libraries and included files, in order
+    include_paths: Vec<PathBuf>,
+    /// Where source files are read from, disk if none
+    sources: Option<Box<dyn SourceProvider>>,
 }
 
-pub fn compile(path: &Path, options: CompileOptions) -> Result<Program, Box<dyn Display>> {
-    let program = parse_file(path).map_err(|e| -> Box<dyn Display> { Box::new(e) })?;
+#[derive(Debug)]
+pub enum CompileError {
+    /// Every error the parser found
+    Parse(Vec<ParseError>),
+    /// Every error the type checker found
+    Type(Vec<TypeError>),
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Parse(errors) => write_lines(f, errors),
+            CompileError::Type(errors) => write_lines(f, errors),
+        }
+    }
+}
+
+fn write_lines<T: Display>(f: &mut fmt::Formatter<'_>, errors: &[T]) -> fmt::Result {
+    let mut first = true;
+    for e in errors {
+        if !first {
+            writeln!(f)?;
+        }
+        first = false;
+        write!(f, "{e}")?;
+    }
+    Ok(())
+}
+
+impl CompileError {
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            CompileError::Parse(errors) => errors.iter().map(ParseError::diagnostic).collect(),
+            CompileError::Type(errors) => errors.iter().map(TypeError::diagnostic).collect(),
+        }
+    }
+}
+
+pub fn compile(path: &Path, options: CompileOptions) -> Result<Program, CompileError> {
+    let program =
+        parse_file(path, &options.include_paths, options.sources()).map_err(CompileError::Parse)?;
+    check(program, options)
+}
+
+/// Compiles `source` as the contents of the file `name`, which does not have to exist
+///
+/// Files it includes or imports are still read through the source provider of `options`.
+pub fn compile_str(
+    name: &Path,
+    source: &str,
+    options: CompileOptions,
+) -> Result<Program, CompileError> {
+    let program = parse_source(
+        name,
+        source.to_owned(),
+        &options.include_paths,
+        options.sources(),
+    )
+    .map_err(CompileError::Parse)?;
+    check(program, options)
+}
+
+fn check(
+    program: self::parse::ast::Program,
+    options: CompileOptions,
+) -> Result<Program, CompileError> {
     if let Some(hook) = options.parsed_hook {
         hook(&program);
     }
-    let program = check_program(program).map_err(|e| -> Box<dyn Display> { Box::new(e) })?;
+    let program = check_program(program).map_err(CompileError::Type)?;
     if let Some(hook) = options.checked_hook {
         hook(&program);
     }
@@ -41,6 +118,9 @@ pub fn compile(path: &Path, options: CompileOptions) -> Result<Program, Box<dyn
 }
 
 impl CompileOptions {
+    fn sources(&self) -> &dyn SourceProvider {
+        self.sources.as_deref().unwrap_or(&Disk)
+    }
     pub fn hook_parsed(self, hook: fn(&self::parse::ast::Program)) -> Self {
         Self {
             parsed_hook: Some(hook),
@@ -53,4 +133,21 @@ impl CompileOptions {
             ..self
         }
     }
+    /// Searches `dir` for libraries and included files, after the directories added before it
+    pub fn include_path(mut self, dir: PathBuf) -> Self {
+        self.include_paths.push(dir);
+        self
+    }
+    /// Searches the directories in the `EDD_PATH` environment variable, after those added before
+    pub fn include_edd_path(mut self) -> Self {
+        self.include_paths.extend(edd_path());
+        self
+    }
+    /// Reads source files through `sources` instead of from disk
+    pub fn read_sources(self, sources: impl SourceProvider + 'static) -> Self {
+        Self {
+            sources: Some(Box::new(sources)),
+            ..self
+        }
+    }
 }
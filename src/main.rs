@@ -1,9 +1,9 @@
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use edd::{
-    compile, flat::{passes::{const_prop_pass, dead_path_removal_pass, dead_removal_pass, Pass}, Program}, rt::{run, RuntimeError, SymbolTable, Value}, telda::compile_to_telda, CompileOptions
+    compile, diagnostic::{Diagnostic, Emitter}, flat::{passes::{const_prop_pass, dead_path_removal_pass, dead_removal_pass, Pass}, Program}, parse::{format::format_source, source::Disk, ParseError}, rt::{run, RuntimeError, SymbolTable, Value}, telda::{compile_to_telda, TeldaError}, CompileOptions
 };
 
-use std::{fs::File, path::PathBuf};
+use std::{fs::{self, File}, io::{self, IsTerminal}, path::PathBuf, process};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 enum Backend {
@@ -13,9 +13,23 @@ enum Backend {
     Telda,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ErrorFormat {
+    /// Errors with the source code they point at
+    #[value(name = "human")]
+    Human,
+    /// One JSON object per line for each error
+    #[value(name = "json")]
+    Json,
+}
+
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(long)]
     /// Emit untyped parsed AST
     emit_untyped: bool,
@@ -32,9 +46,30 @@ struct Args {
     #[arg(short = 'm', long, alias = "machine", default_value = "telda")]
     backend: Backend,
 
-    #[arg()]
+    #[arg(long, default_value = "human", global = true)]
+    /// How errors are printed
+    error_format: ErrorFormat,
+
+    #[arg(short = 'I', long = "include-path", value_name = "DIR")]
+    /// Directory to search for libraries and included files, before those in EDD_PATH
+    include_paths: Vec<PathBuf>,
+
+    #[arg(required = true)]
     /// Root source code file
-    path: PathBuf,
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Format source code files in place
+    Fmt {
+        #[arg(long)]
+        /// Only check that the files are formatted, failing if any are not
+        check: bool,
+        #[arg(required = true)]
+        /// Source code files to format
+        paths: Vec<PathBuf>,
+    },
 }
 
 const STD_OPTIMISATIONS: &[Pass] = &[
@@ -45,15 +80,29 @@ const STD_OPTIMISATIONS: &[Pass] = &[
 
 fn main() {
     let Args {
+        command,
         emit_untyped,
         emit_typed,
         emit_flat,
         optimised,
         backend,
+        error_format,
+        include_paths,
         path,
     } = Args::parse();
 
-    let mut opt = CompileOptions::default();
+    if let Some(Command::Fmt { check, paths }) = command {
+        if !format_files(&paths, check, error_format) {
+            process::exit(1);
+        }
+        return;
+    }
+    let path = path.expect("required unless there is a subcommand");
+
+    let mut opt = include_paths
+        .into_iter()
+        .fold(CompileOptions::default(), CompileOptions::include_path)
+        .include_edd_path();
     if emit_untyped {
         opt = opt.hook_parsed(|p| {
             println!("Parsed:\n{p}\n");
@@ -68,8 +117,8 @@ fn main() {
     let mut program = match compile(&path, opt) {
         Ok(p) => p,
         Err(e) => {
-            eprintln!("Compiler error: {e}");
-            return;
+            report(error_format, e.diagnostics());
+            process::exit(1);
         }
     };
 
@@ -88,20 +137,74 @@ fn main() {
             match run_prgm(program) {
                 Ok(Value::Naught) => (),
                 Ok(v) => println!("Returned {v}"),
-                Err(RuntimeError::Panic(msg)) => eprintln!("Error: Panic {}{msg}", path.display()),
+                Err(e) if error_format == ErrorFormat::Json => report(error_format, vec![e.diagnostic()]),
+                Err(RuntimeError::Panic(loc, reason)) => eprintln!(
+                    "Error: Panic {}:{}:{}: {reason}",
+                    loc.source_file.display(),
+                    loc.line_start,
+                    loc.col_start
+                ),
                 Err(RuntimeError::InvalidMain) => eprintln!("Error: Invalid main function"),
             }
         Backend::Telda => {
-            write_compiled_telda(program, path);
+            if let Err(e) = write_compiled_telda(program, path) {
+                report(error_format, vec![e.diagnostic()]);
+                process::exit(1);
+            }
         }
     }
 
 }
 
+fn report(format: ErrorFormat, diagnostics: Vec<Diagnostic>) {
+    match format {
+        ErrorFormat::Human => {
+            let mut emitter = Emitter::new(io::stderr().is_terminal(), Disk);
+            for diag in diagnostics {
+                eprintln!("{}", emitter.render(&diag));
+            }
+        }
+        ErrorFormat::Json => {
+            for diag in diagnostics {
+                eprintln!("{}", diag.to_json());
+            }
+        }
+    }
+}
+
+/// Formats each file, or checks that it is formatted, returning whether all of them were fine
+fn format_files(paths: &[PathBuf], check: bool, error_format: ErrorFormat) -> bool {
+    let mut ok = true;
+    for path in paths {
+        let formatted = fs::read_to_string(path)
+            .map_err(|e| vec![ParseError::Io(None, path.as_path().into(), e)])
+            .and_then(|source| Ok((format_source(path, source.clone())?, source)));
+        let (formatted, source) = match formatted {
+            Ok(f) => f,
+            Err(errors) => {
+                report(error_format, errors.iter().map(ParseError::diagnostic).collect());
+                ok = false;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", path.display());
+            ok = false;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("Error: could not write {}: {e}", path.display());
+            ok = false;
+        }
+    }
+    ok
+}
+
 fn run_prgm(program: Program) -> Result<Value, RuntimeError> {
     let mut symtab = SymbolTable::new();
 
-    symtab.add_func("puts", put);
+    symtab.add_func("puts", puts);
     symtab.add_func("putu32", put);
     symtab.add_func("puti32", put);
     symtab.add_func("puti16", put);
@@ -119,14 +222,37 @@ fn put(vls: &[Value]) -> Value {
     Value::Naught
 }
 
+fn puts(vls: &[Value]) -> Value {
+    let bytes: Vec<u8> = match vls {
+        [Value::Struct(slice)] => match &**slice {
+            [Value::Ref(ptr), Value::U16(len)] => (0..*len)
+                .map(|i| match ptr.read_index(i) {
+                    Value::U8(b) => b,
+                    // zero-initialised
+                    Value::Naught => 0,
+                    v => unreachable!("non-byte {v} in string"),
+                })
+                .collect(),
+            // an empty slice
+            _ => Vec::new(),
+        },
+        // zero-initialised
+        [Value::Naught] => Vec::new(),
+        _ => unreachable!("puts takes a single slice"),
+    };
+    println!("{}", String::from_utf8_lossy(&bytes));
+    Value::Naught
+}
+
 use std::io::Write;
 
-fn write_compiled_telda(program: Program, mut path: PathBuf) {
-    let telda = compile_to_telda(program);
+fn write_compiled_telda(program: Program, mut path: PathBuf) -> Result<(), TeldaError> {
+    let telda = compile_to_telda(program)?;
     path.set_extension("telda");
 
     let mut file = File::create(path).unwrap();
     for ins in telda {
         writeln!(file, "{ins}").unwrap();
     }
+    Ok(())
 }
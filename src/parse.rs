@@ -1,23 +1,33 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::result::Result as StdResult;
 
 use lazy_static::lazy_static;
 use pest_derive::Parser;
 
-use pest::iterators::Pairs;
+use pest::error::{ErrorVariant, InputLocation, LineColLocation};
+use pest::iterators::{Pair, Pairs};
 use pest::pratt_parser::{Assoc, Op, PrattParser};
-use pest::Parser;
+use pest::{Parser, Span};
 
 pub mod ast;
+pub mod format;
 pub mod location;
+mod modules;
+pub mod source;
 
-use self::ast::{Expr, Literal, PlaceExpr, Program, Statement};
+use self::ast::{Expr, Iterable, Literal, Pattern, PlaceExpr, Program, Statement};
 use self::location::Location;
+pub use self::modules::{bundled_source, locate};
+use self::source::SourceProvider;
+use crate::diagnostic::{Diagnostic, Label};
 use crate::get_only_one;
 use crate::parse::ast::Decl;
 use crate::ttype::Type;
@@ -28,30 +38,257 @@ lazy_static! {
         use Rule::*;
 
         PrattParser::new()
+            .op(Op::infix(or, Left))
+            .op(Op::infix(and, Left))
             .op(Op::infix(eq, Left)
                 | Op::infix(neq, Left)
                 | Op::infix(lt, Left)
                 | Op::infix(lte, Left)
                 | Op::infix(gt, Left)
                 | Op::infix(gte, Left))
+            .op(Op::infix(bit_or, Left) | Op::infix(bit_xor, Left) | Op::infix(bit_and, Left))
             .op(Op::infix(concat, Left))
-            .op(Op::infix(add, Left) | Op::infix(subtract, Left))
-            .op(Op::infix(multiply, Left) | Op::infix(divide, Left))
-            .op(Op::prefix(not) | Op::prefix(r#ref) | Op::prefix(neg) | Op::prefix(deref))
-            .op(Op::infix(cast_as, Left))
+            .op(Op::infix(shl, Left) | Op::infix(shr, Left))
+            .op(Op::infix(add, Left)
+                | Op::infix(subtract, Left)
+                | Op::infix(wrapping_add, Left)
+                | Op::infix(wrapping_sub, Left)
+                | Op::infix(saturating_add, Left)
+                | Op::infix(saturating_sub, Left))
+            .op(Op::infix(multiply, Left)
+                | Op::infix(divide, Left)
+                | Op::infix(remainder, Left)
+                | Op::infix(wrapping_mul, Left)
+                | Op::infix(saturating_mul, Left))
+            .op(Op::prefix(not)
+                | Op::prefix(bit_not)
+                | Op::prefix(r#ref)
+                | Op::prefix(neg)
+                | Op::prefix(deref))
+            .op(Op::postfix(cast_as))
+            .op(Op::postfix(field)
+                | Op::postfix(unwrap)
+                | Op::postfix(subslice)
+                | Op::postfix(index)
+                | Op::postfix(call_args))
     };
 }
 
-pub fn parse_file(path: &Path) -> Result<Program> {
-    let source = {
-        let mut file = File::open(path)?;
-        let mut buf = String::new();
-        file.read_to_string(&mut buf)?;
-        buf
+/// Parses a file and the modules and files it imports and includes, reporting every error found
+/// in them
+///
+/// After a syntax error the declaration it is in gets skipped, so the rest of the file can still
+/// be checked. Libraries, named like `<std>`, are looked for in `include_paths` in order before
+/// those bundled with the compiler. Every file is read through `sources`.
+pub fn parse_file(
+    path: &Path,
+    include_paths: &[PathBuf],
+    sources: &dyn SourceProvider,
+) -> Result<Program> {
+    let source = sources
+        .read(path)
+        .map_err(|e| vec![ParseError::Io(None, path.into(), e)])?;
+    parse_source(path, source, include_paths, sources)
+}
+
+/// Like `parse_file`, but with the contents of the file already read, which may differ from what
+/// `sources` has
+pub fn parse_source(
+    path: &Path,
+    source: String,
+    include_paths: &[PathBuf],
+    sources: &dyn SourceProvider,
+) -> Result<Program> {
+    modules::load(path, source, include_paths, sources)
+}
+
+/// Like `parse_source`, but also giving the full name of what each name usable in the file refers
+/// to, as in `geometry::area` for the `area` exported by the module imported as `geometry`
+pub fn parse_source_with_names(
+    path: &Path,
+    source: String,
+    include_paths: &[PathBuf],
+    sources: &dyn SourceProvider,
+) -> (Result<Program>, HashMap<Rc<str>, Rc<str>>) {
+    modules::load_with_names(path, source, include_paths, sources)
+}
+
+/// The directories listed in the `EDD_PATH` environment variable, searched for libraries after
+/// any given explicitly
+pub fn edd_path() -> Vec<PathBuf> {
+    env::var_os("EDD_PATH")
+        .map(|paths| env::split_paths(&paths).collect())
+        .unwrap_or_default()
+}
+
+/// Parses a single file, giving the includes and imports in it instead of loading the files they
+/// name
+pub fn parse_single(path: &Path, source: String) -> Result<ParsedFile> {
+    let (file, errors) = parse_partial(path, source);
+    if errors.is_empty() {
+        Ok(file)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Like `parse_single`, but giving what could be parsed along with the errors
+fn parse_partial(path: &Path, mut source: String) -> (ParsedFile, Vec<ParseError>) {
+    let sf = SourceFile {
+        path: path.into(),
+        errors: RefCell::new(Vec::new()),
+        includes: RefCell::new(Vec::new()),
+        public: RefCell::new(Vec::new()),
+    };
+    let program = loop {
+        match EddParser::parse(Rule::program, &source) {
+            Ok(pairs) => break EddParser::parse_program(pairs, &sf),
+            Err(e) => {
+                let pos = match e.location {
+                    InputLocation::Pos(pos) | InputLocation::Span((pos, _)) => pos,
+                };
+                sf.report(ParseError::syntax(&sf.path, &e));
+                if !blank_declaration(&mut source, pos) {
+                    break Program::default();
+                }
+            }
+        }
     };
-    let pairs = EddParser::parse(Rule::program, &source)?;
 
-    EddParser::parse_program(pairs, &path.into())
+    let mut errors = sf.errors.into_inner();
+    // syntax errors were found first, put them in order with the rest
+    errors.sort_by_key(|e| e.location().map(|loc| (loc.line_start, loc.col_start)));
+    let file = ParsedFile {
+        program,
+        includes: sf.includes.into_inner(),
+        public: sf.public.into_inner(),
+    };
+    (file, errors)
+}
+
+/// Replaces the top level declaration around the byte offset `pos` with whitespace, keeping the
+/// newlines so everything after it is still at the same line and column
+///
+/// Declarations are taken to end at a `;` or `}` outside of any braces. Returns false if there
+/// was nothing left to remove.
+fn blank_declaration(source: &mut String, pos: usize) -> bool {
+    let mut depth = 0u32;
+    let mut start = 0;
+    let mut end = source.len();
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let ends_decl = match c {
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' | '\n' => break,
+                        _ => (),
+                    }
+                }
+                false
+            }
+            '/' if matches!(chars.peek(), Some((_, '/'))) => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                false
+            }
+            '{' => {
+                depth += 1;
+                false
+            }
+            '}' => {
+                depth = depth.saturating_sub(1);
+                depth == 0
+            }
+            ';' => depth == 0,
+            _ => false,
+        };
+        if ends_decl {
+            if i >= pos {
+                end = i + 1;
+                break;
+            }
+            start = i + 1;
+        }
+    }
+
+    let decl = &source[start..end];
+    if decl.trim().is_empty() {
+        return false;
+    }
+    let blanked: String = decl
+        .chars()
+        .map(|c| if c == '\n' { c } else { ' ' })
+        .collect();
+    source.replace_range(start..end, &blanked);
+    true
+}
+
+/// How a rule is named in syntax errors, grouping the ones that are alike
+fn describe_rule(rule: &Rule) -> Cow<'static, str> {
+    use Rule::*;
+
+    match rule {
+        add | concat | subtract | multiply | divide | remainder | wrapping_add | wrapping_sub
+        | wrapping_mul | saturating_add | saturating_sub | saturating_mul | and | or | bit_and
+        | bit_or | bit_xor | shl | shr | eq | neq | lt | lte | gt | gte | cast_as | unwrap
+        | field | subslice | index => "an operator".into(),
+        static_decl | const_decl | fn_decl | extern_decl | extern_fn_decl | type_decl
+        | newtype_decl | struct_decl | enum_decl | include | import | kw_pub => {
+            "a declaration".into()
+        }
+        // the same name for each groups them together
+        not | bit_not | r#ref | neg | deref => "a prefix operator (`!`, `~`, `&`, `-` or `*`)".into(),
+        kw_as => "`as`".into(),
+        call_args => "`(`".into(),
+        kw_break => "`break`".into(),
+        kw_continue => "`continue`".into(),
+        expr | r#if | if_let | lambda | r#loop | call | variant_lit | r#match | array_lit
+        | struct_lit => "an expression".into(),
+        r#type => "a type".into(),
+        type_annot | type_annot_opt => "a type annotation".into(),
+        ident | qualified => "an identifier".into(),
+        literal | num | boolean | null | string => "a literal".into(),
+        pattern | variant_pattern | wildcard => "a pattern".into(),
+        block => "a block".into(),
+        statement => "a statement".into(),
+        EOI => "the end of the file".into(),
+        rule => format!("{rule:?}").replace('_', " ").into(),
+    }
+}
+
+/// The file being parsed, and what has been found in it so far
+struct SourceFile {
+    path: Rc<Path>,
+    errors: RefCell<Vec<ParseError>>,
+    includes: RefCell<Vec<Include>>,
+    public: RefCell<Vec<bool>>,
+}
+
+/// A file on its own, before the files it includes and imports are loaded
+#[derive(Debug, Clone, Default)]
+pub struct ParsedFile {
+    pub program: Program,
+    pub includes: Vec<Include>,
+    /// Whether each declaration is marked `pub`, in the same order as the declarations
+    pub public: Vec<bool>,
+}
+
+/// An include or import, with the path as it was written
+#[derive(Debug, Clone)]
+pub struct Include {
+    pub loc: Location,
+    pub path: Rc<str>,
+    /// The name of the module for an import, none for an include
+    pub alias: Option<Rc<str>>,
+}
+
+impl SourceFile {
+    fn report(&self, e: ParseError) {
+        self.errors.borrow_mut().push(e);
+    }
 }
 
 #[derive(Parser)]
@@ -59,7 +296,7 @@ pub fn parse_file(path: &Path) -> Result<Program> {
 struct EddParser;
 
 impl EddParser {
-    fn parse_string(s: Pairs<Rule>) -> String {
+    fn parse_string(s: Pairs<Rule>, sf: &SourceFile) -> String {
         let mut buf = String::new();
         for part in s {
             match part.as_rule() {
@@ -74,37 +311,66 @@ impl EddParser {
                     "\\" => buf.push('\\'),
                     "\'" => buf.push('\''),
                     "\"" => buf.push('\"'),
-                    x if x.starts_with('x') => match u8::from_str_radix(&x[1..], 16) {
-                        Ok(c @ 0..=0x7f) => buf.push(c as char),
-                        _ => unreachable!(),
-                    },
-                    _ => todo!("return invalid escape sequence error"),
+                    x if x.len() == 3 && x.starts_with('x') => {
+                        match u8::from_str_radix(&x[1..], 16) {
+                            Ok(c @ 0..=0x7f) => buf.push(c as char),
+                            _ => unreachable!(),
+                        }
+                    }
+                    x => {
+                        // from the backslash before the escape
+                        let span = part.as_span();
+                        let span = Span::new(span.get_input(), span.start() - 1, span.end()).unwrap();
+                        sf.report(ParseError::InvalidEscape(
+                            Location::from_span(&sf.path, span),
+                            x.into(),
+                        ))
+                    }
                 },
                 r => unreachable!("{r:?} {:?}", part.as_span().start_pos().line_col()),
             }
         }
         buf
     }
-    fn parse_literal(lit: Pairs<Rule>) -> Literal {
+    fn parse_literal(lit: Pairs<Rule>, sf: &SourceFile) -> Literal {
         let pair = get_only_one(lit);
         match pair.as_rule() {
             Rule::num => {
+                let loc = Location::from_span(&sf.path, pair.as_span());
                 let s = pair.as_str();
-                s.parse()
-                    .map(Literal::Integer)
-                    .or_else(|_| s.parse().map(Literal::Float))
-                    .unwrap()
+                let is_integer = s
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || b == b'+' || b == b'-');
+                let lit = if is_integer {
+                    s.parse()
+                        .map(Literal::Integer)
+                        .map_err(|_| ParseError::IntegerTooLarge(loc))
+                } else {
+                    s.parse()
+                        .map(Literal::Float)
+                        .map_err(|_| ParseError::InvalidFloat(loc))
+                };
+                lit.unwrap_or_else(|e| {
+                    sf.report(e);
+                    Literal::Unit
+                })
             }
             Rule::boolean => match pair.as_str() {
                 "true" => Literal::Boolean(true),
                 "false" => Literal::Boolean(false),
                 _ => unreachable!(),
             },
-            Rule::string => Literal::String(Self::parse_string(pair.into_inner()).into()),
+            Rule::null => Literal::Null,
+            Rule::string => Literal::String(Self::parse_string(pair.into_inner(), sf).into()),
             r => unreachable!("{r:?}"),
         }
     }
-    fn parse_type(mut pairs_t: Pairs<Rule>) -> Option<Type> {
+    /// Parses an optional type annotation, along with where the type is
+    fn parse_annotation(annotation: Pair<Rule>, sf: &SourceFile) -> Option<(Location, Type)> {
+        let loc = Location::from_span(&sf.path, annotation.clone().into_inner().next()?.as_span());
+        Some((loc, Self::parse_type(annotation.into_inner(), sf)?))
+    }
+    fn parse_type(mut pairs_t: Pairs<Rule>, sf: &SourceFile) -> Option<Type> {
         let t = pairs_t.next();
         assert!(pairs_t.next().is_none());
         let t = t?;
@@ -120,82 +386,183 @@ impl EddParser {
                 Rule::u16_t => Type::U16,
                 Rule::i32_t => Type::I32,
                 Rule::u32_t => Type::U32,
-                Rule::float_t => Type::I8,
+                Rule::float_t => Type::Float,
                 Rule::unit_t => Type::Unit,
                 _ => unreachable!(),
             },
-            Rule::opt => Type::Option(Box::new(Self::parse_type(t.into_inner()).unwrap())),
-            Rule::ptr => Type::Pointer(Box::new(Self::parse_type(t.into_inner()).unwrap())),
-            Rule::slice => Type::Slice(Box::new(Self::parse_type(t.into_inner()).unwrap())),
-            Rule::arrptr => Type::ArrayPointer(Box::new(Self::parse_type(t.into_inner()).unwrap())),
+            Rule::opt => Type::Option(Box::new(Self::parse_type(t.into_inner(), sf).unwrap())),
+            Rule::ptr => Type::Pointer(Box::new(Self::parse_type(t.into_inner(), sf).unwrap())),
+            Rule::slice => Type::Slice(Box::new(Self::parse_type(t.into_inner(), sf).unwrap())),
+            Rule::arrptr => {
+                Type::ArrayPointer(Box::new(Self::parse_type(t.into_inner(), sf).unwrap()))
+            }
             Rule::array => {
                 let mut ps = t.into_inner();
-                let size = ps.next().unwrap().as_str().parse().unwrap();
-                let t = get_only_one(ps).into_inner();
-                Type::Array(Box::new(Self::parse_type(t).unwrap()), size)
+                let size = ps.next().unwrap();
+                let size = size.as_str().parse().unwrap_or_else(|_| {
+                    sf.report(ParseError::InvalidArraySize(Location::from_span(
+                        &sf.path,
+                        size.as_span(),
+                    )));
+                    0
+                });
+                Type::Array(Box::new(Self::parse_type(ps, sf).unwrap()), size)
             }
             Rule::fntype => {
                 let mut ft = t.into_inner();
                 let args = ft.next().unwrap().into_inner();
                 let args = args
-                    .map(|t| Self::parse_type(Pairs::single(t)).unwrap())
+                    .map(|t| Self::parse_type(Pairs::single(t), sf).unwrap())
                     .collect();
-                let ret = Self::parse_type(ft).unwrap();
+                let ret = Self::parse_type(ft, sf).unwrap();
                 Type::Function(args, Box::new(ret))
             }
-            Rule::r#type => Self::parse_type(t.into_inner()).unwrap(),
+            Rule::named => Type::Named(Self::parse_name(get_only_one(t.into_inner()))),
+            Rule::r#type => Self::parse_type(Pairs::single(t), sf).unwrap(),
             _ => unreachable!(),
         })
     }
-    fn parse_typed_ident(mut pairs: Pairs<Rule>) -> (Rc<str>, Option<Type>) {
+    /// Parses an identifier, or a qualified one which is kept as `a::b`
+    fn parse_name(name: Pair<Rule>) -> Rc<str> {
+        match name.as_rule() {
+            Rule::ident => name.as_str().into(),
+            Rule::qualified => {
+                let parts: Vec<_> = name.into_inner().map(|p| p.as_str()).collect();
+                parts.join("::").into()
+            }
+            r => unreachable!("{r:?}"),
+        }
+    }
+    fn parse_label(label: Pair<Rule>) -> Rc<str> {
+        label.as_str().trim_start_matches('\'').into()
+    }
+    fn parse_typed_ident(mut pairs: Pairs<Rule>, sf: &SourceFile) -> (Rc<str>, Option<Type>) {
         let ident = pairs.next().unwrap().as_str();
         let annot = get_only_one(pairs);
-        (ident.into(), Self::parse_type(annot.into_inner()))
+        (ident.into(), Self::parse_type(annot.into_inner(), sf))
     }
-    fn parse_expr(expr: Pairs<Rule>, sf: &Rc<Path>) -> Expr {
+    fn parse_expr(expr: Pairs<Rule>, sf: &SourceFile) -> Expr {
         EXPR_PARSER
             .map_primary(|p| match p.as_rule() {
                 Rule::literal => Expr::Const(
-                    Location::from_span(sf, p.as_span()),
-                    Self::parse_literal(p.into_inner()),
+                    Location::from_span(&sf.path, p.as_span()),
+                    Self::parse_literal(p.into_inner(), sf),
+                ),
+                Rule::ident => Expr::Ident(
+                    Location::from_span(&sf.path, p.as_span()),
+                    p.as_str().into(),
                 ),
-                Rule::ident => Expr::Ident(Location::from_span(sf, p.as_span()), p.as_str().into()),
                 Rule::expr => Self::parse_expr(p.into_inner(), sf),
                 Rule::r#if => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let mut pairs = p.into_inner();
                     let c = Self::parse_expr(pairs.next().unwrap().into_inner(), sf);
                     let t = Self::parse_expr(pairs.next().unwrap().into_inner(), sf);
                     let e = Self::parse_expr(get_only_one(pairs).into_inner(), sf);
                     Expr::If(loc, Box::new(c), Box::new(t), Box::new(e))
                 }
+                Rule::if_let => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let mut pairs = p.into_inner();
+                    let n = pairs.next().unwrap().as_str().into();
+                    let opt = Self::parse_expr(pairs.next().unwrap().into_inner(), sf);
+                    let t = Self::parse_expr(pairs.next().unwrap().into_inner(), sf);
+                    let e = Self::parse_expr(get_only_one(pairs).into_inner(), sf);
+                    Expr::IfLet(loc, n, Box::new(opt), Box::new(t), Box::new(e))
+                }
+                Rule::r#loop => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let mut pairs = p.into_inner().peekable();
+                    let label = pairs
+                        .next_if(|p| p.as_rule() == Rule::label)
+                        .map(Self::parse_label);
+                    let body = Self::parse_expr(Pairs::single(pairs.next().unwrap()), sf);
+
+                    Expr::Loop(loc, label, Box::new(body))
+                }
                 Rule::lambda => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let mut pairs = p.into_inner();
                     let idents = pairs
                         .next()
                         .unwrap()
                         .into_inner()
-                        .map(|p| Self::parse_typed_ident(p.into_inner()))
+                        .map(|p| Self::parse_typed_ident(p.into_inner(), sf))
                         .collect();
-                    let ret = Self::parse_type(pairs.next().unwrap().into_inner());
+                    let ret = Self::parse_type(pairs.next().unwrap().into_inner(), sf);
                     let body = Self::parse_expr(get_only_one(pairs).into_inner(), sf);
 
                     Expr::Lambda(loc, idents, ret, Box::new(body))
                 }
                 Rule::call => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let mut pairs = p.into_inner();
-                    let name = pairs.next().unwrap().as_str().into();
+                    let name = pairs.next().unwrap();
+                    let callee = Expr::Ident(
+                        Location::from_span(&sf.path, name.as_span()),
+                        Self::parse_name(name),
+                    );
                     let exprs = get_only_one(pairs)
                         .into_inner()
                         .map(|p| Self::parse_expr(p.into_inner(), sf))
                         .collect();
 
-                    Expr::Call(loc, name, exprs)
+                    Expr::Call(loc, Box::new(callee), exprs)
+                }
+                Rule::array_lit => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let exprs = get_only_one(p.into_inner())
+                        .into_inner()
+                        .map(|p| Self::parse_expr(p.into_inner(), sf))
+                        .collect();
+
+                    Expr::Array(loc, exprs)
+                }
+                Rule::struct_lit => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let mut pairs = p.into_inner();
+                    let name = Self::parse_name(pairs.next().unwrap());
+                    let fields = pairs
+                        .map(|p| {
+                            let mut field = p.into_inner();
+                            let n = field.next().unwrap().as_str().into();
+                            let e = Self::parse_expr(get_only_one(field).into_inner(), sf);
+                            (Some(n), e)
+                        })
+                        .collect();
+
+                    Expr::StructConstructor(loc, name, fields)
+                }
+                Rule::variant_lit => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let mut pairs = p.into_inner();
+                    let (name, variant) = Self::parse_variant_name(&mut pairs);
+                    let payload = pairs
+                        .next()
+                        .into_iter()
+                        .flat_map(|p| p.into_inner())
+                        .map(|p| Self::parse_expr(p.into_inner(), sf))
+                        .collect();
+
+                    Expr::VariantConstructor(loc, name, variant, payload)
+                }
+                Rule::r#match => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let mut pairs = p.into_inner();
+                    let e = Self::parse_expr(pairs.next().unwrap().into_inner(), sf);
+                    let arms = pairs
+                        .map(|p| {
+                            let mut arm = p.into_inner();
+                            let pattern = Self::parse_pattern(arm.next().unwrap(), sf);
+                            let body = Self::parse_expr(get_only_one(arm).into_inner(), sf);
+                            (pattern, body)
+                        })
+                        .collect();
+
+                    Expr::Match(loc, Box::new(e), arms)
                 }
                 Rule::block => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let stmnts = p
                         .into_inner()
                         .map(|p| Self::parse_statement(p.into_inner(), sf))
@@ -206,120 +573,265 @@ impl EddParser {
                 r => unreachable!("{r:?}"),
             })
             .map_infix(|lhs, op, rhs| match op.as_rule() {
+                Rule::wrapping_add => Expr::WrappingAdd(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::wrapping_sub => Expr::WrappingSub(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::wrapping_mul => Expr::WrappingMul(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::saturating_add => Expr::SaturatingAdd(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::saturating_sub => Expr::SaturatingSub(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::saturating_mul => Expr::SaturatingMul(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::bit_and => Expr::BitAnd(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::bit_or => Expr::BitOr(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::bit_xor => Expr::BitXor(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::shl => Expr::Shl(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::shr => Expr::Shr(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::remainder => Expr::Rem(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::and => Expr::And(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
+                Rule::or => Expr::Or(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(rhs),
+                ),
                 Rule::add => Expr::Add(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::subtract => Expr::Sub(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::multiply => Expr::Mul(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::divide => Expr::Div(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::eq => Expr::Eq(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::neq => Expr::Neq(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::lt => Expr::Lt(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::lte => Expr::Lte(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::gt => Expr::Gt(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::gte => Expr::Gte(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
                 Rule::concat => Expr::Concat(
-                    Location::from_span(sf, op.as_span()),
+                    Location::from_span(&sf.path, op.as_span()),
                     Box::new(lhs),
                     Box::new(rhs),
                 ),
-                Rule::cast_as => Expr::Cast(
-                    Location::from_span(sf, op.as_span()),
-                    Box::new(lhs),
-                    todo!("{}", rhs),
-                ),
                 _ => unreachable!(),
             })
             .map_prefix(|op, rhs| match op.as_rule() {
-                Rule::not => Expr::Not(Location::from_span(sf, op.as_span()), Box::new(rhs)),
-                Rule::r#ref => Expr::Ref(Location::from_span(sf, op.as_span()), Box::new(rhs)),
-                Rule::neg => Expr::Neg(Location::from_span(sf, op.as_span()), Box::new(rhs)),
-                Rule::deref => Expr::Deref(Location::from_span(sf, op.as_span()), Box::new(rhs)),
+                Rule::not => Expr::Not(Location::from_span(&sf.path, op.as_span()), Box::new(rhs)),
+                Rule::bit_not => {
+                    Expr::BitNot(Location::from_span(&sf.path, op.as_span()), Box::new(rhs))
+                }
+                Rule::r#ref => {
+                    Expr::Ref(Location::from_span(&sf.path, op.as_span()), Box::new(rhs))
+                }
+                Rule::neg => Expr::Neg(Location::from_span(&sf.path, op.as_span()), Box::new(rhs)),
+                Rule::deref => {
+                    Expr::Deref(Location::from_span(&sf.path, op.as_span()), Box::new(rhs))
+                }
+                _ => unreachable!(),
+            })
+            .map_postfix(|lhs, op| match op.as_rule() {
+                Rule::cast_as => {
+                    let loc = Location::from_span(&sf.path, op.as_span());
+                    let mut ps = op.into_inner();
+                    ps.next().unwrap();
+                    Expr::Cast(loc, Box::new(lhs), Self::parse_type(ps, sf).unwrap())
+                }
+                Rule::field => Expr::FieldAccess(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    get_only_one(op.into_inner()).as_str().into(),
+                ),
+                Rule::unwrap => {
+                    Expr::Unwrap(Location::from_span(&sf.path, op.as_span()), Box::new(lhs))
+                }
+                Rule::subslice => {
+                    let mut bounds = op.clone().into_inner();
+                    let start = Self::parse_expr(bounds.next().unwrap().into_inner(), sf);
+                    let end = Self::parse_expr(bounds.next().unwrap().into_inner(), sf);
+                    Expr::Slice(
+                        Location::from_span(&sf.path, op.as_span()),
+                        Box::new(lhs),
+                        Box::new(start),
+                        Box::new(end),
+                    )
+                }
+                Rule::index => Expr::Index(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    Box::new(Self::parse_expr(
+                        get_only_one(op.into_inner()).into_inner(),
+                        sf,
+                    )),
+                ),
+                Rule::call_args => Expr::Call(
+                    Location::from_span(&sf.path, op.as_span()),
+                    Box::new(lhs),
+                    get_only_one(op.into_inner())
+                        .into_inner()
+                        .map(|p| Self::parse_expr(p.into_inner(), sf))
+                        .collect(),
+                ),
                 _ => unreachable!(),
             })
             .parse(expr)
     }
-    fn parse_pl_expr(pairs_t: Pairs<Rule>, sf: &Rc<Path>) -> PlaceExpr {
-        let pl_expr = get_only_one(pairs_t);
-        let loc = Location::from_span(sf, pl_expr.as_span());
-        match pl_expr.as_rule() {
-            Rule::ident => PlaceExpr::Ident(loc, pl_expr.as_str().into()),
-            Rule::deref_expr => PlaceExpr::Deref(loc, Self::parse_expr(pl_expr.into_inner(), sf)),
-            Rule::array_index => {
-                let mut pairs = pl_expr.into_inner();
-                let e = Pairs::single(pairs.next().unwrap());
-                let i = Pairs::single(pairs.next().unwrap());
-
-                PlaceExpr::Index(loc, Self::parse_expr(e, sf), Self::parse_expr(i, sf))
-            }
-            Rule::field_access => {
-                let mut pairs = pl_expr.into_inner();
-                let e = Pairs::single(pairs.next().unwrap());
-                let i = Pairs::single(pairs.next().unwrap());
-
-                PlaceExpr::FieldAccess(loc, Self::parse_expr(e, sf), i.as_str().into())
+    fn parse_pattern(pattern: Pair<Rule>, sf: &SourceFile) -> Pattern {
+        let loc = Location::from_span(&sf.path, pattern.as_span());
+        let pattern = get_only_one(pattern.into_inner());
+        match pattern.as_rule() {
+            Rule::wildcard => Pattern::Wildcard(loc),
+            Rule::variant_pattern => {
+                let mut pairs = pattern.into_inner();
+                let (name, variant) = Self::parse_variant_name(&mut pairs);
+                let bindings = pairs
+                    .map(|p| match p.as_rule() {
+                        Rule::ident => Some(p.as_str().into()),
+                        Rule::wildcard => None,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                Pattern::Variant(loc, name, variant, bindings)
             }
             _ => unreachable!(),
         }
     }
-    fn parse_statement(mut stmnt: Pairs<Rule>, sf: &Rc<Path>) -> Statement {
+    /// Parses the name of an enum, which may be in another module, and the name of a variant of it
+    fn parse_variant_name(pairs: &mut Pairs<Rule>) -> (Rc<str>, Rc<str>) {
+        let name = Self::parse_name(pairs.next().unwrap());
+        (name, pairs.next().unwrap().as_str().into())
+    }
+    fn parse_pl_expr(mut pairs_t: Pairs<Rule>, sf: &SourceFile) -> PlaceExpr {
+        let pl_expr = pairs_t.next().unwrap();
+        let loc = Location::from_span(&sf.path, pl_expr.as_span());
+        let mut place = match pl_expr.as_rule() {
+            Rule::ident | Rule::qualified => PlaceExpr::Ident(loc, Self::parse_name(pl_expr)),
+            Rule::deref_expr => PlaceExpr::Deref(loc, Self::parse_expr(pl_expr.into_inner(), sf)),
+            Rule::pl_expr => Self::parse_pl_expr(pl_expr.into_inner(), sf),
+            _ => unreachable!(),
+        };
+        for postfix in pairs_t {
+            let loc = Location::from_span(&sf.path, postfix.as_span());
+            let rule = postfix.as_rule();
+            let inner = get_only_one(postfix.into_inner());
+            place = match rule {
+                Rule::field => {
+                    PlaceExpr::FieldAccess(loc, place.into_expr(), inner.as_str().into())
+                }
+                Rule::index => PlaceExpr::Index(
+                    loc,
+                    place.into_expr(),
+                    Self::parse_expr(inner.into_inner(), sf),
+                ),
+                _ => unreachable!(),
+            };
+        }
+        place
+    }
+    fn parse_statement(mut stmnt: Pairs<Rule>, sf: &SourceFile) -> Statement {
         let Some(stmnt) = stmnt.next() else {
             // TODO: FIXME
-            let loc = Location::new(sf.clone());
+            let loc = Location::new(sf.path.clone());
             return Statement::Express(loc.clone(), Expr::Const(loc, Literal::Unit));
         };
-        let loc = Location::from_span(sf, stmnt.as_span());
+        let loc = Location::from_span(&sf.path, stmnt.as_span());
         match stmnt.as_rule() {
             Rule::expr => Statement::Express(loc, Self::parse_expr(stmnt.into_inner(), sf)),
             Rule::let_bind => {
                 let mut binding = stmnt.into_inner();
                 let id = binding.next().unwrap().as_str().into();
-                let t_annotation = Self::parse_type(binding.next().unwrap().into_inner());
+                let t_annotation = Self::parse_annotation(binding.next().unwrap(), sf);
                 let expr = Self::parse_expr(binding.next().unwrap().into_inner(), sf);
                 Statement::Let(loc, id, t_annotation, expr)
             }
             Rule::var_bind => {
                 let mut binding = stmnt.into_inner();
                 let id = binding.next().unwrap().as_str().into();
-                let t_annotation = Self::parse_type(binding.next().unwrap().into_inner());
+                let t_annotation = Self::parse_annotation(binding.next().unwrap(), sf);
                 let expr = Self::parse_expr(binding.next().unwrap().into_inner(), sf);
                 Statement::Var(loc, id, t_annotation, expr)
             }
@@ -333,106 +845,337 @@ impl EddParser {
                 let expr = get_only_one(stmnt.into_inner());
                 Statement::Return(loc, Self::parse_expr(expr.into_inner(), sf))
             }
+            Rule::while_loop => {
+                let mut pairs = stmnt.into_inner().peekable();
+                let label = pairs
+                    .next_if(|p| p.as_rule() == Rule::label)
+                    .map(Self::parse_label);
+                let cond = Self::parse_expr(pairs.next().unwrap().into_inner(), sf);
+                let body = Self::parse_expr(Pairs::single(pairs.next().unwrap()), sf);
+                Statement::While(loc, label, cond, body)
+            }
+            Rule::for_loop => {
+                let mut pairs = stmnt.into_inner().peekable();
+                let label = pairs
+                    .next_if(|p| p.as_rule() == Rule::label)
+                    .map(Self::parse_label);
+                let var = pairs.next().unwrap().as_str().into();
+                let iterable = pairs.next().unwrap();
+                let iterable = match iterable.as_rule() {
+                    Rule::range => {
+                        let mut bounds = iterable.into_inner();
+                        let start = Self::parse_expr(bounds.next().unwrap().into_inner(), sf);
+                        let end = Self::parse_expr(get_only_one(bounds).into_inner(), sf);
+                        Iterable::Range(start, end)
+                    }
+                    _ => Iterable::Collection(Self::parse_expr(iterable.into_inner(), sf)),
+                };
+                let body = Self::parse_expr(Pairs::single(pairs.next().unwrap()), sf);
+                Statement::For(loc, label, var, iterable, body)
+            }
+            Rule::r#break => {
+                // skip keyword
+                let mut pairs = stmnt.into_inner().skip(1).peekable();
+                let label = pairs
+                    .next_if(|p| p.as_rule() == Rule::label)
+                    .map(Self::parse_label);
+                let expr = pairs.next().map(|p| Self::parse_expr(p.into_inner(), sf));
+                Statement::Break(loc, label, expr)
+            }
+            Rule::r#continue => {
+                // skip keyword
+                let label = stmnt.into_inner().nth(1).map(Self::parse_label);
+                Statement::Continue(loc, label)
+            }
             e => unreachable!("{e:?}"),
         }
     }
-    fn parse_program(mut ps: Pairs<Rule>, sf: &Rc<Path>) -> Result<Program> {
+    fn parse_program(mut ps: Pairs<Rule>, sf: &SourceFile) -> Program {
         let mut decls = Vec::new();
+        let mut public = false;
         loop {
             let p = ps.next().unwrap();
             match p.as_rule() {
+                Rule::kw_pub => {
+                    public = true;
+                    continue;
+                }
                 Rule::static_decl => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let mut ps = p.into_inner();
-                    let (n, t) = Self::parse_typed_ident(ps.next().unwrap().into_inner());
+                    let (n, t) = Self::parse_typed_ident(ps.next().unwrap().into_inner(), sf);
                     let expr = Self::parse_expr(get_only_one(ps).into_inner(), sf);
 
                     decls.push((n, Decl::Static(loc, Box::new((t.unwrap(), expr)))));
                 }
                 Rule::const_decl => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let mut ps: Pairs<'_, Rule> = p.into_inner();
-                    let (n, t) = Self::parse_typed_ident(ps.next().unwrap().into_inner());
+                    let (n, t) = Self::parse_typed_ident(ps.next().unwrap().into_inner(), sf);
                     let expr = Self::parse_expr(get_only_one(ps).into_inner(), sf);
 
                     decls.push((n, Decl::Const(loc, Box::new((t.unwrap(), expr)))));
                 }
                 Rule::fn_decl => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let mut ps = p.into_inner();
                     let n = ps.next().unwrap().as_str().into();
+                    let type_params = match ps.peek().unwrap().as_rule() {
+                        Rule::type_params => ps
+                            .next()
+                            .unwrap()
+                            .into_inner()
+                            .map(|p| {
+                                let mut p = p.into_inner();
+                                let name = p.next().unwrap().as_str().into();
+                                (name, p.next().map(|c| c.as_str().into()))
+                            })
+                            .collect(),
+                        _ => Box::default(),
+                    };
                     let typed_idents = ps
                         .next()
                         .unwrap()
                         .into_inner()
-                        .map(|ps| Self::parse_typed_ident(ps.into_inner()))
+                        .map(|ps| Self::parse_typed_ident(ps.into_inner(), sf))
                         .map(|(n, t)| (n, t.unwrap()))
                         .collect();
-                    let ret = Self::parse_type(ps.next().unwrap().into_inner()).unwrap();
+                    let ret = Self::parse_type(ps.next().unwrap().into_inner(), sf).unwrap();
                     let body = Self::parse_expr(Pairs::single(ps.next().unwrap()), sf);
 
-                    decls.push((n, Decl::Fn(loc, typed_idents, Box::new((ret, body)))));
+                    decls.push((
+                        n,
+                        Decl::Fn(loc, type_params, typed_idents, Box::new((ret, body))),
+                    ));
                 }
                 Rule::extern_fn_decl => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let mut ps = p.into_inner();
                     let n = ps.next().unwrap().as_str().into();
                     let typed_idents = ps
                         .next()
                         .unwrap()
                         .into_inner()
-                        .map(|ps| Self::parse_typed_ident(ps.into_inner()))
+                        .map(|ps| Self::parse_typed_ident(ps.into_inner(), sf))
                         .map(|(n, t)| (n, t.unwrap()))
                         .collect();
-                    let ret = Self::parse_type(ps.next().unwrap().into_inner()).unwrap();
+                    let ret = Self::parse_type(ps.next().unwrap().into_inner(), sf).unwrap();
 
                     decls.push((n, Decl::ExternFn(loc, typed_idents, Box::new(ret))));
                 }
                 Rule::extern_decl => {
-                    let loc = Location::from_span(sf, p.as_span());
+                    let loc = Location::from_span(&sf.path, p.as_span());
                     let mut ps = p.into_inner();
-                    let (n, t) = Self::parse_typed_ident(ps.next().unwrap().into_inner());
+                    let (n, t) = Self::parse_typed_ident(ps.next().unwrap().into_inner(), sf);
 
                     decls.push((n, Decl::ExternStatic(loc, Box::new(t.unwrap()))));
                 }
-                Rule::include => {
-                    let path = Self::parse_string(get_only_one(p.into_inner()).into_inner());
-                    let path = sf.parent().unwrap().join(path);
-                    let mut inner_program = parse_file(&path)?;
-                    decls.append(&mut inner_program.0);
+                Rule::type_decl | Rule::newtype_decl => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let distinct = p.as_rule() == Rule::newtype_decl;
+                    let mut ps = p.into_inner();
+                    let n = ps.next().unwrap().as_str().into();
+                    let t = Box::new(Self::parse_type(ps, sf).unwrap());
+
+                    decls.push((
+                        n,
+                        if distinct {
+                            Decl::Newtype(loc, t)
+                        } else {
+                            Decl::Alias(loc, t)
+                        },
+                    ));
+                }
+                Rule::struct_decl => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let mut ps = p.into_inner();
+                    let n = ps.next().unwrap().as_str().into();
+                    let fields = get_only_one(ps)
+                        .into_inner()
+                        .map(|ps| Self::parse_typed_ident(ps.into_inner(), sf))
+                        .map(|(n, t)| (n, t.unwrap()))
+                        .collect();
+
+                    decls.push((n, Decl::Struct(loc, fields)));
+                }
+                Rule::enum_decl => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let mut ps = p.into_inner();
+                    let n = ps.next().unwrap().as_str().into();
+                    let variants = ps
+                        .map(|p| {
+                            let mut ps = p.into_inner();
+                            let variant = ps.next().unwrap().as_str().into();
+                            let payload = ps
+                                .next()
+                                .into_iter()
+                                .flat_map(|p| p.into_inner())
+                                .map(|t| Self::parse_type(Pairs::single(t), sf).unwrap())
+                                .collect();
+                            (variant, payload)
+                        })
+                        .collect();
+
+                    decls.push((n, Decl::Enum(loc, variants)));
+                }
+                Rule::include | Rule::import => {
+                    let loc = Location::from_span(&sf.path, p.as_span());
+                    let mut ps = p.into_inner();
+                    let path = Self::parse_string(ps.next().unwrap().into_inner(), sf);
+                    sf.includes.borrow_mut().push(Include {
+                        loc,
+                        path: path.into(),
+                        alias: ps.next().map(|alias| alias.as_str().into()),
+                    });
+                    continue;
                 }
                 Rule::EOI => break,
                 _ => unreachable!(),
             }
+            sf.public.borrow_mut().push(public);
+            public = false;
         }
 
-        Ok(Program(decls))
+        Program(decls)
     }
 }
 
 #[derive(Debug)]
 pub enum ParseError {
-    Pest(Box<pest::error::Error<Rule>>),
-    IoError(io::Error),
+    /// Source that does not fit the grammar, with what was expected instead
+    Syntax(Location, Box<str>),
+    InvalidEscape(Location, Box<str>),
+    /// An integer literal too large to fit in any integer
+    IntegerTooLarge(Location),
+    InvalidFloat(Location),
+    InvalidArraySize(Location),
+    /// Reading a file failed, with the include it was read for if there was one
+    Io(Option<Location>, Rc<Path>, io::Error),
+    /// An import or include of a file that is already being loaded, with the files in the cycle
+    ImportCycle(Location, Box<[Rc<Path>]>),
+    /// A name qualified by something that is not a module imported in the file
+    UnknownModule(Location, Rc<str>),
+    /// A name of an imported module that the module does not declare as `pub`
+    NotPublic(Location, Rc<str>, Rc<str>),
+    /// A module imported under a name that is already taken
+    DuplicateModule(Location, Rc<str>),
+    /// A library that is neither in the include paths nor bundled with the compiler
+    LibraryNotFound(Location, Rc<str>),
 }
 
-impl From<pest::error::Error<Rule>> for ParseError {
-    fn from(e: pest::error::Error<Rule>) -> Self {
-        ParseError::Pest(Box::new(e))
+impl ParseError {
+    fn syntax(path: &Rc<Path>, e: &pest::error::Error<Rule>) -> Self {
+        let (start, end) = match e.line_col {
+            LineColLocation::Pos(pos) => (pos, (pos.0, pos.1 + 1)),
+            LineColLocation::Span(start, end) => (start, end),
+        };
+        let loc = Location {
+            source_file: path.clone(),
+            line_start: start.0.try_into().unwrap(),
+            col_start: start.1.try_into().unwrap(),
+            line_end: end.0.try_into().unwrap(),
+            col_end: end.1.try_into().unwrap(),
+        };
+        let msg = match &e.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => {
+                let describe = |rules: &[Rule]| {
+                    let mut names: Vec<Cow<str>> = Vec::new();
+                    for name in rules.iter().map(describe_rule) {
+                        if !names.contains(&name) {
+                            names.push(name);
+                        }
+                    }
+                    match names.split_last() {
+                        None => String::new(),
+                        Some((last, [])) => last.to_string(),
+                        Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+                    }
+                };
+                match (positives.is_empty(), negatives.is_empty()) {
+                    (false, true) => format!("expected {}", describe(positives)),
+                    (true, false) => format!("unexpected {}", describe(negatives)),
+                    (false, false) => format!(
+                        "unexpected {}, expected {}",
+                        describe(negatives),
+                        describe(positives)
+                    ),
+                    (true, true) => "unknown parsing error".to_owned(),
+                }
+            }
+            ErrorVariant::CustomError { message } => message.clone(),
+        };
+        ParseError::Syntax(loc, msg.into())
     }
-}
-impl From<io::Error> for ParseError {
-    fn from(e: io::Error) -> Self {
-        ParseError::IoError(e)
+    /// Stable code identifying the kind of error, like those of type errors
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::Syntax(..) => "E0001",
+            ParseError::Io(..) => "E0002",
+            ParseError::InvalidEscape(..) => "E0003",
+            ParseError::IntegerTooLarge(_) => "E0004",
+            ParseError::InvalidFloat(_) => "E0005",
+            ParseError::InvalidArraySize(_) => "E0006",
+            ParseError::ImportCycle(..) => "E0007",
+            ParseError::UnknownModule(..) => "E0008",
+            ParseError::NotPublic(..) => "E0009",
+            ParseError::DuplicateModule(..) => "E0010",
+            ParseError::LibraryNotFound(..) => "E0011",
+        }
+    }
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            ParseError::Syntax(loc, _)
+            | ParseError::InvalidEscape(loc, _)
+            | ParseError::IntegerTooLarge(loc)
+            | ParseError::InvalidFloat(loc)
+            | ParseError::InvalidArraySize(loc)
+            | ParseError::ImportCycle(loc, _)
+            | ParseError::UnknownModule(loc, _)
+            | ParseError::NotPublic(loc, _, _)
+            | ParseError::DuplicateModule(loc, _)
+            | ParseError::LibraryNotFound(loc, _) => Some(loc),
+            ParseError::Io(loc, _, _) => loc.as_ref(),
+        }
+    }
+    fn message(&self) -> String {
+        match self {
+            ParseError::Syntax(_, msg) => msg.to_string(),
+            ParseError::InvalidEscape(_, esc) => format!("invalid escape sequence \\{esc}"),
+            ParseError::IntegerTooLarge(_) => "integer literal is too large".to_owned(),
+            ParseError::InvalidFloat(_) => "invalid float literal".to_owned(),
+            ParseError::InvalidArraySize(_) => "array size must fit in a u16".to_owned(),
+            ParseError::Io(_, path, e) => format!("could not read {}: {e}", path.display()),
+            ParseError::ImportCycle(_, cycle) => {
+                let cycle: Vec<_> = cycle.iter().map(|p| p.display().to_string()).collect();
+                format!("import cycle: {}", cycle.join(" -> "))
+            }
+            ParseError::UnknownModule(_, name) => format!("no module named {name} is imported"),
+            ParseError::NotPublic(_, module, name) => {
+                format!("module {module} has no public declaration named {name}")
+            }
+            ParseError::DuplicateModule(_, name) => format!("the name {name} is already taken"),
+            ParseError::LibraryNotFound(_, name) => {
+                format!("no library <{name}> in the include paths or bundled with the compiler")
+            }
+        }
+    }
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diag = Diagnostic::error(self.code(), self.message());
+        match self.location() {
+            Some(loc) => diag.with_label(Label::primary(loc.clone())),
+            None => diag,
+        }
     }
 }
 
 impl Display for ParseError {
-    #[inline]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ParseError::Pest(e) => e.fmt(f),
-            ParseError::IoError(e) => e.fmt(f),
+        match self.location() {
+            Some(loc) => write!(f, "{loc}: {}", self.message()),
+            None => write!(f, "{}", self.message()),
         }
     }
 }
@@ -440,10 +1183,11 @@ impl Display for ParseError {
 impl Error for ParseError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            ParseError::Pest(e) => Some(e),
-            ParseError::IoError(e) => Some(e),
+            ParseError::Io(_, _, e) => Some(e),
+            _ => None,
         }
     }
 }
 
-pub type Result<T> = StdResult<T, ParseError>;
+/// Parsing carries on after errors, so it can fail with many
+pub type Result<T> = StdResult<T, Vec<ParseError>>;
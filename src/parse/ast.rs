@@ -1,49 +1,130 @@
 use std::fmt::{self, Display};
 use std::rc::Rc;
 
-use crate::ttype::Type;
+use crate::ttype::{Type, Variant};
 
+use super::format::Printer;
 use super::location::Location;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Program(pub Vec<(Rc<str>, Decl)>);
 
+/// A type parameter and the kind of type it has to be, if any
+pub type TypeParam = (Rc<str>, Option<Rc<str>>);
+
 #[derive(Debug, Clone)]
 pub enum Decl {
     Static(Location, Box<(Type, Expr)>),
     Const(Location, Box<(Type, Expr)>),
-    Fn(Location, Box<[(Rc<str>, Type)]>, Box<(Type, Expr)>),
+    /// Type parameters, which are empty unless it is generic, parameters, return type and body
+    Fn(
+        Location,
+        Box<[TypeParam]>,
+        Box<[(Rc<str>, Type)]>,
+        Box<(Type, Expr)>,
+    ),
     ExternStatic(Location, Box<Type>),
     ExternFn(Location, Box<[(Rc<str>, Type)]>, Box<Type>),
+    /// Another name for the type, which is the same as it
+    Alias(Location, Box<Type>),
+    /// A type that is like its base type but only converts to and from it with `as`
+    Newtype(Location, Box<Type>),
+    Struct(Location, Box<[(Rc<str>, Type)]>),
+    Enum(Location, Box<[Variant]>),
+}
+
+impl Decl {
+    pub fn location(&self) -> &Location {
+        match self {
+            Decl::Static(loc, _)
+            | Decl::Const(loc, _)
+            | Decl::Fn(loc, _, _, _)
+            | Decl::ExternStatic(loc, _)
+            | Decl::ExternFn(loc, _, _)
+            | Decl::Alias(loc, _)
+            | Decl::Newtype(loc, _)
+            | Decl::Struct(loc, _)
+            | Decl::Enum(loc, _) => loc,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Statement {
     Express(Location, Expr),
-    Let(Location, Rc<str>, Option<Type>, Expr),
-    Var(Location, Rc<str>, Option<Type>, Expr),
+    /// The annotation has its own location, for errors about it
+    Let(Location, Rc<str>, Option<(Location, Type)>, Expr),
+    Var(Location, Rc<str>, Option<(Location, Type)>, Expr),
     Rebind(Location, PlaceExpr, Expr),
+    /// Label, condition and body
+    While(Location, Option<Rc<str>>, Expr, Expr),
+    Break(Location, Option<Rc<str>>, Option<Expr>),
+    Continue(Location, Option<Rc<str>>),
+    /// Label, loop variable, what to iterate over and body
+    For(Location, Option<Rc<str>>, Rc<str>, Iterable, Expr),
 
     Return(Location, Expr),
 }
 
+impl Statement {
+    pub fn location(&self) -> &Location {
+        match self {
+            Statement::Express(loc, _)
+            | Statement::Let(loc, _, _, _)
+            | Statement::Var(loc, _, _, _)
+            | Statement::Rebind(loc, _, _)
+            | Statement::While(loc, _, _, _)
+            | Statement::Break(loc, _, _)
+            | Statement::Continue(loc, _)
+            | Statement::For(loc, _, _, _, _)
+            | Statement::Return(loc, _) => loc,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Iterable {
+    /// Half-open range of integers
+    Range(Expr, Expr),
+    /// Elements of an array or slice
+    Collection(Expr),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Integer(i128),
     Float(f64),
     Boolean(bool),
     Unit,
+    Null,
     String(Rc<str>),
 }
 
 impl Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            Literal::Unit => write!(f, "()"),
+            // there is no unit literal, but an empty block is the same
+            Literal::Unit => write!(f, "{{}}"),
+            Literal::Null => write!(f, "null"),
             Literal::Integer(v) => write!(f, "{v}"),
-            Literal::Float(v) => write!(f, "{v}"),
-            // TODO: don't rely on Rust debug print to escape it correctly
-            Literal::String(ref s) => write!(f, "{s:?}"),
+            // debug printing keeps the decimal point, so it is not read back as an integer
+            Literal::Float(v) => write!(f, "{v:?}"),
+            Literal::String(ref s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\0' => write!(f, "\\0")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '"' => write!(f, "\\\"")?,
+                        c if c.is_ascii_control() => write!(f, "\\x{:02x}", c as u8)?,
+                        c => write!(f, "{c}")?,
+                    }
+                }
+                write!(f, "\"")
+            }
             Literal::Boolean(v) => write!(f, "{v}"),
         }
     }
@@ -51,48 +132,9 @@ impl Display for Literal {
 
 impl Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (name, decl) in &self.0 {
-            match decl {
-                Decl::Static(_, bind) => {
-                    let (t, e) = &**bind;
-                    write!(f, "static {name}: {t} = {e}")?;
-                }
-                Decl::Const(_, bind) => {
-                    let (t, e) = &**bind;
-                    write!(f, "const {name}: {t} = {e}")?;
-                }
-                Decl::Fn(_, args, body) => {
-                    let (ret, body) = &**body;
-                    write!(f, "fn {name}(")?;
-                    let mut first = true;
-                    for (arg_n, arg_t) in &**args {
-                        if !first {
-                            write!(f, ", ")?;
-                        }
-                        first = false;
-                        write!(f, "{arg_n}: {arg_t}")?;
-                    }
-                    write!(f, ") {ret} {body}")?;
-                }
-                Decl::ExternStatic(_, t) => {
-                    write!(f, "extern {name}: {t}")?;
-                }
-                Decl::ExternFn(_, args, ret) => {
-                    write!(f, "fn {name}(")?;
-                    let mut first = true;
-                    for (arg_n, arg_t) in &**args {
-                        if !first {
-                            write!(f, ", ")?;
-                        }
-                        first = false;
-                        write!(f, "{arg_n}: {arg_t}")?;
-                    }
-                    write!(f, ") {ret}")?;
-                }
-            }
-            writeln!(f)?;
-        }
-        Ok(())
+        let mut printer = Printer::plain();
+        printer.program(self, &[], &[]);
+        f.write_str(&printer.finish())
     }
 }
 
@@ -103,6 +145,30 @@ pub enum PlaceExpr {
     Index(Location, Expr, Expr),
     FieldAccess(Location, Expr, Rc<str>),
 }
+impl PlaceExpr {
+    /// Expression reading the value in this place
+    pub fn into_expr(self) -> Expr {
+        match self {
+            PlaceExpr::Ident(loc, n) => Expr::Ident(loc, n),
+            PlaceExpr::Deref(loc, e) => Expr::Deref(loc, Box::new(e)),
+            PlaceExpr::Index(loc, e, i) => Expr::Index(loc, Box::new(e), Box::new(i)),
+            PlaceExpr::FieldAccess(loc, e, n) => Expr::FieldAccess(loc, Box::new(e), n),
+        }
+    }
+}
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard(Location),
+    /// Name of the enum, the variant and the bindings for its payload
+    Variant(Location, Rc<str>, Rc<str>, Box<[Option<Rc<str>>]>),
+}
+impl Pattern {
+    pub fn location(&self) -> &Location {
+        match self {
+            Pattern::Wildcard(loc) | Pattern::Variant(loc, _, _, _) => loc,
+        }
+    }
+}
 #[derive(Debug, Clone)]
 pub enum Expr {
     Ident(Location, Rc<str>),
@@ -110,16 +176,41 @@ pub enum Expr {
     Add(Location, Box<Self>, Box<Self>),
     Sub(Location, Box<Self>, Box<Self>),
     Mul(Location, Box<Self>, Box<Self>),
+    WrappingAdd(Location, Box<Self>, Box<Self>),
+    WrappingSub(Location, Box<Self>, Box<Self>),
+    WrappingMul(Location, Box<Self>, Box<Self>),
+    SaturatingAdd(Location, Box<Self>, Box<Self>),
+    SaturatingSub(Location, Box<Self>, Box<Self>),
+    SaturatingMul(Location, Box<Self>, Box<Self>),
+    BitAnd(Location, Box<Self>, Box<Self>),
+    BitOr(Location, Box<Self>, Box<Self>),
+    BitXor(Location, Box<Self>, Box<Self>),
+    Shl(Location, Box<Self>, Box<Self>),
+    Shr(Location, Box<Self>, Box<Self>),
     Div(Location, Box<Self>, Box<Self>),
+    Rem(Location, Box<Self>, Box<Self>),
+    And(Location, Box<Self>, Box<Self>),
+    Or(Location, Box<Self>, Box<Self>),
     Concat(Location, Box<Self>, Box<Self>),
 
     Not(Location, Box<Self>),
+    /// The same as `Not`, but written with `~`
+    BitNot(Location, Box<Self>),
     Neg(Location, Box<Self>),
 
     Ref(Location, Box<Self>),
     Deref(Location, Box<Self>),
     Array(Location, Box<[Self]>),
-    StructConstructor(Location, Box<[(Option<Box<str>>, Expr)]>),
+    /// The array or slice and the index into it
+    Index(Location, Box<Self>, Box<Self>),
+    /// The array or slice and the start and end of the slice of it
+    Slice(Location, Box<Self>, Box<Self>, Box<Self>),
+    /// Name of the struct and its fields
+    StructConstructor(Location, Rc<str>, Box<[(Option<Box<str>>, Expr)]>),
+    FieldAccess(Location, Box<Self>, Rc<str>),
+    /// Name of the enum, the variant and its payload
+    VariantConstructor(Location, Rc<str>, Rc<str>, Box<[Self]>),
+    Match(Location, Box<Self>, Box<[(Pattern, Self)]>),
     Cast(Location, Box<Self>, Type),
 
     Block(Location, Box<[Statement]>),
@@ -129,9 +220,14 @@ pub enum Expr {
         Option<Type>,
         Box<Self>,
     ),
-    Call(Location, Rc<str>, Box<[Self]>),
+    /// Callee and the arguments
+    Call(Location, Box<Self>, Box<[Self]>),
 
     If(Location, Box<Self>, Box<Self>, Box<Self>),
+    /// Binding, the optional and what to evaluate if it is and isn't null
+    IfLet(Location, Rc<str>, Box<Self>, Box<Self>, Box<Self>),
+    Unwrap(Location, Box<Self>),
+    Loop(Location, Option<Rc<str>>, Box<Self>),
     Eq(Location, Box<Self>, Box<Self>),
     Neq(Location, Box<Self>, Box<Self>),
     Lt(Location, Box<Self>, Box<Self>),
@@ -140,117 +236,115 @@ pub enum Expr {
     Gte(Location, Box<Self>, Box<Self>),
 }
 
-impl Display for Expr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Expr {
+    pub fn location(&self) -> &Location {
         match self {
-            Expr::Ident(_, i) => write!(f, "{i}"),
-            Expr::Const(_, v) => write!(f, "{v}"),
-            Expr::Add(_, a, b) => write!(f, "({a} + {b})"),
-            Expr::Sub(_, a, b) => write!(f, "({a} - {b})"),
-            Expr::Mul(_, a, b) => write!(f, "({a} * {b})"),
-            Expr::Div(_, a, b) => write!(f, "({a} / {b})"),
-            Expr::Concat(_, a, b) => write!(f, "({a} ++ {b})"),
-            Expr::Lambda(_, args, ret, body) => {
-                write!(f, "fn(")?;
-                let mut first = true;
-                for (arg, t) in args.iter() {
-                    if !first {
-                        write!(f, ", ")?;
-                    }
-                    first = false;
-                    write!(f, "{arg}")?;
-                    if let Some(t) = t {
-                        write!(f, ": {t}")?;
-                    }
-                }
-                write!(f, ")")?;
-                if let Some(ret) = ret {
-                    write!(f, " {ret}")?;
-                }
-                write!(f, " ({body})")
-            }
-            Expr::Call(_, f_name, args) => {
-                write!(f, "{f_name}(")?;
-                let mut first = true;
-                for arg in args.iter() {
-                    if !first {
-                        write!(f, ", ")?;
-                    }
-                    first = false;
-                    write!(f, "{arg}")?;
-                }
-                write!(f, ")")
-            }
-            Expr::If(_, cond, if_t, if_f) => write!(f, "(if {cond} then {if_t} else {if_f})"),
-            Expr::Eq(_, a, b) => write!(f, "({a} == {b})"),
-            Expr::Neq(_, a, b) => write!(f, "({a} != {b})"),
-            Expr::Lt(_, a, b) => write!(f, "({a} < {b})"),
-            Expr::Lte(_, a, b) => write!(f, "({a} <= {b})"),
-            Expr::Gt(_, a, b) => write!(f, "({a} > {b})"),
-            Expr::Gte(_, a, b) => write!(f, "({a} >= {b})"),
-            Expr::Not(_, a) => write!(f, "!{a}"),
-            Expr::Ref(_, a) => write!(f, "&{a}"),
-            Expr::Neg(_, a) => write!(f, "-{a}"),
-            Expr::Deref(_, a) => write!(f, "*{a}"),
-            Expr::Array(_, a) => f.debug_list().entries(&**a).finish(),
-            Expr::StructConstructor(_, strct) => {
-                write!(f, "{{ ")?;
-                for (name, val) in strct.iter() {
-                    if let Some(name) = name {
-                        write!(f, "{name}: ")?;
-                    }
-                    write!(f, "{val}, ")?;
-                }
-                write!(f, "}}")
-            }
-            Expr::Cast(_, val, t) => write!(f, "({val} as {t}"),
-            Expr::Block(_, stmnts) => {
-                writeln!(f, "{{")?;
-                let mut first = true;
-                for s in stmnts.iter() {
-                    if !first {
-                        writeln!(f, ";")?;
-                    }
-                    first = false;
-                    write!(f, "    {s}")?;
-                }
-                write!(f, "\n}}")
-            }
+            Expr::Ident(loc, _)
+            | Expr::Const(loc, _)
+            | Expr::Add(loc, _, _)
+            | Expr::Sub(loc, _, _)
+            | Expr::Mul(loc, _, _)
+            | Expr::WrappingAdd(loc, _, _)
+            | Expr::WrappingSub(loc, _, _)
+            | Expr::WrappingMul(loc, _, _)
+            | Expr::SaturatingAdd(loc, _, _)
+            | Expr::SaturatingSub(loc, _, _)
+            | Expr::SaturatingMul(loc, _, _)
+            | Expr::BitAnd(loc, _, _)
+            | Expr::BitOr(loc, _, _)
+            | Expr::BitXor(loc, _, _)
+            | Expr::Shl(loc, _, _)
+            | Expr::Shr(loc, _, _)
+            | Expr::Div(loc, _, _)
+            | Expr::Rem(loc, _, _)
+            | Expr::And(loc, _, _)
+            | Expr::Or(loc, _, _)
+            | Expr::Concat(loc, _, _)
+            | Expr::Not(loc, _)
+            | Expr::BitNot(loc, _)
+            | Expr::Neg(loc, _)
+            | Expr::Ref(loc, _)
+            | Expr::Deref(loc, _)
+            | Expr::Array(loc, _)
+            | Expr::Index(loc, _, _)
+            | Expr::Slice(loc, _, _, _)
+            | Expr::StructConstructor(loc, _, _)
+            | Expr::FieldAccess(loc, _, _)
+            | Expr::VariantConstructor(loc, _, _, _)
+            | Expr::Match(loc, _, _)
+            | Expr::Cast(loc, _, _)
+            | Expr::Block(loc, _)
+            | Expr::Lambda(loc, _, _, _)
+            | Expr::Call(loc, _, _)
+            | Expr::If(loc, _, _, _)
+            | Expr::IfLet(loc, _, _, _, _)
+            | Expr::Unwrap(loc, _)
+            | Expr::Loop(loc, _, _)
+            | Expr::Eq(loc, _, _)
+            | Expr::Neq(loc, _, _)
+            | Expr::Lt(loc, _, _)
+            | Expr::Lte(loc, _, _)
+            | Expr::Gt(loc, _, _)
+            | Expr::Gte(loc, _, _) => loc,
         }
     }
 }
 
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::plain();
+        printer.expr(self);
+        f.write_str(&printer.finish())
+    }
+}
+
 impl Display for PlaceExpr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Ident(_, i) => write!(f, "{i}"),
-            Self::Deref(_, a) => write!(f, "*{a}"),
-            Self::Index(_, e, i) => write!(f, "{e}[{i}]"),
-            Self::FieldAccess(_, e, i) => write!(f, "{e}.{i}"),
-        }
+        let mut printer = Printer::plain();
+        printer.place(self);
+        f.write_str(&printer.finish())
     }
 }
 
-impl Display for Statement {
+impl Display for Pattern {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Statement::Express(_, e) => write!(f, "{e}"),
-            Statement::Let(_, n, t, e) => {
-                write!(f, "let {n}")?;
-                if let Some(t) = t {
-                    write!(f, ": {t}")?;
-                }
-                write!(f, " = {e}")
-            }
-            Statement::Var(_, n, t, e) => {
-                write!(f, "var {n}")?;
-                if let Some(t) = t {
-                    write!(f, ": {t}")?;
+            Self::Wildcard(_) => write!(f, "_"),
+            Self::Variant(_, name, variant, bindings) => {
+                write!(f, "{name}::{variant}")?;
+                if !bindings.is_empty() {
+                    write!(f, "(")?;
+                    let mut first = true;
+                    for b in bindings.iter() {
+                        if !first {
+                            write!(f, ", ")?;
+                        }
+                        first = false;
+                        match b {
+                            Some(b) => write!(f, "{b}")?,
+                            None => write!(f, "_")?,
+                        }
+                    }
+                    write!(f, ")")?;
                 }
-                write!(f, " = {e}")
+                Ok(())
             }
-            Statement::Rebind(_, n, e) => write!(f, "{n} = {e}"),
-            Statement::Return(_, e) => write!(f, "ret {e}"),
         }
     }
 }
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::plain();
+        printer.statement(self);
+        f.write_str(&printer.finish())
+    }
+}
+
+impl Display for Iterable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::plain();
+        printer.iterable(self);
+        f.write_str(&printer.finish())
+    }
+}
@@ -0,0 +1,778 @@
+//! Printing the untyped AST back as source code, in one canonical format
+//!
+//! Expressions get as few parentheses as the precedence of their operators allows, and blocks,
+//! matches, structs and enums are spread over several lines with everything else kept on one.
+//!
+//! When formatting a file its comments are kept, on their own line before the declaration,
+//! statement or match arm they were before, or at the end of the line they trailed. Comments
+//! anywhere else, like between the arguments of a call, end up on their own line before the
+//! declaration, statement or match arm they were in.
+
+use std::{collections::VecDeque, path::Path, rc::Rc};
+
+use super::{
+    ast::{Decl, Expr, Iterable, Literal, PlaceExpr, Program, Statement},
+    location::Location,
+    parse_single, Include, ParsedFile, Result,
+};
+use crate::ttype::Type;
+
+const INDENT: &str = "    ";
+
+/// Formats the source of a single file, leaving its includes and imports as they are
+///
+/// Fails if the file does not parse, as there would be nothing to format.
+pub fn format_source(path: &Path, source: String) -> Result<String> {
+    let comments = comments(&source);
+    let ParsedFile {
+        program,
+        includes,
+        public,
+    } = parse_single(path, source)?;
+
+    let mut printer = Printer {
+        comments,
+        keep_blank_lines: true,
+        ..Printer::plain()
+    };
+    printer.program(&program, &includes, &public);
+    Ok(printer.finish())
+}
+
+#[derive(Debug)]
+struct Comment {
+    line: u16,
+    col: u16,
+    /// The comment, starting with the slashes
+    text: Box<str>,
+    /// Whether there is only whitespace before it on its line
+    own_line: bool,
+}
+
+/// Finds the comments in `source`, skipping over any strings
+fn comments(source: &str) -> VecDeque<Comment> {
+    let mut comments = VecDeque::new();
+    let (mut line, mut col) = (1, 1);
+    let mut own_line = true;
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' => {
+                line += 1;
+                col = 1;
+                own_line = true;
+                continue;
+            }
+            '"' => {
+                // strings end at the end of the line if they are not closed
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c != '\n') {
+                    col += 1;
+                    match c {
+                        '\\' if chars.next_if(|&(_, c)| c != '\n').is_some() => col += 1,
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+                own_line = false;
+            }
+            '/' if matches!(chars.peek(), Some((_, '/'))) => {
+                let end = source[i..].find('\n').map_or(source.len(), |n| i + n);
+                comments.push_back(Comment {
+                    line,
+                    col,
+                    text: source[i..end].trim_end().into(),
+                    own_line,
+                });
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                continue;
+            }
+            c if c.is_whitespace() => (),
+            _ => own_line = false,
+        }
+        col += 1;
+    }
+    comments
+}
+
+// How tightly operators bind, the same as in `EXPR_PARSER`
+const LOWEST: u8 = 0;
+const PREFIX: u8 = 9;
+const CAST: u8 = 10;
+const POSTFIX: u8 = 11;
+const ATOM: u8 = 12;
+
+fn precedence(e: &Expr) -> u8 {
+    match e {
+        Expr::Or(..) => 1,
+        Expr::And(..) => 2,
+        Expr::Eq(..)
+        | Expr::Neq(..)
+        | Expr::Lt(..)
+        | Expr::Lte(..)
+        | Expr::Gt(..)
+        | Expr::Gte(..) => 3,
+        Expr::BitOr(..) | Expr::BitXor(..) | Expr::BitAnd(..) => 4,
+        Expr::Concat(..) => 5,
+        Expr::Shl(..) | Expr::Shr(..) => 6,
+        Expr::Add(..)
+        | Expr::Sub(..)
+        | Expr::WrappingAdd(..)
+        | Expr::WrappingSub(..)
+        | Expr::SaturatingAdd(..)
+        | Expr::SaturatingSub(..) => 7,
+        Expr::Mul(..)
+        | Expr::Div(..)
+        | Expr::Rem(..)
+        | Expr::WrappingMul(..)
+        | Expr::SaturatingMul(..) => 8,
+        Expr::Not(..) | Expr::BitNot(..) | Expr::Neg(..) | Expr::Ref(..) | Expr::Deref(..) => {
+            PREFIX
+        }
+        Expr::Cast(..) => CAST,
+        Expr::FieldAccess(..)
+        | Expr::Unwrap(..)
+        | Expr::Index(..)
+        | Expr::Slice(..)
+        | Expr::Call(..) => POSTFIX,
+        _ => ATOM,
+    }
+}
+
+/// The operands and operator of a binary operation
+fn binary(e: &Expr) -> Option<(&Expr, &'static str, &Expr)> {
+    let (a, op, b) = match e {
+        Expr::Add(_, a, b) => (a, "+", b),
+        Expr::Sub(_, a, b) => (a, "-", b),
+        Expr::Mul(_, a, b) => (a, "*", b),
+        Expr::WrappingAdd(_, a, b) => (a, "+%", b),
+        Expr::WrappingSub(_, a, b) => (a, "-%", b),
+        Expr::WrappingMul(_, a, b) => (a, "*%", b),
+        Expr::SaturatingAdd(_, a, b) => (a, "+|", b),
+        Expr::SaturatingSub(_, a, b) => (a, "-|", b),
+        Expr::SaturatingMul(_, a, b) => (a, "*|", b),
+        Expr::BitAnd(_, a, b) => (a, "&", b),
+        Expr::BitOr(_, a, b) => (a, "|", b),
+        Expr::BitXor(_, a, b) => (a, "^", b),
+        Expr::Shl(_, a, b) => (a, "<<", b),
+        Expr::Shr(_, a, b) => (a, ">>", b),
+        Expr::Div(_, a, b) => (a, "/", b),
+        Expr::Rem(_, a, b) => (a, "%", b),
+        Expr::And(_, a, b) => (a, "&&", b),
+        Expr::Or(_, a, b) => (a, "||", b),
+        Expr::Concat(_, a, b) => (a, "++", b),
+        Expr::Eq(_, a, b) => (a, "==", b),
+        Expr::Neq(_, a, b) => (a, "!=", b),
+        Expr::Lt(_, a, b) => (a, "<", b),
+        Expr::Lte(_, a, b) => (a, "<=", b),
+        Expr::Gt(_, a, b) => (a, ">", b),
+        Expr::Gte(_, a, b) => (a, ">=", b),
+        _ => return None,
+    };
+    Some((a, op, b))
+}
+
+/// Whether the expression ends in another expression that would take in anything after it
+///
+/// The operand of a prefix operator is a whole expression, so `-a + b` is `-(a + b)`.
+fn opens_right(e: &Expr) -> bool {
+    matches!(
+        e,
+        Expr::If(..)
+            | Expr::IfLet(..)
+            | Expr::Lambda(..)
+            | Expr::Not(..)
+            | Expr::BitNot(..)
+            | Expr::Neg(..)
+            | Expr::Ref(..)
+            | Expr::Deref(..)
+    )
+}
+
+/// The statement empty statements are parsed as, like the one after a trailing semicolon
+fn is_empty(s: &Statement) -> bool {
+    matches!(s, Statement::Express(_, Expr::Const(_, Literal::Unit)))
+}
+
+/// Prints the untyped AST as source code
+pub(crate) struct Printer {
+    out: String,
+    indent: usize,
+    /// Comments not yet printed, in order
+    comments: VecDeque<Comment>,
+    /// Whether blank lines between items are kept, going by the lines they are on
+    keep_blank_lines: bool,
+    /// Source line the last item at the current level ended on, if there was one
+    last_line: Option<u16>,
+    /// The furthest source line printed so far
+    line: u16,
+    /// Where in the output each item being printed starts, with its indent, innermost last
+    items: Vec<(usize, usize)>,
+}
+
+impl Printer {
+    /// A printer for the AST alone, without any comments
+    pub(crate) fn plain() -> Self {
+        Printer {
+            out: String::new(),
+            indent: 0,
+            comments: VecDeque::new(),
+            keep_blank_lines: false,
+            last_line: None,
+            line: 0,
+            items: Vec::new(),
+        }
+    }
+    pub(crate) fn finish(self) -> String {
+        self.out
+    }
+
+    fn write(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        if self.out.ends_with('\n') {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+        }
+        self.out.push_str(s);
+    }
+    fn newline(&mut self) {
+        self.out.push('\n');
+    }
+    /// Notes that `line` in the source has been reached
+    ///
+    /// Only the ends of locations ending in a token can be trusted, as the rest take in any
+    /// whitespace and comments after them.
+    fn see(&mut self, line: u16) {
+        self.line = self.line.max(line);
+    }
+
+    fn blank_line_before(&mut self, line: u16) {
+        if self.keep_blank_lines && self.last_line.is_some_and(|l| line > l + 1) {
+            self.newline();
+        }
+    }
+    /// Prints the comments before `(line, col)` on their own lines
+    fn comments_before(&mut self, line: u16, col: u16) {
+        while let Some(c) = self.comments.front() {
+            if (c.line, c.col) >= (line, col) {
+                break;
+            }
+            let c = self.comments.pop_front().unwrap();
+            self.blank_line_before(c.line);
+            self.write(&c.text);
+            self.newline();
+            self.last_line = Some(c.line);
+        }
+    }
+    /// Moves the comments before `(line, col)` to their own lines before the item being printed,
+    /// as what has been printed of it since is all on one line
+    fn comments_inside(&mut self, line: u16, col: u16) {
+        let Some(&(start, indent)) = self.items.last() else {
+            return;
+        };
+        let mut inside = String::new();
+        while let Some(c) = self.comments.front() {
+            if (c.line, c.col) >= (line, col) {
+                break;
+            }
+            let c = self.comments.pop_front().unwrap();
+            for _ in 0..indent {
+                inside.push_str(INDENT);
+            }
+            inside.push_str(&c.text);
+            inside.push('\n');
+        }
+        self.out.insert_str(start, &inside);
+    }
+    fn item_start(&mut self, loc: &Location) {
+        self.comments_before(loc.line_start, loc.col_start);
+        self.blank_line_before(loc.line_start);
+        self.see(loc.line_start);
+        self.items.push((self.out.len(), self.indent));
+    }
+    /// Ends the line of the item just printed, with the comment trailing it if there is one
+    fn item_end(&mut self) {
+        self.comments_inside(self.line, 0);
+        self.items.pop();
+        while let Some(c) = self.comments.front() {
+            if c.line != self.line || c.own_line {
+                break;
+            }
+            let c = self.comments.pop_front().unwrap();
+            self.write(" ");
+            self.write(&c.text);
+        }
+        self.newline();
+        self.last_line = Some(self.line);
+    }
+    /// Starts the items inside a block, struct or the like
+    fn open(&mut self) -> Option<u16> {
+        self.newline();
+        self.indent += 1;
+        self.last_line.take()
+    }
+    /// Ends the items inside something ending at `loc`, after the comments left inside it
+    fn close(&mut self, loc: &Location, last_line: Option<u16>) {
+        self.comments_before(loc.line_end, loc.col_end);
+        self.indent -= 1;
+        self.last_line = last_line;
+        self.see(loc.line_end);
+    }
+
+    /// Prints a program, with whether each declaration is `pub` if that is known
+    pub(crate) fn program(
+        &mut self,
+        Program(decls): &Program,
+        includes: &[Include],
+        public: &[bool],
+    ) {
+        let mut includes = includes.iter().peekable();
+        for (i, (name, decl)) in decls.iter().enumerate() {
+            let loc = decl.location();
+            while let Some(inc) = includes
+                .next_if(|i| (i.loc.line_start, i.loc.col_start) < (loc.line_start, loc.col_start))
+            {
+                self.include(inc);
+            }
+            self.item_start(loc);
+            if public.get(i) == Some(&true) {
+                self.write("pub ");
+            }
+            self.decl(name, decl);
+            self.see(loc.line_end);
+            self.item_end();
+        }
+        for inc in includes {
+            self.include(inc);
+        }
+        self.comments_before(u16::MAX, u16::MAX);
+    }
+    fn include(&mut self, inc: &Include) {
+        self.item_start(&inc.loc);
+        let path = Literal::String(inc.path.clone());
+        match &inc.alias {
+            Some(alias) => self.write(&format!("import {path} as {alias};")),
+            None => self.write(&format!("include({path});")),
+        }
+        self.see(inc.loc.line_end);
+        self.item_end();
+    }
+
+    fn params(&mut self, params: &[(Rc<str>, Type)]) {
+        let params: Vec<_> = params.iter().map(|(n, t)| format!("{n}: {t}")).collect();
+        self.write(&format!("({})", params.join(", ")));
+    }
+    fn decl(&mut self, name: &str, decl: &Decl) {
+        match decl {
+            Decl::Static(_, bind) | Decl::Const(_, bind) => {
+                let (t, e) = &**bind;
+                let keyword = if let Decl::Static(..) = decl {
+                    "static"
+                } else {
+                    "const"
+                };
+                self.write(&format!("{keyword} {name}: {t} = "));
+                self.expr(e);
+                self.write(";");
+            }
+            Decl::Fn(_, type_params, params, body) => {
+                let (ret, body) = &**body;
+                self.write(&format!("fn {name}"));
+                if !type_params.is_empty() {
+                    let type_params: Vec<_> = type_params
+                        .iter()
+                        .map(|(n, kind)| match kind {
+                            Some(kind) => format!("{n}: {kind}"),
+                            None => n.to_string(),
+                        })
+                        .collect();
+                    self.write(&format!("<{}>", type_params.join(", ")));
+                }
+                self.params(params);
+                self.write(&format!(" {ret} "));
+                self.body(body);
+            }
+            Decl::ExternStatic(_, t) => self.write(&format!("extern {name}: {t};")),
+            Decl::ExternFn(_, params, ret) => {
+                self.write(&format!("extern fn {name}"));
+                self.params(params);
+                self.write(&format!(" {ret};"));
+            }
+            Decl::Alias(_, t) => self.write(&format!("type {name} = {t};")),
+            Decl::Newtype(_, t) => self.write(&format!("newtype {name} = {t};")),
+            Decl::Struct(loc, fields) => {
+                let fields = fields.iter().map(|(n, t)| format!("{n}: {t},"));
+                self.members(&format!("struct {name}"), loc, fields);
+            }
+            Decl::Enum(loc, variants) => {
+                let variants = variants.iter().map(|(n, payload)| {
+                    if payload.is_empty() {
+                        format!("{n},")
+                    } else {
+                        let payload: Vec<_> = payload.iter().map(Type::to_string).collect();
+                        format!("{n}({}),", payload.join(", "))
+                    }
+                });
+                self.members(&format!("enum {name}"), loc, variants);
+            }
+        }
+    }
+    /// Prints the fields of a struct or the variants of an enum, one on each line
+    ///
+    /// Where the members are is not known, so comments in between them go before them all.
+    fn members(
+        &mut self,
+        head: &str,
+        loc: &Location,
+        members: impl ExactSizeIterator<Item = String>,
+    ) {
+        self.write(head);
+        self.write(" {");
+        let has_comments = self
+            .comments
+            .front()
+            .is_some_and(|c| (c.line, c.col) < (loc.line_end, loc.col_end));
+        if members.len() == 0 && !has_comments {
+            self.write("}");
+            return;
+        }
+        let last_line = self.open();
+        self.comments_before(loc.line_end, loc.col_end);
+        for m in members {
+            self.write(&m);
+            self.newline();
+        }
+        self.close(loc, last_line);
+        self.write("}");
+    }
+
+    /// Prints the body of a function or loop, which is always spread over several lines
+    fn body(&mut self, e: &Expr) {
+        match e {
+            Expr::Block(loc, stmnts) => self.block(loc, stmnts, false),
+            e => self.expr(e),
+        }
+    }
+    /// Prints a block, on one line if `inline` is set and it is a single short statement
+    fn block(&mut self, loc: &Location, stmnts: &[Statement], inline: bool) {
+        // like those between the parameters of a function before its body
+        self.comments_inside(loc.line_start, loc.col_start);
+        let has_comments = self
+            .comments
+            .front()
+            .is_some_and(|c| (c.line, c.col) < (loc.line_end, loc.col_end));
+        let mut non_empty = stmnts.iter().enumerate().filter(|(_, s)| !is_empty(s));
+        match (non_empty.next(), non_empty.next()) {
+            _ if has_comments => (),
+            (None, _) => {
+                self.write("{}");
+                return;
+            }
+            (Some((i, s)), None)
+                if inline && !matches!(s, Statement::While(..) | Statement::For(..)) =>
+            {
+                let mut printer = Printer::plain();
+                printer.statement(s);
+                let s = printer.finish();
+                if !s.contains('\n') {
+                    let semicolon = if i + 1 != stmnts.len() { ";" } else { "" };
+                    self.write(&format!("{{ {s}{semicolon} }}"));
+                    self.see(loc.line_end);
+                    return;
+                }
+            }
+            _ => (),
+        }
+
+        self.write("{");
+        let last_line = self.open();
+        for (i, s) in stmnts.iter().enumerate() {
+            if is_empty(s) {
+                continue;
+            }
+            self.item_start(s.location());
+            self.statement(s);
+            // loops end in a block, so they do not need one, and the last statement is the value
+            // of the block if it has none
+            let is_loop = matches!(s, Statement::While(..) | Statement::For(..));
+            if i + 1 != stmnts.len() && !is_loop {
+                self.write(";");
+            }
+            self.item_end();
+        }
+        self.close(loc, last_line);
+        self.write("}");
+    }
+
+    pub(crate) fn statement(&mut self, s: &Statement) {
+        match s {
+            Statement::Express(_, e) => self.expr(e),
+            Statement::Let(_, n, t, e) | Statement::Var(_, n, t, e) => {
+                let keyword = if let Statement::Let(..) = s {
+                    "let"
+                } else {
+                    "var"
+                };
+                self.write(&format!("{keyword} {n}"));
+                if let Some((_, t)) = t {
+                    self.write(&format!(": {t}"));
+                }
+                self.write(" = ");
+                self.expr(e);
+            }
+            Statement::Rebind(_, place, e) => {
+                self.place(place);
+                self.write(" = ");
+                self.expr(e);
+            }
+            Statement::While(_, label, cond, body) => {
+                self.write("while ");
+                if let Some(l) = label {
+                    self.write(&format!("'{l} "));
+                }
+                self.expr(cond);
+                self.write(" ");
+                self.body(body);
+            }
+            Statement::For(_, label, var, iter, body) => {
+                self.write("for ");
+                if let Some(l) = label {
+                    self.write(&format!("'{l} "));
+                }
+                self.write(&format!("{var} in "));
+                self.iterable(iter);
+                self.write(" ");
+                self.body(body);
+            }
+            Statement::Break(_, label, e) => {
+                self.write("break");
+                if let Some(l) = label {
+                    self.write(&format!(" '{l}"));
+                }
+                if let Some(e) = e {
+                    self.write(" ");
+                    self.expr(e);
+                }
+            }
+            Statement::Continue(_, label) => {
+                self.write("continue");
+                if let Some(l) = label {
+                    self.write(&format!(" '{l}"));
+                }
+            }
+            Statement::Return(_, e) => {
+                self.write("ret ");
+                self.expr(e);
+            }
+        }
+    }
+    pub(crate) fn iterable(&mut self, iter: &Iterable) {
+        match iter {
+            Iterable::Range(start, end) => {
+                self.expr(start);
+                self.write("..");
+                self.expr(end);
+            }
+            Iterable::Collection(e) => self.expr(e),
+        }
+    }
+    pub(crate) fn place(&mut self, place: &PlaceExpr) {
+        match place {
+            PlaceExpr::Ident(_, n) => self.write(n),
+            PlaceExpr::Deref(_, e) => {
+                self.write("*");
+                self.operand(e, PREFIX, true);
+            }
+            PlaceExpr::Index(_, e, i) => {
+                self.operand(e, POSTFIX, false);
+                self.write("[");
+                self.expr(i);
+                self.write("]");
+            }
+            PlaceExpr::FieldAccess(_, e, field) => {
+                self.operand(e, POSTFIX, false);
+                self.write(&format!(".{field}"));
+            }
+        }
+    }
+
+    pub(crate) fn expr(&mut self, e: &Expr) {
+        self.operand(e, LOWEST, true);
+    }
+    /// Prints `e` where it has to bind at least as tightly as `min`, and in parentheses if it
+    /// doesn't
+    ///
+    /// Unless `last` is set, something follows it that it must not take in.
+    fn operand(&mut self, e: &Expr, min: u8, last: bool) {
+        let is_number = matches!(e, Expr::Const(_, Literal::Integer(_) | Literal::Float(_)));
+        self.see(e.location().line_start);
+        let parens = precedence(e) < min
+            || (!last && opens_right(e))
+            // the dot would be read as a decimal point
+            || (is_number && min >= POSTFIX);
+        if parens {
+            self.write("(");
+            self.expr_inner(e, true);
+            self.write(")");
+        } else {
+            self.expr_inner(e, last);
+        }
+    }
+    fn list(&mut self, es: &[Expr]) {
+        for (i, e) in es.iter().enumerate() {
+            if i != 0 {
+                self.write(", ");
+            }
+            self.expr(e);
+        }
+    }
+    fn expr_inner(&mut self, e: &Expr, last: bool) {
+        if let Some((a, op, b)) = binary(e) {
+            // all binary operators are left associative
+            let p = precedence(e);
+            self.operand(a, p, false);
+            self.write(&format!(" {op} "));
+            self.operand(b, p + 1, last);
+            return;
+        }
+
+        match e {
+            Expr::Ident(_, n) => self.write(n),
+            Expr::Const(_, lit) => self.write(&lit.to_string()),
+            Expr::Not(_, a)
+            | Expr::BitNot(_, a)
+            | Expr::Neg(_, a)
+            | Expr::Ref(_, a)
+            | Expr::Deref(_, a) => {
+                self.write(match e {
+                    Expr::Not(..) => "!",
+                    Expr::BitNot(..) => "~",
+                    Expr::Neg(..) => "-",
+                    Expr::Ref(..) => "&",
+                    _ => "*",
+                });
+                self.operand(a, PREFIX, last);
+            }
+            Expr::Cast(_, val, t) => {
+                self.operand(val, CAST, false);
+                self.write(&format!(" as {t}"));
+            }
+            Expr::FieldAccess(_, a, field) => {
+                self.operand(a, POSTFIX, false);
+                self.write(&format!(".{field}"));
+            }
+            Expr::Unwrap(_, a) => {
+                self.operand(a, POSTFIX, false);
+                self.write(".?");
+            }
+            Expr::Index(_, a, i) => {
+                self.operand(a, POSTFIX, false);
+                self.write("[");
+                self.expr(i);
+                self.write("]");
+            }
+            Expr::Slice(_, a, start, end) => {
+                self.operand(a, POSTFIX, false);
+                self.write("[");
+                self.expr(start);
+                self.write("..");
+                self.expr(end);
+                self.write("]");
+            }
+            Expr::Array(_, es) => {
+                self.write("[");
+                self.list(es);
+                self.write("]");
+            }
+            Expr::Call(_, callee, args) => {
+                self.operand(callee, POSTFIX, false);
+                self.write("(");
+                self.list(args);
+                self.write(")");
+            }
+            Expr::StructConstructor(_, name, fields) => {
+                self.write(&format!("{name} {{ "));
+                for (i, (field, e)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        self.write(", ");
+                    }
+                    if let Some(field) = field {
+                        self.write(&format!("{field}: "));
+                    }
+                    self.expr(e);
+                }
+                self.write(" }");
+            }
+            Expr::VariantConstructor(_, name, variant, payload) => {
+                self.write(&format!("{name}::{variant}"));
+                if !payload.is_empty() {
+                    self.write("(");
+                    self.list(payload);
+                    self.write(")");
+                }
+            }
+            Expr::Block(loc, stmnts) => self.block(loc, stmnts, true),
+            Expr::Loop(_, label, body) => {
+                self.write("loop ");
+                if let Some(l) = label {
+                    self.write(&format!("'{l} "));
+                }
+                self.body(body);
+            }
+            Expr::Match(loc, e, arms) => {
+                self.write("match ");
+                self.expr(e);
+                self.write(" {");
+                let last_line = self.open();
+                for (pattern, arm) in arms.iter() {
+                    self.item_start(pattern.location());
+                    self.write(&format!("{pattern} => "));
+                    self.expr(arm);
+                    self.write(",");
+                    self.item_end();
+                }
+                self.close(loc, last_line);
+                self.write("}");
+            }
+            Expr::If(_, cond, then, otherwise) => {
+                self.write("if ");
+                self.expr(cond);
+                self.write(": ");
+                self.expr(then);
+                self.write(" else ");
+                self.operand(otherwise, LOWEST, last);
+            }
+            Expr::IfLet(_, n, opt, then, otherwise) => {
+                self.write(&format!("if let {n} = "));
+                self.expr(opt);
+                self.write(": ");
+                self.expr(then);
+                self.write(" else ");
+                self.operand(otherwise, LOWEST, last);
+            }
+            Expr::Lambda(_, params, ret, body) => {
+                let params: Vec<_> = params
+                    .iter()
+                    .map(|(n, t)| match t {
+                        Some(t) => format!("{n}: {t}"),
+                        None => n.to_string(),
+                    })
+                    .collect();
+                self.write(&format!("fn({})", params.join(", ")));
+                match ret {
+                    // these would be read as the start of the body
+                    Some(ret @ (Type::Named(_) | Type::Function(_, _))) => {
+                        self.write(&format!(" ({ret})"))
+                    }
+                    Some(ret) => self.write(&format!(" {ret}")),
+                    None => (),
+                }
+                self.write(" ");
+                self.operand(body, LOWEST, last);
+            }
+            _ => unreachable!("binary operators are printed above"),
+        }
+    }
+}
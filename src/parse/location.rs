@@ -49,7 +49,7 @@ impl Display for Location {
             self.line_start,
             self.col_start,
             self.line_end,
-            self.col_start
+            self.col_end
         )
     }
 }
@@ -0,0 +1,693 @@
+//! Loading the modules a program is made of, and resolving the names used across them
+//!
+//! Every imported file is a module of its own, loaded once however many files import it. Its
+//! declarations get the name of the module in front of theirs, as in `geometry::area`, so they
+//! cannot clash with those of other modules, while the declarations of the root file keep their
+//! names. Included files are part of the module including them, and each is included only once.
+//!
+//! Files are named relative to the file naming them, or else to one of the include paths.
+//! Libraries are named in angle brackets, as in `<std>`, and are looked for only in the include
+//! paths and then among the libraries bundled with the compiler.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io, mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use super::{
+    ast::{Decl, Expr, Iterable, Pattern, PlaceExpr, Program, Statement},
+    location::Location,
+    parse_partial,
+    source::SourceProvider,
+    ParseError, ParsedFile, Result,
+};
+use crate::ttype::Type;
+
+/// Libraries bundled with the compiler, by name
+const BUNDLED: &[(&str, &str)] = &[("std", include_str!("../../lib/std.ðs"))];
+
+/// The source of the bundled library `path` names, as in `<std>`
+pub fn bundled_source(path: &Path) -> Option<&'static str> {
+    let name = library_name(path.to_str()?)?;
+    BUNDLED
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, source)| *source)
+}
+
+/// The name of the library `target` names, unless it names a file
+fn library_name(target: &str) -> Option<&str> {
+    target.strip_prefix('<')?.strip_suffix('>')
+}
+
+/// Loads the module in `path`, with `source` as its contents, and every module it imports
+pub fn load(
+    path: &Path,
+    source: String,
+    include_paths: &[PathBuf],
+    sources: &dyn SourceProvider,
+) -> Result<Program> {
+    load_with_names(path, source, include_paths, sources).0
+}
+
+/// Like `load`, but also giving the full name of what each name usable in the root module refers
+/// to, the names of what it imports qualified by their module included
+pub fn load_with_names(
+    path: &Path,
+    source: String,
+    include_paths: &[PathBuf],
+    sources: &dyn SourceProvider,
+) -> (Result<Program>, HashMap<Rc<str>, Rc<str>>) {
+    let mut loader = Loader {
+        include_paths,
+        sources,
+        files: HashMap::new(),
+        modules: HashMap::new(),
+        loading: Vec::new(),
+        names: HashSet::new(),
+        root_names: HashMap::new(),
+        decls: Vec::new(),
+        errors: Vec::new(),
+    };
+    // the file might not be on disk at all
+    let canonical = sources
+        .canonicalize(path)
+        .unwrap_or_else(|_| path.to_owned());
+    let (file, errors) = parse_partial(path, source);
+    loader.errors.extend(errors);
+    loader.files.insert(canonical.clone(), Rc::new(file));
+
+    loader.load_module(canonical, path.into(), None, true);
+    let program = if loader.errors.is_empty() {
+        Ok(Program(loader.decls))
+    } else {
+        Err(loader.errors)
+    };
+    (program, loader.root_names)
+}
+
+/// What other modules can use of a module
+#[derive(Debug)]
+struct Module {
+    /// Full names of the declarations marked `pub`
+    exports: HashMap<Rc<str>, Rc<str>>,
+}
+
+struct Loader<'a> {
+    /// Directories searched for libraries and files not found relative to the file naming them
+    include_paths: &'a [PathBuf],
+    /// Where every file but the root is read from
+    sources: &'a dyn SourceProvider,
+    /// Every file parsed so far, by canonical path
+    files: HashMap<PathBuf, Rc<ParsedFile>>,
+    /// Every module loaded so far, by the canonical path of its file
+    modules: HashMap<PathBuf, Rc<Module>>,
+    /// Files of the modules being loaded, both canonical and as they were named
+    loading: Vec<(PathBuf, Rc<Path>)>,
+    /// Names given to modules so far
+    names: HashSet<Rc<str>>,
+    /// Full names of what the names usable in the root module refer to
+    root_names: HashMap<Rc<str>, Rc<str>>,
+    decls: Vec<(Rc<str>, Decl)>,
+    errors: Vec<ParseError>,
+}
+
+/// The path `target` names when written in the file `file`
+fn relative(file: &Path, target: &str) -> Rc<Path> {
+    file.parent().unwrap_or(Path::new("")).join(target).into()
+}
+
+/// Why the file an include or import names could not be found
+enum NotFound<'a> {
+    /// A library, named like `<std>`, with no file in the include paths nor bundled
+    Library(&'a str),
+    /// A file, with the path it was named by and what went wrong reading it from there
+    File(Rc<Path>, io::Error),
+}
+
+/// Finds the file `target` names when written in the file `file`, giving its canonical path and
+/// the path to refer to it by
+///
+/// Bundled libraries are found as the name they are included by, as in `<std>`.
+fn find<'a>(
+    file: &Path,
+    target: &'a str,
+    include_paths: &[PathBuf],
+    sources: &dyn SourceProvider,
+) -> std::result::Result<(PathBuf, Rc<Path>), NotFound<'a>> {
+    // the first file named `target` in the include paths
+    let search = |target: &str| {
+        include_paths.iter().find_map(|dir| {
+            let path = dir.join(target);
+            let canonical = sources.canonicalize(&path).ok()?;
+            Some((canonical, path.into()))
+        })
+    };
+    if let Some(name) = library_name(target) {
+        return match search(&format!("{name}.ðs")) {
+            Some(found) => Ok(found),
+            None if bundled_source(Path::new(target)).is_some() => {
+                Ok((target.into(), Path::new(target).into()))
+            }
+            None => Err(NotFound::Library(name)),
+        };
+    }
+    let path = relative(file, target);
+    match sources.canonicalize(&path) {
+        Ok(canonical) => Ok((canonical, path)),
+        Err(e) => search(target).ok_or(NotFound::File(path, e)),
+    }
+}
+
+/// Finds the file `target` names when written in the file `file` the same way as when loading
+/// modules, giving its canonical path, or the name of the library if it is a bundled one
+pub fn locate(
+    file: &Path,
+    target: &str,
+    include_paths: &[PathBuf],
+    sources: &dyn SourceProvider,
+) -> Option<PathBuf> {
+    find(file, target, include_paths, sources)
+        .ok()
+        .map(|(canonical, _)| canonical)
+}
+
+impl Loader<'_> {
+    /// Finds the file `target` names when written in the file `file`, giving its canonical path
+    /// and the path to refer to it by
+    fn locate(
+        &mut self,
+        file: &Path,
+        target: &str,
+        from: &Location,
+    ) -> Option<(PathBuf, Rc<Path>)> {
+        match find(file, target, self.include_paths, self.sources) {
+            Ok(found) => Some(found),
+            Err(NotFound::Library(name)) => {
+                let e = ParseError::LibraryNotFound(from.clone(), name.into());
+                self.errors.push(e);
+                None
+            }
+            Err(NotFound::File(path, e)) => {
+                let e = ParseError::Io(Some(from.clone()), path, e);
+                self.errors.push(e);
+                None
+            }
+        }
+    }
+    fn parse(&mut self, canonical: PathBuf, path: &Rc<Path>, from: &Location) -> Rc<ParsedFile> {
+        if let Some(file) = self.files.get(&canonical) {
+            return file.clone();
+        }
+        let source = match bundled_source(&canonical) {
+            Some(source) => Ok(source.to_owned()),
+            None => self.sources.read(&canonical),
+        };
+        let file = match source {
+            Ok(source) => {
+                let (file, errors) = parse_partial(path, source);
+                self.errors.extend(errors);
+                file
+            }
+            Err(e) => {
+                let e = ParseError::Io(Some(from.clone()), path.clone(), e);
+                self.errors.push(e);
+                ParsedFile::default()
+            }
+        };
+        let file = Rc::new(file);
+        self.files.insert(canonical, file.clone());
+        file
+    }
+    /// A name for the module in `path` that no other module has
+    fn module_name(&mut self, path: &Path) -> Rc<str> {
+        let stem = path
+            .file_stem()
+            .map_or("module".into(), |s| s.to_string_lossy());
+        // libraries are named like `<std>`
+        let stem = stem.trim_start_matches('<').trim_end_matches('>');
+        let mut name: Rc<str> = stem.into();
+        for n in 2.. {
+            if self.names.insert(name.clone()) {
+                break;
+            }
+            name = format!("{stem}{n}").into();
+        }
+        name
+    }
+
+    /// Loads a module and the modules it imports, unless it has been already
+    ///
+    /// Gives nothing if it could not be loaded, and the errors saying why have been reported.
+    fn load_module(
+        &mut self,
+        canonical: PathBuf,
+        path: Rc<Path>,
+        from: Option<&Location>,
+        root: bool,
+    ) -> Option<Rc<Module>> {
+        if let Some(module) = self.modules.get(&canonical) {
+            return Some(module.clone());
+        }
+        if let Some(i) = self.loading.iter().position(|(c, _)| *c == canonical) {
+            let cycle = self.loading[i..]
+                .iter()
+                .map(|(_, p)| p.clone())
+                .chain([path])
+                .collect();
+            // only imports can lead back to a module being loaded, never the root
+            self.errors
+                .push(ParseError::ImportCycle(from.unwrap().clone(), cycle));
+            return None;
+        }
+        self.loading.push((canonical.clone(), path.clone()));
+
+        let file = match from {
+            Some(from) => self.parse(canonical.clone(), &path, from),
+            None => self.files[&canonical].clone(),
+        };
+        let mut files = Vec::new();
+        self.include(
+            &path,
+            file,
+            &mut HashSet::from([canonical.clone()]),
+            &mut files,
+        );
+
+        let mut aliases = HashMap::new();
+        let mut imported_at = Vec::new();
+        for (file_path, file) in &files {
+            for import in &file.includes {
+                let Some(alias) = &import.alias else {
+                    continue;
+                };
+                let module = match self.locate(file_path, &import.path, &import.loc) {
+                    Some((c, target)) => self.load_module(c, target, Some(&import.loc), false),
+                    None => None,
+                };
+                if aliases.contains_key(alias) {
+                    let e = ParseError::DuplicateModule(import.loc.clone(), alias.clone());
+                    self.errors.push(e);
+                    continue;
+                }
+                aliases.insert(alias.clone(), module);
+                imported_at.push((alias.clone(), import.loc.clone()));
+            }
+        }
+
+        let prefix = (!root).then(|| self.module_name(&path));
+        let mut globals = HashMap::new();
+        let mut exports = HashMap::new();
+        for (_, file) in &files {
+            for ((name, _), &public) in file.program.0.iter().zip(&file.public) {
+                let full: Rc<str> = match &prefix {
+                    Some(prefix) => format!("{prefix}::{name}").into(),
+                    None => name.clone(),
+                };
+                if public {
+                    exports.insert(name.clone(), full.clone());
+                }
+                globals.insert(name.clone(), full);
+            }
+        }
+        for (alias, loc) in imported_at {
+            if globals.contains_key(&alias) {
+                self.errors.push(ParseError::DuplicateModule(loc, alias));
+            }
+        }
+        if root {
+            self.root_names = globals.clone();
+            for (alias, module) in &aliases {
+                for (item, full) in module.iter().flat_map(|m| &m.exports) {
+                    let name = format!("{alias}::{item}").into();
+                    self.root_names.insert(name, full.clone());
+                }
+            }
+        }
+
+        let mut resolver = Resolver {
+            globals: &globals,
+            aliases: &aliases,
+            locals: Vec::new(),
+            type_params: Vec::new(),
+            errors: &mut self.errors,
+        };
+        for (_, file) in files {
+            for (name, mut decl) in file.program.0.iter().cloned() {
+                resolver.decl(&mut decl);
+                self.decls.push((globals[&name].clone(), decl));
+            }
+        }
+
+        self.loading.pop();
+        let module = Rc::new(Module { exports });
+        self.modules.insert(canonical, module.clone());
+        Some(module)
+    }
+    /// Adds `file` and the files it includes to `files`, skipping those already in `included`
+    fn include(
+        &mut self,
+        path: &Rc<Path>,
+        file: Rc<ParsedFile>,
+        included: &mut HashSet<PathBuf>,
+        files: &mut Vec<(Rc<Path>, Rc<ParsedFile>)>,
+    ) {
+        for include in &file.includes {
+            if include.alias.is_some() {
+                continue;
+            }
+            let Some((canonical, target)) = self.locate(path, &include.path, &include.loc) else {
+                continue;
+            };
+            if included.insert(canonical.clone()) {
+                let inner = self.parse(canonical, &target, &include.loc);
+                self.include(&target, inner, included, files);
+            }
+        }
+        files.push((path.clone(), file));
+    }
+}
+
+/// Gives the names used in the declarations of a module the full names of what they refer to
+struct Resolver<'a> {
+    /// Full names of the declarations of the module
+    globals: &'a HashMap<Rc<str>, Rc<str>>,
+    /// Modules imported, by the name they are imported as, or none if they failed to load
+    aliases: &'a HashMap<Rc<str>, Option<Rc<Module>>>,
+    /// Local variables in scope, which hide globals of the same name
+    locals: Vec<Rc<str>>,
+    /// Type parameters of the function being resolved, which hide types of the same name
+    type_params: Vec<Rc<str>>,
+    errors: &'a mut Vec<ParseError>,
+}
+
+impl Resolver<'_> {
+    /// Resolves the name of a global, which may be qualified by the name of a module
+    fn global(&mut self, loc: &Location, name: &mut Rc<str>) {
+        let Some((module, item)) = name.split_once("::") else {
+            if let Some(full) = self.globals.get(name) {
+                *name = full.clone();
+            }
+            return;
+        };
+        let full = match self.aliases.get(module) {
+            Some(Some(m)) => match m.exports.get(item) {
+                Some(full) => full.clone(),
+                None => {
+                    let e = ParseError::NotPublic(loc.clone(), module.into(), item.into());
+                    self.errors.push(e);
+                    return;
+                }
+            },
+            // the module failed to load, which has been reported
+            Some(None) => return,
+            None => {
+                let e = ParseError::UnknownModule(loc.clone(), module.into());
+                self.errors.push(e);
+                return;
+            }
+        };
+        *name = full;
+    }
+    fn value(&mut self, loc: &Location, name: &mut Rc<str>) {
+        if !self.locals.contains(name) {
+            self.global(loc, name);
+        }
+    }
+
+    fn decl(&mut self, decl: &mut Decl) {
+        match decl {
+            Decl::Static(loc, bind) | Decl::Const(loc, bind) => {
+                self.ttype(loc, &mut bind.0);
+                self.expr(&mut bind.1);
+            }
+            Decl::Fn(loc, type_params, params, body) => {
+                self.type_params = type_params.iter().map(|(n, _)| n.clone()).collect();
+                for (_, t) in params.iter_mut() {
+                    self.ttype(loc, t);
+                }
+                self.ttype(loc, &mut body.0);
+                self.locals = params.iter().map(|(n, _)| n.clone()).collect();
+                self.expr(&mut body.1);
+                self.locals.clear();
+                self.type_params.clear();
+            }
+            Decl::ExternStatic(loc, t) | Decl::Alias(loc, t) | Decl::Newtype(loc, t) => {
+                self.ttype(loc, t)
+            }
+            Decl::ExternFn(loc, params, ret) => {
+                for (_, t) in params.iter_mut() {
+                    self.ttype(loc, t);
+                }
+                self.ttype(loc, ret);
+            }
+            Decl::Struct(loc, fields) => {
+                for (_, t) in fields.iter_mut() {
+                    self.ttype(loc, t);
+                }
+            }
+            Decl::Enum(loc, variants) => {
+                for t in variants
+                    .iter_mut()
+                    .flat_map(|(_, payload)| payload.iter_mut())
+                {
+                    self.ttype(loc, t);
+                }
+            }
+        }
+    }
+    /// Resolves the names in a type, reporting errors in it at `loc`
+    fn ttype(&mut self, loc: &Location, t: &mut Type) {
+        match t {
+            Type::Named(name) if self.type_params.contains(name) => (),
+            Type::Named(name) => self.global(loc, name),
+            Type::Function(args, ret) => {
+                for t in args.iter_mut() {
+                    self.ttype(loc, t);
+                }
+                self.ttype(loc, ret);
+            }
+            Type::Struct(fields) => {
+                for (_, t) in fields.iter_mut() {
+                    self.ttype(loc, t);
+                }
+            }
+            Type::Enum(variants) => {
+                for t in variants
+                    .iter_mut()
+                    .flat_map(|(_, payload)| payload.iter_mut())
+                {
+                    self.ttype(loc, t);
+                }
+            }
+            Type::Option(t)
+            | Type::Pointer(t)
+            | Type::ArrayPointer(t)
+            | Type::Slice(t)
+            | Type::Array(t, _) => self.ttype(loc, t),
+            _ => (),
+        }
+    }
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::Wildcard(_) => (),
+            Pattern::Variant(loc, name, _, bindings) => {
+                self.global(loc, name);
+                self.locals.extend(bindings.iter().flatten().cloned());
+            }
+        }
+    }
+    fn place(&mut self, place: &mut PlaceExpr) {
+        match place {
+            PlaceExpr::Ident(loc, name) => self.value(loc, name),
+            PlaceExpr::Deref(_, e) | PlaceExpr::FieldAccess(_, e, _) => self.expr(e),
+            PlaceExpr::Index(_, e, i) => {
+                self.expr(e);
+                self.expr(i);
+            }
+        }
+    }
+    fn statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::Express(_, e) | Statement::Return(_, e) => self.expr(e),
+            Statement::Let(_, name, annot, e) | Statement::Var(_, name, annot, e) => {
+                self.expr(e);
+                if let Some((loc, t)) = annot {
+                    self.ttype(loc, t);
+                }
+                self.locals.push(name.clone());
+            }
+            Statement::Rebind(_, place, e) => {
+                self.place(place);
+                self.expr(e);
+            }
+            Statement::While(_, _, cond, body) => {
+                self.expr(cond);
+                self.expr(body);
+            }
+            Statement::Break(_, _, e) => {
+                if let Some(e) = e {
+                    self.expr(e);
+                }
+            }
+            Statement::Continue(_, _) => (),
+            Statement::For(_, _, var, iterable, body) => {
+                match iterable {
+                    Iterable::Range(start, end) => {
+                        self.expr(start);
+                        self.expr(end);
+                    }
+                    Iterable::Collection(e) => self.expr(e),
+                }
+                let scope = self.locals.len();
+                self.locals.push(var.clone());
+                self.expr(body);
+                self.locals.truncate(scope);
+            }
+        }
+    }
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Ident(loc, name) => self.value(loc, name),
+            Expr::Const(_, _) => (),
+            Expr::Add(_, a, b)
+            | Expr::Sub(_, a, b)
+            | Expr::Mul(_, a, b)
+            | Expr::WrappingAdd(_, a, b)
+            | Expr::WrappingSub(_, a, b)
+            | Expr::WrappingMul(_, a, b)
+            | Expr::SaturatingAdd(_, a, b)
+            | Expr::SaturatingSub(_, a, b)
+            | Expr::SaturatingMul(_, a, b)
+            | Expr::BitAnd(_, a, b)
+            | Expr::BitOr(_, a, b)
+            | Expr::BitXor(_, a, b)
+            | Expr::Shl(_, a, b)
+            | Expr::Shr(_, a, b)
+            | Expr::Div(_, a, b)
+            | Expr::Rem(_, a, b)
+            | Expr::And(_, a, b)
+            | Expr::Or(_, a, b)
+            | Expr::Concat(_, a, b)
+            | Expr::Index(_, a, b)
+            | Expr::Eq(_, a, b)
+            | Expr::Neq(_, a, b)
+            | Expr::Lt(_, a, b)
+            | Expr::Lte(_, a, b)
+            | Expr::Gt(_, a, b)
+            | Expr::Gte(_, a, b) => {
+                self.expr(a);
+                self.expr(b);
+            }
+            Expr::Not(_, e)
+            | Expr::BitNot(_, e)
+            | Expr::Neg(_, e)
+            | Expr::Ref(_, e)
+            | Expr::Deref(_, e)
+            | Expr::FieldAccess(_, e, _)
+            | Expr::Unwrap(_, e)
+            | Expr::Loop(_, _, e) => self.expr(e),
+            Expr::Array(_, es) => {
+                for e in es.iter_mut() {
+                    self.expr(e);
+                }
+            }
+            Expr::Slice(_, e, start, end) => {
+                self.expr(e);
+                self.expr(start);
+                self.expr(end);
+            }
+            Expr::StructConstructor(loc, name, fields) => {
+                self.global(loc, name);
+                for (_, e) in fields.iter_mut() {
+                    self.expr(e);
+                }
+            }
+            Expr::VariantConstructor(loc, name, variant, payload) => {
+                for e in payload.iter_mut() {
+                    self.expr(e);
+                }
+                // `a::b` without parentheses is something declared in module `a`, if there is one
+                if self.aliases.contains_key(name) {
+                    let mut full = format!("{name}::{variant}").into();
+                    self.global(loc, &mut full);
+                    *expr = Expr::Ident(loc.clone(), full);
+                } else {
+                    self.global(loc, name);
+                }
+            }
+            Expr::Call(loc, callee, args) => {
+                for e in args.iter_mut() {
+                    self.expr(e);
+                }
+                let variant = match &**callee {
+                    Expr::Ident(_, name) => name
+                        .split_once("::")
+                        .filter(|(module, _)| !self.aliases.contains_key(*module))
+                        .map(|(enum_name, variant)| (enum_name.into(), variant.into())),
+                    _ => None,
+                };
+                match variant {
+                    // not a module, so this is constructing a variant of an enum
+                    Some((mut enum_name, variant)) => {
+                        self.global(loc, &mut enum_name);
+                        *expr = Expr::VariantConstructor(
+                            loc.clone(),
+                            enum_name,
+                            variant,
+                            mem::take(args),
+                        );
+                    }
+                    None => self.expr(callee),
+                }
+            }
+            Expr::Match(_, e, arms) => {
+                self.expr(e);
+                for (pattern, arm) in arms.iter_mut() {
+                    let scope = self.locals.len();
+                    self.pattern(pattern);
+                    self.expr(arm);
+                    self.locals.truncate(scope);
+                }
+            }
+            Expr::Cast(loc, e, t) => {
+                self.expr(e);
+                self.ttype(loc, t);
+            }
+            Expr::Block(_, statements) => {
+                let scope = self.locals.len();
+                for statement in statements.iter_mut() {
+                    self.statement(statement);
+                }
+                self.locals.truncate(scope);
+            }
+            Expr::Lambda(loc, params, ret, body) => {
+                for t in params.iter_mut().filter_map(|(_, t)| t.as_mut()) {
+                    self.ttype(loc, t);
+                }
+                if let Some(ret) = ret {
+                    self.ttype(loc, ret);
+                }
+                let scope = self.locals.len();
+                self.locals.extend(params.iter().map(|(n, _)| n.clone()));
+                self.expr(body);
+                self.locals.truncate(scope);
+            }
+            Expr::If(_, cond, e_true, e_false) => {
+                self.expr(cond);
+                self.expr(e_true);
+                self.expr(e_false);
+            }
+            Expr::IfLet(_, name, opt, e_some, e_null) => {
+                self.expr(opt);
+                let scope = self.locals.len();
+                self.locals.push(name.clone());
+                self.expr(e_some);
+                self.locals.truncate(scope);
+                self.expr(e_null);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Where source files are read from
+//!
+//! Parsing reads the root file and everything it includes and imports through a
+//! `SourceProvider`, so files can come from disk, from memory, or from memory in front of disk,
+//! like unsaved changes to files in an editor.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    io::{self, ErrorKind},
+    path::{Component, Path, PathBuf},
+};
+
+pub trait SourceProvider: Debug {
+    /// The contents of the file at `path`
+    fn read(&self, path: &Path) -> io::Result<String>;
+    /// The one path the file at `path` is known by, however it is named, failing if there is no
+    /// such file
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+/// Files on disk
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Disk;
+
+impl SourceProvider for Disk {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+/// Files kept in memory, by path
+#[derive(Debug, Clone, Default)]
+pub struct InMemory {
+    files: HashMap<PathBuf, String>,
+}
+
+impl InMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a file, replacing any already at `path`
+    pub fn insert(&mut self, path: impl AsRef<Path>, source: impl Into<String>) {
+        self.files.insert(normalise(path.as_ref()), source.into());
+    }
+    pub fn with_file(mut self, path: impl AsRef<Path>, source: impl Into<String>) -> Self {
+        self.insert(path, source);
+        self
+    }
+    /// Removes the file at `path`, giving its contents if there was one
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> Option<String> {
+        self.files.remove(&normalise(path.as_ref()))
+    }
+}
+
+impl SourceProvider for InMemory {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        self.files
+            .get(&normalise(path))
+            .cloned()
+            .ok_or_else(not_found)
+    }
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = normalise(path);
+        if self.files.contains_key(&path) {
+            Ok(path)
+        } else {
+            Err(not_found())
+        }
+    }
+}
+
+/// Files from `top`, or else from `bottom` if `top` has no such file
+#[derive(Debug, Clone, Default)]
+pub struct Overlay<T, B> {
+    pub top: T,
+    pub bottom: B,
+}
+
+impl<T: SourceProvider, B: SourceProvider> Overlay<T, B> {
+    pub fn new(top: T, bottom: B) -> Self {
+        Overlay { top, bottom }
+    }
+}
+
+impl<T: SourceProvider, B: SourceProvider> SourceProvider for Overlay<T, B> {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        match self.top.read(path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => self.bottom.read(path),
+            read => read,
+        }
+    }
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.top.canonicalize(path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => self.bottom.canonicalize(path),
+            canonical => canonical,
+        }
+    }
+}
+
+/// `path` with the `.` and `..` in it resolved, without looking at any files
+fn normalise(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normal.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(ErrorKind::NotFound, "no such file in memory")
+}
@@ -16,6 +16,10 @@ pub trait Ins<R, L> {
     fn is_move_from(&self, from: &R) -> bool;
     fn new_write_to_stack_var(offset: usize, src: R) -> Self;
     fn new_read_from_stack_var(dest: R, offset: usize) -> Self;
+    /// Instructions at the start of a function making room for `len` stack variables
+    fn new_stack_frame(len: usize) -> Vec<Self> where Self: Sized;
+    /// Instructions before a return removing what `new_stack_frame` made
+    fn new_stack_frame_end() -> Vec<Self> where Self: Sized;
 
     fn new_push(src: R) -> Self;
     fn new_pop(dest: R) -> Self;
@@ -25,7 +29,9 @@ pub trait Ins<R, L> {
 }
 
 pub trait Register<S, P> {
-    fn new_symbolic(symbol: S) -> Self;
+    /// A symbolic register of the same kind as this one, which is different for every `n`, and
+    /// from the registers the code was generated with
+    fn new_symbolic(&self, n: usize) -> Self;
     fn new_physical(physical_register: P) -> Self;
 
     fn as_symbolic(&self) -> Option<&S>;
@@ -47,6 +53,7 @@ impl<R: Copy, const CALLER_SAVE_LEN: usize, const CALLEE_SAVE_LEN: usize> Callin
             still_caller_save: true,
             allocator: self,
             stack_offset: 0,
+            symbol_counter: 0,
         }
     }
     const COLOURS_AVAILABLE: usize = CALLER_SAVE_LEN + CALLEE_SAVE_LEN;
@@ -58,6 +65,7 @@ struct AllocatorInstance<'a, R, const CALLER_SAVE_LEN: usize, const CALLEE_SAVE_
     regs_to_allocate: Vec<R>,
 
     stack_offset: usize,
+    symbol_counter: usize,
 }
 
 impl<R: Copy, const A: usize, const B: usize> AllocatorInstance<'_, R, A, B> {
@@ -79,6 +87,11 @@ impl<R: Copy, const A: usize, const B: usize> AllocatorInstance<'_, R, A, B> {
         self.stack_offset += 1;
         so
     }
+    fn new_symbol(&mut self) -> usize {
+        let n = self.symbol_counter;
+        self.symbol_counter += 1;
+        n
+    }
     fn unalloc_regs(&mut self) -> &mut Self {
         self.regs_to_allocate = self.allocator.caller_save.iter().rev().copied().collect();
         self.still_caller_save = true;
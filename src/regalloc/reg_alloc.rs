@@ -23,7 +23,26 @@ pub fn register_allocate<L, I, R, S, P, const CALLER_SAVE_LEN: usize, const CALL
 
     let mut instance = conv.allocator();
 
-    register_allocate_inner(&mut body, &mut instance);
+    register_allocate_inner(&mut body, &mut instance, &mut Vec::new());
+
+    // Make room for spilled registers
+    if instance.stack_offset > 0 {
+        for ins in I::new_stack_frame(instance.stack_offset).into_iter().rev() {
+            body.insert(0, ins);
+        }
+        let return_instructions: Vec<_> = body
+            .iter()
+            .enumerate()
+            .filter_map(|(i, ins)| ins.is_return().then_some(i))
+            .rev()
+            .collect();
+
+        for ri in return_instructions {
+            for ins in I::new_stack_frame_end().into_iter().rev() {
+                body.insert(ri, ins);
+            }
+        }
+    }
 
     // Save registers
     for reg in instance.regs_to_save() {
@@ -45,6 +64,7 @@ pub fn register_allocate<L, I, R, S, P, const CALLER_SAVE_LEN: usize, const CALL
 fn register_allocate_inner<L, R, P, I, S, const CALLER_SAVE_LEN: usize, const CALLEE_SAVE_LEN: usize>(
     body: &mut VecView<I>,
     alloc_ins: &mut AllocatorInstance<P, CALLER_SAVE_LEN, CALLEE_SAVE_LEN>,
+    spill_temps: &mut Vec<R>,
 )
     where L: Hash + Eq + Clone, R: Register<S, P> + Clone + Eq + Hash, P: Copy + Eq + Hash, I: Ins<R, L>, S: Clone + Eq + Hash
 {
@@ -128,7 +148,17 @@ fn register_allocate_inner<L, R, P, I, S, const CALLER_SAVE_LEN: usize, const CA
                     break;
                 }
             } else {
-                spilled.push(reg);
+                if !spill_temps.contains(&reg) {
+                    spilled.push(reg);
+                    break;
+                }
+                // spilling the register of a spilled one again would never end, so one of the
+                // registers live at the same time goes instead
+                let neighbour = neighbours
+                    .iter()
+                    .find(|&n| n.as_symbolic().is_some() && !spill_temps.contains(n) && !spilled.contains(n))
+                    .expect("an instruction needs more registers than there are");
+                spilled.push(neighbour.clone());
                 break;
             }
         }
@@ -154,21 +184,11 @@ fn register_allocate_inner<L, R, P, I, S, const CALLER_SAVE_LEN: usize, const CA
             rename_instruction(ins, &renames);
         }
     } else {
-        spill(body, spilled, alloc_ins);
-        register_allocate_inner(body, alloc_ins.unalloc_regs())
+        spill(body, spilled, alloc_ins, spill_temps);
+        register_allocate_inner(body, alloc_ins.unalloc_regs(), spill_temps)
     }
 }
 
-fn rename<R: Register<S, P>, S, P: Copy>(from: R, to: R, i: usize) -> R {
-    let from = from.as_symbolic().unwrap();
-
-    if let Some(&p) = to.as_physical() {
-        return R::new_physical(p);
-    }
-
-    todo!("make new symbolic register combining `from` {from:p} and {i}");
-}
-
 fn rename_instruction<P: Copy + Eq, L, I: Ins<R, L>, R: Register<S, P>, S: Clone + Eq>(ins: &mut I, renames: &[(S, P)]) {
     let do_rename = |r: R| {
         if let Some(&r) = r.as_physical() {
@@ -185,71 +205,61 @@ fn rename_instruction<P: Copy + Eq, L, I: Ins<R, L>, R: Register<S, P>, S: Clone
     ins.rename_src_args(|r| R::new_physical(do_rename(r)));
 }
 
-/// Renames registers given in the argument `renames` and returns the
-/// old rewritten register and what it was renamed to
-fn rename_writes<P: Copy + Eq, R: Register<S, P> + Clone + Eq, S, L, I: Ins<R, L>>(ins: &mut I, renames: &[(R, R)], i: usize) -> Vec<(R, R)> {
-    let mut renamed = Vec::new();
-    let do_rename = |r| {
-        if let Some((r, to)) = renames.iter().find(|(rename_r, _)| &r == rename_r).cloned() {
-            let old_r = r.clone();
-            let r = rename(r, to, i);
-            renamed.push((old_r, r.clone()));
-            r
-        } else { r }
-    };
-
-    ins.rename_dest_args(do_rename);
-    renamed
-}
-/// Renames registers read from and returns all the registers that were renamed: what it was renamed from and to
-fn rename_reads<P: Copy, R: Register<S, P> + Eq + Clone, S, L, I: Ins<R, L>>(ins: &mut I, renames: &[(R, R)], i: usize) -> Vec<(R, R)> {
-    let mut renamed = Vec::new();
-    let do_rename = |r| {
-        if let Some((r, to)) = renames.iter().find(|(rename_r, _)| &r == rename_r).cloned() {
-            let old_r = r.clone();
-            let r = rename(r, to, i);
-            renamed.push((old_r, r.clone()));
-            r
-        } else { r }
-    };
-
-    ins.rename_src_args(do_rename);
-    renamed
-}
-
-fn spill<P: Copy + Eq, R: Register<S, P> + Eq + Clone, L: Clone, I: Ins<R, L>, S: Hash + Eq + Clone, const CALLER_SAVE_LEN: usize, const CALLEE_SAVE_LEN: usize>(
+/// Gives the spilled registers places in the stack, which they are loaded from just before every
+/// instruction reading them and stored to just after every one writing them
+///
+/// Each instruction gets new registers for the spilled ones it uses, which are only live around it.
+fn spill<P: Copy + Eq, R: Register<S, P> + Eq + Clone + Hash, L: Clone, I: Ins<R, L>, S: Hash + Eq + Clone, const CALLER_SAVE_LEN: usize, const CALLEE_SAVE_LEN: usize>(
     body: &mut VecView<I>,
     spilled: Vec<R>,
     alloc_ins: &mut AllocatorInstance<P, CALLER_SAVE_LEN, CALLEE_SAVE_LEN>,
+    spill_temps: &mut Vec<R>,
 ) {
-    // rename register to itself
-    let renames: Vec<_> = spilled
-        .iter()
-        .map(|r| (r.clone(), r.clone()))
-        .collect();
-
-    let stack_offset: HashMap<S, usize> = spilled
-        .iter()
-        .map(|r| (r.as_symbolic().unwrap().clone(), alloc_ins.new_stack_offset()))
+    let stack_offset: HashMap<R, usize> = spilled
+        .into_iter()
+        .map(|r| (r, alloc_ins.new_stack_offset()))
         .collect();
 
-    let lookup_so_for_reg = move |reg: &R| -> usize {
-        let s = reg.as_symbolic().unwrap();
-        stack_offset[s]
-    };
-
     let mut to_insert: Vec<(usize, _)> = Vec::new();
     for (i, ins) in body.iter_mut().enumerate() {
-        let insert_writes = rename_writes(ins, &renames, i);
-        let insert_reads = rename_reads(ins, &renames, i);
+        let mut renames: Vec<(R, R)> = Vec::new();
+        let mut rename = |r: R| {
+            if !stack_offset.contains_key(&r) {
+                return None;
+            }
+            if let Some((_, to)) = renames.iter().find(|(from, _)| *from == r) {
+                return Some(to.clone());
+            }
+            let to = r.new_symbolic(alloc_ins.new_symbol());
+            spill_temps.push(to.clone());
+            renames.push((r, to.clone()));
+            Some(to)
+        };
+
+        let mut reads = Vec::new();
+        ins.rename_src_args(|r| match rename(r.clone()) {
+            Some(to) => {
+                reads.push((r, to.clone()));
+                to
+            }
+            None => r,
+        });
+        let mut writes = Vec::new();
+        ins.rename_dest_args(|r| match rename(r.clone()) {
+            Some(to) => {
+                writes.push((r, to.clone()));
+                to
+            }
+            None => r,
+        });
+        reads.dedup();
+        writes.dedup();
 
-        for (old_reg, renamed_reg) in insert_reads {
-            let so = lookup_so_for_reg(&old_reg);
-            to_insert.push((i, Ins::new_read_from_stack_var(renamed_reg, so)));
+        for (old_reg, renamed_reg) in reads {
+            to_insert.push((i, Ins::new_read_from_stack_var(renamed_reg, stack_offset[&old_reg])));
         }
-        for (old_reg, renamed_reg) in insert_writes {
-            let so = lookup_so_for_reg(&old_reg);
-            to_insert.push((i+1, Ins::new_write_to_stack_var(so, renamed_reg)));
+        for (old_reg, renamed_reg) in writes {
+            to_insert.push((i+1, Ins::new_write_to_stack_var(stack_offset[&old_reg], renamed_reg)));
         }
     }
 
@@ -2,18 +2,37 @@ use std::{
     cell::RefCell,
     cmp::Ordering,
     collections::{BTreeMap, HashMap},
-    iter,
+    iter, mem,
     rc::Rc,
 };
 
-use crate::flat::{Binop, Const, Global, Ident, Label, Line, Program, StaticDecl, Temp, Unop};
+use crate::{
+    diagnostic::{self, Diagnostic},
+    flat::{
+        Binop, Const, FlatType, Global, Ident, Label, Line, PanicReason, Program, StaticDecl, Temp,
+        Unop,
+    },
+    parse::location::Location,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum RuntimeError {
-    Panic(Box<str>),
+    Panic(Location, PanicReason),
     InvalidMain,
 }
 
+impl RuntimeError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            RuntimeError::Panic(loc, reason) => {
+                Diagnostic::error(reason.code(), reason.to_string())
+                    .with_label(diagnostic::Label::primary(loc.clone()))
+            }
+            RuntimeError::InvalidMain => Diagnostic::error("E0204", "invalid main function"),
+        }
+    }
+}
+
 mod value_impl;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,16 +44,82 @@ pub enum Value {
     U16(u16),
     I32(i32),
     U32(u32),
-    String(Rc<str>),
     Float(f64),
+    Struct(Box<[Value]>),
 
     Function(Rc<[Line]>),
     BuiltinFn(fn(&[Value]) -> Value),
-    Ref(Ident),
+    Ref(Pointer),
 
     Naught,
 }
 
+/// Points to a whole variable, or to an element of the array in one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pointer {
+    pointee: Rc<RefCell<Value>>,
+    element: Option<u16>,
+}
+
+impl Pointer {
+    const fn new(pointee: Rc<RefCell<Value>>) -> Self {
+        Self {
+            pointee,
+            element: None,
+        }
+    }
+    /// Pointer to the element `n` elements after the one this points to
+    pub fn offset(&self, n: u16) -> Self {
+        Self {
+            pointee: self.pointee.clone(),
+            element: Some(self.element.unwrap_or(0) + n),
+        }
+    }
+    pub fn read(&self) -> Value {
+        let pointee = self.pointee.borrow();
+        match self.element {
+            None => pointee.clone(),
+            Some(n) => match &*pointee {
+                // zero-initialised if it has not been written to yet
+                Value::Struct(elems) => elems.get(n as usize).cloned().unwrap_or(Value::Naught),
+                Value::Naught => Value::Naught,
+                // a lone value is an array of one
+                v if n == 0 => v.clone(),
+                _ => unreachable!("element of non-array"),
+            },
+        }
+    }
+    /// Reads the element `n` elements after the one this points to
+    pub fn read_index(&self, n: u16) -> Value {
+        self.offset(n).read()
+    }
+    pub fn write(&self, val: Value) {
+        let mut pointee = self.pointee.borrow_mut();
+        match self.element {
+            None => *pointee = val,
+            Some(n) => {
+                let n = n as usize;
+                let mut elems = match mem::replace(&mut *pointee, Value::Naught) {
+                    Value::Struct(elems) => elems.into_vec(),
+                    // zero-initialised
+                    Value::Naught => Vec::new(),
+                    // a lone value is an array of one
+                    _ if n == 0 => {
+                        *pointee = val;
+                        return;
+                    }
+                    _ => unreachable!("element of non-array"),
+                };
+                if elems.len() <= n {
+                    elems.resize(n + 1, Value::Naught);
+                }
+                elems[n] = val;
+                *pointee = Value::Struct(elems.into_boxed_slice());
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SymbolError {
     Undefined,
@@ -43,20 +128,31 @@ pub enum SymbolError {
 
 #[derive(Debug, Clone, Default)]
 pub struct SymbolTable {
-    map: HashMap<Rc<str>, RefCell<Value>>,
+    map: HashMap<Rc<str>, Rc<RefCell<Value>>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct RuntimeState<'a> {
     globals: &'a SymbolTable,
-    stack: Vec<Value>,
+    stack: Vec<Slot>,
+}
+
+/// Where a temp is kept, which only needs a cell of its own once a pointer to it is taken, so
+/// the pointer sees it change
+#[derive(Debug, Clone)]
+enum Slot {
+    Value(Value),
+    Cell(Rc<RefCell<Value>>),
 }
 
 impl<'a> RuntimeState<'a> {
     pub fn with_args(globals: &'a SymbolTable, args: impl Iterator<Item = Value>) -> Self {
         Self {
             globals,
-            stack: iter::once(Value::Naught).chain(args).collect(),
+            stack: iter::once(Value::Naught)
+                .chain(args)
+                .map(Slot::Value)
+                .collect(),
         }
     }
     pub const fn new(&self) -> Self {
@@ -71,14 +167,39 @@ impl<'a> RuntimeState<'a> {
     pub fn set_temp(&mut self, temp: Temp, val: Value) {
         let index = temp.inner();
         if self.stack.len() <= index {
-            self.stack.resize(index + 1, Value::Naught);
+            self.stack
+                .resize_with(index + 1, || Slot::Value(Value::Naught));
+        }
+        match &mut self.stack[index] {
+            Slot::Value(v) => *v = val,
+            Slot::Cell(cell) => *cell.borrow_mut() = val,
         }
-        self.stack[index] = val;
     }
     pub fn lookup<I: Into<Ident>>(&self, ident: I) -> Value {
         match ident.into() {
             Ident::Global(g) => self.globals.lookup(g.inner()),
-            Ident::Temp(t) => self.stack[t.inner()].clone(),
+            Ident::Temp(t) => match &self.stack[t.inner()] {
+                Slot::Value(v) => v.clone(),
+                Slot::Cell(cell) => cell.borrow().clone(),
+            },
+        }
+    }
+    pub fn address_of<I: Into<Ident>>(&mut self, ident: I) -> Pointer {
+        match ident.into() {
+            Ident::Global(g) => self.globals.address_of(g.inner()),
+            Ident::Temp(t) => {
+                if self.stack.len() <= t.inner() {
+                    self.set_temp(t.clone(), Value::Naught);
+                }
+                let slot = &mut self.stack[t.inner()];
+                if let Slot::Value(v) = slot {
+                    *slot = Slot::Cell(Rc::new(RefCell::new(mem::replace(v, Value::Naught))));
+                }
+                let Slot::Cell(cell) = slot else {
+                    unreachable!("the temp was just given a cell");
+                };
+                Pointer::new(cell.clone())
+            }
         }
     }
 }
@@ -89,7 +210,7 @@ impl SymbolTable {
         Self::default()
     }
     pub fn iter(&self) -> impl Iterator<Item = (&Rc<str>, &RefCell<Value>)> {
-        self.map.iter()
+        self.map.iter().map(|(name, val)| (name, &**val))
     }
     pub fn add_func<S: Into<Rc<str>>>(&mut self, name: S, f: fn(&[Value]) -> Value) {
         self.add_var(name, Value::BuiltinFn(f))
@@ -97,11 +218,14 @@ impl SymbolTable {
     pub fn add_var<S: Into<Rc<str>>>(&mut self, name: S, val: Value) {
         let name = name.into();
 
-        self.map.insert(name, RefCell::new(val));
+        self.map.insert(name, Rc::new(RefCell::new(val)));
     }
     pub fn lookup(&self, name: &str) -> Value {
         self.map[name].borrow().clone()
     }
+    pub fn address_of(&self, name: &str) -> Pointer {
+        Pointer::new(self.map[name].clone())
+    }
     pub fn mutate(&self, name: &str, new_val: Value) {
         let var = &self.map[name];
         *var.borrow_mut() = new_val;
@@ -131,13 +255,24 @@ pub fn run(program: Program, symtab: &mut SymbolTable) -> Result<Value, RuntimeE
                 symtab.add_var(n.into_inner(), val);
             }
             StaticDecl::SetString(n, _, val) => {
-                symtab.add_var(n.into_inner(), Value::String(val.into()));
+                let bytes = val.bytes().map(Value::U8).collect();
+                symtab.add_var(n.into_inner(), Value::Struct(bytes));
+            }
+            StaticDecl::SetArray(n, _, vals) => {
+                let vals = vals.iter().map(|&val| const_to_val(val)).collect();
+                symtab.add_var(n.into_inner(), Value::Struct(vals));
             }
-            StaticDecl::SetArray(_n, _, _val) => todo!(),
             StaticDecl::SetPtr(n, _, val) => {
-                let val = Value::Ref(val.into());
+                let val = Value::Ref(symtab.address_of(val.inner()));
                 symtab.add_var(n.into_inner(), val);
             }
+            StaticDecl::SetSlice(n, _, arr, len) => {
+                let ptr = Value::Ref(symtab.address_of(arr.inner()));
+                symtab.add_var(
+                    n.into_inner(),
+                    Value::Struct(Box::new([ptr, Value::U16(len)])),
+                );
+            }
             StaticDecl::External(n, _) => {
                 let _ = symtab.lookup(n.inner());
             }
@@ -195,6 +330,18 @@ fn run_lines(lines: &[Line], state: &mut RuntimeState) -> Result<Value, RuntimeE
                     Binop::Sub => left - right,
                     Binop::Mul => left * right,
                     Binop::Div => left / right,
+                    Binop::Rem => left % right,
+                    Binop::SatAdd => left.saturating_add(right),
+                    Binop::SatSub => left.saturating_sub(right),
+                    Binop::SatMul => left.saturating_mul(right),
+                    Binop::AddOverflows => left.add_overflows(right),
+                    Binop::SubOverflows => left.sub_overflows(right),
+                    Binop::MulOverflows => left.mul_overflows(right),
+                    Binop::And => left & right,
+                    Binop::Or => left | right,
+                    Binop::Xor => left ^ right,
+                    Binop::Shl => left << right,
+                    Binop::Shr => left >> right,
                     Binop::Eq => Value::Boolean(left.cmp_op(right, Ordering::Equal, false)),
                     Binop::Neq => Value::Boolean(left.cmp_op(right, Ordering::Equal, true)),
                     Binop::Lt => Value::Boolean(left.cmp_op(right, Ordering::Less, false)),
@@ -205,20 +352,33 @@ fn run_lines(lines: &[Line], state: &mut RuntimeState) -> Result<Value, RuntimeE
                 state.set_temp(dest.clone(), val);
             }
             Line::SetAddrOf(dest, _, src) => {
-                state.set_temp(dest.clone(), Value::Ref(src.clone()));
+                let ptr = state.address_of(src.clone());
+                state.set_temp(dest.clone(), Value::Ref(ptr));
+            }
+            Line::SetAlloc(dest, _, src) => {
+                let val = state.lookup(src.clone());
+                let ptr = Pointer::new(Rc::new(RefCell::new(val)));
+                state.set_temp(dest.clone(), Value::Ref(ptr));
             }
-            Line::SetUnop(dest, _, unop, operand) => {
+            Line::SetElementAddr(dest, _, ptr, index) => {
+                let Value::U16(index) = state.lookup(index.clone()) else {
+                    unreachable!("non-u16 index");
+                };
+                let Value::Ref(ptr) = state.lookup(ptr.clone()) else {
+                    unreachable!("index through non-pointer");
+                };
+                state.set_temp(dest.clone(), Value::Ref(ptr.offset(index)));
+            }
+            Line::SetUnop(dest, t, unop, operand) => {
                 let operand = state.lookup(operand.clone());
-                let val = match *unop {
-                    Unop::Not => match operand {
-                        Value::Boolean(b) => Value::Boolean(!b),
-                        _ => unreachable!(),
-                    },
+                let val = match unop {
+                    Unop::Not => !operand,
                     Unop::Neg => -operand,
                     Unop::Deref => match operand {
-                        Value::Ref(r) => state.lookup(r),
+                        Value::Ref(ptr) => ptr.read(),
                         _ => unreachable!(),
                     },
+                    Unop::Convert(_) => operand.convert(t),
                 };
                 state.set_temp(dest.clone(), val);
             }
@@ -249,7 +409,10 @@ fn run_lines(lines: &[Line], state: &mut RuntimeState) -> Result<Value, RuntimeE
             Line::If(cond, lbl_true, lbl_false) => {
                 match state.lookup(cond.clone()) {
                     Value::Boolean(true) => goto(&mut line_pointer, lbl_true, &label_cache),
-                    Value::Boolean(false) => goto(&mut line_pointer, lbl_false, &label_cache),
+                    // zero-initialised
+                    Value::Boolean(false) | Value::Naught => {
+                        goto(&mut line_pointer, lbl_false, &label_cache)
+                    }
                     _ => unreachable!("non-boolean condition"),
                 }
                 continue;
@@ -269,18 +432,81 @@ fn run_lines(lines: &[Line], state: &mut RuntimeState) -> Result<Value, RuntimeE
             Line::WriteTo(dest_ptr, _, src) => {
                 let val = state.lookup(src.clone());
 
-                let ptr = state.lookup(dest_ptr.clone());
-                let Value::Ref(v) = ptr else {
+                let Value::Ref(ptr) = state.lookup(dest_ptr.clone()) else {
                     unreachable!();
                 };
-                match v {
-                    Ident::Global(g) => state.set_global(g, val),
-                    Ident::Temp(t) => state.set_temp(t, val),
-                }
+                ptr.write(val);
+            }
+            Line::SetIndex(dest, t, index, src) => {
+                let Value::U16(index) = state.lookup(index.clone()) else {
+                    unreachable!("non-u16 index");
+                };
+                let val = state.lookup(src.clone());
+                let mut elems = match state.lookup(dest.clone()) {
+                    Value::Struct(elems) => elems,
+                    // zero-initialised
+                    Value::Naught => {
+                        let &FlatType::Arr(_, len) = t else {
+                            unreachable!("index into non-array");
+                        };
+                        vec![Value::Naught; len as usize].into_boxed_slice()
+                    }
+                    _ => unreachable!("index into non-array"),
+                };
+                elems[index as usize] = val;
+                state.set_temp(dest.clone(), Value::Struct(elems));
+            }
+            Line::ReadField(dest, _, src, n) => {
+                let val = match state.lookup(src.clone()) {
+                    Value::Struct(fields) => fields[*n as usize].clone(),
+                    // zero-initialised
+                    Value::Naught => Value::Naught,
+                    _ => unreachable!("field of non-aggregate"),
+                };
+                state.set_temp(dest.clone(), val);
+            }
+            Line::WriteField(dest, t, n, src) => {
+                let val = state.lookup(src.clone());
+                let mut fields = match state.lookup(dest.clone()) {
+                    Value::Struct(fields) => fields,
+                    // zero-initialised
+                    Value::Naught => {
+                        let len = match t {
+                            FlatType::Struct(fields) => fields.len(),
+                            FlatType::Arr(_, len) => *len as usize,
+                            _ => unreachable!("field of non-aggregate"),
+                        };
+                        vec![Value::Naught; len].into_boxed_slice()
+                    }
+                    _ => unreachable!("field of non-aggregate"),
+                };
+                fields[*n as usize] = val;
+                state.set_temp(dest.clone(), Value::Struct(fields));
+            }
+            Line::ReadIndex(dest, _, ptr, index) => {
+                let Value::U16(index) = state.lookup(index.clone()) else {
+                    unreachable!("non-u16 index");
+                };
+                let Value::Ref(ptr) = state.lookup(ptr.clone()) else {
+                    unreachable!("index through non-pointer");
+                };
+                let val = ptr.read_index(index);
+                state.set_temp(dest.clone(), val);
+            }
+            Line::ReadElement(dest, _, arr, index) => {
+                let Value::U16(index) = state.lookup(index.clone()) else {
+                    unreachable!("non-u16 index");
+                };
+                let val = match state.lookup(arr.clone()) {
+                    Value::Struct(elems) => elems[index as usize].clone(),
+                    // zero-initialised
+                    Value::Naught => Value::Naught,
+                    _ => unreachable!("index into non-array"),
+                };
+                state.set_temp(dest.clone(), val);
             }
-            Line::SetIndex(_, _, _) => todo!(),
-            Line::Panic(msg) => {
-                return Err(RuntimeError::Panic(msg.clone()));
+            Line::Panic(loc, reason) => {
+                return Err(RuntimeError::Panic(loc.clone(), *reason));
             }
             Line::Ret(name) => {
                 break Ok(state.lookup(name.clone()));
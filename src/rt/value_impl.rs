@@ -1,9 +1,12 @@
 use std::{
     cmp::Ordering,
     fmt::{self, Display},
-    ops::{Add, Div, Mul, Neg, Sub},
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub},
+    rc::Rc,
 };
 
+use crate::flat::FlatType;
+
 use super::Value;
 
 impl Display for Value {
@@ -18,7 +21,18 @@ impl Display for Value {
             Value::U32(i) => write!(f, "{i}"),
             Value::Float(v) => write!(f, "{v}"),
             Value::Boolean(v) => write!(f, "{v}"),
-            Value::String(s) => write!(f, "{s}"),
+            Value::Struct(fields) => {
+                write!(f, "{{")?;
+                let mut first = true;
+                for field in fields.iter() {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    first = false;
+                    write!(f, "{field}")?;
+                }
+                write!(f, "}}")
+            }
             Value::Function(body) => {
                 writeln!(f, "fn(...):")?;
                 for line in &**body {
@@ -27,7 +41,10 @@ impl Display for Value {
                 Ok(())
             }
             Value::BuiltinFn(func) => write!(f, "fn({func:p})"),
-            Value::Ref(var) => write!(f, "&{}", var.display()),
+            Value::Ref(ptr) => match ptr.element {
+                None => write!(f, "&{}", ptr.pointee.borrow()),
+                Some(n) => write!(f, "&{}[{n}]", ptr.pointee.borrow()),
+            },
         }
     }
 }
@@ -64,6 +81,22 @@ impl Neg for Value {
     }
 }
 
+impl Not for Value {
+    type Output = Self;
+    fn not(self) -> Self::Output {
+        match self {
+            Value::I8(i) => Value::I8(!i),
+            Value::U8(i) => Value::U8(!i),
+            Value::I16(i) => Value::I16(!i),
+            Value::U16(i) => Value::U16(!i),
+            Value::I32(i) => Value::I32(!i),
+            Value::U32(i) => Value::U32(!i),
+            Value::Boolean(b) => Value::Boolean(!b),
+            a => unreachable!("tried to invert {a}"),
+        }
+    }
+}
+
 impl Sub for Value {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self::Output {
@@ -114,6 +147,110 @@ impl Div for Value {
     }
 }
 
+impl Rem for Value {
+    type Output = Self;
+    fn rem(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::I8(i1.wrapping_rem(i2)),
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1.wrapping_rem(i2)),
+            (Value::I16(i1), Value::I16(i2)) => Value::I16(i1.wrapping_rem(i2)),
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1.wrapping_rem(i2)),
+            (Value::I32(i1), Value::I32(i2)) => Value::I32(i1.wrapping_rem(i2)),
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1.wrapping_rem(i2)),
+            (Value::Float(f1), Value::Float(f2)) => Value::Float(f1 % f2),
+
+            (a, b) => unreachable!("tried to take the remainder of {a} by {b}"),
+        }
+    }
+}
+
+impl BitAnd for Value {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::I8(i1 & i2),
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1 & i2),
+            (Value::I16(i1), Value::I16(i2)) => Value::I16(i1 & i2),
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1 & i2),
+            (Value::I32(i1), Value::I32(i2)) => Value::I32(i1 & i2),
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1 & i2),
+            (Value::Boolean(b1), Value::Boolean(b2)) => Value::Boolean(b1 & b2),
+            (a, b) => unreachable!("tried to and {a} with {b}"),
+        }
+    }
+}
+
+impl BitOr for Value {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::I8(i1 | i2),
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1 | i2),
+            (Value::I16(i1), Value::I16(i2)) => Value::I16(i1 | i2),
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1 | i2),
+            (Value::I32(i1), Value::I32(i2)) => Value::I32(i1 | i2),
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1 | i2),
+            (Value::Boolean(b1), Value::Boolean(b2)) => Value::Boolean(b1 | b2),
+            (a, b) => unreachable!("tried to or {a} with {b}"),
+        }
+    }
+}
+
+impl BitXor for Value {
+    type Output = Self;
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::I8(i1 ^ i2),
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1 ^ i2),
+            (Value::I16(i1), Value::I16(i2)) => Value::I16(i1 ^ i2),
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1 ^ i2),
+            (Value::I32(i1), Value::I32(i2)) => Value::I32(i1 ^ i2),
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1 ^ i2),
+            (Value::Boolean(b1), Value::Boolean(b2)) => Value::Boolean(b1 ^ b2),
+            (a, b) => unreachable!("tried to xor {a} with {b}"),
+        }
+    }
+}
+
+/// Shifting by the width of the type or more, which every negative count is, shifts out every bit
+impl Shl for Value {
+    type Output = Self;
+    fn shl(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::I8(i1.checked_shl(i2 as u32).unwrap_or(0)),
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1.checked_shl(i2 as u32).unwrap_or(0)),
+            (Value::I16(i1), Value::I16(i2)) => Value::I16(i1.checked_shl(i2 as u32).unwrap_or(0)),
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1.checked_shl(i2 as u32).unwrap_or(0)),
+            (Value::I32(i1), Value::I32(i2)) => Value::I32(i1.checked_shl(i2 as u32).unwrap_or(0)),
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1.checked_shl(i2).unwrap_or(0)),
+            (a, b) => unreachable!("tried to shift {a} left by {b}"),
+        }
+    }
+}
+
+/// Shifting by the width of the type or more, which every negative count is, shifts out every bit,
+/// leaving only the sign for signed types
+impl Shr for Value {
+    type Output = Self;
+    fn shr(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => {
+                Value::I8(i1.checked_shr(i2 as u32).unwrap_or(i1 >> (i8::BITS - 1)))
+            }
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1.checked_shr(i2 as u32).unwrap_or(0)),
+            (Value::I16(i1), Value::I16(i2)) => {
+                Value::I16(i1.checked_shr(i2 as u32).unwrap_or(i1 >> (i16::BITS - 1)))
+            }
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1.checked_shr(i2 as u32).unwrap_or(0)),
+            (Value::I32(i1), Value::I32(i2)) => {
+                Value::I32(i1.checked_shr(i2 as u32).unwrap_or(i1 >> (i32::BITS - 1)))
+            }
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1.checked_shr(i2).unwrap_or(0)),
+            (a, b) => unreachable!("tried to shift {a} right by {b}"),
+        }
+    }
+}
+
 impl Value {
     pub fn cmp_op(self, other: Value, target_ord: Ordering, negated: bool) -> bool {
         match self.partial_cmp(&other) {
@@ -121,10 +258,110 @@ impl Value {
             Some(ord) => (ord == target_ord) ^ negated,
         }
     }
-    pub fn concat(self, other: Value) -> Value {
-        match (self, other) {
-            (Value::String(s1), Value::String(s2)) => Value::String(format!("{s1}{s2}").into()),
-            (v1, v2) => unreachable!("tried to concatenate {v1} and {v2}"),
+
+    /// Converts the number to the number type `t`, wrapping when it does not fit and cutting
+    /// floats toward zero
+    pub fn convert(self, t: &FlatType) -> Self {
+        let n: i64 = match self {
+            Value::Boolean(b) => b.into(),
+            Value::I8(i) => i.into(),
+            Value::U8(i) => i.into(),
+            Value::I16(i) => i.into(),
+            Value::U16(i) => i.into(),
+            Value::I32(i) => i.into(),
+            Value::U32(i) => i.into(),
+            Value::Float(_) if *t == FlatType::Float => return self,
+            Value::Float(f) => f as i64,
+            a => unreachable!("tried to convert {a}"),
+        };
+        match t {
+            FlatType::Bool => Value::Boolean(n != 0),
+            FlatType::I8 => Value::I8(n as i8),
+            FlatType::U8 => Value::U8(n as u8),
+            FlatType::I16 => Value::I16(n as i16),
+            FlatType::U16 => Value::U16(n as u16),
+            FlatType::I32 => Value::I32(n as i32),
+            FlatType::U32 => Value::U32(n as u32),
+            FlatType::Float => Value::Float(n as f64),
+            t => unreachable!("tried to convert {self} to {t}"),
+        }
+    }
+    /// Adds, clamping the result to the bounds of the type
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::I8(i1.saturating_add(i2)),
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1.saturating_add(i2)),
+            (Value::I16(i1), Value::I16(i2)) => Value::I16(i1.saturating_add(i2)),
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1.saturating_add(i2)),
+            (Value::I32(i1), Value::I32(i2)) => Value::I32(i1.saturating_add(i2)),
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1.saturating_add(i2)),
+            (Value::Float(f1), Value::Float(f2)) => Value::Float(f1 + f2),
+            (a, b) => unreachable!("tried to saturating_add {a} and {b}"),
+        }
+    }
+    /// Subtracts, clamping the result to the bounds of the type
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::I8(i1.saturating_sub(i2)),
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1.saturating_sub(i2)),
+            (Value::I16(i1), Value::I16(i2)) => Value::I16(i1.saturating_sub(i2)),
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1.saturating_sub(i2)),
+            (Value::I32(i1), Value::I32(i2)) => Value::I32(i1.saturating_sub(i2)),
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1.saturating_sub(i2)),
+            (Value::Float(f1), Value::Float(f2)) => Value::Float(f1 - f2),
+            (a, b) => unreachable!("tried to saturating_sub {a} and {b}"),
+        }
+    }
+    /// Multiplies, clamping the result to the bounds of the type
+    pub fn saturating_mul(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::I8(i1.saturating_mul(i2)),
+            (Value::U8(i1), Value::U8(i2)) => Value::U8(i1.saturating_mul(i2)),
+            (Value::I16(i1), Value::I16(i2)) => Value::I16(i1.saturating_mul(i2)),
+            (Value::U16(i1), Value::U16(i2)) => Value::U16(i1.saturating_mul(i2)),
+            (Value::I32(i1), Value::I32(i2)) => Value::I32(i1.saturating_mul(i2)),
+            (Value::U32(i1), Value::U32(i2)) => Value::U32(i1.saturating_mul(i2)),
+            (Value::Float(f1), Value::Float(f2)) => Value::Float(f1 * f2),
+            (a, b) => unreachable!("tried to saturating_mul {a} and {b}"),
+        }
+    }
+    /// Whether adding would overflow the type
+    pub fn add_overflows(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::Boolean(i1.checked_add(i2).is_none()),
+            (Value::U8(i1), Value::U8(i2)) => Value::Boolean(i1.checked_add(i2).is_none()),
+            (Value::I16(i1), Value::I16(i2)) => Value::Boolean(i1.checked_add(i2).is_none()),
+            (Value::U16(i1), Value::U16(i2)) => Value::Boolean(i1.checked_add(i2).is_none()),
+            (Value::I32(i1), Value::I32(i2)) => Value::Boolean(i1.checked_add(i2).is_none()),
+            (Value::U32(i1), Value::U32(i2)) => Value::Boolean(i1.checked_add(i2).is_none()),
+            (Value::Float(_), Value::Float(_)) => Value::Boolean(false),
+            (a, b) => unreachable!("tried to add_overflows {a} and {b}"),
+        }
+    }
+    /// Whether subtracting would overflow the type
+    pub fn sub_overflows(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::Boolean(i1.checked_sub(i2).is_none()),
+            (Value::U8(i1), Value::U8(i2)) => Value::Boolean(i1.checked_sub(i2).is_none()),
+            (Value::I16(i1), Value::I16(i2)) => Value::Boolean(i1.checked_sub(i2).is_none()),
+            (Value::U16(i1), Value::U16(i2)) => Value::Boolean(i1.checked_sub(i2).is_none()),
+            (Value::I32(i1), Value::I32(i2)) => Value::Boolean(i1.checked_sub(i2).is_none()),
+            (Value::U32(i1), Value::U32(i2)) => Value::Boolean(i1.checked_sub(i2).is_none()),
+            (Value::Float(_), Value::Float(_)) => Value::Boolean(false),
+            (a, b) => unreachable!("tried to sub_overflows {a} and {b}"),
+        }
+    }
+    /// Whether multiplying would overflow the type
+    pub fn mul_overflows(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Value::I8(i1), Value::I8(i2)) => Value::Boolean(i1.checked_mul(i2).is_none()),
+            (Value::U8(i1), Value::U8(i2)) => Value::Boolean(i1.checked_mul(i2).is_none()),
+            (Value::I16(i1), Value::I16(i2)) => Value::Boolean(i1.checked_mul(i2).is_none()),
+            (Value::U16(i1), Value::U16(i2)) => Value::Boolean(i1.checked_mul(i2).is_none()),
+            (Value::I32(i1), Value::I32(i2)) => Value::Boolean(i1.checked_mul(i2).is_none()),
+            (Value::U32(i1), Value::U32(i2)) => Value::Boolean(i1.checked_mul(i2).is_none()),
+            (Value::Float(_), Value::Float(_)) => Value::Boolean(false),
+            (a, b) => unreachable!("tried to mul_overflows {a} and {b}"),
         }
     }
 }
@@ -157,6 +394,15 @@ impl PartialOrd for Value {
             (Value::Naught, Value::U32(i2)) => 0.partial_cmp(i2),
             (Value::Naught, Value::Float(f2)) => (0.).partial_cmp(f2),
 
+            // null pointers
+            (Value::Naught, Value::Naught) => Some(Ordering::Equal),
+            (Value::Ref(_), Value::Naught) => Some(Ordering::Greater),
+            (Value::Naught, Value::Ref(_)) => Some(Ordering::Less),
+            (Value::Ref(p1), Value::Ref(p2)) if Rc::ptr_eq(&p1.pointee, &p2.pointee) => p1
+                .element
+                .unwrap_or(0)
+                .partial_cmp(&p2.element.unwrap_or(0)),
+
             (v1, v2) => unreachable!("tried to compare {v1} and {v2}"),
         }
     }
@@ -1,10 +1,13 @@
-use std::{collections::BTreeMap, rc::Rc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
 
 use crate::flat::{
     Binop, Const, FlatType, Function, Global, Ident, Label, Line, Program, StaticDecl, Temp, Unop,
 };
 
-use super::{Bi, Br, Br::*, Ins, Wi, Wr, Wr::*, Reg};
+use super::{sizeof, Bi, Br, Br::*, Ins, Wi, Wr, Wr::*, Reg};
 
 #[derive(Debug, Clone, Default)]
 struct ObjectState {
@@ -41,6 +44,16 @@ impl ObjectState {
 struct FunctionState<'a> {
     pseudo_counter: usize,
     temps: BTreeMap<Temp, Box<[Reg]>>,
+    /// Temps that have their address taken, which live in the stack frame instead of registers
+    ///
+    /// Their registers only hold their value from just before a line reading them to just after
+    /// one writing them, so writes through pointers to them are seen.
+    addressed: BTreeSet<Temp>,
+    /// Offsets from the stack pointer of the addressed temps given a place in the frame
+    slots: BTreeMap<Temp, u16>,
+    frame_size: u16,
+    /// Where the size of the frame is loaded, which is only known after the whole function
+    frame_size_loads: Vec<usize>,
     global_state: &'a mut ObjectState,
 }
 impl<'a> FunctionState<'a> {
@@ -51,9 +64,53 @@ impl<'a> FunctionState<'a> {
         Self {
             pseudo_counter: 0,
             temps,
+            addressed: BTreeSet::new(),
+            slots: BTreeMap::new(),
+            frame_size: 0,
+            frame_size_loads: Vec::new(),
             global_state,
         }
     }
+    fn slot(&mut self, temp: &Temp) -> u16 {
+        if let Some(&offset) = self.slots.get(temp) {
+            return offset;
+        }
+        let offset = self.frame_size;
+        let size: u16 = self.temps[temp].iter().map(reg_size).sum();
+        // keeps words aligned
+        self.frame_size += size + size % 2;
+        self.slots.insert(temp.clone(), offset);
+        offset
+    }
+    /// Reads an addressed temp from its place in the frame into its registers
+    fn generate_load(&mut self, code: &mut Vec<Ins>, temp: &Temp) {
+        let mut offset = self.slot(temp);
+        for &r in self.temps[temp].iter() {
+            match r {
+                Reg::ByteReg(r) => code.push(Ins::LoadBI(r, Rs, Wi::Constant(offset))),
+                Reg::WideReg(r) => code.push(Ins::LoadWI(r, Rs, Wi::Constant(offset))),
+            }
+            offset += reg_size(&r);
+        }
+    }
+    /// Writes the registers of an addressed temp to its place in the frame
+    fn generate_store(&mut self, code: &mut Vec<Ins>, temp: &Temp) {
+        let mut offset = self.slot(temp);
+        for &r in self.temps[temp].iter() {
+            match r {
+                Reg::ByteReg(r) => code.push(Ins::StoreBI(Rs, Wi::Constant(offset), r)),
+                Reg::WideReg(r) => code.push(Ins::StoreWI(Rs, Wi::Constant(offset), r)),
+            }
+            offset += reg_size(&r);
+        }
+    }
+    /// Moves the stack pointer by the size of the frame, which `op` makes or removes it
+    fn generate_frame_adjust(&mut self, code: &mut Vec<Ins>, op: fn(Wr, Wr, Wr) -> Ins) {
+        let size = self.new_wide_reg();
+        self.frame_size_loads.push(code.len());
+        code.push(Ins::LdiW(size, Wi::Constant(0)));
+        code.push(op(Rs, Rs, size));
+    }
     fn new_byte_reg(&mut self) -> Br {
         self.pseudo_counter += 1;
         Rpb(self.pseudo_counter)
@@ -147,6 +204,52 @@ impl<'a> FunctionState<'a> {
     }
 }
 
+/// Number of registers a value of the type is spread over
+fn reg_count(t: &FlatType) -> usize {
+    match t {
+        FlatType::Unit => 0,
+        FlatType::Bool | FlatType::U8 | FlatType::I8 => 1,
+        FlatType::U16 | FlatType::I16 | FlatType::Ptr(_) | FlatType::FnPtr(_, _) => 1,
+        FlatType::U32 | FlatType::I32 => 2,
+        FlatType::Float => todo!(),
+        FlatType::Arr(t, sz) => *sz as usize * reg_count(t),
+        FlatType::Struct(strct) => strct.iter().map(reg_count).sum(),
+    }
+}
+
+fn reg_size(r: &Reg) -> u16 {
+    match r {
+        Reg::ByteReg(_) => 1,
+        Reg::WideReg(_) => 2,
+    }
+}
+
+/// Offsets in memory of each register a value of the type is spread over
+fn reg_offsets(t: &FlatType) -> Vec<u16> {
+    fn add_offsets(t: &FlatType, base: u16, offsets: &mut Vec<u16>) {
+        match t {
+            FlatType::Unit => (),
+            FlatType::U32 | FlatType::I32 => offsets.extend([base, base + 2]),
+            FlatType::Arr(elem_t, sz) => {
+                for i in 0..*sz {
+                    add_offsets(elem_t, base + i * sizeof(elem_t), offsets);
+                }
+            }
+            FlatType::Struct(fields) => {
+                let mut offset = base;
+                for field_t in fields.iter() {
+                    add_offsets(field_t, offset, offsets);
+                    offset += sizeof(field_t);
+                }
+            }
+            _ => offsets.push(base),
+        }
+    }
+    let mut offsets = Vec::with_capacity(reg_count(t));
+    add_offsets(t, 0, &mut offsets);
+    offsets
+}
+
 const fn split_u32(dw: u32) -> (u16, u16) {
     let [l1, l2, h1, h2] = u32::to_le_bytes(dw);
     let l = u16::from_le_bytes([l1, l2]);
@@ -154,16 +257,41 @@ const fn split_u32(dw: u32) -> (u16, u16) {
     (l, h)
 }
 
+/// Bytes of memory that `SetAlloc` lines take from, which are never freed
+///
+/// Running out of it is not checked for, since the branch would be in the middle of the code
+/// making each closure.
+const HEAP_SIZE: u16 = 0x400;
+const HEAP: &str = "___heap";
+/// Where the next allocation starts
+const HEAP_NEXT: &str = "___heap_next";
+
 pub fn generate_program(program: Program) -> Vec<Ins> {
     let mut code = Vec::new();
     code.push(Ins::Seg("data"));
     for decl in program.statics {
         generate_decl(decl, &mut code);
     }
+    let allocates = program
+        .fns
+        .values()
+        .any(|f| f.lines.iter().any(|l| matches!(l, Line::SetAlloc(..))));
+    if allocates {
+        code.push(Ins::StaticMarker);
+        code.push(Ins::Label(HEAP_NEXT.into()));
+        code.push(Ins::Wide(Wi::Symbol(HEAP.into())));
+        code.push(Ins::StaticMarker);
+        code.push(Ins::Label(HEAP.into()));
+        for _ in 0..HEAP_SIZE {
+            code.push(Ins::Byte(Bi::Constant(0)));
+        }
+    }
 
     code.push(Ins::Seg("text"));
     let mut state = ObjectState::default();
     for (name, f) in program.fns {
+        // flat labels are only unique within their function
+        state.labels.clear();
         let state = FunctionState::new(&mut state);
         generate_fn(&mut code, state, name, f);
     }
@@ -178,6 +306,7 @@ pub fn generate_program(program: Program) -> Vec<Ins> {
         code.push(Ins::FunctionStartMarker);
         code.push(Ins::Label(call_reg));
         code.push(Ins::JmpR(Wr::Rf));
+        code.push(Ins::FunctionEndMarker);
     }
 
     code
@@ -186,30 +315,23 @@ pub fn generate_program(program: Program) -> Vec<Ins> {
 fn generate_decl(decl: StaticDecl, code: &mut Vec<Ins>) {
     code.push(Ins::StaticMarker);
     match decl {
-        StaticDecl::SetConst(g, _, c) => {
+        StaticDecl::SetConst(g, t, Const::ConstZero) => {
             code.push(Ins::Label(g.into_inner()));
-            match c {
-                Const::ConstBoolean(b) => code.push(Ins::Byte(Bi::Constant(b as u8))),
-                Const::ConstU8(b) => code.push(Ins::Byte(Bi::Constant(b))),
-                Const::ConstI8(b) => code.push(Ins::Byte(Bi::Constant(b as u8))),
-                Const::ConstI16(w) => code.push(Ins::Wide(Wi::Constant(w as u16))),
-                Const::ConstU16(w) => code.push(Ins::Wide(Wi::Constant(w))),
-                Const::ConstI32(dw) => {
-                    let (l, h) = split_u32(dw as u32);
-                    code.push(Ins::Wide(Wi::Constant(l)));
-                    code.push(Ins::Wide(Wi::Constant(h)));
-                }
-                Const::ConstU32(dw) => {
-                    let (l, h) = split_u32(dw);
-                    code.push(Ins::Wide(Wi::Constant(l)));
-                    code.push(Ins::Wide(Wi::Constant(h)));
-                }
-                Const::ConstFloat(_) => todo!(),
-                Const::ConstZero => todo!(),
+            for _ in 0..sizeof(&t) {
+                code.push(Ins::Byte(Bi::Constant(0)));
             }
         }
+        StaticDecl::SetConst(g, _, c) => {
+            code.push(Ins::Label(g.into_inner()));
+            generate_const(c, code);
+        }
         StaticDecl::SetAlias(_, _, _) => todo!(),
-        StaticDecl::SetArray(_, _, _) => todo!(),
+        StaticDecl::SetArray(g, _, vals) => {
+            code.push(Ins::Label(g.into_inner()));
+            for &c in &*vals {
+                generate_const(c, code);
+            }
+        }
         StaticDecl::SetString(g, _, s) => {
             code.push(Ins::Label(g.into_inner()));
             code.push(Ins::String(s));
@@ -218,12 +340,75 @@ fn generate_decl(decl: StaticDecl, code: &mut Vec<Ins>) {
             code.push(Ins::Label(g.into_inner()));
             code.push(Ins::Wide(Wi::Symbol(o.into_inner())));
         }
+        StaticDecl::SetSlice(g, _, arr, len) => {
+            code.push(Ins::Label(g.into_inner()));
+            code.push(Ins::Wide(Wi::Symbol(arr.into_inner())));
+            code.push(Ins::Wide(Wi::Constant(len)));
+        }
         StaticDecl::External(g, _) => {
             code.push(Ins::Ref(g.into_inner()));
         }
     }
 }
 
+fn generate_const(c: Const, code: &mut Vec<Ins>) {
+    match c {
+        Const::ConstBoolean(b) => code.push(Ins::Byte(Bi::Constant(b as u8))),
+        Const::ConstU8(b) => code.push(Ins::Byte(Bi::Constant(b))),
+        Const::ConstI8(b) => code.push(Ins::Byte(Bi::Constant(b as u8))),
+        Const::ConstI16(w) => code.push(Ins::Wide(Wi::Constant(w as u16))),
+        Const::ConstU16(w) => code.push(Ins::Wide(Wi::Constant(w))),
+        Const::ConstI32(dw) => {
+            let (l, h) = split_u32(dw as u32);
+            code.push(Ins::Wide(Wi::Constant(l)));
+            code.push(Ins::Wide(Wi::Constant(h)));
+        }
+        Const::ConstU32(dw) => {
+            let (l, h) = split_u32(dw);
+            code.push(Ins::Wide(Wi::Constant(l)));
+            code.push(Ins::Wide(Wi::Constant(h)));
+        }
+        Const::ConstFloat(_) => todo!(),
+        Const::ConstZero => todo!(),
+    }
+}
+
+/// Moves the registers of `src` into those of `dest`
+fn generate_moves(code: &mut Vec<Ins>, dest: &[Reg], src: &[Reg]) {
+    for (&dest, &src) in dest.iter().zip(src) {
+        match (dest, src) {
+            (Reg::ByteReg(dest), Reg::ByteReg(src)) => code.push(Ins::MoveB(dest, src)),
+            (Reg::WideReg(dest), Reg::WideReg(src)) => code.push(Ins::MoveW(dest, src)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Registers cannot be indexed into, so this compares `index` against every index of an array of
+/// `len` elements that are `elem_regs` registers each, calling `f` with the offset of the
+/// matching element's first register
+fn generate_element_select(
+    code: &mut Vec<Ins>,
+    state: &mut FunctionState,
+    index: Wr,
+    len: u16,
+    elem_regs: usize,
+    mut f: impl FnMut(&mut Vec<Ins>, usize),
+) {
+    let end = state.new_label();
+    let current = state.new_wide_reg();
+    for i in 0..len {
+        let next = state.new_label();
+        code.push(Ins::LdiW(current, Wi::Constant(i)));
+        code.push(Ins::SubW(R0, index, current));
+        code.push(Ins::Jnz(Wi::Symbol(next.clone())));
+        f(code, i as usize * elem_regs);
+        code.push(Ins::Jump(Wi::Symbol(end.clone())));
+        code.push(Ins::Label(next));
+    }
+    code.push(Ins::Label(end));
+}
+
 fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: Function) {
     if &**name.inner() == "main" {
         // FIXME: hard-coded export of `main`
@@ -231,7 +416,33 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
     }
     code.push(Ins::FunctionStartMarker);
     code.push(Ins::Label(name.into_inner()));
+    // parameters may be passed on without being read first
+    for (param, t) in f.params() {
+        state.get(&param, Some(t));
+    }
+    state.addressed = f
+        .lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::SetAddrOf(_, _, Ident::Temp(t)) => Some(t.clone()),
+            _ => None,
+        })
+        .collect();
+    if !state.addressed.is_empty() {
+        state.generate_frame_adjust(code, Ins::SubW);
+        for (param, _) in f.params() {
+            if state.addressed.contains(&param) {
+                state.generate_store(code, &param);
+            }
+        }
+    }
     for line in f.lines {
+        let (reads, write) = line_temps(&line);
+        for temp in reads {
+            if state.addressed.contains(&temp) {
+                state.generate_load(code, &temp);
+            }
+        }
         match line {
             Line::SetConst(t, ty, c) => match c {
                 Const::ConstBoolean(b) => {
@@ -259,19 +470,14 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                 }
                 Const::ConstFloat(_) => todo!(),
                 Const::ConstZero => {
-                    // make sure the temporary exists in the database
-                    _ = state.get(&t, Some(&ty));
-                    match ty {
-                        // cheap!
-                        FlatType::Unit => (),
-                        FlatType::U8 | FlatType::I8 => todo!("load zero byte"),
-                        FlatType::Ptr(_)
-                        | FlatType::FnPtr(_, _)
-                        | FlatType::U16
-                        | FlatType::I16 => todo!("load zero wide"),
-                        FlatType::U32 | FlatType::I32 => todo!("load zero dwide"),
-                        FlatType::Float => unimplemented!(),
-                        _ => unreachable!("type cannot get value 0"),
+                    if let FlatType::Float = ty {
+                        unimplemented!()
+                    }
+                    for r in state.get(&t, Some(&ty)).to_vec() {
+                        match r {
+                            Reg::ByteReg(r) => code.push(Ins::LdiB(r, Bi::Constant(0))),
+                            Reg::WideReg(r) => code.push(Ins::LdiW(r, Wi::Constant(0))),
+                        }
                     }
                 }
             },
@@ -304,24 +510,7 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                     ));
                 }
                 (Binop::Add, FlatType::I32 | FlatType::U32) => {
-                    let (dl, dh) = state.get_dwide(&dest);
-                    let (t1l, t1h) = state.get_dwide(&t1);
-                    let (t2l, t2h) = state.get_dwide(&t2);
-
-                    // Store 0 in the high register whether we use it or not
-                    code.push(Ins::MoveW(dh, R0));
-                    code.push(Ins::AddW(dl, t1l, t2l));
-
-                    // load `1` into `dh` unless the add set the overflow flag, if it didn't we jump down
-                    let add_high = state.new_label();
-                    code.push(Ins::Jno(Wi::Symbol(add_high.clone())));
-                    code.push(Ins::LdiW(dh, Wi::Constant(1)));
-
-                    code.push(Ins::Label(add_high));
-                    let temp = state.new_wide_reg();
-                    // TODO: use add with carry instruction when it is out
-                    code.push(Ins::AddW(temp, t1h, t2h));
-                    code.push(Ins::AddW(dh, dh, temp));
+                    generate_dwide_op(code, &mut state, Binop::Add, &dest, &t1, &t2);
                 }
                 (Binop::Add, FlatType::Float) => unimplemented!(),
                 (Binop::Sub, FlatType::I8 | FlatType::U8) => {
@@ -339,24 +528,7 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                     ));
                 }
                 (Binop::Sub, FlatType::I32 | FlatType::U32) => {
-                    let (dl, dh) = state.get_dwide(&dest);
-                    let (t1l, t1h) = state.get_dwide(&t1);
-                    let (t2l, t2h) = state.get_dwide(&t2);
-
-                    // Store 0 in the high register whether we use it or not
-                    code.push(Ins::MoveW(dh, R0));
-                    code.push(Ins::SubW(dl, t1l, t2l));
-
-                    // load `1` into `dh` unless the sub set the overflow flag, if it didn't we jump down
-                    let sub_high = state.new_label();
-                    code.push(Ins::Jno(Wi::Symbol(sub_high.clone())));
-                    code.push(Ins::LdiW(dh, Wi::Constant(1)));
-
-                    code.push(Ins::Label(sub_high));
-                    let temp = state.new_wide_reg();
-                    // TODO: use sub with borrow instruction when it is out
-                    code.push(Ins::SubW(temp, t1h, t2h));
-                    code.push(Ins::SubW(dh, temp, dh));
+                    generate_dwide_op(code, &mut state, Binop::Sub, &dest, &t1, &t2);
                 }
                 (Binop::Sub, FlatType::Float) => unimplemented!(),
                 (Binop::Mul, FlatType::I8 | FlatType::U8) => {
@@ -376,14 +548,7 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                     ));
                 }
                 (Binop::Mul, FlatType::I32 | FlatType::U32) => {
-                    let (dl, dh) = state.get_dwide(&dest);
-                    let (t1l, t1h) = state.get_dwide(&t1);
-                    let (t2l, t2h) = state.get_dwide(&t2);
-
-                    code.push(Ins::MulW(dh, dl, t1l, t2l));
-                    let temp = state.new_wide_reg();
-                    code.push(Ins::MulW(R0, temp, t1h, t2h));
-                    code.push(Ins::AddW(dh, dh, temp));
+                    generate_dwide_op(code, &mut state, Binop::Mul, &dest, &t1, &t2);
                 }
                 (Binop::Mul, FlatType::Float) => unimplemented!(),
                 (Binop::Div, FlatType::I8 | FlatType::U8) => {
@@ -402,11 +567,113 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                         state.get_wide(&t2),
                     ));
                 }
-                (Binop::Div, FlatType::I32 | FlatType::U32) => todo!(),
+                (Binop::Div, t @ (FlatType::I32 | FlatType::U32)) => {
+                    generate_dwide_div(code, &mut state, Binop::Div, &t, &dest, &t1, &t2);
+                }
                 (Binop::Div, FlatType::Float) => unimplemented!(),
+                // the remainder is written to the first register, the quotient to the second
+                (Binop::Rem, FlatType::I8 | FlatType::U8) => {
+                    code.push(Ins::DivB(
+                        state.get_byte(&dest),
+                        R0b,
+                        state.get_byte(&t1),
+                        state.get_byte(&t2),
+                    ));
+                }
+                (Binop::Rem, FlatType::I16 | FlatType::U16) => {
+                    code.push(Ins::DivW(
+                        state.get_wide(&dest),
+                        R0,
+                        state.get_wide(&t1),
+                        state.get_wide(&t2),
+                    ));
+                }
+                (Binop::Rem, t @ (FlatType::I32 | FlatType::U32)) => {
+                    generate_dwide_div(code, &mut state, Binop::Rem, &t, &dest, &t1, &t2);
+                }
+                (Binop::Rem, FlatType::Float) => unimplemented!(),
+                (Binop::And, FlatType::Bool | FlatType::I8 | FlatType::U8) => {
+                    code.push(Ins::AndB(
+                        state.get_byte(&dest),
+                        state.get_byte(&t1),
+                        state.get_byte(&t2),
+                    ));
+                }
+                (Binop::And, FlatType::I16 | FlatType::U16) => {
+                    code.push(Ins::AndW(
+                        state.get_wide(&dest),
+                        state.get_wide(&t1),
+                        state.get_wide(&t2),
+                    ));
+                }
+                (Binop::And, FlatType::I32 | FlatType::U32) => {
+                    let (dl, dh) = state.get_dwide(&dest);
+                    let (t1l, t1h) = state.get_dwide(&t1);
+                    let (t2l, t2h) = state.get_dwide(&t2);
+
+                    code.push(Ins::AndW(dl, t1l, t2l));
+                    code.push(Ins::AndW(dh, t1h, t2h));
+                }
+                (Binop::And, FlatType::Float) => unreachable!(),
+                (Binop::Or, FlatType::Bool | FlatType::I8 | FlatType::U8) => {
+                    code.push(Ins::OrB(
+                        state.get_byte(&dest),
+                        state.get_byte(&t1),
+                        state.get_byte(&t2),
+                    ));
+                }
+                (Binop::Or, FlatType::I16 | FlatType::U16) => {
+                    code.push(Ins::OrW(
+                        state.get_wide(&dest),
+                        state.get_wide(&t1),
+                        state.get_wide(&t2),
+                    ));
+                }
+                (Binop::Or, FlatType::I32 | FlatType::U32) => {
+                    let (dl, dh) = state.get_dwide(&dest);
+                    let (t1l, t1h) = state.get_dwide(&t1);
+                    let (t2l, t2h) = state.get_dwide(&t2);
+
+                    code.push(Ins::OrW(dl, t1l, t2l));
+                    code.push(Ins::OrW(dh, t1h, t2h));
+                }
+                (Binop::Or, FlatType::Float) => unreachable!(),
+                (Binop::Xor, FlatType::Bool | FlatType::I8 | FlatType::U8) => {
+                    code.push(Ins::XorB(
+                        state.get_byte(&dest),
+                        state.get_byte(&t1),
+                        state.get_byte(&t2),
+                    ));
+                }
+                (Binop::Xor, FlatType::I16 | FlatType::U16) => {
+                    code.push(Ins::XorW(
+                        state.get_wide(&dest),
+                        state.get_wide(&t1),
+                        state.get_wide(&t2),
+                    ));
+                }
+                (Binop::Xor, FlatType::I32 | FlatType::U32) => {
+                    let (dl, dh) = state.get_dwide(&dest);
+                    let (t1l, t1h) = state.get_dwide(&t1);
+                    let (t2l, t2h) = state.get_dwide(&t2);
+
+                    code.push(Ins::XorW(dl, t1l, t2l));
+                    code.push(Ins::XorW(dh, t1h, t2h));
+                }
+                (Binop::Xor, FlatType::Float) => unreachable!(),
+                (Binop::Shl | Binop::Shr, FlatType::Float) => unreachable!(),
+                (r @ (Binop::Shl | Binop::Shr), t) => {
+                    generate_set_binop_shift(code, &mut state, r, t, dest, t1, t2);
+                }
                 (r @ (Binop::Eq | Binop::Neq | Binop::Gt | Binop::Gte | Binop::Lt | Binop::Lte), t) => {
                     generate_set_binop_rel(code, &mut state, r, t, dest, t1, t2);
                 }
+                (r @ (Binop::AddOverflows | Binop::SubOverflows | Binop::MulOverflows), t) => {
+                    generate_set_binop_overflows(code, &mut state, r, t, dest, t1, t2);
+                }
+                (r @ (Binop::SatAdd | Binop::SatSub | Binop::SatMul), t) => {
+                    generate_set_binop_saturating(code, &mut state, r, t, dest, t1, t2);
+                }
                 (_, FlatType::Unit | FlatType::Bool | FlatType::Arr(_, _) | FlatType::Struct(_) | FlatType::FnPtr(_, _) | FlatType::Ptr(_)) => unreachable!("no binop on non-numeric types"),
             },
             Line::SetUnop(dest, t, unop, s) => match (unop, t) {
@@ -416,6 +683,58 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                 (Unop::Neg, FlatType::I16) => {
                     code.push(Ins::SubW(state.get_wide(&dest), R0, state.get_wide(&s)));
                 }
+                (Unop::Neg, FlatType::I32) => {
+                    // inverts both words and adds one, carrying into the high word
+                    let (dl, dh) = state.get_dwide(&dest);
+                    let (sl, sh) = state.get_dwide(&s);
+                    let ones = state.new_wide_reg();
+                    let one = state.new_wide_reg();
+                    let no_carry = state.new_label();
+                    code.push(Ins::LdiW(ones, Wi::Constant(u16::MAX)));
+                    code.push(Ins::XorW(dl, sl, ones));
+                    code.push(Ins::XorW(dh, sh, ones));
+                    code.push(Ins::LdiW(one, Wi::Constant(1)));
+                    code.push(Ins::AddW(dl, dl, one));
+                    code.push(Ins::Jnc(Wi::Symbol(no_carry.clone())));
+                    code.push(Ins::AddW(dh, dh, one));
+                    code.push(Ins::Label(no_carry));
+                }
+                (Unop::Not, FlatType::Bool) => {
+                    let one = state.new_byte_reg();
+                    code.push(Ins::LdiB(one, Bi::Constant(1)));
+                    code.push(Ins::XorB(state.get_byte(&dest), state.get_byte(&s), one));
+                }
+                (Unop::Not, FlatType::I8 | FlatType::U8) => {
+                    let ones = state.new_byte_reg();
+                    code.push(Ins::LdiB(ones, Bi::Constant(u8::MAX)));
+                    code.push(Ins::XorB(state.get_byte(&dest), state.get_byte(&s), ones));
+                }
+                (Unop::Not, FlatType::I16 | FlatType::U16) => {
+                    let ones = state.new_wide_reg();
+                    code.push(Ins::LdiW(ones, Wi::Constant(u16::MAX)));
+                    code.push(Ins::XorW(state.get_wide(&dest), state.get_wide(&s), ones));
+                }
+                (Unop::Not, FlatType::I32 | FlatType::U32) => {
+                    let (dl, dh) = state.get_dwide(&dest);
+                    let (sl, sh) = state.get_dwide(&s);
+                    let ones = state.new_wide_reg();
+                    code.push(Ins::LdiW(ones, Wi::Constant(u16::MAX)));
+                    code.push(Ins::XorW(dl, sl, ones));
+                    code.push(Ins::XorW(dh, sh, ones));
+                }
+                (Unop::Deref, t) => {
+                    let ptr = state.get_wide(&s);
+                    let offset = state.new_wide_reg();
+                    let dest_regs = state.get(&dest, Some(&t)).to_vec();
+                    for (r, off) in dest_regs.into_iter().zip(reg_offsets(&t)) {
+                        code.push(Ins::LdiW(offset, Wi::Constant(off)));
+                        match r {
+                            Reg::ByteReg(r) => code.push(Ins::LoadBR(r, ptr, offset)),
+                            Reg::WideReg(r) => code.push(Ins::LoadWR(r, ptr, offset)),
+                        }
+                    }
+                }
+                (Unop::Convert(from), to) => generate_convert(code, &mut state, dest, &to, s, &from),
                 _ => todo!(),
             },
             Line::SetCall(dest, t, f_name, arguments) => {
@@ -486,9 +805,12 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                     Ident::Global(g) => code.push(Ins::Call(Wi::Symbol(g.into_inner()))),
                     Ident::Temp(t) => {
                         let call_reg = state.global_state.get_call_reg();
-                        // HACK: using the frame pointer for register call destination
+                        // HACK: using the frame pointer for register call destination, so it is
+                        // saved for the stack variables of this function
+                        code.push(Ins::PushW(Wr::Rf));
                         code.push(Ins::MoveW(Wr::Rf, state.get_wide(&t)));
                         code.push(Ins::Call(Wi::Symbol(call_reg)));
+                        code.push(Ins::PopW(Wr::Rf));
                     }
                 }
                 let mut save_code = Vec::with_capacity(save_regs.len() + (save_r1) as usize);
@@ -502,9 +824,145 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                 // get return
                 code.extend(ret_code.into_iter().chain(save_code.into_iter()));
             }
-            Line::WriteTo(_, _, _) => todo!(),
-            Line::SetIndex(_, _, _) => todo!(),
-            Line::SetAddrOf(_, _, _) => todo!(),
+            Line::WriteTo(ptr, t, src) => {
+                let ptr = state.get_wide(&ptr);
+                let offset = state.new_wide_reg();
+                let src_regs = state.get(&src, Some(&t)).to_vec();
+                for (r, off) in src_regs.into_iter().zip(reg_offsets(&t)) {
+                    code.push(Ins::LdiW(offset, Wi::Constant(off)));
+                    match r {
+                        Reg::ByteReg(r) => code.push(Ins::StoreBR(ptr, offset, r)),
+                        Reg::WideReg(r) => code.push(Ins::StoreWR(ptr, offset, r)),
+                    }
+                }
+            }
+            Line::SetIndex(dest, t, index, src) => {
+                let FlatType::Arr(elem_t, len) = &t else {
+                    unreachable!("index into non-array")
+                };
+                let elem_regs = reg_count(elem_t);
+                let index = state.get_wide(&index);
+                let src_regs = state.get(&src, Some(elem_t)).to_vec();
+                let dest_regs = state.get(&dest, Some(&t)).to_vec();
+                generate_element_select(code, &mut state, index, *len, elem_regs, |code, skip| {
+                    generate_moves(code, &dest_regs[skip..skip + elem_regs], &src_regs);
+                });
+            }
+            Line::ReadField(dest, t, src, n) => {
+                let (skip, field_t) = match &t {
+                    FlatType::Struct(fields) => (
+                        fields[..n as usize].iter().map(reg_count).sum(),
+                        fields[n as usize].clone(),
+                    ),
+                    FlatType::Arr(elem_t, _) => (n as usize * reg_count(elem_t), (**elem_t).clone()),
+                    _ => unreachable!("field of non-aggregate"),
+                };
+                let src_regs = state.get(&src, Some(&t))[skip..].to_vec();
+                let dest_regs = state.get(&dest, Some(&field_t)).to_vec();
+                for (dest, src) in dest_regs.into_iter().zip(src_regs) {
+                    match (dest, src) {
+                        (Reg::ByteReg(dest), Reg::ByteReg(src)) => code.push(Ins::MoveB(dest, src)),
+                        (Reg::WideReg(dest), Reg::WideReg(src)) => code.push(Ins::MoveW(dest, src)),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Line::WriteField(dest, t, n, src) => {
+                let (skip, field_t) = match &t {
+                    FlatType::Struct(fields) => (
+                        fields[..n as usize].iter().map(reg_count).sum(),
+                        fields[n as usize].clone(),
+                    ),
+                    FlatType::Arr(elem_t, _) => (n as usize * reg_count(elem_t), (**elem_t).clone()),
+                    _ => unreachable!("field of non-aggregate"),
+                };
+                let src_regs = state.get(&src, Some(&field_t)).to_vec();
+                let dest_regs = state.get(&dest, Some(&t))[skip..].to_vec();
+                for (dest, src) in dest_regs.into_iter().zip(src_regs) {
+                    match (dest, src) {
+                        (Reg::ByteReg(dest), Reg::ByteReg(src)) => code.push(Ins::MoveB(dest, src)),
+                        (Reg::WideReg(dest), Reg::WideReg(src)) => code.push(Ins::MoveW(dest, src)),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Line::ReadIndex(dest, t, ptr, index) => {
+                let ptr = state.get_wide(&ptr);
+                let index = state.get_wide(&index);
+                let offset = state.new_wide_reg();
+                let step = state.new_wide_reg();
+                code.push(Ins::LdiW(step, Wi::Constant(sizeof(&t))));
+                code.push(Ins::MulW(R0, offset, index, step));
+                let mut to_offset = 0;
+                for r in state.get(&dest, Some(&t)).to_vec() {
+                    if to_offset != 0 {
+                        code.push(Ins::LdiW(step, Wi::Constant(to_offset)));
+                        code.push(Ins::AddW(offset, offset, step));
+                    }
+                    match r {
+                        Reg::ByteReg(r) => {
+                            code.push(Ins::LoadBR(r, ptr, offset));
+                            to_offset = 1;
+                        }
+                        Reg::WideReg(r) => {
+                            code.push(Ins::LoadWR(r, ptr, offset));
+                            to_offset = 2;
+                        }
+                    }
+                }
+            }
+            Line::ReadElement(dest, t, src, index) => {
+                let FlatType::Arr(elem_t, len) = &t else {
+                    unreachable!("index into non-array")
+                };
+                let elem_regs = reg_count(elem_t);
+                let index = state.get_wide(&index);
+                let src_regs = state.get(&src, Some(&t)).to_vec();
+                let dest_regs = state.get(&dest, Some(elem_t)).to_vec();
+                generate_element_select(code, &mut state, index, *len, elem_regs, |code, skip| {
+                    generate_moves(code, &dest_regs, &src_regs[skip..skip + elem_regs]);
+                });
+            }
+            Line::SetAlloc(dest, t, src) => {
+                let ptr = state.get_wide(&dest);
+                let next = state.new_wide_reg();
+                let size = state.new_wide_reg();
+                code.push(Ins::LoadWI(ptr, R0, Wi::Symbol(HEAP_NEXT.into())));
+                code.push(Ins::LdiW(size, Wi::Constant(sizeof(&t))));
+                code.push(Ins::AddW(next, ptr, size));
+                code.push(Ins::StoreWI(R0, Wi::Symbol(HEAP_NEXT.into()), next));
+
+                let offset = size;
+                let src_regs = state.get(&src, Some(&t)).to_vec();
+                for (r, off) in src_regs.into_iter().zip(reg_offsets(&t)) {
+                    code.push(Ins::LdiW(offset, Wi::Constant(off)));
+                    match r {
+                        Reg::ByteReg(r) => code.push(Ins::StoreBR(ptr, offset, r)),
+                        Reg::WideReg(r) => code.push(Ins::StoreWR(ptr, offset, r)),
+                    }
+                }
+            }
+            Line::SetAddrOf(dest, _, Ident::Global(g)) => {
+                code.push(Ins::LdiW(state.get_wide(&dest), Wi::Symbol(g.into_inner())));
+            }
+            Line::SetAddrOf(dest, _, Ident::Temp(t)) => {
+                let dest = state.get_wide(&dest);
+                code.push(Ins::LdiW(dest, Wi::Constant(state.slot(&t))));
+                code.push(Ins::AddW(dest, Rs, dest));
+            }
+            Line::SetElementAddr(dest, t, ptr, index) => {
+                let ptr = state.get_wide(&ptr);
+                let index = state.get_wide(&index);
+                let offset = state.new_wide_reg();
+                let step = state.new_wide_reg();
+                code.push(Ins::LdiW(step, Wi::Constant(sizeof(&t))));
+                code.push(Ins::MulW(R0, offset, index, step));
+                code.push(Ins::AddW(state.get_wide(&dest), ptr, offset));
+            }
+            // the value of a function is its address
+            Line::ReadGlobal(dest, FlatType::FnPtr(_, _), glbl) => {
+                code.push(Ins::LdiW(state.get_wide(&dest), Wi::Symbol(glbl.into_inner())));
+            }
             Line::ReadGlobal(dest, t, glbl) => {
                 let offset = state.new_wide_reg();
                 let one = state.new_wide_reg();
@@ -513,7 +971,7 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                 let mut to_offset = 0;
                 for r in state.get(&dest, Some(&t)) {
                     for _ in 0..to_offset {
-                        code.push(Ins::AddW(offset, R0, one));
+                        code.push(Ins::AddW(offset, offset, one));
                     }
                     match *r {
                         Reg::ByteReg(r) => {
@@ -535,7 +993,7 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                 let mut to_offset = 0;
                 for r in state.get(&src, Some(&t)) {
                     for _ in 0..to_offset {
-                        code.push(Ins::AddW(offset, R0, one));
+                        code.push(Ins::AddW(offset, offset, one));
                     }
                     match *r {
                         Reg::ByteReg(r) => {
@@ -562,13 +1020,16 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
             }
             Line::If(cond, true_lbl, false_lbl) => {
                 code.push(Ins::SubB(R0b, state.get_byte(&cond), R0b));
-                code.push(Ins::Jez(Wi::Symbol(state.get_label(&true_lbl))));
+                code.push(Ins::Jnz(Wi::Symbol(state.get_label(&true_lbl))));
                 code.push(Ins::Jump(Wi::Symbol(state.get_label(&false_lbl))));
             }
             Line::Goto(lbl) => {
                 code.push(Ins::Jump(Wi::Symbol(state.get_label(&lbl))));
             }
             Line::Ret(rets) => {
+                if !state.addressed.is_empty() {
+                    state.generate_frame_adjust(code, Ins::AddW);
+                }
                 let mut ret_reg = Some(R1);
                 for ret_dest in state.get(&rets, None).iter().rev() {
                     match (*ret_dest, ret_reg.take()) {
@@ -583,12 +1044,184 @@ fn generate_fn(code: &mut Vec<Ins>, mut state: FunctionState, name: Global, f: F
                 // TODO: put the right value here to clean up objects stored in stack-space
                 code.push(Ins::Ret(Bi::Constant(0)));
             }
-            Line::Panic(_) => todo!(),
+            Line::Panic(loc, reason) => {
+                // executing a null instruction halts the machine
+                code.push(Ins::Comment(
+                    format!("panic: :{}:{}: {reason}", loc.line_start, loc.col_start).into(),
+                ));
+                code.push(Ins::Null);
+            }
+        }
+        if let Some(temp) = write.filter(|t| state.addressed.contains(t)) {
+            state.generate_store(code, &temp);
         }
     }
+    for i in state.frame_size_loads {
+        let Ins::LdiW(_, size) = &mut code[i] else {
+            unreachable!()
+        };
+        *size = Wi::Constant(state.frame_size);
+    }
     code.push(Ins::FunctionEndMarker);
 }
 
+/// The temps a line reads, and the one it writes, which is also read if only part of it is written
+fn line_temps(line: &Line) -> (Vec<Temp>, Option<Temp>) {
+    match line {
+        Line::SetConst(dest, _, _)
+        | Line::ReadGlobal(dest, _, _)
+        | Line::SetAddrOf(dest, _, _) => (vec![], Some(dest.clone())),
+        Line::SetTo(dest, _, src)
+        | Line::SetUnop(dest, _, _, src)
+        | Line::ReadField(dest, _, src, _)
+        | Line::SetAlloc(dest, _, src) => (vec![src.clone()], Some(dest.clone())),
+        Line::SetBinop(dest, _, _, a, b)
+        | Line::ReadIndex(dest, _, a, b)
+        | Line::ReadElement(dest, _, a, b)
+        | Line::SetElementAddr(dest, _, a, b) => (vec![a.clone(), b.clone()], Some(dest.clone())),
+        Line::SetCall(dest, _, f, args) => {
+            let mut reads = args.to_vec();
+            if let Ident::Temp(f) = f {
+                reads.push(f.clone());
+            }
+            (reads, Some(dest.clone()))
+        }
+        Line::SetIndex(dest, _, index, src) => {
+            (vec![dest.clone(), index.clone(), src.clone()], Some(dest.clone()))
+        }
+        Line::WriteField(dest, _, _, src) => (vec![dest.clone(), src.clone()], Some(dest.clone())),
+        Line::WriteTo(ptr, _, src) => (vec![ptr.clone(), src.clone()], None),
+        Line::WriteGlobal(_, _, src) | Line::If(src, _, _) | Line::Ret(src) => {
+            (vec![src.clone()], None)
+        }
+        Line::Label(_) | Line::Goto(_) | Line::Panic(_, _) => (vec![], None),
+    }
+}
+
+/// Shifts by the width of the type or more, which every negative count is, shift out every bit,
+/// so the count is checked before using the shift instructions
+fn generate_set_binop_shift(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, r: Binop, t: FlatType, dest: Temp, t1: Temp, t2: Temp) {
+    let signed = matches!(t, FlatType::I8 | FlatType::I16 | FlatType::I32);
+    let in_range = state.new_label();
+    let end = state.new_label();
+
+    match t {
+        FlatType::I8 | FlatType::U8 => {
+            let (d, a, c) = (state.get_byte(&dest), state.get_byte(&t1), state.get_byte(&t2));
+            let width = state.new_byte_reg();
+            code.push(Ins::LdiB(width, Bi::Constant(8)));
+            code.push(Ins::SubB(R0b, c, width));
+            code.push(Ins::Jb(Wi::Symbol(in_range.clone())));
+            if r == Binop::Shr && signed {
+                code.push(Ins::LdiB(width, Bi::Constant(7)));
+                code.push(Ins::AsrB(d, a, width));
+            } else {
+                code.push(Ins::LdiB(d, Bi::Constant(0)));
+            }
+            code.push(Ins::Jump(Wi::Symbol(end.clone())));
+            code.push(Ins::Label(in_range));
+            code.push(match (r, signed) {
+                (Binop::Shl, _) => Ins::ShlB(d, a, c),
+                (_, true) => Ins::AsrB(d, a, c),
+                (_, false) => Ins::LsrB(d, a, c),
+            });
+        }
+        FlatType::I16 | FlatType::U16 => {
+            let (d, a, c) = (state.get_wide(&dest), state.get_wide(&t1), state.get_wide(&t2));
+            let width = state.new_wide_reg();
+            code.push(Ins::LdiW(width, Wi::Constant(16)));
+            code.push(Ins::SubW(R0, c, width));
+            code.push(Ins::Jb(Wi::Symbol(in_range.clone())));
+            if r == Binop::Shr && signed {
+                code.push(Ins::LdiW(width, Wi::Constant(15)));
+                code.push(Ins::AsrW(d, a, width));
+            } else {
+                code.push(Ins::LdiW(d, Wi::Constant(0)));
+            }
+            code.push(Ins::Jump(Wi::Symbol(end.clone())));
+            code.push(Ins::Label(in_range));
+            code.push(match (r, signed) {
+                (Binop::Shl, _) => Ins::ShlW(d, a, c),
+                (_, true) => Ins::AsrW(d, a, c),
+                (_, false) => Ins::LsrW(d, a, c),
+            });
+        }
+        FlatType::I32 | FlatType::U32 => {
+            let (dl, dh) = state.get_dwide(&dest);
+            let (l, h) = state.get_dwide(&t1);
+            let (cl, ch) = state.get_dwide(&t2);
+            let k = state.new_wide_reg();
+            let other = state.new_wide_reg();
+            let whole_word = state.new_label();
+            let out_of_range = state.new_label();
+            let no_shift = state.new_label();
+            let shr_high: fn(Wr, Wr, Wr) -> Ins = if signed { Ins::AsrW } else { Ins::LsrW };
+            // the high word of a right shift once it has all been shifted out
+            let fill_high = |code: &mut Vec<Ins>| {
+                if signed {
+                    code.push(Ins::LdiW(k, Wi::Constant(15)));
+                    code.push(Ins::AsrW(dh, h, k));
+                } else {
+                    code.push(Ins::MoveW(dh, R0));
+                }
+            };
+
+            code.push(Ins::SubW(R0, ch, R0));
+            code.push(Ins::Jnz(Wi::Symbol(out_of_range.clone())));
+            code.push(Ins::LdiW(k, Wi::Constant(32)));
+            code.push(Ins::SubW(R0, cl, k));
+            code.push(Ins::Jae(Wi::Symbol(out_of_range.clone())));
+            code.push(Ins::LdiW(k, Wi::Constant(16)));
+            code.push(Ins::SubW(R0, cl, k));
+            code.push(Ins::Jae(Wi::Symbol(whole_word.clone())));
+            code.push(Ins::SubW(R0, cl, R0));
+            code.push(Ins::Jez(Wi::Symbol(no_shift.clone())));
+
+            // bits move across from the other word, which is shifted the other way by the rest
+            code.push(Ins::SubW(other, k, cl));
+            if r == Binop::Shl {
+                code.push(Ins::ShlW(dh, h, cl));
+                code.push(Ins::LsrW(other, l, other));
+                code.push(Ins::OrW(dh, dh, other));
+                code.push(Ins::ShlW(dl, l, cl));
+            } else {
+                code.push(Ins::LsrW(dl, l, cl));
+                code.push(Ins::ShlW(other, h, other));
+                code.push(Ins::OrW(dl, dl, other));
+                code.push(shr_high(dh, h, cl));
+            }
+            code.push(Ins::Jump(Wi::Symbol(end.clone())));
+
+            // one word is shifted out and the other one into its place
+            code.push(Ins::Label(whole_word));
+            code.push(Ins::SubW(other, cl, k));
+            if r == Binop::Shl {
+                code.push(Ins::ShlW(dh, l, other));
+                code.push(Ins::MoveW(dl, R0));
+            } else {
+                code.push(shr_high(dl, h, other));
+                fill_high(code);
+            }
+            code.push(Ins::Jump(Wi::Symbol(end.clone())));
+
+            code.push(Ins::Label(out_of_range));
+            if r == Binop::Shl {
+                code.push(Ins::MoveW(dh, R0));
+            } else {
+                fill_high(code);
+            }
+            code.push(Ins::MoveW(dl, dh));
+            code.push(Ins::Jump(Wi::Symbol(end.clone())));
+
+            code.push(Ins::Label(no_shift));
+            code.push(Ins::MoveW(dl, l));
+            code.push(Ins::MoveW(dh, h));
+        }
+        _ => unreachable!(),
+    }
+    code.push(Ins::Label(end));
+}
+
 fn generate_set_binop_rel(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, r: Binop, t: FlatType, dest: Temp, t1: Temp, t2: Temp) {
     type JumpIns = fn(Wi) -> Ins;
     let (ucjmp, scjmp, unotcjmp, snotcjmp): (JumpIns, JumpIns, JumpIns, JumpIns) = match r {
@@ -598,7 +1231,10 @@ fn generate_set_binop_rel(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, r:
         Binop::Lte => (Ins::Jbe, Ins::Jle, Ins::Ja, Ins::Jgt),
         Binop::Gt => (Ins::Ja, Ins::Jgt, Ins::Jbe, Ins::Jle),
         Binop::Gte => (Ins::Jae, Ins::Jge, Ins::Jb, Ins::Jlt),
-        Binop::Mul | Binop::Add | Binop::Div | Binop::Sub => unreachable!(),
+        Binop::Mul | Binop::Add | Binop::Div | Binop::Rem | Binop::Sub => unreachable!(),
+        Binop::SatAdd | Binop::SatSub | Binop::SatMul => unreachable!(),
+        Binop::AddOverflows | Binop::SubOverflows | Binop::MulOverflows => unreachable!(),
+        Binop::And | Binop::Or | Binop::Xor | Binop::Shl | Binop::Shr => unreachable!(),
     };
     let dest = state.get_byte(&dest);
 
@@ -661,3 +1297,614 @@ fn generate_set_binop_rel(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, r:
     code.push(Ins::LdiB(dest, Bi::Constant(1)));
     code.push(Ins::Label(end_label));
 }
+
+/// Converts `s` of the number type `from` into `dest` of the number type `to`, keeping the low
+/// bytes and filling new high ones with the sign of signed types or else with zeros
+///
+/// Bytes and wides are moved between each other through the stack, which grows down and keeps the
+/// low byte of a wide first.
+fn generate_convert(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, dest: Temp, to: &FlatType, s: Temp, from: &FlatType) {
+    let signed = matches!(from, FlatType::I8 | FlatType::I16 | FlatType::I32);
+    match (sizeof(from), sizeof(to)) {
+        (1, 1) => code.push(Ins::MoveB(state.get_byte(&dest), state.get_byte(&s))),
+        (2, 2) => code.push(Ins::MoveW(state.get_wide(&dest), state.get_wide(&s))),
+        (4, 4) => {
+            let (dl, dh) = state.get_dwide(&dest);
+            let (sl, sh) = state.get_dwide(&s);
+            code.push(Ins::MoveW(dl, sl));
+            code.push(Ins::MoveW(dh, sh));
+        }
+        (1, to_size) => {
+            let b = state.get_byte(&s);
+            let high = if signed {
+                let seven = state.new_byte_reg();
+                let sign = state.new_byte_reg();
+                code.push(Ins::LdiB(seven, Bi::Constant(7)));
+                code.push(Ins::AsrB(sign, b, seven));
+                sign
+            } else {
+                R0b
+            };
+            let low = if to_size == 2 { state.get_wide(&dest) } else { state.get_dwide(&dest).0 };
+            code.push(Ins::PushB(high));
+            code.push(Ins::PushB(b));
+            code.push(Ins::PopW(low));
+            if to_size == 4 {
+                let (_, dh) = state.get_dwide(&dest);
+                generate_sign_extension(code, state, signed, dh, low);
+            }
+        }
+        (2, 4) => {
+            let w = state.get_wide(&s);
+            let (dl, dh) = state.get_dwide(&dest);
+            code.push(Ins::MoveW(dl, w));
+            generate_sign_extension(code, state, signed, dh, w);
+        }
+        (from_size, 1) => {
+            let low = if from_size == 2 { state.get_wide(&s) } else { state.get_dwide(&s).0 };
+            let high = state.new_byte_reg();
+            code.push(Ins::PushW(low));
+            code.push(Ins::PopB(state.get_byte(&dest)));
+            code.push(Ins::PopB(high));
+        }
+        (4, 2) => code.push(Ins::MoveW(state.get_wide(&dest), state.get_dwide(&s).0)),
+        _ => unreachable!("conversion from {from} to {to}"),
+    }
+}
+
+/// Sets `high` to the wide that extends `low` to 32 bits, which is all ones if `signed` and `low`
+/// is negative and otherwise zero
+fn generate_sign_extension(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, signed: bool, high: Wr, low: Wr) {
+    if signed {
+        let fifteen = state.new_wide_reg();
+        code.push(Ins::LdiW(fifteen, Wi::Constant(15)));
+        code.push(Ins::AsrW(high, low, fifteen));
+    } else {
+        code.push(Ins::MoveW(high, R0));
+    }
+}
+
+/// Does the wrapping arithmetic `op` into `res`, returning the jump to take if it overflowed `t`
+fn generate_overflowing_op(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, op: Binop, t: &FlatType, res: Reg, t1: &Temp, t2: &Temp) -> fn(Wi) -> Ins {
+    let signed = matches!(t, FlatType::I8 | FlatType::I16);
+    // signed overflow sets the overflow flag, unsigned overflow the carry flag
+    let ojmp: fn(Wi) -> Ins = if signed { Ins::Jo } else { Ins::Jb };
+
+    match (op, res) {
+        (Binop::Add, Reg::ByteReg(res)) => {
+            code.push(Ins::AddB(res, state.get_byte(t1), state.get_byte(t2)));
+            ojmp
+        }
+        (Binop::Add, Reg::WideReg(res)) => {
+            code.push(Ins::AddW(res, state.get_wide(t1), state.get_wide(t2)));
+            ojmp
+        }
+        (Binop::Sub, Reg::ByteReg(res)) => {
+            code.push(Ins::SubB(res, state.get_byte(t1), state.get_byte(t2)));
+            ojmp
+        }
+        (Binop::Sub, Reg::WideReg(res)) => {
+            code.push(Ins::SubW(res, state.get_wide(t1), state.get_wide(t2)));
+            ojmp
+        }
+        (Binop::Mul, Reg::ByteReg(res)) => {
+            let (a, b) = (state.get_byte(t1), state.get_byte(t2));
+            let high = state.new_byte_reg();
+            code.push(Ins::MulB(high, res, a, b));
+            let expected_high = if signed {
+                let seven = state.new_byte_reg();
+                let sign = state.new_byte_reg();
+                code.push(Ins::LdiB(seven, Bi::Constant(7)));
+                // the multiplication is unsigned, so subtract the other operand for each negative one
+                code.push(Ins::AsrB(sign, a, seven));
+                code.push(Ins::AndB(sign, sign, b));
+                code.push(Ins::SubB(high, high, sign));
+                code.push(Ins::AsrB(sign, b, seven));
+                code.push(Ins::AndB(sign, sign, a));
+                code.push(Ins::SubB(high, high, sign));
+                // a signed result fits if the high part is just the sign extension of the low part
+                code.push(Ins::AsrB(sign, res, seven));
+                sign
+            } else {
+                R0b
+            };
+            code.push(Ins::SubB(R0b, high, expected_high));
+            Ins::Jnz
+        }
+        (Binop::Mul, Reg::WideReg(res)) => {
+            let (a, b) = (state.get_wide(t1), state.get_wide(t2));
+            let high = state.new_wide_reg();
+            code.push(Ins::MulW(high, res, a, b));
+            let expected_high = if signed {
+                let fifteen = state.new_wide_reg();
+                let sign = state.new_wide_reg();
+                code.push(Ins::LdiW(fifteen, Wi::Constant(15)));
+                // the multiplication is unsigned, so subtract the other operand for each negative one
+                code.push(Ins::AsrW(sign, a, fifteen));
+                code.push(Ins::AndW(sign, sign, b));
+                code.push(Ins::SubW(high, high, sign));
+                code.push(Ins::AsrW(sign, b, fifteen));
+                code.push(Ins::AndW(sign, sign, a));
+                code.push(Ins::SubW(high, high, sign));
+                // a signed result fits if the high part is just the sign extension of the low part
+                code.push(Ins::AsrW(sign, res, fifteen));
+                sign
+            } else {
+                R0
+            };
+            code.push(Ins::SubW(R0, high, expected_high));
+            Ins::Jnz
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Does the wrapping 32-bit arithmetic `op` into `dest`
+fn generate_dwide_op(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, op: Binop, dest: &Temp, t1: &Temp, t2: &Temp) {
+    let (dl, dh) = state.get_dwide(dest);
+    let (al, ah) = state.get_dwide(t1);
+    let (bl, bh) = state.get_dwide(t2);
+    let temp = state.new_wide_reg();
+
+    match op {
+        // TODO: use add with carry and sub with borrow instructions when they are out
+        Binop::Add | Binop::Sub => {
+            // `dh` is the carry or borrow out of the low words, then the high words are added to it
+            let no_carry = state.new_label();
+            code.push(Ins::MoveW(dh, R0));
+            if op == Binop::Add {
+                code.push(Ins::AddW(dl, al, bl));
+            } else {
+                code.push(Ins::SubW(dl, al, bl));
+            }
+            code.push(Ins::Jnc(Wi::Symbol(no_carry.clone())));
+            code.push(Ins::LdiW(dh, Wi::Constant(1)));
+            code.push(Ins::Label(no_carry));
+            if op == Binop::Add {
+                code.push(Ins::AddW(temp, ah, bh));
+                code.push(Ins::AddW(dh, dh, temp));
+            } else {
+                code.push(Ins::SubW(temp, ah, bh));
+                code.push(Ins::SubW(dh, temp, dh));
+            }
+        }
+        Binop::Mul => {
+            // the high words times each other only affect bits past the 32nd
+            code.push(Ins::MulW(dh, dl, al, bl));
+            code.push(Ins::MulW(R0, temp, al, bh));
+            code.push(Ins::AddW(dh, dh, temp));
+            code.push(Ins::MulW(R0, temp, ah, bl));
+            code.push(Ins::AddW(dh, dh, temp));
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Jumps to `overflow_label` if the unsigned 32-bit product of `a` and `b` does not fit in 32
+/// bits, giving the high word of the product otherwise
+fn generate_dwide_mul_overflow_jump(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, (al, ah): (Wr, Wr), (bl, bh): (Wr, Wr), overflow_label: &Rc<str>) -> Wr {
+    let high = state.new_wide_reg();
+    let cross = state.new_wide_reg();
+    let temp = state.new_wide_reg();
+    let one_high = state.new_label();
+
+    // at most one of the high words can be non-zero
+    code.push(Ins::SubW(R0, ah, R0));
+    code.push(Ins::Jez(Wi::Symbol(one_high.clone())));
+    code.push(Ins::SubW(R0, bh, R0));
+    code.push(Ins::Jnz(Wi::Symbol(overflow_label.clone())));
+    code.push(Ins::Label(one_high));
+    // and then its product with the other low word has to fit in a word
+    code.push(Ins::MulW(temp, cross, ah, bl));
+    code.push(Ins::SubW(R0, temp, R0));
+    code.push(Ins::Jnz(Wi::Symbol(overflow_label.clone())));
+    code.push(Ins::MulW(temp, high, al, bh));
+    code.push(Ins::SubW(R0, temp, R0));
+    code.push(Ins::Jnz(Wi::Symbol(overflow_label.clone())));
+    code.push(Ins::AddW(cross, cross, high));
+    // as does its sum with the high word of the product of the low words
+    code.push(Ins::MulW(high, R0, al, bl));
+    code.push(Ins::AddW(high, high, cross));
+    code.push(Ins::Jc(Wi::Symbol(overflow_label.clone())));
+    high
+}
+
+/// Replaces the signed 32-bit number `(l, h)` with its magnitude, which fits in an unsigned one
+fn generate_dwide_abs(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, l: Wr, h: Wr) {
+    let fifteen = state.new_wide_reg();
+    let sign = state.new_wide_reg();
+
+    code.push(Ins::LdiW(fifteen, Wi::Constant(15)));
+    code.push(Ins::AsrW(sign, h, fifteen));
+    generate_dwide_negate_if(code, state, l, h, sign);
+}
+
+/// Negates the 32-bit number `(l, h)` in place if `sign` is all ones, leaving it if it is zero
+fn generate_dwide_negate_if(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, l: Wr, h: Wr, sign: Wr) {
+    let one = state.new_wide_reg();
+    let no_carry = state.new_label();
+
+    // negating is flipping every bit and adding one
+    code.push(Ins::XorW(l, l, sign));
+    code.push(Ins::XorW(h, h, sign));
+    code.push(Ins::LdiW(one, Wi::Constant(1)));
+    code.push(Ins::AndW(sign, sign, one));
+    code.push(Ins::AddW(l, l, sign));
+    code.push(Ins::Jnc(Wi::Symbol(no_carry.clone())));
+    code.push(Ins::AddW(h, h, one));
+    code.push(Ins::Label(no_carry));
+}
+
+/// Divides the unsigned 32-bit number `(ql, qh)` by `(bl, bh)` a bit at a time, leaving the
+/// quotient in its place and writing the remainder to `(rl, rh)`
+///
+/// Only the numbers stay in registers during the loop, so it can be used where many other values
+/// are live
+fn generate_dwide_udiv(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, (ql, qh): (Wr, Wr), (bl, bh): (Wr, Wr), (rl, rh): (Wr, Wr)) {
+    let [next_bit, bit_out, subtract, keep, done] = [(); 5].map(|()| state.new_label());
+    // `r |= 1`, with the one only living for as long as it is needed
+    let set_low_bit = |code: &mut Vec<Ins>, state: &mut FunctionState<'_>, r: Wr| {
+        let one = state.new_wide_reg();
+        code.push(Ins::LdiW(one, Wi::Constant(1)));
+        code.push(Ins::OrW(r, r, one));
+    };
+    // the remainder and quotient are shifted left as one number
+    let shift = |code: &mut Vec<Ins>, state: &mut FunctionState<'_>| {
+        let [c0, c1, c2] = [(); 3].map(|()| state.new_label());
+        code.push(Ins::AddW(rh, rh, rh));
+        code.push(Ins::AddW(rl, rl, rl));
+        code.push(Ins::Jnc(Wi::Symbol(c0.clone())));
+        set_low_bit(code, state, rh);
+        code.push(Ins::Label(c0));
+        code.push(Ins::AddW(qh, qh, qh));
+        code.push(Ins::Jnc(Wi::Symbol(c1.clone())));
+        set_low_bit(code, state, rl);
+        code.push(Ins::Label(c1));
+        code.push(Ins::AddW(ql, ql, ql));
+        code.push(Ins::Jnc(Wi::Symbol(c2.clone())));
+        set_low_bit(code, state, qh);
+        code.push(Ins::Label(c2));
+    };
+
+    code.push(Ins::MoveW(rl, R0));
+    code.push(Ins::MoveW(rh, R0));
+    // the number of bits left is kept on the stack
+    let bits = state.new_wide_reg();
+    code.push(Ins::LdiW(bits, Wi::Constant(32)));
+    code.push(Ins::PushW(bits));
+    code.push(Ins::Label(next_bit.clone()));
+
+    // the divisor always goes into the remainder if a bit is shifted out of it
+    code.push(Ins::SubW(R0, rh, R0));
+    code.push(Ins::Jlt(Wi::Symbol(bit_out.clone())));
+    shift(code, state);
+    code.push(Ins::SubW(R0, rh, bh));
+    code.push(Ins::Jb(Wi::Symbol(keep.clone())));
+    code.push(Ins::Jnz(Wi::Symbol(subtract.clone())));
+    code.push(Ins::SubW(R0, rl, bl));
+    code.push(Ins::Jb(Wi::Symbol(keep.clone())));
+    code.push(Ins::Jump(Wi::Symbol(subtract.clone())));
+    code.push(Ins::Label(bit_out));
+    shift(code, state);
+
+    code.push(Ins::Label(subtract));
+    let no_borrow = state.new_label();
+    code.push(Ins::SubW(rl, rl, bl));
+    code.push(Ins::Jnc(Wi::Symbol(no_borrow.clone())));
+    let one = state.new_wide_reg();
+    code.push(Ins::LdiW(one, Wi::Constant(1)));
+    code.push(Ins::SubW(rh, rh, one));
+    code.push(Ins::Label(no_borrow));
+    code.push(Ins::SubW(rh, rh, bh));
+    set_low_bit(code, state, ql);
+    code.push(Ins::Label(keep));
+
+    let bits = state.new_wide_reg();
+    let one = state.new_wide_reg();
+    code.push(Ins::PopW(bits));
+    code.push(Ins::LdiW(one, Wi::Constant(1)));
+    code.push(Ins::SubW(bits, bits, one));
+    code.push(Ins::Jez(Wi::Symbol(done.clone())));
+    code.push(Ins::PushW(bits));
+    code.push(Ins::Jump(Wi::Symbol(next_bit)));
+    code.push(Ins::Label(done));
+}
+
+/// Writes the 32-bit quotient or remainder (`op`) into `dest`, which like in the interpreter are
+/// truncated towards zero and wrap for the smallest number divided by -1
+fn generate_dwide_div(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, op: Binop, t: &FlatType, dest: &Temp, t1: &Temp, t2: &Temp) {
+    let (dl, dh) = state.get_dwide(dest);
+    let (al, ah) = state.get_dwide(t1);
+    let (bl, bh) = state.get_dwide(t2);
+    // the number that is divided in place, which is the quotient if that is what is wanted
+    let (ql, qh) = match op {
+        Binop::Div => (dl, dh),
+        _ => (state.new_wide_reg(), state.new_wide_reg()),
+    };
+    let (rl, rh) = match op {
+        Binop::Div => (state.new_wide_reg(), state.new_wide_reg()),
+        _ => (dl, dh),
+    };
+    code.push(Ins::MoveW(ql, al));
+    code.push(Ins::MoveW(qh, ah));
+
+    if *t == FlatType::U32 {
+        generate_dwide_udiv(code, state, (ql, qh), (bl, bh), (rl, rh));
+        return;
+    }
+
+    // the magnitudes are divided, the quotient is negative if the signs differ and the remainder
+    // has the sign of the dividend
+    let fifteen = state.new_wide_reg();
+    let sign = state.new_wide_reg();
+    code.push(Ins::LdiW(fifteen, Wi::Constant(15)));
+    if op == Binop::Div {
+        code.push(Ins::XorW(sign, ah, bh));
+        code.push(Ins::AsrW(sign, sign, fifteen));
+    } else {
+        code.push(Ins::AsrW(sign, ah, fifteen));
+    }
+    // the divisor's magnitude is taken in place, so it is saved on the stack with the sign
+    code.push(Ins::PushW(bl));
+    code.push(Ins::PushW(bh));
+    code.push(Ins::PushW(sign));
+    generate_dwide_abs(code, state, ql, qh);
+    generate_dwide_abs(code, state, bl, bh);
+    generate_dwide_udiv(code, state, (ql, qh), (bl, bh), (rl, rh));
+    let sign = state.new_wide_reg();
+    code.push(Ins::PopW(sign));
+    code.push(Ins::PopW(bh));
+    code.push(Ins::PopW(bl));
+    generate_dwide_negate_if(code, state, dl, dh, sign);
+}
+
+/// Jumps to `overflow_label` if the 32-bit arithmetic `op` would overflow `t`
+fn generate_dwide_overflow_jump(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, op: Binop, t: &FlatType, t1: &Temp, t2: &Temp, overflow_label: Rc<str>) {
+    let (al, ah) = state.get_dwide(t1);
+    let (bl, bh) = state.get_dwide(t2);
+    let fits_label = state.new_label();
+
+    match (op, t) {
+        (Binop::Add, FlatType::U32) => {
+            let high = state.new_wide_reg();
+            let one = state.new_wide_reg();
+            code.push(Ins::AddW(high, ah, bh));
+            code.push(Ins::Jc(Wi::Symbol(overflow_label.clone())));
+            // the carry from the low words only overflows if the high words sum to all ones
+            code.push(Ins::AddW(R0, al, bl));
+            code.push(Ins::Jnc(Wi::Symbol(fits_label.clone())));
+            code.push(Ins::LdiW(one, Wi::Constant(1)));
+            code.push(Ins::AddW(R0, high, one));
+            code.push(Ins::Jc(Wi::Symbol(overflow_label)));
+        }
+        (Binop::Sub, FlatType::U32) => {
+            // it overflows exactly when `t1 < t2`
+            code.push(Ins::SubW(R0, ah, bh));
+            code.push(Ins::Jb(Wi::Symbol(overflow_label.clone())));
+            code.push(Ins::Jnz(Wi::Symbol(fits_label.clone())));
+            code.push(Ins::SubW(R0, al, bl));
+            code.push(Ins::Jb(Wi::Symbol(overflow_label)));
+        }
+        (Binop::Add | Binop::Sub, FlatType::I32) => {
+            let carry = state.new_wide_reg();
+            let high = state.new_wide_reg();
+            let sign = state.new_wide_reg();
+            let temp = state.new_wide_reg();
+            let no_carry = state.new_label();
+
+            code.push(Ins::MoveW(carry, R0));
+            if op == Binop::Add {
+                code.push(Ins::AddW(R0, al, bl));
+            } else {
+                code.push(Ins::SubW(R0, al, bl));
+            }
+            code.push(Ins::Jnc(Wi::Symbol(no_carry.clone())));
+            code.push(Ins::LdiW(carry, Wi::Constant(1)));
+            code.push(Ins::Label(no_carry));
+
+            // signed overflow happened if the sign of the result is wrong for the signs of the operands
+            if op == Binop::Add {
+                code.push(Ins::AddW(high, ah, bh));
+                code.push(Ins::AddW(high, high, carry));
+                code.push(Ins::XorW(sign, high, ah));
+                code.push(Ins::XorW(temp, high, bh));
+            } else {
+                code.push(Ins::SubW(high, ah, bh));
+                code.push(Ins::SubW(high, high, carry));
+                code.push(Ins::XorW(sign, ah, bh));
+                code.push(Ins::XorW(temp, ah, high));
+            }
+            code.push(Ins::AndW(sign, sign, temp));
+            code.push(Ins::SubW(R0, sign, R0));
+            code.push(Ins::Jlt(Wi::Symbol(overflow_label)));
+        }
+        (Binop::Mul, FlatType::U32) => {
+            generate_dwide_mul_overflow_jump(code, state, (al, ah), (bl, bh), &overflow_label);
+        }
+        (Binop::Mul, FlatType::I32) => {
+            // the magnitudes are multiplied, which fits if it is below 2^31, or is 2^31 and negative
+            // they are taken in place, so the operands are saved on the stack and put back on
+            // every way out of the check
+            let operands = [al, ah, bl, bh];
+            let restore = |code: &mut Vec<Ins>| {
+                for r in operands.into_iter().rev() {
+                    code.push(Ins::PopW(r));
+                }
+            };
+            for r in operands {
+                code.push(Ins::PushW(r));
+            }
+            generate_dwide_abs(code, state, al, ah);
+            generate_dwide_abs(code, state, bl, bh);
+            let restore_overflow = state.new_label();
+            let high = generate_dwide_mul_overflow_jump(code, state, (al, ah), (bl, bh), &restore_overflow);
+
+            let restore_fits = state.new_label();
+            let limit = state.new_wide_reg();
+            let low = state.new_wide_reg();
+            code.push(Ins::LdiW(limit, Wi::Constant(0x8000)));
+            code.push(Ins::SubW(R0, high, limit));
+            code.push(Ins::Jb(Wi::Symbol(restore_fits.clone())));
+            code.push(Ins::Jnz(Wi::Symbol(restore_overflow.clone())));
+            code.push(Ins::MulW(R0, low, al, bl));
+            code.push(Ins::SubW(R0, low, R0));
+            code.push(Ins::Jnz(Wi::Symbol(restore_overflow.clone())));
+            // exactly 2^31, so the signs of the operands have to differ
+            restore(code);
+            let fifteen = state.new_wide_reg();
+            let sign = state.new_wide_reg();
+            code.push(Ins::LdiW(fifteen, Wi::Constant(15)));
+            code.push(Ins::XorW(sign, ah, bh));
+            code.push(Ins::AsrW(sign, sign, fifteen));
+            code.push(Ins::SubW(R0, sign, R0));
+            code.push(Ins::Jez(Wi::Symbol(overflow_label.clone())));
+            code.push(Ins::Jump(Wi::Symbol(fits_label.clone())));
+
+            code.push(Ins::Label(restore_fits));
+            restore(code);
+            code.push(Ins::Jump(Wi::Symbol(fits_label.clone())));
+            code.push(Ins::Label(restore_overflow));
+            restore(code);
+            code.push(Ins::Jump(Wi::Symbol(overflow_label)));
+        }
+        _ => unreachable!(),
+    }
+    if Rc::strong_count(&fits_label) > 1 {
+        code.push(Ins::Label(fits_label));
+    }
+}
+
+fn generate_set_binop_overflows(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, r: Binop, t: FlatType, dest: Temp, t1: Temp, t2: Temp) {
+    let op = match r {
+        Binop::AddOverflows => Binop::Add,
+        Binop::SubOverflows => Binop::Sub,
+        Binop::MulOverflows => Binop::Mul,
+        _ => unreachable!(),
+    };
+    let dest = state.get_byte(&dest);
+
+    let overflow_label = state.new_label();
+    let end_label = state.new_label();
+
+    match t {
+        FlatType::I8 | FlatType::U8 => {
+            let res = Reg::ByteReg(state.new_byte_reg());
+            let ojmp = generate_overflowing_op(code, state, op, &t, res, &t1, &t2);
+            code.push(ojmp(Wi::Symbol(overflow_label.clone())));
+        }
+        FlatType::I16 | FlatType::U16 => {
+            let res = Reg::WideReg(state.new_wide_reg());
+            let ojmp = generate_overflowing_op(code, state, op, &t, res, &t1, &t2);
+            code.push(ojmp(Wi::Symbol(overflow_label.clone())));
+        }
+        FlatType::I32 | FlatType::U32 => {
+            generate_dwide_overflow_jump(code, state, op, &t, &t1, &t2, overflow_label.clone());
+        }
+        _ => unreachable!(),
+    }
+    code.push(Ins::LdiB(dest, Bi::Constant(0)));
+    code.push(Ins::Jump(Wi::Symbol(end_label.clone())));
+    code.push(Ins::Label(overflow_label));
+    code.push(Ins::LdiB(dest, Bi::Constant(1)));
+    code.push(Ins::Label(end_label));
+}
+
+fn generate_set_binop_saturating(code: &mut Vec<Ins>, state: &mut FunctionState<'_>, r: Binop, t: FlatType, dest: Temp, t1: Temp, t2: Temp) {
+    let op = match r {
+        Binop::SatAdd => Binop::Add,
+        Binop::SatSub => Binop::Sub,
+        Binop::SatMul => Binop::Mul,
+        _ => unreachable!(),
+    };
+
+    let overflow_label = state.new_label();
+    let end_label = state.new_label();
+
+    // signed results saturate towards the sign of `t1`, or that of `t1 * t2` when multiplying
+    match t {
+        FlatType::U8 | FlatType::I8 => {
+            let dest = state.get_byte(&dest);
+            let ojmp = generate_overflowing_op(code, state, op, &t, Reg::ByteReg(dest), &t1, &t2);
+            code.push(ojmp(Wi::Symbol(overflow_label.clone())));
+            code.push(Ins::Jump(Wi::Symbol(end_label.clone())));
+            code.push(Ins::Label(overflow_label));
+            match (op, t) {
+                (Binop::Sub, FlatType::U8) => code.push(Ins::MoveB(dest, R0b)),
+                (_, FlatType::U8) => code.push(Ins::LdiB(dest, Bi::Constant(u8::MAX))),
+                (op, _) => {
+                    let seven = state.new_byte_reg();
+                    let sign = state.new_byte_reg();
+                    code.push(Ins::LdiB(seven, Bi::Constant(7)));
+                    if op == Binop::Mul {
+                        code.push(Ins::XorB(sign, state.get_byte(&t1), state.get_byte(&t2)));
+                        code.push(Ins::AsrB(sign, sign, seven));
+                    } else {
+                        code.push(Ins::AsrB(sign, state.get_byte(&t1), seven));
+                    }
+                    code.push(Ins::LdiB(dest, Bi::Constant(i8::MAX as u8)));
+                    code.push(Ins::XorB(dest, dest, sign));
+                }
+            }
+        }
+        FlatType::U16 | FlatType::I16 => {
+            let dest = state.get_wide(&dest);
+            let ojmp = generate_overflowing_op(code, state, op, &t, Reg::WideReg(dest), &t1, &t2);
+            code.push(ojmp(Wi::Symbol(overflow_label.clone())));
+            code.push(Ins::Jump(Wi::Symbol(end_label.clone())));
+            code.push(Ins::Label(overflow_label));
+            match (op, t) {
+                (Binop::Sub, FlatType::U16) => code.push(Ins::MoveW(dest, R0)),
+                (_, FlatType::U16) => code.push(Ins::LdiW(dest, Wi::Constant(u16::MAX))),
+                (op, _) => {
+                    let fifteen = state.new_wide_reg();
+                    let sign = state.new_wide_reg();
+                    code.push(Ins::LdiW(fifteen, Wi::Constant(15)));
+                    if op == Binop::Mul {
+                        code.push(Ins::XorW(sign, state.get_wide(&t1), state.get_wide(&t2)));
+                        code.push(Ins::AsrW(sign, sign, fifteen));
+                    } else {
+                        code.push(Ins::AsrW(sign, state.get_wide(&t1), fifteen));
+                    }
+                    code.push(Ins::LdiW(dest, Wi::Constant(i16::MAX as u16)));
+                    code.push(Ins::XorW(dest, dest, sign));
+                }
+            }
+        }
+        FlatType::U32 | FlatType::I32 => {
+            generate_dwide_overflow_jump(code, state, op, &t, &t1, &t2, overflow_label.clone());
+            generate_dwide_op(code, state, op, &dest, &t1, &t2);
+            code.push(Ins::Jump(Wi::Symbol(end_label.clone())));
+            code.push(Ins::Label(overflow_label));
+            let (dl, dh) = state.get_dwide(&dest);
+            match (op, t) {
+                (Binop::Sub, FlatType::U32) => {
+                    code.push(Ins::MoveW(dl, R0));
+                    code.push(Ins::MoveW(dh, R0));
+                }
+                (_, FlatType::U32) => {
+                    code.push(Ins::LdiW(dl, Wi::Constant(u16::MAX)));
+                    code.push(Ins::LdiW(dh, Wi::Constant(u16::MAX)));
+                }
+                (op, _) => {
+                    let (_, ah) = state.get_dwide(&t1);
+                    let (_, bh) = state.get_dwide(&t2);
+                    let fifteen = state.new_wide_reg();
+                    let sign = state.new_wide_reg();
+                    code.push(Ins::LdiW(fifteen, Wi::Constant(15)));
+                    if op == Binop::Mul {
+                        code.push(Ins::XorW(sign, ah, bh));
+                        code.push(Ins::AsrW(sign, sign, fifteen));
+                    } else {
+                        code.push(Ins::AsrW(sign, ah, fifteen));
+                    }
+                    code.push(Ins::LdiW(dl, Wi::Constant(u16::MAX)));
+                    code.push(Ins::XorW(dl, dl, sign));
+                    code.push(Ins::LdiW(dh, Wi::Constant(i16::MAX as u16)));
+                    code.push(Ins::XorW(dh, dh, sign));
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
+    code.push(Ins::Label(end_label));
+}
@@ -22,9 +22,12 @@ pub const CONV: CallingConvention<Reg, 5, 5> = CallingConvention {
 };
 
 impl Register<usize, Reg> for Reg {
-    #[track_caller]
-    fn new_symbolic(symbol: usize) -> Self {
-        todo!("{symbol}")
+    fn new_symbolic(&self, n: usize) -> Self {
+        // codegen counts its pseudo registers up from one
+        match self {
+            ByteReg(_) => ByteReg(Rpb(usize::MAX - n)),
+            WideReg(_) => WideReg(Rpw(usize::MAX - n)),
+        }
     }
 
     #[track_caller]
@@ -57,6 +60,8 @@ impl Ins<Reg, Rc<str>> for TeldaIns {
     fn following_labels(&self) -> Vec<Option<&Rc<str>>> {
         match self {
             TeldaIns::Ret(_) => vec![],
+            // only used as a tail call, which leaves the function
+            TeldaIns::JmpR(_) => vec![],
             TeldaIns::Null => vec![],
             TeldaIns::Jez(i) |
             TeldaIns::Jlt(i) |
@@ -77,7 +82,6 @@ impl Ins<Reg, Rc<str>> for TeldaIns {
                 Wi::Symbol(s) => vec![Some(s)],
                 _ => unimplemented!(),
             }
-            TeldaIns::JmpR(_) => todo!(),
             TeldaIns::Call(_) => vec![None],
             _ => vec![None],
         }
@@ -151,10 +155,10 @@ impl Ins<Reg, Rc<str>> for TeldaIns {
             TeldaIns::AsrW(_, r1, r2) => vec![WideReg(r1), WideReg(r2)],
             TeldaIns::LsrB(_, r1, r2) => vec![ByteReg(r1), ByteReg(r2)],
             TeldaIns::LsrW(_, r1, r2) => vec![WideReg(r1), WideReg(r2)],
-            TeldaIns::DivB(_, r1, r2, r3) => vec![ByteReg(r1), ByteReg(r2), ByteReg(r3)],
-            TeldaIns::DivW(_, r1, r2, r3) => vec![WideReg(r1), WideReg(r2), WideReg(r3)],
-            TeldaIns::MulB(_, r1, r2, r3) => vec![ByteReg(r1), ByteReg(r2), ByteReg(r3)],
-            TeldaIns::MulW(_, r1, r2, r3) => vec![WideReg(r1), WideReg(r2), WideReg(r3)],
+            TeldaIns::DivB(_, _, r1, r2) => vec![ByteReg(r1), ByteReg(r2)],
+            TeldaIns::DivW(_, _, r1, r2) => vec![WideReg(r1), WideReg(r2)],
+            TeldaIns::MulB(_, _, r1, r2) => vec![ByteReg(r1), ByteReg(r2)],
+            TeldaIns::MulW(_, _, r1, r2) => vec![WideReg(r1), WideReg(r2)],
         }
     }
 
@@ -248,23 +252,33 @@ impl Ins<Reg, Rc<str>> for TeldaIns {
     }
 
     fn new_write_to_stack_var(offset: usize, src: Reg) -> Self {
-        // two's complement manually
-        let offset = (!i16::try_from(offset).unwrap()).wrapping_neg() as u16;
+        let offset = stack_var_offset(offset);
         match src {
-            WideReg(src) => Self::StoreWI(Rs, Wi::Constant(offset), src),
-            ByteReg(src) => Self::StoreBI(Rs, Wi::Constant(offset), src),
+            WideReg(src) => Self::StoreWI(Rf, Wi::Constant(offset), src),
+            ByteReg(src) => Self::StoreBI(Rf, Wi::Constant(offset), src),
         }
     }
 
     fn new_read_from_stack_var(dest: Reg, offset: usize) -> Self {
-        // two's complement manually
-        let offset = (!i16::try_from(offset).unwrap()).wrapping_neg() as u16;
+        let offset = stack_var_offset(offset);
         match dest {
-            WideReg(dest) => Self::LoadWI(dest, Rs, Wi::Constant(offset)),
-            ByteReg(dest) => Self::LoadBI(dest, Rs, Wi::Constant(offset)),
+            WideReg(dest) => Self::LoadWI(dest, Rf, Wi::Constant(offset)),
+            ByteReg(dest) => Self::LoadBI(dest, Rf, Wi::Constant(offset)),
         }
     }
 
+    /// The frame pointer is saved and pointed at the saved value, with the stack variables below
+    /// it, since the stack pointer moves while pushing around calls
+    fn new_stack_frame(len: usize) -> Vec<Self> {
+        let mut frame = vec![Self::PushW(Rf), Self::MoveW(Rf, Rs)];
+        frame.extend((0..len).map(|_| Self::PushW(R0)));
+        frame
+    }
+
+    fn new_stack_frame_end() -> Vec<Self> {
+        vec![Self::MoveW(Rs, Rf), Self::PopW(Rf)]
+    }
+
     fn new_push(src: Reg) -> Self {
         match src {
             WideReg(src) => Self::PushW(src),
@@ -412,25 +426,21 @@ impl Ins<Reg, Rc<str>> for TeldaIns {
                 *r1 = rename_wide(*r1, &mut rename_register);
                 *r2 = rename_wide(*r2, &mut rename_register);
             }
-            TeldaIns::DivB(_, r1, r2, r3) => {
+            TeldaIns::DivB(_, _, r1, r2) => {
                 *r1 = rename_byte(*r1, &mut rename_register);
                 *r2 = rename_byte(*r2, &mut rename_register);
-                *r3 = rename_byte(*r3, &mut rename_register);
             }
-            TeldaIns::DivW(_, r1, r2, r3) => {
+            TeldaIns::DivW(_, _, r1, r2) => {
                 *r1 = rename_wide(*r1, &mut rename_register);
                 *r2 = rename_wide(*r2, &mut rename_register);
-                *r3 = rename_wide(*r3, &mut rename_register);
             }
-            TeldaIns::MulB(_, r1, r2, r3) => {
+            TeldaIns::MulB(_, _, r1, r2) => {
                 *r1 = rename_byte(*r1, &mut rename_register);
                 *r2 = rename_byte(*r2, &mut rename_register);
-                *r3 = rename_byte(*r3, &mut rename_register);
             }
-            TeldaIns::MulW(_, r1, r2, r3) => {
+            TeldaIns::MulW(_, _, r1, r2) => {
                 *r1 = rename_wide(*r1, &mut rename_register);
                 *r2 = rename_wide(*r2, &mut rename_register);
-                *r3 = rename_wide(*r3, &mut rename_register);
             }
         }
     }
@@ -531,3 +541,10 @@ fn rename_wide<F: FnMut(Reg) -> Reg>(w: Wr, mut rename_register: F) -> Wr {
         ByteReg(r) => unreachable!("{r}"),
     }
 }
+
+/// Offset from the frame pointer of the stack variable, each of which is a word
+fn stack_var_offset(offset: usize) -> u16 {
+    // below the frame pointer, as an immediate in two's complement
+    let offset = i16::try_from(offset + 1).unwrap() * 2;
+    offset.wrapping_neg() as u16
+}
@@ -3,14 +3,39 @@ use std::{
     rc::Rc,
 };
 
-use crate::{flat::{FlatType, Program}, regalloc::{reg_alloc::register_allocate, vec_view::VecView}};
+use crate::{diagnostic::Diagnostic, flat::{Const, FlatType, Global, Line, Program, StaticDecl, Unop}, regalloc::{reg_alloc::register_allocate, vec_view::VecView}};
 
 use self::{codegen::generate_program, Br::*, Wr::*};
 
 mod codegen;
 mod impl_regalloc;
 
-pub fn compile_to_telda(program: Program) -> Vec<Ins> {
+/// Why a program cannot be compiled to Telda
+#[derive(Debug, Clone)]
+pub enum TeldaError {
+    /// Telda has no floating point instructions, and the static or function uses floats
+    Float(Global),
+}
+
+impl Display for TeldaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TeldaError::Float(name) => write!(f, "`{name}` uses floats, which Telda does not support"),
+        }
+    }
+}
+
+impl TeldaError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            TeldaError::Float(name) => Diagnostic::error("E0300", "floats are not supported on Telda")
+                .with_note(format!("`{name}` uses floats")),
+        }
+    }
+}
+
+pub fn compile_to_telda(program: Program) -> Result<Vec<Ins>, TeldaError> {
+    check_no_floats(&program)?;
     let mut code = generate_program(program);
 
     // pre-regalloc optimisations
@@ -19,7 +44,77 @@ pub fn compile_to_telda(program: Program) -> Vec<Ins> {
     // run post-regalloc optimisations (remove zero-moves)
     simple_optimisations(&mut code);
 
-    code
+    Ok(code)
+}
+
+fn check_no_floats(program: &Program) -> Result<(), TeldaError> {
+    for decl in &program.statics {
+        let (name, t) = match decl {
+            StaticDecl::SetConst(name, t, _)
+            | StaticDecl::SetAlias(name, t, _)
+            | StaticDecl::SetArray(name, t, _)
+            | StaticDecl::SetString(name, t, _)
+            | StaticDecl::SetPtr(name, t, _)
+            | StaticDecl::SetSlice(name, t, _, _)
+            | StaticDecl::External(name, t) => (name, t),
+        };
+        if has_float(t) {
+            return Err(TeldaError::Float(name.clone()));
+        }
+    }
+    // in order of name so the same function is reported every time
+    let mut fns: Vec<_> = program.fns.iter().collect();
+    fns.sort_by_key(|(name, _)| *name);
+    for (name, f) in fns {
+        let uses_float = f.arg_types.iter().chain([&f.ret_type]).any(has_float)
+            || f.lines.iter().any(|line| match line {
+                Line::SetConst(_, _, Const::ConstFloat(_)) => true,
+                Line::SetUnop(_, t, Unop::Convert(from), _) => has_float(t) || has_float(from),
+                Line::SetConst(_, t, _)
+                | Line::SetTo(_, t, _)
+                | Line::SetBinop(_, t, _, _, _)
+                | Line::SetUnop(_, t, _, _)
+                | Line::SetCall(_, t, _, _)
+                | Line::WriteTo(_, t, _)
+                | Line::SetIndex(_, t, _, _)
+                | Line::ReadField(_, t, _, _)
+                | Line::WriteField(_, t, _, _)
+                | Line::ReadIndex(_, t, _, _)
+                | Line::ReadElement(_, t, _, _)
+                | Line::SetAddrOf(_, t, _)
+                | Line::SetElementAddr(_, t, _, _)
+                | Line::SetAlloc(_, t, _)
+                | Line::ReadGlobal(_, t, _)
+                | Line::WriteGlobal(_, t, _) => has_float(t),
+                Line::Label(_)
+                | Line::If(_, _, _)
+                | Line::Goto(_)
+                | Line::Ret(_)
+                | Line::Panic(_, _) => false,
+            });
+        if uses_float {
+            return Err(TeldaError::Float(name.clone()));
+        }
+    }
+    Ok(())
+}
+
+fn has_float(t: &FlatType) -> bool {
+    match t {
+        FlatType::Float => true,
+        FlatType::Ptr(t) => t.as_deref().is_some_and(has_float),
+        FlatType::FnPtr(args, ret) => args.iter().any(has_float) || has_float(ret),
+        FlatType::Arr(t, _) => has_float(t),
+        FlatType::Struct(fields) => fields.iter().any(has_float),
+        FlatType::Unit
+        | FlatType::Bool
+        | FlatType::U8
+        | FlatType::I8
+        | FlatType::U16
+        | FlatType::I16
+        | FlatType::U32
+        | FlatType::I32 => false,
+    }
 }
 
 fn apply_register_allocation(code: &mut Vec<Ins>) {
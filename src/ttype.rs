@@ -7,7 +7,10 @@ use std::{
 
 use collect_result::CollectResult;
 
-use crate::parse::location::Location;
+use crate::{
+    diagnostic::{Diagnostic, Label},
+    parse::location::Location,
+};
 
 use self::typevar::TypeVar;
 
@@ -18,6 +21,9 @@ pub mod typevar;
 
 pub type Result<T, E = TypeError> = StdResult<T, E>;
 
+/// Name of an enum variant and its payload types
+pub type Variant = (Rc<str>, Box<[Type]>);
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
     Unknown(TypeVar),
@@ -37,6 +43,12 @@ pub enum Type {
 
     Function(Box<[Self]>, Box<Self>),
     Struct(Box<[(Rc<str>, Self)]>),
+    Enum(Box<[Variant]>),
+    /// Name of a declared type, replaced by the type it names during type checking
+    Named(Rc<str>),
+    /// A `newtype`, by its name and the type it is based on, which it is only the same as when
+    /// cast with `as`
+    Distinct(Box<(Rc<str>, Self)>),
     Unit,
 
     Option(Box<Self>),
@@ -54,6 +66,55 @@ impl Type {
     fn constrained<I: IntoIterator<Item = Type>>(possible_types: I) -> Type {
         Type::Unknown(TypeVar::constrained_type(possible_types))
     }
+    /// Replaces every named type inside this type with what `lookup` gives for the name
+    fn resolve_names<E, F>(&self, lookup: &mut F) -> StdResult<Type, E>
+    where
+        F: FnMut(&Rc<str>) -> StdResult<Type, E>,
+    {
+        Ok(match self {
+            Type::Named(name) => lookup(name)?,
+            Type::Function(args, ret) => {
+                let args: Vec<_> = args
+                    .iter()
+                    .map(|t| t.resolve_names(lookup))
+                    .collect_result()?;
+                Type::Function(args.into_boxed_slice(), Box::new(ret.resolve_names(lookup)?))
+            }
+            Type::Struct(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(n, t)| Ok((n.clone(), t.resolve_names(lookup)?)))
+                    .collect_result()?;
+                Type::Struct(fields.into_boxed_slice())
+            }
+            Type::Enum(variants) => {
+                let variants: Vec<_> = variants
+                    .iter()
+                    .map(|(n, payload)| {
+                        let payload: Vec<_> = payload
+                            .iter()
+                            .map(|t| t.resolve_names(lookup))
+                            .collect_result()?;
+                        Ok((n.clone(), payload.into_boxed_slice()))
+                    })
+                    .collect_result()?;
+                Type::Enum(variants.into_boxed_slice())
+            }
+            Type::Option(t) => Type::Option(Box::new(t.resolve_names(lookup)?)),
+            Type::Pointer(t) => Type::Pointer(Box::new(t.resolve_names(lookup)?)),
+            Type::ArrayPointer(t) => Type::ArrayPointer(Box::new(t.resolve_names(lookup)?)),
+            Type::Slice(t) => Type::Slice(Box::new(t.resolve_names(lookup)?)),
+            Type::Array(t, n) => Type::Array(Box::new(t.resolve_names(lookup)?), *n),
+            t => t.clone(),
+        })
+    }
+    /// The type a `newtype` is based on, or the type itself if it is not one
+    pub fn base(&self) -> &Type {
+        match self {
+            Type::Distinct(distinct) => distinct.1.base(),
+            t => t,
+        }
+    }
     const INT: [Type; 6] = [
         Type::I8,
         Type::U8,
@@ -77,6 +138,16 @@ impl Type {
         Type::I32,
         Type::U32,
     ];
+    /// The types of the kind a type parameter is limited to
+    fn of_kind(kind: &str) -> Option<&'static [Type]> {
+        Some(match kind {
+            "int" => &Type::INT,
+            "signed" => &Type::SIGNED,
+            "num" => &Type::NUM,
+            "bits" => &Type::BITS,
+            _ => return None,
+        })
+    }
     const BITS: [Type; 7] = [
         Type::Bool,
         Type::I8,
@@ -120,7 +191,44 @@ impl Display for Type {
                 }
                 write!(f, ") {ret}")
             }
-            Type::Struct(_) => todo!(),
+            Type::Struct(fields) => {
+                write!(f, "{{")?;
+                let mut first = true;
+                for (name, t) in fields.iter() {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    first = false;
+                    write!(f, "{name}: {t}")?;
+                }
+                write!(f, "}}")
+            }
+            Type::Enum(variants) => {
+                write!(f, "enum {{")?;
+                let mut first = true;
+                for (name, payload) in variants.iter() {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    first = false;
+                    write!(f, "{name}")?;
+                    if !payload.is_empty() {
+                        write!(f, "(")?;
+                        let mut first = true;
+                        for t in payload.iter() {
+                            if !first {
+                                write!(f, ", ")?;
+                            }
+                            first = false;
+                            write!(f, "{t}")?;
+                        }
+                        write!(f, ")")?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            Type::Named(name) => write!(f, "{name}"),
+            Type::Distinct(distinct) => write!(f, "{}", distinct.0),
         }
     }
 }
@@ -129,6 +237,8 @@ impl Display for Type {
 pub struct TypeError {
     pub error_type: TypeErrorType,
     pub loc: Location,
+    /// Secondary labels
+    pub labels: Vec<Label>,
 }
 
 #[derive(Debug, Clone)]
@@ -144,8 +254,30 @@ pub enum TypeErrorType {
     UnequalArgLen(u16, u16),
     NotPtr(Type),
     DisjointContraints(HashSet<Type>, HashSet<Type>),
+    /// possible types, actual
+    UnsatisfiedConstraint(HashSet<Type>, Type),
     NonConcreteType,
     DuplicateGlobalDefinition(Box<str>),
+    OutsideOfLoop(Box<str>),
+    UndefinedLabel(Box<str>),
+    UndefinedType(Box<str>),
+    RecursiveType(Box<str>),
+    NoSuchField(Type, Box<str>),
+    MissingField(Box<str>),
+    DuplicateField(Box<str>),
+    NotAssignable(Box<str>),
+    CannotIndex(Type),
+    DuplicateVariant(Box<str>),
+    NoSuchVariant(Type, Box<str>),
+    /// variant, expected, actual
+    WrongPayloadLen(Box<str>, u16, u16),
+    CannotMatch(Type),
+    NonExhaustiveMatch(Box<[Rc<str>]>),
+    UnreachablePattern,
+    AssignToCapture(Box<str>),
+    UnknownKind(Box<str>),
+    /// from, to
+    InvalidCast(Type, Type),
 }
 
 impl TypeErrorType {
@@ -153,15 +285,88 @@ impl TypeErrorType {
         TypeError {
             error_type: self,
             loc,
+            labels: Vec::new(),
+        }
+    }
+    /// Stable code identifying the kind of error, codes are never reused for a different kind
+    pub fn code(&self) -> &'static str {
+        use self::TypeErrorType::*;
+        match self {
+            TypeMismatch(..) => "E0100",
+            InvalidConcatOps(..) => "E0101",
+            CannotDeref(_) => "E0102",
+            Undefined(_) => "E0103",
+            NotMutable(_) => "E0104",
+            CannotCall(_) => "E0105",
+            UnequalArraySizes(..) => "E0106",
+            UnequalArgLen(..) => "E0107",
+            NotPtr(_) => "E0108",
+            DisjointContraints(..) => "E0109",
+            UnsatisfiedConstraint(..) => "E0110",
+            NonConcreteType => "E0111",
+            DuplicateGlobalDefinition(_) => "E0112",
+            OutsideOfLoop(_) => "E0113",
+            UndefinedLabel(_) => "E0114",
+            UndefinedType(_) => "E0115",
+            RecursiveType(_) => "E0116",
+            NoSuchField(..) => "E0117",
+            MissingField(_) => "E0118",
+            DuplicateField(_) => "E0119",
+            NotAssignable(_) => "E0120",
+            CannotIndex(_) => "E0121",
+            DuplicateVariant(_) => "E0122",
+            NoSuchVariant(..) => "E0123",
+            WrongPayloadLen(..) => "E0124",
+            CannotMatch(_) => "E0125",
+            NonExhaustiveMatch(_) => "E0126",
+            UnreachablePattern => "E0127",
+            AssignToCapture(_) => "E0128",
+            UnknownKind(_) => "E0129",
+            InvalidCast(..) => "E0130",
         }
     }
+    /// Extra help that applies to every error of this kind
+    fn note(&self) -> Option<&'static str> {
+        use self::TypeErrorType::*;
+        match self {
+            NotMutable(_) => Some("only variables declared with `var` can be re-assigned"),
+            NonConcreteType => Some("consider adding a type annotation"),
+            NonExhaustiveMatch(_) => Some("add arms for the missing variants, or a `_` arm"),
+            RecursiveType(_) => Some("use a pointer to refer to the type inside itself"),
+            AssignToCapture(_) => Some("closures get a copy of the variables they use when made"),
+            UnknownKind(_) => Some("the kinds of type are int, signed, num and bits"),
+            _ => None,
+        }
+    }
+}
+
+impl TypeError {
+    /// Adds a secondary label pointing at the code that gives the error context
+    fn with_label(mut self, loc: Location, message: &str) -> Self {
+        self.labels.push(Label::secondary(loc, message));
+        self
+    }
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut diag = Diagnostic::error(self.error_type.code(), self.error_type.to_string())
+            .with_label(Label::primary(self.loc.clone()));
+        diag.labels.extend(self.labels.iter().cloned());
+        if let Some(note) = self.error_type.note() {
+            diag = diag.with_note(note);
+        }
+        diag
+    }
 }
 
 impl Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.loc, self.error_type)
+    }
+}
+
+impl Display for TypeErrorType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use self::TypeErrorType::*;
-        write!(f, "{}: ", self.loc)?;
-        match &self.error_type {
+        match self {
             TypeMismatch(e, a) => write!(f, "expected type {e}, got {a}"),
             InvalidConcatOps(t1, t2) => write!(f, "concatenation of {t1} and {t2} is not possible"),
             CannotDeref(t) => write!(f, "cannot dereference type {t}"),
@@ -171,12 +376,43 @@ impl Display for TypeError {
             UnequalArraySizes(s1, s2) => write!(f, "arrays did not have same length: {s1} != {s2}"),
             UnequalArgLen(s1, s2) => write!(
                 f,
-                "functions did not have number of arguments: {s1} != {s2}"
+                "functions did not have the same number of arguments: {s1} != {s2}"
             ),
             NotPtr(t) => write!(f, "type {t} is not a pointer"),
             DisjointContraints(s1, s2) => write!(f, "incompatible type constraints: {s1:?} {s2:?}"),
+            // only integer literals too big for every integer type can be constrained to none
+            UnsatisfiedConstraint(s, t) if s.is_empty() => write!(
+                f,
+                "integer literal does not fit in {t} or any other integer type"
+            ),
+            UnsatisfiedConstraint(s, t) => {
+                let mut names: Vec<_> = s.iter().map(|t| t.to_string()).collect();
+                names.sort();
+                write!(f, "expected one of the types {}, got {t}", names.join(", "))
+            }
             NonConcreteType => write!(f, "could not infer concrete type"),
             DuplicateGlobalDefinition(name) => write!(f, "duplicate global definition of {name}"),
+            OutsideOfLoop(kw) => write!(f, "`{kw}` outside of a loop"),
+            UndefinedLabel(l) => write!(f, "undefined loop label '{l}"),
+            UndefinedType(t) => write!(f, "undefined type {t}"),
+            RecursiveType(t) => write!(f, "type {t} contains itself"),
+            NoSuchField(t, n) => write!(f, "type {t} has no field {n}"),
+            MissingField(n) => write!(f, "missing field {n}"),
+            DuplicateField(n) => write!(f, "field {n} given more than once"),
+            NotAssignable(e) => write!(f, "cannot assign to {e}"),
+            CannotIndex(t) => write!(f, "cannot index into type {t}"),
+            DuplicateVariant(n) => write!(f, "variant {n} declared more than once"),
+            NoSuchVariant(t, n) => write!(f, "type {t} has no variant {n}"),
+            WrongPayloadLen(n, s1, s2) => write!(
+                f,
+                "variant {n} has {s1} payload values, but {s2} were given"
+            ),
+            CannotMatch(t) => write!(f, "cannot match on type {t}"),
+            NonExhaustiveMatch(vs) => write!(f, "match does not cover {}", vs.join(", ")),
+            UnreachablePattern => write!(f, "unreachable pattern"),
+            AssignToCapture(v) => write!(f, "cannot assign to {v}, which is captured by a closure"),
+            UnknownKind(k) => write!(f, "unknown kind of type {k}"),
+            InvalidCast(t1, t2) => write!(f, "cannot cast {t1} to {t2}"),
         }
     }
 }
@@ -202,7 +438,7 @@ pub fn unify_types(loc: &Location, exp: &Type, act: &Type) -> Result<Type> {
         (Function(t1, rt1), Function(t2, rt2)) => {
             if t1.len() != t2.len() {
                 return Err(
-                    TypeErrorType::UnequalArraySizes(t1.len() as u16, t2.len() as u16)
+                    TypeErrorType::UnequalArgLen(t1.len() as u16, t2.len() as u16)
                         .location(loc.clone()),
                 );
             }
@@ -223,7 +459,48 @@ pub fn unify_types(loc: &Location, exp: &Type, act: &Type) -> Result<Type> {
         (Pointer(t1), Pointer(t2)) => Ok(Pointer(Box::new(unify_types(loc, t1, t2)?))),
         (Option(t1), Option(t2)) => Ok(Option(Box::new(unify_types(loc, t1, t2)?))),
         (Slice(t1), Slice(t2)) => Ok(Slice(Box::new(unify_types(loc, t1, t2)?))),
-        (Struct(_), Struct(_)) => todo!(),
+        (Struct(f1), Struct(f2)) => {
+            let same_fields = f1.len() == f2.len()
+                && f1.iter().zip(f2.iter()).all(|((n1, _), (n2, _))| n1 == n2);
+            if !same_fields {
+                return Err(
+                    TypeErrorType::TypeMismatch(exp.clone(), act.clone()).location(loc.clone())
+                );
+            }
+            let fields: Vec<_> = f1
+                .iter()
+                .zip(f2.iter())
+                .map(|((n, t1), (_, t2))| Ok((n.clone(), unify_types(loc, t1, t2)?)))
+                .collect_result()?;
+
+            Ok(Struct(fields.into_boxed_slice()))
+        }
+        (Enum(v1), Enum(v2)) => {
+            let same_variants = v1.len() == v2.len()
+                && v1
+                    .iter()
+                    .zip(v2.iter())
+                    .all(|((n1, p1), (n2, p2))| n1 == n2 && p1.len() == p2.len());
+            if !same_variants {
+                return Err(
+                    TypeErrorType::TypeMismatch(exp.clone(), act.clone()).location(loc.clone())
+                );
+            }
+            let variants: Vec<_> = v1
+                .iter()
+                .zip(v2.iter())
+                .map(|((n, p1), (_, p2))| {
+                    let payload: Vec<_> = p1
+                        .iter()
+                        .zip(p2.iter())
+                        .map(|(t1, t2)| unify_types(loc, t1, t2))
+                        .collect_result()?;
+                    Ok((n.clone(), payload.into_boxed_slice()))
+                })
+                .collect_result()?;
+
+            Ok(Enum(variants.into_boxed_slice()))
+        }
         (t1, t2) => Err(TypeErrorType::TypeMismatch(t1.clone(), t2.clone()).location(loc.clone())),
     }
 }
@@ -22,19 +22,51 @@ pub enum Statement {
     Let(Location, Rc<str>, Box<Type>, Expr),
     Var(Location, Rc<str>, Box<Type>, Expr),
     Rebind(Location, PlaceExpr, Expr),
+    /// Label, condition and body
+    While(Location, Option<Rc<str>>, Expr, Expr),
+    Break(Location, Option<Rc<str>>, Option<Expr>),
+    Continue(Location, Option<Rc<str>>),
+    /// Label, loop variable and its type, what to iterate over and body
+    For(
+        Location,
+        Option<Rc<str>>,
+        Rc<str>,
+        Box<Type>,
+        Iterable,
+        Expr,
+    ),
 
     Return(Location, Expr),
 }
 #[derive(Debug, Clone, PartialEq)]
+pub enum Iterable {
+    Range(Expr, Expr),
+    Slice(Expr),
+}
+#[derive(Debug, Clone, PartialEq)]
 pub enum PlaceExpr {
     Ident(Location, Rc<str>),
     Deref(Location, Box<Expr>, Box<Type>),
-    Index(Location, Box<Expr>, Box<Expr>),
-    FieldAccess(Location, Box<Expr>, Rc<str>),
+    /// Type of the array is the last field
+    Index(Location, Box<PlaceExpr>, Box<Expr>, Box<Type>),
+    /// Type of the struct is the last field
+    FieldAccess(Location, Box<PlaceExpr>, Rc<str>, Box<Type>),
+    /// Element of a slice, which is written through the slice's pointer, type of the slice is the
+    /// last field
+    SliceElement(Location, Box<Expr>, Box<Expr>, Box<Type>),
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Wildcard(Location),
+    /// Variant and the bindings for its payload
+    Variant(Location, Rc<str>, Box<[Option<Rc<str>>]>),
 }
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Ident(Location, Rc<str>),
+    /// A generic function and the types its type parameters are used with, which is replaced by
+    /// the name of the function for those types once they are concrete
+    Instance(Location, Rc<str>, Box<[Type]>),
     ConstBoolean(Location, bool),
     ConstI8(Location, i8),
     ConstU8(Location, u8),
@@ -50,14 +82,39 @@ pub enum Expr {
 
     Ref(Location, Result<PlaceExpr, Box<Self>>),
     Array(Location, Box<[Self]>),
+    /// Type of what is being indexed into is the last field
+    Index(Location, Box<Self>, Box<Self>, Box<Type>),
+    /// Start and end of the slice, type of what is being sliced is the last field
+    Slice(Location, Box<Self>, Box<Self>, Box<Self>, Box<Type>),
+    /// Fields are in the order they were declared in
     StructConstructor(Location, Box<[(Option<Box<str>>, Expr)]>),
+    /// Type of the struct is the last field
+    FieldAccess(Location, Box<Self>, Rc<str>, Box<Type>),
+    /// Variant, its payload and the type of the enum
+    VariantConstructor(Location, Rc<str>, Box<[Self]>, Box<Type>),
+    /// Type of the enum being matched on is the second field
+    Match(Location, Box<Self>, Box<Type>, Box<[(Pattern, Self)]>),
     /// Span, first type is the original type, the second is the target
     Cast(Location, Box<Self>, Box<Type>, Box<Type>),
 
     Add(Location, Box<Self>, Box<Self>),
     Sub(Location, Box<Self>, Box<Self>),
     Mul(Location, Box<Self>, Box<Self>),
+    WrappingAdd(Location, Box<Self>, Box<Self>),
+    WrappingSub(Location, Box<Self>, Box<Self>),
+    WrappingMul(Location, Box<Self>, Box<Self>),
+    SaturatingAdd(Location, Box<Self>, Box<Self>),
+    SaturatingSub(Location, Box<Self>, Box<Self>),
+    SaturatingMul(Location, Box<Self>, Box<Self>),
+    BitAnd(Location, Box<Self>, Box<Self>),
+    BitOr(Location, Box<Self>, Box<Self>),
+    BitXor(Location, Box<Self>, Box<Self>),
+    Shl(Location, Box<Self>, Box<Self>),
+    Shr(Location, Box<Self>, Box<Self>),
     Div(Location, Box<Self>, Box<Self>),
+    Rem(Location, Box<Self>, Box<Self>),
+    And(Location, Box<Self>, Box<Self>),
+    Or(Location, Box<Self>, Box<Self>),
     Concat(Location, Box<Self>, Box<Self>),
 
     Not(Location, Box<Self>),
@@ -65,10 +122,30 @@ pub enum Expr {
     Deref(Location, Box<Self>),
 
     Block(Location, Box<[Statement]>),
-    Lambda(Location, Box<[(Rc<str>, Type)]>, Type, Box<Self>),
-    Call(Location, Rc<str>, Box<[Self]>),
+    /// Parameters, return type, body and the locals of enclosing functions it captures
+    Lambda(
+        Location,
+        Box<[(Rc<str>, Type)]>,
+        Type,
+        Box<Self>,
+        Box<[(Rc<str>, Type)]>,
+    ),
+    /// Callee and the arguments, type of the callee is the last field
+    Call(Location, Box<Self>, Box<[Self]>, Box<Type>),
 
     If(Location, Box<Self>, Box<Self>, Box<Self>),
+    /// Binding, the optional, the type inside it and what to evaluate if it is and isn't null
+    IfLet(
+        Location,
+        Rc<str>,
+        Box<Self>,
+        Box<Type>,
+        Box<Self>,
+        Box<Self>,
+    ),
+    /// Type of the value inside the optional is the last field
+    Unwrap(Location, Box<Self>, Box<Type>),
+    Loop(Location, Option<Rc<str>>, Box<Self>),
     Eq(Location, Box<Self>, Box<Self>, Box<Type>),
     Neq(Location, Box<Self>, Box<Self>, Box<Type>),
     Lt(Location, Box<Self>, Box<Self>, Box<Type>),
@@ -81,6 +158,7 @@ impl Expr {
     pub(crate) fn location(&self) -> Location {
         match self {
             Expr::Ident(loc, _)
+            | Expr::Instance(loc, _, _)
             | Expr::ConstBoolean(loc, _)
             | Expr::ConstI8(loc, _)
             | Expr::ConstU8(loc, _)
@@ -95,20 +173,42 @@ impl Expr {
             | Expr::ConstNull(loc)
             | Expr::Ref(loc, _)
             | Expr::Array(loc, _)
+            | Expr::Index(loc, _, _, _)
+            | Expr::Slice(loc, _, _, _, _)
             | Expr::StructConstructor(loc, _)
+            | Expr::FieldAccess(loc, _, _, _)
+            | Expr::VariantConstructor(loc, _, _, _)
+            | Expr::Match(loc, _, _, _)
             | Expr::Cast(loc, _, _, _)
             | Expr::Add(loc, _, _)
             | Expr::Sub(loc, _, _)
             | Expr::Mul(loc, _, _)
+            | Expr::WrappingAdd(loc, _, _)
+            | Expr::WrappingSub(loc, _, _)
+            | Expr::WrappingMul(loc, _, _)
+            | Expr::SaturatingAdd(loc, _, _)
+            | Expr::SaturatingSub(loc, _, _)
+            | Expr::SaturatingMul(loc, _, _)
+            | Expr::BitAnd(loc, _, _)
+            | Expr::BitOr(loc, _, _)
+            | Expr::BitXor(loc, _, _)
+            | Expr::Shl(loc, _, _)
+            | Expr::Shr(loc, _, _)
             | Expr::Div(loc, _, _)
+            | Expr::Rem(loc, _, _)
+            | Expr::And(loc, _, _)
+            | Expr::Or(loc, _, _)
             | Expr::Concat(loc, _, _)
             | Expr::Not(loc, _)
             | Expr::Neg(loc, _)
             | Expr::Deref(loc, _)
             | Expr::Block(loc, _)
-            | Expr::Lambda(loc, _, _, _)
-            | Expr::Call(loc, _, _)
+            | Expr::Lambda(loc, _, _, _, _)
+            | Expr::Call(loc, _, _, _)
             | Expr::If(loc, _, _, _)
+            | Expr::IfLet(loc, _, _, _, _, _)
+            | Expr::Unwrap(loc, _, _)
+            | Expr::Loop(loc, _, _)
             | Expr::Eq(loc, _, _, _)
             | Expr::Neq(loc, _, _, _)
             | Expr::Lt(loc, _, _, _)
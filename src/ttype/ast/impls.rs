@@ -1,6 +1,6 @@
 use std::fmt::{self, Display};
 
-use super::{Decl, Expr, PlaceExpr, Program, Statement};
+use super::{Decl, Expr, Iterable, Pattern, PlaceExpr, Program, Statement};
 
 impl Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -56,18 +56,77 @@ impl Display for Statement {
             Statement::Let(_, n, t, e) => write!(f, "let {n}: {t} = {e}"),
             Statement::Var(_, n, t, e) => write!(f, "var {n}: {t} = {e}"),
             Statement::Rebind(_, n, e) => write!(f, "{n} = {e}"),
+            Statement::While(_, l, c, b) => {
+                write!(f, "while ")?;
+                if let Some(l) = l {
+                    write!(f, "'{l} ")?;
+                }
+                write!(f, "{c} {b}")
+            }
+            Statement::Break(_, l, e) => {
+                write!(f, "break")?;
+                if let Some(l) = l {
+                    write!(f, " '{l}")?;
+                }
+                if let Some(e) = e {
+                    write!(f, " {e}")?;
+                }
+                Ok(())
+            }
+            Statement::Continue(_, None) => write!(f, "continue"),
+            Statement::Continue(_, Some(l)) => write!(f, "continue '{l}"),
+            Statement::For(_, l, n, t, i, b) => {
+                write!(f, "for ")?;
+                if let Some(l) = l {
+                    write!(f, "'{l} ")?;
+                }
+                write!(f, "{n}: {t} in {i} {b}")
+            }
             Statement::Return(_, e) => write!(f, "{e}"),
         }
     }
 }
 
+impl Display for Iterable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Iterable::Range(a, b) => write!(f, "{a}..{b}"),
+            Iterable::Slice(e) => write!(f, "{e}"),
+        }
+    }
+}
+
 impl Display for PlaceExpr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Ident(_, i) => write!(f, "{i}"),
             Self::Deref(_, a, t) => write!(f, "*{a} (: {t})"),
-            Self::Index(_, e, i) => write!(f, "{e}[{i}]"),
-            Self::FieldAccess(_, e, i) => write!(f, "{e}.{i}"),
+            Self::Index(_, e, i, t) => write!(f, "({e} (: {t}))[{i}]"),
+            Self::FieldAccess(_, e, i, t) => write!(f, "({e} (: {t})).{i}"),
+            Self::SliceElement(_, e, i, t) => write!(f, "({e} (: {t}))[{i}]"),
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Wildcard(_) => write!(f, "_"),
+            Self::Variant(_, variant, bindings) => {
+                write!(f, "::{variant}(")?;
+                let mut first = true;
+                for b in bindings.iter() {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    first = false;
+                    match b {
+                        Some(b) => write!(f, "{b}")?,
+                        None => write!(f, "_")?,
+                    }
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -76,6 +135,10 @@ impl Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expr::Ident(_, i) => write!(f, "{i}"),
+            Expr::Instance(_, i, types) => {
+                let types: Vec<_> = types.iter().map(ToString::to_string).collect();
+                write!(f, "{i}<{}>", types.join(", "))
+            }
             Expr::ConstBoolean(_, v) => write!(f, "{v}"),
             Expr::ConstI8(_, v) => write!(f, "{v}i8"),
             Expr::ConstU8(_, v) => write!(f, "{v}u8"),
@@ -92,10 +155,29 @@ impl Display for Expr {
             Expr::Add(_, a, b) => write!(f, "({a} + {b})"),
             Expr::Sub(_, a, b) => write!(f, "({a} - {b})"),
             Expr::Mul(_, a, b) => write!(f, "({a} * {b})"),
+            Expr::WrappingAdd(_, a, b) => write!(f, "({a} +% {b})"),
+            Expr::WrappingSub(_, a, b) => write!(f, "({a} -% {b})"),
+            Expr::WrappingMul(_, a, b) => write!(f, "({a} *% {b})"),
+            Expr::SaturatingAdd(_, a, b) => write!(f, "({a} +| {b})"),
+            Expr::SaturatingSub(_, a, b) => write!(f, "({a} -| {b})"),
+            Expr::SaturatingMul(_, a, b) => write!(f, "({a} *| {b})"),
+            Expr::BitAnd(_, a, b) => write!(f, "({a} & {b})"),
+            Expr::BitOr(_, a, b) => write!(f, "({a} | {b})"),
+            Expr::BitXor(_, a, b) => write!(f, "({a} ^ {b})"),
+            Expr::Shl(_, a, b) => write!(f, "({a} << {b})"),
+            Expr::Shr(_, a, b) => write!(f, "({a} >> {b})"),
             Expr::Div(_, a, b) => write!(f, "({a} / {b})"),
+            Expr::Rem(_, a, b) => write!(f, "({a} % {b})"),
+            Expr::And(_, a, b) => write!(f, "({a} && {b})"),
+            Expr::Or(_, a, b) => write!(f, "({a} || {b})"),
             Expr::Concat(_, a, b) => write!(f, "({a} ++ {b})"),
-            Expr::Lambda(_, args, ret, body) => {
-                write!(f, "fn(")?;
+            Expr::Lambda(_, args, ret, body, captures) => {
+                write!(f, "fn")?;
+                if !captures.is_empty() {
+                    let names: Vec<_> = captures.iter().map(|(n, _)| &**n).collect();
+                    write!(f, "[{}]", names.join(", "))?;
+                }
+                write!(f, "(")?;
                 let mut first = true;
                 for (arg, t) in args.iter() {
                     if !first {
@@ -106,8 +188,8 @@ impl Display for Expr {
                 }
                 write!(f, ") {ret} ({body})")
             }
-            Expr::Call(_, f_name, args) => {
-                write!(f, "{f_name}(")?;
+            Expr::Call(_, callee, args, _) => {
+                write!(f, "{callee}(")?;
                 let mut first = true;
                 for arg in args.iter() {
                     if !first {
@@ -119,6 +201,12 @@ impl Display for Expr {
                 write!(f, ")")
             }
             Expr::If(_, cond, if_t, if_f) => write!(f, "(if {cond} then {if_t} else {if_f})"),
+            Expr::IfLet(_, n, opt, t, if_t, if_f) => {
+                write!(f, "(if let {n}: {t} = {opt} then {if_t} else {if_f})")
+            }
+            Expr::Unwrap(_, e, t) => write!(f, "({e}.? (: {t}))"),
+            Expr::Loop(_, None, body) => write!(f, "loop {body}"),
+            Expr::Loop(_, Some(l), body) => write!(f, "loop '{l} {body}"),
             Expr::Eq(_, a, b, t) => write!(f, "({a} == {b} (: {t}))"),
             Expr::Neq(_, a, b, t) => write!(f, "({a} != {b} (: {t}))"),
             Expr::Lt(_, a, b, t) => write!(f, "({a} < {b} (: {t}))"),
@@ -132,7 +220,20 @@ impl Display for Expr {
             },
             Expr::Neg(_, a) => write!(f, "-{a}"),
             Expr::Deref(_, a) => write!(f, "*{a}"),
-            Expr::Array(_, _a) => todo!(),
+            Expr::Array(_, a) => {
+                write!(f, "[")?;
+                let mut first = true;
+                for e in a.iter() {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    first = false;
+                    write!(f, "{e}")?;
+                }
+                write!(f, "]")
+            }
+            Expr::Index(_, e, i, t) => write!(f, "({e} (: {t}))[{i}]"),
+            Expr::Slice(_, e, start, end, t) => write!(f, "({e} (: {t}))[{start}..{end}]"),
             Expr::StructConstructor(_, strct) => {
                 write!(f, "{{ ")?;
                 for (name, val) in strct.iter() {
@@ -143,6 +244,26 @@ impl Display for Expr {
                 }
                 write!(f, "}}")
             }
+            Expr::FieldAccess(_, e, i, t) => write!(f, "({e} (: {t})).{i}"),
+            Expr::VariantConstructor(_, variant, payload, t) => {
+                write!(f, "(::{variant}(")?;
+                let mut first = true;
+                for e in payload.iter() {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    first = false;
+                    write!(f, "{e}")?;
+                }
+                write!(f, ") (: {t}))")
+            }
+            Expr::Match(_, e, t, arms) => {
+                writeln!(f, "match {e} (: {t}) {{")?;
+                for (pat, arm) in arms.iter() {
+                    writeln!(f, "    {pat} => {arm},")?;
+                }
+                write!(f, "}}")
+            }
             Expr::Cast(_, val, ft, tt) => write!(f, "({val} as {tt} (<-{ft}))"),
             Expr::Block(_, stmnts) => {
                 writeln!(f, "{{")?;
@@ -1,23 +1,62 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, convert::Infallible, mem, rc::Rc};
 
 use crate::parse::location::Location;
 
-use super::{unify_types, Result, Type, TypeErrorType};
+use super::{type_checker::CheckInfo, unify_types, Result, Type, TypeError, TypeErrorType};
 
 #[derive(Debug, Clone)]
 pub struct Symbol {
     mutable: bool,
     s_type: Type,
+    /// How deeply nested the function it is local to is, zero for globals
+    function: u16,
 }
 impl Symbol {
-    fn new(s_type: Type, mutable: bool) -> Self {
-        Symbol { mutable, s_type }
+    fn new(s_type: Type, mutable: bool, function: u16) -> Self {
+        Symbol {
+            mutable,
+            s_type,
+            function,
+        }
     }
 }
 
+/// Names of the outer locals a function uses, with their types
+type Captures = Vec<(Rc<str>, Type)>;
+
+/// A use of a generic function and the types its type parameters are used with
+pub type Instance = (Location, Rc<str>, Box<[Type]>);
+
+/// Type parameters of a generic function and the types each can be, if limited
+pub type TypeParams = Box<[(Rc<str>, Option<Box<[Type]>>)]>;
+
+/// The type of a generic function, which uses the names of its type parameters
+#[derive(Debug, Clone)]
+struct Generic {
+    params: TypeParams,
+    t: Type,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct SymbolTable {
     map: HashMap<Rc<str>, Symbol>,
+    /// Enclosing loops, innermost last, with their optional label and the type they evaluate to
+    loops: Vec<(Option<Rc<str>>, Type)>,
+    /// How deeply nested the function being checked is, zero outside of any
+    function: u16,
+    /// Locals of outer functions used by each enclosing function, outermost first, which are
+    /// shared with the scopes inside it
+    captures: Vec<Rc<RefCell<Captures>>>,
+    /// Generic functions by name, which are only added to `map` once they are used
+    generics: Rc<HashMap<Rc<str>, Generic>>,
+    /// Every use of a generic function so far, shared between all scopes
+    instances: Rc<RefCell<Vec<Instance>>>,
+    /// Declared types by name, already resolved
+    types: Rc<HashMap<Rc<str>, Type>>,
+    /// Errors found so far, shared between all scopes so checking can continue after an error
+    errors: Rc<RefCell<Vec<TypeError>>>,
+    /// Where to record what is found out while checking, if anywhere
+    info: Option<Rc<RefCell<CheckInfo>>>,
 }
 
 impl SymbolTable {
@@ -25,20 +64,182 @@ impl SymbolTable {
     pub fn new() -> Self {
         Self::default()
     }
+    /// A symbol table that records types and scopes into `info` as they are checked
+    pub fn recording(info: Rc<RefCell<CheckInfo>>) -> Self {
+        SymbolTable {
+            info: Some(info),
+            ..Self::default()
+        }
+    }
+    pub fn with_types(self, types: HashMap<Rc<str>, Type>) -> Self {
+        SymbolTable {
+            types: Rc::new(types),
+            ..self
+        }
+    }
+    /// Declares the type parameters of a generic function as the given types
+    pub fn with_type_params(&self, params: impl IntoIterator<Item = (Rc<str>, Type)>) -> Self {
+        let mut types = (*self.types).clone();
+        types.extend(params);
+        SymbolTable {
+            types: Rc::new(types),
+            ..self.clone()
+        }
+    }
+    /// The names in scope and their types
+    pub fn symbols(&self) -> impl Iterator<Item = (&Rc<str>, &Type)> {
+        self.map.iter().map(|(name, sym)| (name, &sym.s_type))
+    }
+    /// The declared types and what they are
+    pub fn types(&self) -> impl Iterator<Item = (&Rc<str>, &Type)> {
+        self.types.iter()
+    }
+    pub(super) fn map_types(&mut self, mut f: impl FnMut(&mut Type)) {
+        for sym in self.map.values_mut() {
+            f(&mut sym.s_type);
+        }
+    }
+    pub fn record_type(&self, loc: Location, t: &Type) {
+        if let Some(info) = &self.info {
+            info.borrow_mut().types.push((loc, t.clone()));
+        }
+    }
+    /// Records the names in scope at the end of the block at `loc`
+    pub fn record_scope(&self, loc: Location) {
+        if let Some(info) = &self.info {
+            info.borrow_mut().scopes.push((loc, self.detached()));
+        }
+    }
+    pub fn record_globals(&self) {
+        if let Some(info) = &self.info {
+            info.borrow_mut().globals = self.detached();
+        }
+    }
+    /// A copy that does not record anything, so it can be kept in the records
+    fn detached(&self) -> Self {
+        SymbolTable {
+            info: None,
+            ..self.clone()
+        }
+    }
+    /// Replaces the names of declared types in `t` with the types they name
+    pub fn resolve_type(&self, t: &Type) -> Result<Type, TypeErrorType> {
+        t.resolve_names(&mut |name| {
+            self.types
+                .get(name)
+                .cloned()
+                .ok_or_else(|| TypeErrorType::UndefinedType((**name).into()))
+        })
+    }
+    /// Records an error to be returned once checking is done
+    pub fn report(&self, e: TypeError) {
+        self.errors.borrow_mut().push(e);
+    }
+    pub fn take_errors(&self) -> Vec<TypeError> {
+        mem::take(&mut self.errors.borrow_mut())
+    }
     pub fn add<S: Into<Rc<str>>>(&mut self, mutable: bool, name: S, ty: Type) -> bool {
         self.map
-            .insert(name.into(), Symbol::new(ty, mutable))
+            .insert(name.into(), Symbol::new(ty, mutable, self.function))
             .is_some()
     }
+    /// Adds a generic function, whose type uses the names of its type parameters
+    pub fn add_generic(&mut self, name: Rc<str>, params: TypeParams, t: Type) {
+        Rc::make_mut(&mut self.generics).insert(name, Generic { params, t });
+    }
+    /// The type of a use of the generic function `name` at `loc`, with new type variables for its
+    /// type parameters, which are also given
+    pub fn instantiate(&self, loc: &Location, name: &str) -> Option<(Type, Box<[Type]>)> {
+        let Generic { params, t } = self.generics.get(name)?;
+        let types: Box<[Type]> = params
+            .iter()
+            .map(|(_, possible)| match possible {
+                Some(possible) => Type::constrained(possible.iter().cloned()),
+                None => Type::any(),
+            })
+            .collect();
+        let Ok(t) = t.resolve_names(&mut |n| {
+            let param = params.iter().position(|(p, _)| p == n);
+            Ok::<_, Infallible>(param.map_or_else(|| Type::Named(n.clone()), |i| types[i].clone()))
+        });
+        self.instances
+            .borrow_mut()
+            .push((loc.clone(), name.into(), types.clone()));
+        Some((t, types))
+    }
+    /// The `n`th use of a generic function, if there have been that many
+    pub fn instance(&self, n: usize) -> Option<Instance> {
+        self.instances.borrow().get(n).cloned()
+    }
+    /// Whether `sym` is a local of a function enclosing the one being checked
+    fn is_captured(&self, sym: &Symbol) -> bool {
+        sym.function != 0 && sym.function < self.function
+    }
+    /// Looks up a symbol, recording it as captured by every function between the one it is local
+    /// to and the one being checked
     pub fn lookup_raw(&self, name: &str) -> Result<Symbol, TypeErrorType> {
-        self.map
+        let sym = self
+            .map
             .get(name)
             .cloned()
-            .ok_or_else(|| TypeErrorType::Undefined(name.into()))
+            .ok_or_else(|| TypeErrorType::Undefined(name.into()))?;
+        if self.is_captured(&sym) {
+            for captures in &self.captures[sym.function as usize..] {
+                let mut captures = captures.borrow_mut();
+                if !captures.iter().any(|(n, _)| **n == *name) {
+                    captures.push((name.into(), sym.s_type.clone()));
+                }
+            }
+        }
+        Ok(sym)
     }
     pub fn lookup(&self, name: &str) -> Result<Type, TypeErrorType> {
         self.lookup_raw(name).map(|sym| sym.s_type)
     }
+    /// Like `lookup` but fails if the symbol cannot be mutated
+    pub fn lookup_mutable(&self, name: &str) -> Result<Type, TypeErrorType> {
+        let sym = self.lookup_raw(name)?;
+        if !sym.mutable {
+            return Err(TypeErrorType::NotMutable(name.into()));
+        }
+        if self.is_captured(&sym) {
+            return Err(TypeErrorType::AssignToCapture(name.into()));
+        }
+        Ok(sym.s_type)
+    }
+    pub fn enter_loop(&mut self, label: Option<Rc<str>>, t: Type) {
+        self.loops.push((label, t));
+    }
+    /// Used when entering a new function body, where outer loops cannot be broken out of and
+    /// outer locals are captured
+    pub fn enter_function(&mut self) {
+        self.loops.clear();
+        self.function += 1;
+        self.captures.push(Rc::default());
+    }
+    /// The locals of outer functions used so far in the function being checked, with their types
+    pub fn captures(&self) -> Box<[(Rc<str>, Type)]> {
+        self.captures
+            .last()
+            .map_or_else(Box::default, |c| c.borrow().iter().cloned().collect())
+    }
+    /// Finds the type of the loop with the given label or the innermost loop if there is no label
+    pub fn lookup_loop(&self, keyword: &str, label: Option<&str>) -> Result<Type, TypeErrorType> {
+        match label {
+            None => self
+                .loops
+                .last()
+                .map(|(_, t)| t.clone())
+                .ok_or_else(|| TypeErrorType::OutsideOfLoop(keyword.into())),
+            Some(label) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|(l, _)| l.as_deref() == Some(label))
+                .map(|(_, t)| t.clone())
+                .ok_or_else(|| TypeErrorType::UndefinedLabel(label.into())),
+        }
+    }
     pub fn specify(&mut self, loc: &Location, name: &str, t: &Type) -> Result<Type> {
         let et = &self.map.get(name).unwrap().s_type;
         let ut = unify_types(loc, et, t)?;
@@ -47,7 +248,10 @@ impl SymbolTable {
         Ok(ut)
     }
     pub fn mutate(&mut self, loc: &Location, name: &str, t: &Type) -> Result<Type> {
-        let Some(Symbol { s_type, mutable }) = self.map.get(name) else {
+        let Some(Symbol {
+            s_type, mutable, ..
+        }) = self.map.get(name)
+        else {
             return Err(TypeErrorType::Undefined(name.into()).location(loc.clone()));
         };
         if !mutable {
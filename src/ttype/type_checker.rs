@@ -1,27 +1,90 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    path::Path,
+    rc::Rc,
+};
+
 use collect_result::CollectResult;
 
 use self::concrete::{concretise_expr, concretise_type};
 
 use super::{
-    ast::{Decl, Expr, PlaceExpr, Program, Statement},
+    ast::{Decl, Expr, Iterable, Pattern, PlaceExpr, Program, Statement},
     stab::SymbolTable,
-    unify_types, Result, Type, TypeErrorType,
+    unify_types, Result, Type, TypeError, TypeErrorType, Variant,
 };
 use crate::parse::{
     ast::{
-        Decl as UntypedDecl, Expr as UntypedExpr, Literal as UntypedLiteral,
-        PlaceExpr as UntypedPle, Program as Prgm, Statement as UntypedStatement,
+        Decl as UntypedDecl, Expr as UntypedExpr, Iterable as UntypedIterable,
+        Literal as UntypedLiteral, Pattern as UntypedPattern, PlaceExpr as UntypedPle,
+        Program as Prgm, Statement as UntypedStatement,
     },
     location::Location,
 };
 
-pub fn check_program(Prgm(decls): Prgm) -> Result<Program> {
-    let mut stab = SymbolTable::new();
+/// What was found out about a program while checking it, for tools like the language server
+#[derive(Debug, Default)]
+pub struct CheckInfo {
+    /// Types of the expressions and bindings that were checked, by where they are
+    pub types: Vec<(Location, Type)>,
+    /// What was in scope at the end of each block
+    pub scopes: Vec<(Location, SymbolTable)>,
+    /// Everything declared at the top level
+    pub globals: SymbolTable,
+}
+
+/// Checks the whole program, carrying on past errors to report as many of them as possible
+pub fn check_program(program: Prgm) -> Result<Program, Vec<TypeError>> {
+    check(program, SymbolTable::new())
+}
+
+/// Like `check_program`, but also records what was found out about the program, even if it has
+/// errors
+pub fn check_program_with_info(program: Prgm) -> (Result<Program, Vec<TypeError>>, CheckInfo) {
+    let info = Rc::new(RefCell::new(CheckInfo::default()));
+    let checked = check(program, SymbolTable::recording(info.clone()));
+    let mut info = Rc::try_unwrap(info)
+        .expect("all symbol tables are dropped after checking")
+        .into_inner();
+
+    // the types are only known now that the whole program has been checked
+    for (loc, t) in &mut info.types {
+        let _ = concretise_type(loc.clone(), t);
+    }
+    for (loc, scope) in &mut info.scopes {
+        scope.map_types(|t| {
+            let _ = concretise_type(loc.clone(), t);
+        });
+    }
+    let loc = Location::new(Path::new("").into());
+    info.globals.map_types(|t| {
+        let _ = concretise_type(loc.clone(), t);
+    });
+
+    (checked, info)
+}
+
+fn check(Prgm(decls): Prgm, stab: SymbolTable) -> Result<Program, Vec<TypeError>> {
+    let mut errors = Vec::new();
+    let types = collect_types(&decls, &mut errors);
+    let mut stab = stab.with_types(types);
+    for e in errors {
+        stab.report(e);
+    }
+    let decls: Vec<_> = decls
+        .into_iter()
+        .filter(|(_, decl)| !is_type_decl(decl))
+        .map(|(name, decl)| (name, resolve_decl_types(decl, &stab)))
+        .collect();
+
+    let mut defined: HashMap<_, &Location> = HashMap::new();
     for (name, decl) in &decls {
         let (mutable, loc, t) = match decl {
             UntypedDecl::Const(loc, b) => (false, loc, b.0.clone()),
             UntypedDecl::Static(loc, b) => (true, loc, b.0.clone()),
-            UntypedDecl::Fn(loc, args, b) => (
+            UntypedDecl::Fn(loc, _, args, b) => (
                 false,
                 loc,
                 Type::Function(
@@ -35,87 +98,495 @@ pub fn check_program(Prgm(decls): Prgm) -> Result<Program> {
                 loc,
                 Type::Function(args.iter().map(|(_, t)| t.clone()).collect(), ret.clone()),
             ),
+            _ => unreachable!(),
         };
-        if stab.add(mutable, name.clone(), t) {
-            return Err(
-                TypeErrorType::DuplicateGlobalDefinition((&**name).into()).location(loc.clone())
+        let duplicate = defined.contains_key(name);
+        match decl {
+            UntypedDecl::Fn(_, type_params, _, _) if !type_params.is_empty() => {
+                let params = type_params
+                    .iter()
+                    .map(|(n, kind)| {
+                        let kind = kind.as_ref().and_then(|kind| {
+                            let types = Type::of_kind(kind);
+                            if types.is_none() {
+                                let e = TypeErrorType::UnknownKind((**kind).into());
+                                stab.report(e.location(loc.clone()));
+                            }
+                            types.map(Box::from)
+                        });
+                        (n.clone(), kind)
+                    })
+                    .collect();
+                stab.add_generic(name.clone(), params, t);
+            }
+            _ => {
+                stab.add(mutable, name.clone(), t);
+            }
+        }
+        if duplicate {
+            stab.report(
+                TypeErrorType::DuplicateGlobalDefinition((&**name).into())
+                    .location(loc.clone())
+                    .with_label(defined[name].clone(), "first defined here"),
             );
+        } else {
+            defined.insert(name.clone(), loc);
         }
     }
 
     let mut new_decls = Vec::with_capacity(decls.len());
+    let mut generics = HashMap::new();
 
     for (name, decl) in decls {
-        match decl {
-            UntypedDecl::Static(loc, b) => {
-                let (et, e) = *b;
-                let (at, e) = check_expr(&e, &stab)?;
-                let t = unify_types(&loc, &et, &at)?;
-                let t = stab.specify(&loc, &name, &t)?;
-                new_decls.push((name, Decl::Static(loc, Box::new((t, e)))));
+        if let UntypedDecl::Fn(_, type_params, _, _) = &decl {
+            if !type_params.is_empty() {
+                generics.insert(name, decl);
+                continue;
             }
-            UntypedDecl::Const(loc, b) => {
-                let (et, e) = *b;
-                let (at, e) = check_expr(&e, &stab)?;
-                let t = unify_types(&loc, &et, &at)?;
-                let t = stab.specify(&loc, &name, &t)?;
-                new_decls.push((name, Decl::Const(loc, Box::new((t, e)))));
-            }
-            UntypedDecl::Fn(loc, args, b) => {
-                let (t, e) = {
-                    let mut stab = stab.clone();
-                    for (arg, arg_t) in &*args {
-                        stab.add(false, arg.clone(), arg_t.clone());
-                    }
+        }
+        match check_decl(&name, decl, &mut stab) {
+            Ok(decl) => new_decls.push((name, decl)),
+            Err(e) => stab.report(e),
+        }
+    }
 
-                    let (et, e) = *b;
-                    let (at, e) = check_expr(&e, &stab)?;
-                    let t = unify_types(&loc, &et, &at)?;
+    for (_, decl) in &mut new_decls {
+        if let Err(e) = concretise_decl(decl) {
+            stab.report(e);
+        }
+    }
 
-                    (t, e)
-                };
-                new_decls.push((name, Decl::Fn(loc, args, Box::new((t, e)))));
+    // generic functions are checked again for every set of types they are used with, which can
+    // use more generic functions
+    let mut instantiated = HashSet::new();
+    let mut next = 0;
+    while let Some((loc, name, mut types)) = stab.instance(next) {
+        next += 1;
+        let mut concrete = true;
+        for t in types.iter_mut() {
+            if let Err(e) = concretise_type(loc.clone(), t) {
+                stab.report(e);
+                concrete = false;
             }
-            UntypedDecl::ExternFn(loc, args, ret) => {
-                new_decls.push((name, Decl::ExternFn(loc, args, ret)))
+        }
+        if !concrete {
+            continue;
+        }
+        let instance = instance_name(&name, &types);
+        if !instantiated.insert(instance.clone()) {
+            continue;
+        }
+        let Some(UntypedDecl::Fn(fn_loc, type_params, args, b)) = generics.get(&name).cloned()
+        else {
+            unreachable!("only generic functions are instantiated");
+        };
+        let params = type_params
+            .iter()
+            .map(|(n, _)| n.clone())
+            .zip(types.into_vec());
+        let mut stab = stab.with_type_params(params);
+        let decl = resolve_decl_types(UntypedDecl::Fn(fn_loc, Box::default(), args, b), &stab);
+        match check_decl(&instance, decl, &mut stab) {
+            Ok(mut decl) => {
+                if let Err(e) = concretise_decl(&mut decl) {
+                    stab.report(e);
+                }
+                new_decls.push((instance, decl));
             }
-            UntypedDecl::ExternStatic(loc, t) => new_decls.push((name, Decl::ExternStatic(loc, t))),
+            Err(e) => stab.report(e),
         }
     }
 
-    for (_, decl) in &mut new_decls {
-        match decl {
-            Decl::Static(loc, b) => {
-                concretise_type(loc.clone(), &mut b.0)?;
-                concretise_expr(&mut b.1)?;
-            }
-            Decl::Const(loc, b) => {
-                concretise_type(loc.clone(), &mut b.0)?;
-                concretise_expr(&mut b.1)?;
-            }
-            Decl::Fn(loc, a, b) => {
-                for (_, t) in &mut **a {
-                    concretise_type(loc.clone(), t)?;
+    stab.record_globals();
+    let errors = stab.take_errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Program(new_decls.into_boxed_slice()))
+}
+
+/// Name of the instance of the generic function `name` for the types of its type parameters
+fn instance_name(name: &str, types: &[Type]) -> Rc<str> {
+    let types: Vec<_> = types.iter().map(Type::to_string).collect();
+    format!("{name}<{}>", types.join(", ")).into()
+}
+
+fn check_decl(name: &str, decl: UntypedDecl, stab: &mut SymbolTable) -> Result<Decl> {
+    Ok(match decl {
+        UntypedDecl::Static(loc, b) => {
+            let (et, e) = *b;
+            let (t, e) = coerce(&loc, check_expr(&e, stab)?, &et)?;
+            let t = stab.specify(&loc, name, &t)?;
+            Decl::Static(loc, Box::new((t, e)))
+        }
+        UntypedDecl::Const(loc, b) => {
+            let (et, e) = *b;
+            let (t, e) = coerce(&loc, check_expr(&e, stab)?, &et)?;
+            let t = stab.specify(&loc, name, &t)?;
+            Decl::Const(loc, Box::new((t, e)))
+        }
+        UntypedDecl::Fn(loc, _, args, b) => {
+            let (t, e) = {
+                let mut stab = stab.clone();
+                stab.enter_function();
+                for (arg, arg_t) in &*args {
+                    stab.add(false, arg.clone(), arg_t.clone());
                 }
-                concretise_type(loc.clone(), &mut b.0)?;
-                concretise_expr(&mut b.1)?;
+
+                let (et, e) = *b;
+                coerce(&loc, check_expr(&e, &stab)?, &et)?
+            };
+            Decl::Fn(loc, args, Box::new((t, e)))
+        }
+        UntypedDecl::ExternFn(loc, args, ret) => Decl::ExternFn(loc, args, ret),
+        UntypedDecl::ExternStatic(loc, t) => Decl::ExternStatic(loc, t),
+        _ => unreachable!(),
+    })
+}
+
+fn concretise_decl(decl: &mut Decl) -> Result<()> {
+    match decl {
+        Decl::Static(loc, b) => {
+            concretise_type(loc.clone(), &mut b.0)?;
+            concretise_expr(&mut b.1)?;
+        }
+        Decl::Const(loc, b) => {
+            concretise_type(loc.clone(), &mut b.0)?;
+            concretise_expr(&mut b.1)?;
+        }
+        Decl::Fn(loc, a, b) => {
+            for (_, t) in &mut **a {
+                concretise_type(loc.clone(), t)?;
             }
-            Decl::ExternStatic(loc, t) => {
+            concretise_type(loc.clone(), &mut b.0)?;
+            concretise_expr(&mut b.1)?;
+        }
+        Decl::ExternStatic(loc, t) => {
+            concretise_type(loc.clone(), t)?;
+        }
+        Decl::ExternFn(loc, a, ret) => {
+            for (_, t) in &mut **a {
                 concretise_type(loc.clone(), t)?;
             }
-            Decl::ExternFn(loc, a, ret) => {
-                for (_, t) in &mut **a {
-                    concretise_type(loc.clone(), t)?;
+            concretise_type(loc.clone(), ret)?;
+        }
+    }
+    Ok(())
+}
+
+mod concrete;
+
+/// Resolves the struct and enum declarations so their names can be used as types, types that
+/// cannot be resolved are made opaque
+fn collect_types(
+    decls: &[(Rc<str>, UntypedDecl)],
+    errors: &mut Vec<TypeError>,
+) -> HashMap<Rc<str>, Type> {
+    let mut type_decls = HashMap::new();
+    for (name, decl) in decls {
+        if is_type_decl(decl) {
+            if let Some(first) = type_decls.insert(name.clone(), decl) {
+                let (loc, first) = (decl.location(), first.location());
+                errors.push(
+                    TypeErrorType::DuplicateGlobalDefinition((&**name).into())
+                        .location(loc.clone())
+                        .with_label(first.clone(), "first defined here"),
+                );
+            }
+        }
+    }
+
+    let mut types = HashMap::new();
+    for (name, decl) in decls {
+        if is_type_decl(decl) {
+            let loc = decl.location();
+            resolve_type_decl(name, loc, &type_decls, &mut types, &mut Vec::new(), errors);
+        }
+    }
+    types
+}
+
+type TypeDecls<'a> = HashMap<Rc<str>, &'a UntypedDecl>;
+
+/// Whether the declaration is of a type rather than a value
+fn is_type_decl(decl: &UntypedDecl) -> bool {
+    matches!(
+        decl,
+        UntypedDecl::Alias(_, _)
+            | UntypedDecl::Newtype(_, _)
+            | UntypedDecl::Struct(_, _)
+            | UntypedDecl::Enum(_, _)
+    )
+}
+
+fn resolve_type_decl(
+    name: &Rc<str>,
+    loc: &Location,
+    type_decls: &TypeDecls,
+    types: &mut HashMap<Rc<str>, Type>,
+    visiting: &mut Vec<Rc<str>>,
+    errors: &mut Vec<TypeError>,
+) -> Type {
+    if let Some(t) = types.get(name) {
+        return t.clone();
+    }
+    let Some(&decl) = type_decls.get(name) else {
+        errors.push(TypeErrorType::UndefinedType((**name).into()).location(loc.clone()));
+        return Type::Opaque;
+    };
+    let decl_loc = decl.location();
+    if visiting.contains(name) {
+        errors.push(TypeErrorType::RecursiveType((**name).into()).location(decl_loc.clone()));
+        return Type::Opaque;
+    }
+
+    visiting.push(name.clone());
+    let mut resolve = |t: &Type, errors: &mut Vec<TypeError>| {
+        let resolved: Result<_, Infallible> = t.resolve_names(&mut |n| {
+            Ok(resolve_type_decl(
+                n, decl_loc, type_decls, types, visiting, errors,
+            ))
+        });
+        let Ok(t) = resolved;
+        t
+    };
+    let t = match decl {
+        UntypedDecl::Struct(_, fields) => {
+            let mut resolved: Vec<(Rc<str>, Type)> = Vec::with_capacity(fields.len());
+            for (field_n, field_t) in &**fields {
+                if resolved.iter().any(|(n, _)| n == field_n) {
+                    errors.push(
+                        TypeErrorType::DuplicateField((**field_n).into())
+                            .location(decl_loc.clone()),
+                    );
+                    continue;
+                }
+                resolved.push((field_n.clone(), resolve(field_t, errors)));
+            }
+            Type::Struct(resolved.into_boxed_slice())
+        }
+        UntypedDecl::Enum(_, variants) => {
+            let mut resolved: Vec<Variant> = Vec::with_capacity(variants.len());
+            for (variant_n, payload) in &**variants {
+                if resolved.iter().any(|(n, _)| n == variant_n) {
+                    errors.push(
+                        TypeErrorType::DuplicateVariant((**variant_n).into())
+                            .location(decl_loc.clone()),
+                    );
+                    continue;
                 }
-                concretise_type(loc.clone(), ret)?;
+                let payload: Vec<_> = payload.iter().map(|t| resolve(t, errors)).collect();
+                resolved.push((variant_n.clone(), payload.into_boxed_slice()));
             }
+            Type::Enum(resolved.into_boxed_slice())
+        }
+        UntypedDecl::Alias(_, t) => resolve(t, errors),
+        UntypedDecl::Newtype(_, t) => Type::Distinct(Box::new((name.clone(), resolve(t, errors)))),
+        _ => unreachable!(),
+    };
+    visiting.pop();
+
+    types.insert(name.clone(), t.clone());
+    t
+}
+
+/// Resolves the named types in the declaration, reporting the ones that do not exist and making
+/// them opaque
+fn resolve_decl_types(decl: UntypedDecl, stab: &SymbolTable) -> UntypedDecl {
+    let resolve = |loc: &Location, t: &Type| {
+        stab.resolve_type(t).unwrap_or_else(|e| {
+            stab.report(e.location(loc.clone()));
+            Type::Opaque
+        })
+    };
+    let resolve_args = |loc: &Location, args: &[(Rc<str>, Type)]| {
+        args.iter()
+            .map(|(n, t)| (n.clone(), resolve(loc, t)))
+            .collect()
+    };
+
+    match decl {
+        UntypedDecl::Static(loc, b) => {
+            let (t, e) = *b;
+            let t = resolve(&loc, &t);
+            UntypedDecl::Static(loc, Box::new((t, e)))
         }
+        UntypedDecl::Const(loc, b) => {
+            let (t, e) = *b;
+            let t = resolve(&loc, &t);
+            UntypedDecl::Const(loc, Box::new((t, e)))
+        }
+        UntypedDecl::Fn(loc, type_params, args, b) if !type_params.is_empty() => {
+            // what the type parameters are is only known once the function is used
+            let generic = stab.with_type_params(
+                type_params
+                    .iter()
+                    .map(|(n, _)| (n.clone(), Type::Named(n.clone()))),
+            );
+            let UntypedDecl::Fn(loc, _, args, b) =
+                resolve_decl_types(UntypedDecl::Fn(loc, Box::default(), args, b), &generic)
+            else {
+                unreachable!()
+            };
+            UntypedDecl::Fn(loc, type_params, args, b)
+        }
+        UntypedDecl::Fn(loc, type_params, args, b) => {
+            let (t, e) = *b;
+            let args: Vec<_> = resolve_args(&loc, &args);
+            let t = resolve(&loc, &t);
+            UntypedDecl::Fn(loc, type_params, args.into_boxed_slice(), Box::new((t, e)))
+        }
+        UntypedDecl::ExternStatic(loc, t) => {
+            let t = resolve(&loc, &t);
+            UntypedDecl::ExternStatic(loc, Box::new(t))
+        }
+        UntypedDecl::ExternFn(loc, args, ret) => {
+            let args: Vec<_> = resolve_args(&loc, &args);
+            let ret = resolve(&loc, &ret);
+            UntypedDecl::ExternFn(loc, args.into_boxed_slice(), Box::new(ret))
+        }
+        decl => decl,
     }
+}
 
-    Ok(Program(new_decls.into_boxed_slice()))
+/// Type of the field `field` of a value of type `t`
+fn field_type(t: &Type, field: &str) -> Result<Type, TypeErrorType> {
+    match t {
+        Type::Struct(fields) => fields
+            .iter()
+            .find(|(n, _)| &**n == field)
+            .map(|(_, t)| t.clone())
+            .ok_or_else(|| TypeErrorType::NoSuchField(t.clone(), field.into())),
+        // the error that made it opaque has already been reported
+        Type::Opaque => Ok(Type::Opaque),
+        Type::Unknown(tv) => match tv.known_type() {
+            Some(known) => field_type(&known, field),
+            None => Err(TypeErrorType::NoSuchField(t.clone(), field.into())),
+        },
+        t => Err(TypeErrorType::NoSuchField(t.clone(), field.into())),
+    }
 }
 
-mod concrete;
+/// Type of reading `field` of a value of type `t`, which unlike assigning to it also allows the
+/// length of arrays and slices
+fn property_type(t: &Type, field: &str) -> Result<Type, TypeErrorType> {
+    match (t, field) {
+        (Type::Array(_, _) | Type::Slice(_), "len") => Ok(Type::U16),
+        (Type::Unknown(tv), _) => match tv.known_type() {
+            Some(known) => property_type(&known, field),
+            None => field_type(t, field),
+        },
+        _ => field_type(t, field),
+    }
+}
+
+/// Payload types of the variant `variant` of a value of type `t`
+fn variant_payload(t: &Type, variant: &str) -> Result<Box<[Type]>, TypeErrorType> {
+    match t {
+        Type::Enum(variants) => variants
+            .iter()
+            .find(|(n, _)| &**n == variant)
+            .map(|(_, payload)| payload.clone())
+            .ok_or_else(|| TypeErrorType::NoSuchVariant(t.clone(), variant.into())),
+        t => Err(TypeErrorType::NoSuchVariant(t.clone(), variant.into())),
+    }
+}
+
+/// Type of the elements of a value of type `t` that can be indexed into
+fn element_type(t: &Type) -> Result<Type, TypeErrorType> {
+    match t {
+        Type::Array(elem_t, _) | Type::Slice(elem_t) | Type::ArrayPointer(elem_t) => {
+            Ok((**elem_t).clone())
+        }
+        Type::Opaque => Ok(Type::Opaque),
+        Type::Unknown(tv) => match tv.known_type() {
+            Some(known) => element_type(&known),
+            None => Err(TypeErrorType::CannotIndex(t.clone())),
+        },
+        t => Err(TypeErrorType::CannotIndex(t.clone())),
+    }
+}
+
+/// The type a type variable is known to be, if it is
+fn known(t: Type) -> Type {
+    match t {
+        Type::Unknown(tv) => tv.known_type().unwrap_or(Type::Unknown(tv)),
+        t => t,
+    }
+}
+
+/// Checks an expression that is being assigned to, such as the struct in a field assignment,
+/// `loc` is where the assignment happens
+fn check_place_expr(
+    e: &UntypedExpr,
+    loc: &Location,
+    state: &SymbolTable,
+) -> Result<(Type, PlaceExpr)> {
+    match e {
+        UntypedExpr::Ident(loc, n) => {
+            let t = state
+                .lookup_mutable(n)
+                .map_err(|e| e.location(loc.clone()))?;
+            Ok((t, PlaceExpr::Ident(loc.clone(), n.clone())))
+        }
+        UntypedExpr::Deref(loc, ptr_e) => {
+            let (ptr_t, ptr_e) = check_expr(ptr_e, state)?;
+            let Type::Pointer(inner_t) = ptr_t else {
+                return Err(TypeErrorType::NotPtr(ptr_t).location(loc.clone()));
+            };
+            Ok((
+                (*inner_t).clone(),
+                PlaceExpr::Deref(loc.clone(), Box::new(ptr_e), inner_t),
+            ))
+        }
+        UntypedExpr::FieldAccess(loc, strct_e, field) => {
+            let (strct_t, strct_e) = check_place_expr(strct_e, loc, state)?;
+            let t = field_type(&strct_t, field).map_err(|e| e.location(loc.clone()))?;
+            Ok((
+                t,
+                PlaceExpr::FieldAccess(
+                    loc.clone(),
+                    Box::new(strct_e),
+                    field.clone(),
+                    Box::new(strct_t),
+                ),
+            ))
+        }
+        UntypedExpr::Index(loc, arr_e, index_e) => {
+            // only arrays hold their elements in the place itself, slices are written through
+            let (arr_t, arr_e) = match check_place_expr(arr_e, loc, state) {
+                Ok((arr_t, arr_e)) if matches!(known(arr_t.clone()), Type::Array(_, _)) => {
+                    (known(arr_t), Ok(arr_e))
+                }
+                place => match check_expr(arr_e, state) {
+                    Ok((slice_t, slice_e)) if matches!(known(slice_t.clone()), Type::Slice(_)) => {
+                        (known(slice_t), Err(slice_e))
+                    }
+                    _ => {
+                        place?;
+                        return Err(TypeErrorType::NotAssignable(e.to_string().into())
+                            .location(loc.clone()));
+                    }
+                },
+            };
+            let (Type::Array(elem_t, _) | Type::Slice(elem_t)) = &arr_t else {
+                unreachable!("checked to be an array or a slice");
+            };
+            let elem_t = (**elem_t).clone();
+            let index_e = Box::new(check_expr_as(index_e, state, Type::U16)?);
+            let loc = loc.clone();
+            let arr_t = Box::new(arr_t);
+            Ok((
+                elem_t,
+                match arr_e {
+                    Ok(arr_e) => PlaceExpr::Index(loc, Box::new(arr_e), index_e, arr_t),
+                    Err(slice_e) => PlaceExpr::SliceElement(loc, Box::new(slice_e), index_e, arr_t),
+                },
+            ))
+        }
+        e => Err(TypeErrorType::NotAssignable(e.to_string().into()).location(loc.clone())),
+    }
+}
 
 fn check_statements(
     statements: Box<[UntypedStatement]>,
@@ -125,57 +596,32 @@ fn check_statements(
     let mut block_type = Type::Unit;
     let mut stmnts = Vec::with_capacity(statements.len());
     for stmnt in statements.into_vec() {
-        match stmnt {
-            UntypedStatement::Express(loc, e) => {
-                let (t, e) = check_expr(&e, state)?;
-                block_type = t.clone();
-                stmnts.push(Statement::Express(loc, Box::new(t), e));
-            }
-            UntypedStatement::Let(loc, n, t, e) => {
-                let (ct, e) = check_expr(&e, state)?;
-                let t = unify_types(&loc, &t.unwrap_or_else(Type::any), &ct)?;
-                state.add(false, n.clone(), t.clone());
-                stmnts.push(Statement::Let(loc, n, Box::new(t), e));
-            }
-            UntypedStatement::Var(loc, n, t, e) => {
-                let (ct, e) = check_expr(&e, state)?;
-                let t = unify_types(&loc, &t.unwrap_or_else(Type::any), &ct)?;
-                state.add(true, n.clone(), t.clone());
-                stmnts.push(Statement::Var(loc, n, Box::new(t), e));
-            }
-            UntypedStatement::Rebind(loc, UntypedPle::Ident(loc2, n), e) => {
-                let (t, e) = check_expr(&e, state)?;
-                let _t = state.mutate(&loc, &n, &t)?;
-                stmnts.push(Statement::Rebind(loc, PlaceExpr::Ident(loc2, n), e));
-            }
-            UntypedStatement::Rebind(loc, UntypedPle::Deref(loc2, ptr_e), e) => {
-                let (ptr_t, ptr_e) = check_expr(&ptr_e, state)?;
-                let (t, e) = check_expr(&e, state)?;
-                let Type::Pointer(inner_t) = ptr_t else {
-                    return Err(TypeErrorType::NotPtr(ptr_t).location(loc));
-                };
-                let t = unify_types(&loc, &t, &inner_t)?;
-                stmnts.push(Statement::Rebind(
-                    loc,
-                    PlaceExpr::Deref(loc2, Box::new(ptr_e), Box::new(t)),
-                    e,
-                ));
-            }
-            UntypedStatement::Rebind(_loc, UntypedPle::Index(_loc2, arr_e, ind_e), e) => {
-                todo!("check Index({arr_e}, {ind_e}), {e})")
-            }
-            UntypedStatement::Rebind(_loc, UntypedPle::FieldAccess(_loc2, str_e, i), e) => {
-                todo!("check FieldAccess({str_e}, {i}), {e})")
+        let binding = match &stmnt {
+            UntypedStatement::Let(_, n, t, _) => Some((false, n.clone(), t.clone())),
+            UntypedStatement::Var(_, n, t, _) => Some((true, n.clone(), t.clone())),
+            _ => None,
+        };
+        let is_express = matches!(stmnt, UntypedStatement::Express(_, _));
+        match check_statement(stmnt, state, ret) {
+            Ok(stmnt) => {
+                if let Statement::Express(_, t, _) = &stmnt {
+                    block_type = (**t).clone();
+                }
+                stmnts.push(stmnt);
             }
-            UntypedStatement::Return(loc, e) => {
-                let (t, e) = check_expr(&e, state)?;
-                if let Some(ret_t) = ret.take() {
-                    *ret = Some(unify_types(&loc, &ret_t, &t)?);
-                } else {
-                    *ret = Some(t);
+            Err(e) => {
+                state.report(e);
+                // keep checking what comes after with the types that failed made opaque, so the
+                // error does not cause more errors, but annotated bindings keep their annotation
+                if let Some((mutable, n, annotation)) = binding {
+                    let t = annotation
+                        .and_then(|(_, t)| state.resolve_type(&t).ok())
+                        .unwrap_or(Type::Opaque);
+                    state.add(mutable, n, t);
+                }
+                if is_express {
+                    block_type = Type::Opaque;
                 }
-
-                stmnts.push(Statement::Return(loc, e));
             }
         }
     }
@@ -183,6 +629,151 @@ fn check_statements(
     Ok((block_type, stmnts))
 }
 
+fn check_statement(
+    stmnt: UntypedStatement,
+    state: &mut SymbolTable,
+    ret: &mut Option<Type>,
+) -> Result<Statement> {
+    match stmnt {
+        UntypedStatement::Express(loc, e) => {
+            let (t, e) = check_expr(&e, state)?;
+            Ok(Statement::Express(loc, Box::new(t), e))
+        }
+        UntypedStatement::Let(loc, n, t, e) => {
+            let (t, e) = check_binding(&loc, t, &e, state)?;
+            state.record_type(loc.clone(), &t);
+            state.add(false, n.clone(), t.clone());
+            Ok(Statement::Let(loc, n, Box::new(t), e))
+        }
+        UntypedStatement::Var(loc, n, t, e) => {
+            let (t, e) = check_binding(&loc, t, &e, state)?;
+            state.record_type(loc.clone(), &t);
+            state.add(true, n.clone(), t.clone());
+            Ok(Statement::Var(loc, n, Box::new(t), e))
+        }
+        UntypedStatement::Rebind(loc, UntypedPle::Ident(loc2, n), e) => {
+            let t = state
+                .lookup_mutable(&n)
+                .map_err(|e| e.location(loc.clone()))?;
+            let (t, e) = coerce(&loc, check_expr(&e, state)?, &t)?;
+            let _t = state.mutate(&loc, &n, &t)?;
+            Ok(Statement::Rebind(loc, PlaceExpr::Ident(loc2, n), e))
+        }
+        UntypedStatement::Rebind(loc, UntypedPle::Deref(loc2, ptr_e), e) => {
+            let (ptr_t, ptr_e) = check_expr(&ptr_e, state)?;
+            let (t, e) = check_expr(&e, state)?;
+            let Type::Pointer(inner_t) = ptr_t else {
+                return Err(TypeErrorType::NotPtr(ptr_t).location(loc));
+            };
+            let t = unify_types(&loc, &t, &inner_t)?;
+            Ok(Statement::Rebind(
+                loc,
+                PlaceExpr::Deref(loc2, Box::new(ptr_e), Box::new(t)),
+                e,
+            ))
+        }
+        UntypedStatement::Rebind(loc, pl_e @ UntypedPle::Index(_, _, _), e) => {
+            let (t, pl_e) = check_place_expr(&pl_e.into_expr(), &loc, state)?;
+            let e = check_expr_as(&e, state, t)?;
+            Ok(Statement::Rebind(loc, pl_e, e))
+        }
+        UntypedStatement::Rebind(loc, UntypedPle::FieldAccess(loc2, strct_e, field), e) => {
+            let (strct_t, strct_e) = check_place_expr(&strct_e, &loc2, state)?;
+            let t = field_type(&strct_t, &field).map_err(|e| e.location(loc2.clone()))?;
+            let e = check_expr_as(&e, state, t)?;
+            Ok(Statement::Rebind(
+                loc,
+                PlaceExpr::FieldAccess(loc2, Box::new(strct_e), field, Box::new(strct_t)),
+                e,
+            ))
+        }
+        UntypedStatement::While(loc, label, c, b) => {
+            let c = check_expr_as(&c, state, Type::Bool)?;
+            let mut body_state = state.clone();
+            body_state.enter_loop(label.clone(), Type::Unit);
+            let (bt, b) = check_expr(&b, &body_state)?;
+            unify_types(&loc, &Type::Unit, &bt)?;
+            Ok(Statement::While(loc, label, c, b))
+        }
+        UntypedStatement::Break(loc, label, e) => {
+            let loop_t = state
+                .lookup_loop("break", label.as_deref())
+                .map_err(|e| e.location(loc.clone()))?;
+            let e = match e {
+                Some(e) => Some(check_expr_as(&e, state, loop_t)?),
+                None => {
+                    unify_types(&loc, &loop_t, &Type::Unit)?;
+                    None
+                }
+            };
+            Ok(Statement::Break(loc, label, e))
+        }
+        UntypedStatement::Continue(loc, label) => {
+            state
+                .lookup_loop("continue", label.as_deref())
+                .map_err(|e| e.location(loc.clone()))?;
+            Ok(Statement::Continue(loc, label))
+        }
+        UntypedStatement::For(loc, label, n, iterable, b) => {
+            let (t, iterable) = match iterable {
+                UntypedIterable::Range(start, end) => {
+                    let t = Type::constrained(Type::INT);
+                    let start = check_expr_as(&start, state, t.clone())?;
+                    let end = check_expr_as(&end, state, t.clone())?;
+                    (t, Iterable::Range(start, end))
+                }
+                UntypedIterable::Collection(e) => {
+                    // arrays get cast to slices
+                    let t = Type::any();
+                    let e = check_expr_as(&e, state, Type::Slice(Box::new(t.clone())))?;
+                    (t, Iterable::Slice(e))
+                }
+            };
+            let mut body_state = state.clone();
+            body_state.add(false, n.clone(), t.clone());
+            body_state.enter_loop(label.clone(), Type::Unit);
+            let (bt, b) = check_expr(&b, &body_state)?;
+            unify_types(&loc, &Type::Unit, &bt)?;
+            Ok(Statement::For(loc, label, n, Box::new(t), iterable, b))
+        }
+        UntypedStatement::Return(loc, e) => {
+            let (t, e) = check_expr(&e, state)?;
+            *ret = Some(match ret {
+                Some(ret_t) => unify_types(&loc, ret_t, &t)?,
+                None => t,
+            });
+
+            Ok(Statement::Return(loc, e))
+        }
+    }
+}
+
+/// Checks the value bound by a `let` or `var` against its annotation, if it has one
+fn check_binding(
+    loc: &Location,
+    annotation: Option<(Location, Type)>,
+    e: &UntypedExpr,
+    state: &SymbolTable,
+) -> Result<(Type, Expr)> {
+    let ct = check_expr(e, state)?;
+    let Some((annotation_loc, t)) = annotation else {
+        return coerce(loc, ct, &Type::any());
+    };
+    let t = state
+        .resolve_type(&t)
+        .map_err(|e| e.location(annotation_loc.clone()))?;
+    coerce(&ct.1.location(), ct, &t)
+        .map_err(|e| e.with_label(annotation_loc, "expected because of this annotation"))
+}
+
+/// Resolves a type annotation, missing annotations can be any type
+fn check_annotation(loc: &Location, t: Option<Type>, state: &SymbolTable) -> Result<Type> {
+    match t {
+        Some(t) => state.resolve_type(&t).map_err(|e| e.location(loc.clone())),
+        None => Ok(Type::any()),
+    }
+}
+
 fn check_literal(loc: Location, lit: &UntypedLiteral) -> (Type, Expr) {
     match lit {
         // TODO: check if literal can fit in candidate types
@@ -206,6 +797,7 @@ fn check_literal(loc: Location, lit: &UntypedLiteral) -> (Type, Expr) {
         &UntypedLiteral::Float(f) => (Type::Float, Expr::ConstFloat(loc, f)),
         &UntypedLiteral::Boolean(b) => (Type::Bool, Expr::ConstBoolean(loc, b)),
         &UntypedLiteral::Unit => (Type::Unit, Expr::ConstUnit(loc)),
+        UntypedLiteral::Null => (Type::Option(Box::new(Type::any())), Expr::ConstNull(loc)),
         UntypedLiteral::String(s) => (
             Type::Array(Box::new(Type::Byte), s.len() as u16),
             Expr::ConstString(loc, s.clone()),
@@ -213,20 +805,202 @@ fn check_literal(loc: Location, lit: &UntypedLiteral) -> (Type, Expr) {
     }
 }
 
-fn check_expr_as(expr: &UntypedExpr, state: &SymbolTable, expected_type: Type) -> Result<Expr> {
-    let (t, e) = check_expr(expr, state)?;
-    let loc = e.location();
-    let unified_type = unify_types(&loc, &expected_type, &t)?;
-    if t != unified_type {
-        Ok(Expr::Cast(
-            loc,
+/// If `t` is known to be an optional, the type of the value inside it
+fn option_inner(t: &Type) -> Option<Type> {
+    match t {
+        Type::Option(inner) => Some((**inner).clone()),
+        Type::Unknown(tv) => option_inner(&tv.known_type()?),
+        _ => None,
+    }
+}
+
+/// Whether a value of type `t` can only become an optional by wrapping it
+fn is_wrappable(t: &Type) -> bool {
+    match t {
+        Type::Option(_) | Type::Opaque => false,
+        Type::Unknown(tv) => !tv.is_any() && option_inner(t).is_none(),
+        _ => true,
+    }
+}
+
+/// How many optionals `t` is nested in, unless it could be nested in more once it is inferred
+fn option_depth(t: &Type) -> Option<usize> {
+    match t {
+        Type::Option(inner) => Some(option_depth(inner)? + 1),
+        Type::Unknown(tv) => match tv.known_type() {
+            Some(t) => option_depth(&t),
+            // only integers are constrained to a set of types
+            None => (!tv.is_any()).then_some(0),
+        },
+        Type::Opaque => None,
+        _ => Some(0),
+    }
+}
+
+/// Whether `t` is nested in fewer optionals than `expected_type`, so it has to be wrapped, as in
+/// a `?i16` where a `??i16` is expected
+fn is_shallower(t: &Type, expected_type: &Type) -> bool {
+    matches!((option_depth(t), option_depth(expected_type)), (Some(d), Some(e)) if d < e)
+}
+
+/// Implicitly turns `e` of type `t` into an optional holding a value of type `inner`
+fn wrap_optional(loc: &Location, e: Expr, t: Type, inner: &Type) -> Result<(Type, Expr)> {
+    // `inner` might be an optional as well
+    let (inner, e) = coerce(loc, (t, e), inner)?;
+    let opt_t = Type::Option(Box::new(inner.clone()));
+    Ok((
+        opt_t.clone(),
+        Expr::Cast(loc.clone(), Box::new(e), Box::new(inner), Box::new(opt_t)),
+    ))
+}
+
+/// Unifies the type of `e` with `expected_type`, wrapping `e` if an optional is expected and
+/// casting it if it unified to a different type, like an array to a slice or a smaller integer to
+/// a larger one
+fn coerce(loc: &Location, (t, e): (Type, Expr), expected_type: &Type) -> Result<(Type, Expr)> {
+    if let Some(inner) =
+        option_inner(expected_type).filter(|_| is_wrappable(&t) || is_shallower(&t, expected_type))
+    {
+        return wrap_optional(loc, e, t, &inner);
+    }
+    if widens(&t, expected_type) {
+        let e = Expr::Cast(
+            loc.clone(),
             Box::new(e),
             Box::new(t),
-            Box::new(unified_type),
-        ))
+            Box::new(expected_type.clone()),
+        );
+        return Ok((expected_type.clone(), e));
+    }
+    let unified_type = unify_types(loc, expected_type, &t)?;
+    let e = if t != unified_type {
+        Expr::Cast(
+            loc.clone(),
+            Box::new(e),
+            Box::new(t),
+            Box::new(unified_type.clone()),
+        )
     } else {
-        Ok(e)
+        e
+    };
+    Ok((unified_type, e))
+}
+
+/// Casts `e` of type `t` to `to_t`, which can convert between any numbers and `bool`, between
+/// pointers to the same type, and between a `newtype` and the type it is based on, as well as
+/// anything done implicitly
+fn check_cast(loc: &Location, (t, e): (Type, Expr), to_t: Type) -> Result<(Type, Expr)> {
+    let from_t = known(t.clone());
+    let (from_b, to_b) = (from_t.base(), to_t.base());
+    let invalid =
+        |from_t: Type| TypeErrorType::InvalidCast(from_t, to_t.clone()).location(loc.clone());
+    let cast = |e: Expr, t: Type| {
+        (
+            to_t.clone(),
+            Expr::Cast(
+                loc.clone(),
+                Box::new(e),
+                Box::new(t),
+                Box::new(to_t.clone()),
+            ),
+        )
+    };
+
+    if let Type::Unknown(_) = from_b {
+        // a literal is of the type it is cast to if it fits, or else is converted like any number
+        if unify_types(loc, to_b, &t).is_ok() {
+            return if from_b == to_b {
+                Ok((t, e))
+            } else {
+                Ok(cast(e, t))
+            };
+        }
+        if !is_convertible(to_b) {
+            return Err(invalid(from_t));
+        }
+        let possible = Type::NUM.into_iter().chain([Type::Bool]);
+        unify_types(loc, &Type::constrained(possible), &t).map_err(|_| invalid(from_t))?;
+        return Ok(cast(e, t));
+    }
+    let converts = match (from_b, to_b) {
+        (Type::Bool, Type::Float) | (Type::Float, Type::Bool) => false,
+        (a, b) if is_convertible(a) && is_convertible(b) => true,
+        (Type::Pointer(a) | Type::ArrayPointer(a), Type::Pointer(b) | Type::ArrayPointer(b)) => {
+            a == b
+        }
+        _ => false,
+    };
+    let distinct = |t: &Type| matches!(t, Type::Distinct(_));
+    if from_t != to_t && (converts || distinct(&from_t) || distinct(&to_t)) {
+        if !converts {
+            unify_types(loc, to_b, from_b).map_err(|_| invalid(from_t))?;
+        }
+        return Ok(cast(e, t));
+    }
+    coerce(loc, (t, e), &to_t).map_err(|_| invalid(from_t))
+}
+
+/// Whether `as` can convert values of type `t` to other types like it, which are the numbers and
+/// `bool`
+fn is_convertible(t: &Type) -> bool {
+    matches!(
+        t,
+        Type::Bool
+            | Type::Byte
+            | Type::U8
+            | Type::I8
+            | Type::U16
+            | Type::I16
+            | Type::U32
+            | Type::I32
+            | Type::Float
+    )
+}
+
+/// Whether `from` is an integer type that fits in the larger one `to` of the same signedness, so
+/// it is widened to it implicitly
+fn widens(from: &Type, to: &Type) -> bool {
+    matches!(
+        (known(from.clone()), known(to.clone())),
+        (Type::U8, Type::U16 | Type::U32)
+            | (Type::U16, Type::U32)
+            | (Type::I8, Type::I16 | Type::I32)
+            | (Type::I16, Type::I32)
+    )
+}
+
+/// Unifies the types of two branches, wrapping one of them if only the other is optional
+fn unify_branches(
+    loc: &Location,
+    (ta, ea): (Type, Expr),
+    (tb, eb): (Type, Expr),
+) -> Result<(Type, Expr, Expr)> {
+    if option_inner(&tb).is_some() && is_wrappable(&ta) {
+        let (t, ea) = wrap_optional(loc, ea, ta, &option_inner(&tb).unwrap())?;
+        return Ok((t, ea, eb));
+    }
+    if widens(&ta, &tb) {
+        let (t, ea) = coerce(loc, (ta, ea), &tb)?;
+        return Ok((t, ea, eb));
+    }
+    let (t, eb) = coerce(loc, (tb, eb), &ta)?;
+    Ok((t, ea, eb))
+}
+
+/// Checks the optional `e`, giving the type of the value inside it
+fn check_optional(loc: &Location, e: &UntypedExpr, state: &SymbolTable) -> Result<(Type, Expr)> {
+    let (t, e) = check_expr(e, state)?;
+    if let Some(inner) = option_inner(&t) {
+        return Ok((inner, e));
     }
+    let inner = Type::any();
+    unify_types(loc, &Type::Option(Box::new(inner.clone())), &t)?;
+    Ok((inner, e))
+}
+
+fn check_expr_as(expr: &UntypedExpr, state: &SymbolTable, expected_type: Type) -> Result<Expr> {
+    let (t, e) = check_expr(expr, state)?;
+    Ok(coerce(&e.location(), (t, e), &expected_type)?.1)
 }
 
 fn check_binop_expr<F, E>(
@@ -240,8 +1014,15 @@ fn check_binop_expr<F, E>(
 where
     F: FnOnce(Location, Box<Expr>, Box<Expr>) -> E,
 {
-    let ea = check_expr_as(a, state, operand_t.clone())?;
-    let eb = check_expr_as(b, state, operand_t.clone())?;
+    let (ca, cb) = (check_expr(a, state)?, check_expr(b, state)?);
+    // the operand of the larger type has to give the operand type before the other is widened
+    let (ea, eb) = if widens(&ca.0, &cb.0) {
+        let eb = coerce(&cb.1.location(), cb, &operand_t)?.1;
+        (coerce(&ca.1.location(), ca, &operand_t)?.1, eb)
+    } else {
+        let ea = coerce(&ca.1.location(), ca, &operand_t)?.1;
+        (ea, coerce(&cb.1.location(), cb, &operand_t)?.1)
+    };
     Ok((
         operand_t,
         binop_expr(loc.clone(), Box::new(ea), Box::new(eb)),
@@ -249,10 +1030,24 @@ where
 }
 
 fn check_expr(expr: &UntypedExpr, state: &SymbolTable) -> Result<(Type, Expr)> {
+    let (t, e) = check_expr_kind(expr, state)?;
+    state.record_type(e.location(), &t);
+    Ok((t, e))
+}
+
+fn check_expr_kind(expr: &UntypedExpr, state: &SymbolTable) -> Result<(Type, Expr)> {
     match expr {
         UntypedExpr::Const(loc, l) => Ok(check_literal(loc.clone(), l)),
         UntypedExpr::Ident(loc, i) => {
-            let t = state.lookup(i).map_err(|e| e.location(loc.clone()))?;
+            let t = match state.lookup(i) {
+                Ok(t) => t,
+                Err(e) => {
+                    let (t, types) = state
+                        .instantiate(loc, i)
+                        .ok_or_else(|| e.location(loc.clone()))?;
+                    return Ok((t, Expr::Instance(loc.clone(), i.clone(), types)));
+                }
+            };
 
             Ok((t, Expr::Ident(loc.clone(), i.clone())))
         }
@@ -265,15 +1060,93 @@ fn check_expr(expr: &UntypedExpr, state: &SymbolTable) -> Result<(Type, Expr)> {
         UntypedExpr::Mul(loc, a, b) => {
             check_binop_expr(loc, a, b, Expr::Mul, state, Type::constrained(Type::NUM))
         }
+        UntypedExpr::WrappingAdd(loc, a, b) => check_binop_expr(
+            loc,
+            a,
+            b,
+            Expr::WrappingAdd,
+            state,
+            Type::constrained(Type::INT),
+        ),
+        UntypedExpr::WrappingSub(loc, a, b) => check_binop_expr(
+            loc,
+            a,
+            b,
+            Expr::WrappingSub,
+            state,
+            Type::constrained(Type::INT),
+        ),
+        UntypedExpr::WrappingMul(loc, a, b) => check_binop_expr(
+            loc,
+            a,
+            b,
+            Expr::WrappingMul,
+            state,
+            Type::constrained(Type::INT),
+        ),
+        UntypedExpr::SaturatingAdd(loc, a, b) => check_binop_expr(
+            loc,
+            a,
+            b,
+            Expr::SaturatingAdd,
+            state,
+            Type::constrained(Type::INT),
+        ),
+        UntypedExpr::SaturatingSub(loc, a, b) => check_binop_expr(
+            loc,
+            a,
+            b,
+            Expr::SaturatingSub,
+            state,
+            Type::constrained(Type::INT),
+        ),
+        UntypedExpr::SaturatingMul(loc, a, b) => check_binop_expr(
+            loc,
+            a,
+            b,
+            Expr::SaturatingMul,
+            state,
+            Type::constrained(Type::INT),
+        ),
         UntypedExpr::Div(loc, a, b) => {
             check_binop_expr(loc, a, b, Expr::Div, state, Type::constrained(Type::NUM))
         }
+        UntypedExpr::Rem(loc, a, b) => {
+            check_binop_expr(loc, a, b, Expr::Rem, state, Type::constrained(Type::NUM))
+        }
+        UntypedExpr::And(loc, a, b) => check_binop_expr(loc, a, b, Expr::And, state, Type::Bool),
+        UntypedExpr::Or(loc, a, b) => check_binop_expr(loc, a, b, Expr::Or, state, Type::Bool),
+        UntypedExpr::BitAnd(loc, a, b) => check_binop_expr(
+            loc,
+            a,
+            b,
+            Expr::BitAnd,
+            state,
+            Type::constrained(Type::BITS),
+        ),
+        UntypedExpr::BitOr(loc, a, b) => {
+            check_binop_expr(loc, a, b, Expr::BitOr, state, Type::constrained(Type::BITS))
+        }
+        UntypedExpr::BitXor(loc, a, b) => check_binop_expr(
+            loc,
+            a,
+            b,
+            Expr::BitXor,
+            state,
+            Type::constrained(Type::BITS),
+        ),
+        UntypedExpr::Shl(loc, a, b) => {
+            check_binop_expr(loc, a, b, Expr::Shl, state, Type::constrained(Type::INT))
+        }
+        UntypedExpr::Shr(loc, a, b) => {
+            check_binop_expr(loc, a, b, Expr::Shr, state, Type::constrained(Type::INT))
+        }
         UntypedExpr::Neg(loc, e) => {
             let t = Type::constrained(Type::SIGNED);
             let e = check_expr_as(e, state, t.clone())?;
             Ok((t, Expr::Neg(loc.clone(), Box::new(e))))
         }
-        UntypedExpr::Not(loc, e) => {
+        UntypedExpr::Not(loc, e) | UntypedExpr::BitNot(loc, e) => {
             let t = Type::constrained(Type::BITS);
             let e = check_expr_as(e, state, t.clone())?;
             Ok((t, Expr::Not(loc.clone(), Box::new(e))))
@@ -296,14 +1169,50 @@ fn check_expr(expr: &UntypedExpr, state: &SymbolTable) -> Result<(Type, Expr)> {
         UntypedExpr::If(loc, c, i_t, i_f) => {
             let (tc, ec) = check_expr(c, state)?;
             unify_types(loc, &Type::Bool, &tc)?;
-            let (tt, et) = check_expr(i_t, state)?;
-            let (tf, ef) = check_expr(i_f, state)?;
-            let t = unify_types(loc, &tt, &tf)?;
+            let (t, et, ef) =
+                unify_branches(loc, check_expr(i_t, state)?, check_expr(i_f, state)?)?;
             Ok((
                 t,
                 Expr::If(loc.clone(), Box::new(ec), Box::new(et), Box::new(ef)),
             ))
         }
+        UntypedExpr::IfLet(loc, n, opt, i_t, i_f) => {
+            let (inner_t, opt) = check_optional(loc, opt, state)?;
+            let mut some_state = state.clone();
+            some_state.add(false, n.clone(), inner_t.clone());
+            let (t, et, ef) =
+                unify_branches(loc, check_expr(i_t, &some_state)?, check_expr(i_f, state)?)?;
+            Ok((
+                t,
+                Expr::IfLet(
+                    loc.clone(),
+                    n.clone(),
+                    Box::new(opt),
+                    Box::new(inner_t),
+                    Box::new(et),
+                    Box::new(ef),
+                ),
+            ))
+        }
+        UntypedExpr::Unwrap(loc, opt) => {
+            let (inner_t, opt) = check_optional(loc, opt, state)?;
+            Ok((
+                inner_t.clone(),
+                Expr::Unwrap(loc.clone(), Box::new(opt), Box::new(inner_t)),
+            ))
+        }
+        UntypedExpr::Loop(loc, label, body) => {
+            let t = Type::any();
+            let mut body_state = state.clone();
+            body_state.enter_loop(label.clone(), t.clone());
+            let (bt, body) = check_expr(body, &body_state)?;
+            unify_types(loc, &Type::Unit, &bt)?;
+            // a loop that is never broken out of has nothing to evaluate to
+            if matches!(&t, Type::Unknown(tv) if tv.is_any()) {
+                unify_types(loc, &t, &Type::Unit)?;
+            }
+            Ok((t, Expr::Loop(loc.clone(), label.clone(), Box::new(body))))
+        }
         UntypedExpr::Eq(loc, a, b) => check_binop_expr(
             loc,
             a,
@@ -372,14 +1281,33 @@ fn check_expr(expr: &UntypedExpr, state: &SymbolTable) -> Result<(Type, Expr)> {
                     inner.as_ref().clone(),
                     Expr::Deref(loc.clone(), Box::new(e)),
                 )),
+                Type::Opaque => Ok((Type::Opaque, Expr::Deref(loc.clone(), Box::new(e)))),
                 t => Err(TypeErrorType::CannotDeref(t).location(loc.clone())),
             }
         }
-        UntypedExpr::Call(loc, name, args) => {
-            let ft = state.lookup(name).map_err(|e| e.location(loc.clone()))?;
-            let (t_args, ret_type) = match ft {
-                Type::Function(t_args, ret_type) => (t_args, ret_type),
-                t => return Err(TypeErrorType::CannotCall(t.clone()).location(loc.clone())),
+        UntypedExpr::Call(loc, callee, args) => {
+            let (ft, callee) = check_expr(callee, state)?;
+            let ft = match ft {
+                Type::Function(_, _) | Type::Unknown(_) => {
+                    // the callee can be anything that turns out to be a function taking as many
+                    // arguments as are given
+                    let call_t = Type::Function(
+                        args.iter().map(|_| Type::any()).collect(),
+                        Box::new(Type::any()),
+                    );
+                    unify_types(loc, &call_t, &ft).map_err(|e| match e.error_type {
+                        TypeErrorType::UnequalArgLen(_, _) => e,
+                        _ => TypeErrorType::CannotCall(known(ft.clone())).location(loc.clone()),
+                    })?
+                }
+                Type::Opaque => Type::Function(
+                    args.iter().map(|_| Type::Opaque).collect(),
+                    Box::new(Type::Opaque),
+                ),
+                t => return Err(TypeErrorType::CannotCall(t).location(loc.clone())),
+            };
+            let Type::Function(t_args, ret_type) = ft.clone() else {
+                unreachable!("calls are unified with a function type")
             };
 
             let args: Vec<_> = args
@@ -387,65 +1315,266 @@ fn check_expr(expr: &UntypedExpr, state: &SymbolTable) -> Result<(Type, Expr)> {
                 .zip(t_args.iter().cloned())
                 .map(|(e, ta)| {
                     let (t, e) = check_expr(e, state)?;
-                    let at = unify_types(loc, &ta, &t)?;
-                    if at != t {
-                        Ok(Expr::Cast(
-                            loc.clone(),
-                            Box::new(e),
-                            Box::new(t),
-                            Box::new(at),
-                        ))
-                    } else {
-                        Ok(e)
-                    }
+                    let loc = e.location();
+                    let (t, e) = coerce(&loc, (t, e), &ta)?;
+                    // an array passed for a type variable keeps a cast to it, as it can still
+                    // become a slice
+                    Ok(match (&ta, &t) {
+                        (Type::Unknown(_), Type::Array(_, _)) => {
+                            Expr::Cast(loc, Box::new(e), Box::new(t), Box::new(ta))
+                        }
+                        _ => e,
+                    })
                 })
                 .collect_result()?;
 
             Ok((
                 ret_type.as_ref().clone(),
-                Expr::Call(loc.clone(), name.clone(), args.into_boxed_slice()),
+                Expr::Call(
+                    loc.clone(),
+                    Box::new(callee),
+                    args.into_boxed_slice(),
+                    Box::new(ft),
+                ),
             ))
         }
         UntypedExpr::Lambda(loc, args, ret, body) => {
-            let args: Box<[_]> = args
+            let args: Vec<_> = args
                 .iter()
-                .map(|(n, t)| (n.clone(), t.clone().unwrap_or_else(Type::any)))
-                .collect();
+                .map(|(n, t)| Ok((n.clone(), check_annotation(loc, t.clone(), state)?)))
+                .collect_result()?;
+            let args = args.into_boxed_slice();
             let targs = args.iter().map(|(_, t)| t.clone()).collect();
 
             let mut stab = state.clone();
+            stab.enter_function();
             for (name, ty) in &*args {
                 stab.add(false, name.clone(), ty.clone());
             }
 
-            let any;
-            let ret = match ret {
-                Some(r) => r,
-                None => {
-                    any = Type::any();
-                    &any
-                }
-            };
+            let ret = check_annotation(loc, ret.clone(), state)?;
             let (bt, be) = check_expr(body, &stab)?;
-            let rt = unify_types(loc, ret, &bt)?;
+            let rt = unify_types(loc, &ret, &bt)?;
 
             Ok((
                 Type::Function(targs, Box::new(rt.clone())),
-                Expr::Lambda(loc.clone(), args, rt, Box::new(be)),
+                Expr::Lambda(loc.clone(), args, rt, Box::new(be), stab.captures()),
             ))
         }
         UntypedExpr::Block(loc, stmnts) => {
             let stab = &mut state.clone();
             let mut ret = None;
             let (t, stmnts) = check_statements(stmnts.clone(), stab, &mut ret)?;
+            stab.record_scope(loc.clone());
             if ret.is_some() {
                 unimplemented!("returning from block, correctly unsupported");
             }
             Ok((t, Expr::Block(loc.clone(), stmnts.into_boxed_slice())))
         }
-        UntypedExpr::Array(_loc, _) => todo!(),
-        UntypedExpr::StructConstructor(_loc, _) => todo!(),
-        UntypedExpr::Cast(_loc, _, _) => todo!(),
+        UntypedExpr::Array(loc, es) => {
+            let elem_t = Type::any();
+            let es: Vec<_> = es
+                .iter()
+                .map(|e| check_expr_as(e, state, elem_t.clone()))
+                .collect_result()?;
+            Ok((
+                Type::Array(Box::new(elem_t), es.len() as u16),
+                Expr::Array(loc.clone(), es.into_boxed_slice()),
+            ))
+        }
+        UntypedExpr::Index(loc, arr_e, index_e) => {
+            let (arr_t, arr_e) = check_expr(arr_e, state)?;
+            let t = element_type(&arr_t).map_err(|e| e.location(loc.clone()))?;
+            let index_e = check_expr_as(index_e, state, Type::U16)?;
+            Ok((
+                t,
+                Expr::Index(
+                    loc.clone(),
+                    Box::new(arr_e),
+                    Box::new(index_e),
+                    Box::new(arr_t),
+                ),
+            ))
+        }
+        UntypedExpr::Slice(loc, arr_e, start_e, end_e) => {
+            let (arr_t, arr_e) = check_expr(arr_e, state)?;
+            let elem_t = element_type(&arr_t).map_err(|e| e.location(loc.clone()))?;
+            let start_e = check_expr_as(start_e, state, Type::U16)?;
+            let end_e = check_expr_as(end_e, state, Type::U16)?;
+            Ok((
+                Type::Slice(Box::new(elem_t)),
+                Expr::Slice(
+                    loc.clone(),
+                    Box::new(arr_e),
+                    Box::new(start_e),
+                    Box::new(end_e),
+                    Box::new(arr_t),
+                ),
+            ))
+        }
+        UntypedExpr::StructConstructor(loc, name, fields) => {
+            let t = state
+                .resolve_type(&Type::Named(name.clone()))
+                .map_err(|e| e.location(loc.clone()))?;
+            let Type::Struct(decl_fields) = &t else {
+                return Err(
+                    TypeErrorType::TypeMismatch(t, Type::Named(name.clone())).location(loc.clone())
+                );
+            };
+
+            for (i, (field, _)) in fields.iter().enumerate() {
+                let field = field.as_deref().unwrap();
+                if fields[..i].iter().any(|(f, _)| f.as_deref() == Some(field)) {
+                    return Err(TypeErrorType::DuplicateField(field.into()).location(loc.clone()));
+                }
+                field_type(&t, field).map_err(|e| e.location(loc.clone()))?;
+            }
+            // fields get evaluated in the order they were declared in
+            let exprs: Vec<_> = decl_fields
+                .iter()
+                .map(|(field, field_t)| {
+                    let Some((_, e)) = fields.iter().find(|(f, _)| f.as_deref() == Some(field))
+                    else {
+                        return Err(
+                            TypeErrorType::MissingField((**field).into()).location(loc.clone())
+                        );
+                    };
+                    let e = check_expr_as(e, state, field_t.clone())?;
+                    Ok((Some((**field).into()), e))
+                })
+                .collect_result()?;
+
+            Ok((
+                t.clone(),
+                Expr::StructConstructor(loc.clone(), exprs.into_boxed_slice()),
+            ))
+        }
+        UntypedExpr::FieldAccess(loc, strct_e, field) => {
+            let (strct_t, strct_e) = check_expr(strct_e, state)?;
+            let t = property_type(&strct_t, field).map_err(|e| e.location(loc.clone()))?;
+            Ok((
+                t,
+                Expr::FieldAccess(
+                    loc.clone(),
+                    Box::new(strct_e),
+                    field.clone(),
+                    Box::new(strct_t),
+                ),
+            ))
+        }
+        UntypedExpr::VariantConstructor(loc, name, variant, payload) => {
+            let t = state
+                .resolve_type(&Type::Named(name.clone()))
+                .map_err(|e| e.location(loc.clone()))?;
+            let payload_ts = variant_payload(&t, variant).map_err(|e| e.location(loc.clone()))?;
+            if payload_ts.len() != payload.len() {
+                return Err(TypeErrorType::WrongPayloadLen(
+                    (**variant).into(),
+                    payload_ts.len() as u16,
+                    payload.len() as u16,
+                )
+                .location(loc.clone()));
+            }
+            let exprs: Vec<_> = payload
+                .iter()
+                .zip(payload_ts.iter())
+                .map(|(e, t)| check_expr_as(e, state, t.clone()))
+                .collect_result()?;
+
+            Ok((
+                t.clone(),
+                Expr::VariantConstructor(
+                    loc.clone(),
+                    variant.clone(),
+                    exprs.into_boxed_slice(),
+                    Box::new(t),
+                ),
+            ))
+        }
+        UntypedExpr::Match(loc, e, arms) => {
+            let (mut et, e) = check_expr(e, state)?;
+            let mut t = Type::any();
+            let mut covered: Vec<Rc<str>> = Vec::new();
+            let mut has_wildcard = false;
+            let mut typed_arms = Vec::with_capacity(arms.len());
+
+            for (pattern, arm) in arms.iter() {
+                if has_wildcard {
+                    let (UntypedPattern::Wildcard(loc) | UntypedPattern::Variant(loc, _, _, _)) =
+                        pattern;
+                    return Err(TypeErrorType::UnreachablePattern.location(loc.clone()));
+                }
+                let mut arm_state = state.clone();
+                let pattern = match pattern {
+                    UntypedPattern::Wildcard(loc) => {
+                        has_wildcard = true;
+                        Pattern::Wildcard(loc.clone())
+                    }
+                    UntypedPattern::Variant(loc, name, variant, bindings) => {
+                        let pt = state
+                            .resolve_type(&Type::Named(name.clone()))
+                            .map_err(|e| e.location(loc.clone()))?;
+                        et = unify_types(loc, &pt, &et)?;
+                        let payload_ts =
+                            variant_payload(&et, variant).map_err(|e| e.location(loc.clone()))?;
+                        if payload_ts.len() != bindings.len() {
+                            return Err(TypeErrorType::WrongPayloadLen(
+                                (**variant).into(),
+                                payload_ts.len() as u16,
+                                bindings.len() as u16,
+                            )
+                            .location(loc.clone()));
+                        }
+                        if covered.contains(variant) {
+                            return Err(TypeErrorType::UnreachablePattern.location(loc.clone()));
+                        }
+                        covered.push(variant.clone());
+
+                        for (binding, bt) in bindings.iter().zip(payload_ts.iter()) {
+                            if let Some(binding) = binding {
+                                arm_state.add(false, binding.clone(), bt.clone());
+                            }
+                        }
+                        Pattern::Variant(loc.clone(), variant.clone(), bindings.clone())
+                    }
+                };
+                let (at, arm) = check_expr(arm, &arm_state)?;
+                t = unify_types(loc, &t, &at)?;
+                typed_arms.push((pattern, arm));
+            }
+
+            let Type::Enum(variants) = &et else {
+                return Err(TypeErrorType::CannotMatch(et).location(loc.clone()));
+            };
+            if !has_wildcard {
+                let missing: Vec<_> = variants
+                    .iter()
+                    .map(|(n, _)| n)
+                    .filter(|n| !covered.contains(n))
+                    .cloned()
+                    .collect();
+                if !missing.is_empty() {
+                    return Err(
+                        TypeErrorType::NonExhaustiveMatch(missing.into_boxed_slice())
+                            .location(loc.clone()),
+                    );
+                }
+            }
+
+            Ok((
+                t,
+                Expr::Match(
+                    loc.clone(),
+                    Box::new(e),
+                    Box::new(et),
+                    typed_arms.into_boxed_slice(),
+                ),
+            ))
+        }
+        UntypedExpr::Cast(loc, e, t) => {
+            let to_t = state.resolve_type(t).map_err(|e| e.location(loc.clone()))?;
+            check_cast(loc, check_expr(e, state)?, to_t)
+        }
     }
 }
 
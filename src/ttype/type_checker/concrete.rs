@@ -1,11 +1,13 @@
 use crate::{
     parse::location::Location,
     ttype::{
-        ast::{Expr, PlaceExpr, Statement},
+        ast::{Expr, Iterable, PlaceExpr, Statement},
         Result, Type,
     },
 };
 
+use super::instance_name;
+
 pub fn concretise_type(loc: Location, t: &mut Type) -> Result<()> {
     match t {
         Type::Unknown(v) => {
@@ -23,8 +25,24 @@ pub fn concretise_type(loc: Location, t: &mut Type) -> Result<()> {
             }
             concretise_type(loc, ret)
         }
-        Type::Struct(_) => todo!(),
-        Type::Opaque
+        Type::Struct(fields) => {
+            for (_, t) in &mut **fields {
+                concretise_type(loc.clone(), t)?;
+            }
+            Ok(())
+        }
+        Type::Enum(variants) => {
+            for (_, payload) in &mut **variants {
+                for t in &mut **payload {
+                    concretise_type(loc.clone(), t)?;
+                }
+            }
+            Ok(())
+        }
+        Type::Named(_) => unreachable!("named types are resolved during type checking"),
+        // what a newtype is based on is always known
+        Type::Distinct(_)
+        | Type::Opaque
         | Type::Bool
         | Type::Byte
         | Type::U8
@@ -62,6 +80,23 @@ pub fn concretise_statement(stmnt: &mut Statement) -> Result<()> {
             concretise_pexpr(p)?;
             concretise_expr(e)
         }
+        Statement::While(_, _, c, b) => {
+            concretise_expr(c)?;
+            concretise_expr(b)
+        }
+        Statement::Break(_, _, Some(e)) => concretise_expr(e),
+        Statement::Break(_, _, None) | Statement::Continue(_, _) => Ok(()),
+        Statement::For(loc, _, _, t, i, b) => {
+            concretise_type(loc.clone(), t)?;
+            match i {
+                Iterable::Range(start, end) => {
+                    concretise_expr(start)?;
+                    concretise_expr(end)?;
+                }
+                Iterable::Slice(e) => concretise_expr(e)?,
+            }
+            concretise_expr(b)
+        }
         Statement::Return(_, e) => concretise_expr(e),
     }
 }
@@ -73,12 +108,31 @@ fn concretise_pexpr(p: &mut PlaceExpr) -> Result<()> {
             concretise_expr(e)?;
             concretise_type(loc.clone(), t)
         }
-        PlaceExpr::Index(_, e, e2) => concretise_expr(e).and_then(|()| concretise_expr(e2)),
-        PlaceExpr::FieldAccess(_, e, _) => concretise_expr(e),
+        PlaceExpr::Index(loc, p, e, t) => {
+            concretise_pexpr(p)?;
+            concretise_expr(e)?;
+            concretise_type(loc.clone(), t)
+        }
+        PlaceExpr::FieldAccess(loc, p, _, t) => {
+            concretise_pexpr(p)?;
+            concretise_type(loc.clone(), t)
+        }
+        PlaceExpr::SliceElement(loc, e1, e2, t) => {
+            concretise_expr(e1)?;
+            concretise_expr(e2)?;
+            concretise_type(loc.clone(), t)
+        }
     }
 }
 pub fn concretise_expr(expr: &mut Expr) -> Result<()> {
     match expr {
+        Expr::Instance(loc, name, types) => {
+            for t in types.iter_mut() {
+                concretise_type(loc.clone(), t)?;
+            }
+            *expr = Expr::Ident(loc.clone(), instance_name(name, types));
+            Ok(())
+        }
         Expr::Ident(_, _)
         | Expr::ConstBoolean(_, _)
         | Expr::ConstI8(_, _)
@@ -92,11 +146,13 @@ pub fn concretise_expr(expr: &mut Expr) -> Result<()> {
         | Expr::ConstUnit(_)
         | Expr::ConstString(_, _)
         | Expr::ConstNull(_) => Ok(()),
-        Expr::Ref(_, Err(e)) | Expr::Not(_, e) | Expr::Neg(_, e) | Expr::Deref(_, e) => {
-            concretise_expr(e)
-        }
-        Expr::Lambda(loc, args, ret, e) => {
-            for (_, at) in args.iter_mut() {
+        Expr::Ref(_, Err(e))
+        | Expr::Not(_, e)
+        | Expr::Neg(_, e)
+        | Expr::Deref(_, e)
+        | Expr::Loop(_, _, e) => concretise_expr(e),
+        Expr::Lambda(loc, args, ret, e, captures) => {
+            for (_, at) in args.iter_mut().chain(captures.iter_mut()) {
                 concretise_type(loc.clone(), at)?;
             }
             concretise_type(loc.clone(), ret)?;
@@ -107,6 +163,21 @@ pub fn concretise_expr(expr: &mut Expr) -> Result<()> {
             concretise_type(loc.clone(), t1)?;
             concretise_type(loc.clone(), t2)
         }
+        Expr::FieldAccess(loc, e, _, t) => {
+            concretise_expr(e)?;
+            concretise_type(loc.clone(), t)
+        }
+        Expr::Index(loc, e, e2, t) => {
+            concretise_expr(e)?;
+            concretise_expr(e2)?;
+            concretise_type(loc.clone(), t)
+        }
+        Expr::Slice(loc, e, e2, e3, t) => {
+            concretise_expr(e)?;
+            concretise_expr(e2)?;
+            concretise_expr(e3)?;
+            concretise_type(loc.clone(), t)
+        }
         Expr::Ref(_, Ok(pl_e)) => concretise_pexpr(pl_e),
         Expr::Block(_, stmnts) => concretise_statements(stmnts),
         Expr::StructConstructor(_, es) => {
@@ -115,21 +186,66 @@ pub fn concretise_expr(expr: &mut Expr) -> Result<()> {
             }
             Ok(())
         }
-        Expr::Array(_, es) | Expr::Call(_, _, es) => {
+        Expr::VariantConstructor(loc, _, es, t) => {
             for e in es.iter_mut() {
                 concretise_expr(e)?;
             }
+            concretise_type(loc.clone(), t)
+        }
+        Expr::Match(loc, e, t, arms) => {
+            concretise_expr(e)?;
+            concretise_type(loc.clone(), t)?;
+            for (_, arm) in arms.iter_mut() {
+                concretise_expr(arm)?;
+            }
             Ok(())
         }
+        Expr::Array(_, es) => {
+            for e in es.iter_mut() {
+                concretise_expr(e)?;
+            }
+            Ok(())
+        }
+        Expr::Call(loc, e, es, t) => {
+            concretise_expr(e)?;
+            for e in es.iter_mut() {
+                concretise_expr(e)?;
+            }
+            concretise_type(loc.clone(), t)
+        }
         Expr::If(_, e, e2, e3) => {
             concretise_expr(e)?;
             concretise_expr(e2)?;
             concretise_expr(e3)
         }
+        Expr::IfLet(loc, _, e, t, e2, e3) => {
+            concretise_expr(e)?;
+            concretise_type(loc.clone(), t)?;
+            concretise_expr(e2)?;
+            concretise_expr(e3)
+        }
+        Expr::Unwrap(loc, e, t) => {
+            concretise_expr(e)?;
+            concretise_type(loc.clone(), t)
+        }
         Expr::Add(_, e1, e2)
         | Expr::Sub(_, e1, e2)
         | Expr::Mul(_, e1, e2)
+        | Expr::WrappingAdd(_, e1, e2)
+        | Expr::WrappingSub(_, e1, e2)
+        | Expr::WrappingMul(_, e1, e2)
+        | Expr::SaturatingAdd(_, e1, e2)
+        | Expr::SaturatingSub(_, e1, e2)
+        | Expr::SaturatingMul(_, e1, e2)
+        | Expr::BitAnd(_, e1, e2)
+        | Expr::BitOr(_, e1, e2)
+        | Expr::BitXor(_, e1, e2)
+        | Expr::Shl(_, e1, e2)
+        | Expr::Shr(_, e1, e2)
         | Expr::Div(_, e1, e2)
+        | Expr::Rem(_, e1, e2)
+        | Expr::And(_, e1, e2)
+        | Expr::Or(_, e1, e2)
         | Expr::Concat(_, e1, e2) => {
             concretise_expr(e1)?;
             concretise_expr(e2)
@@ -18,7 +18,7 @@ enum Inner {
     Any,
     // TODO: maybe use more abstract contraints
     // FIXME: merge with Concrete?
-    /// the inner set contains the possible types
+    /// the inner set contains the possible types, which a `newtype` of one of them satisfies too
     Constrained(HashSet<Type>),
 }
 
@@ -48,6 +48,22 @@ impl TypeVar {
             inner: Rc::new(RefCell::new(Inner::Constrained(possible_types))),
         }
     }
+    /// Whether nothing is known about this type yet
+    pub fn is_any(&self) -> bool {
+        match &*RefCell::borrow(&self.inner) {
+            Inner::Any => true,
+            Inner::Alias(tv) => tv.is_any(),
+            Inner::Concrete(_) | Inner::Constrained(_) => false,
+        }
+    }
+    /// The type this has been inferred to be, if it is known yet
+    pub fn known_type(&self) -> Option<Type> {
+        match &*RefCell::borrow(&self.inner) {
+            Inner::Concrete(t) => Some(t.clone()),
+            Inner::Alias(tv) => tv.known_type(),
+            Inner::Any | Inner::Constrained(_) => None,
+        }
+    }
     pub fn concretise(self) -> Result<Type, TypeErrorType> {
         match &*(*self.inner).borrow() {
             Inner::Concrete(t) => Ok(t.clone()),
@@ -75,10 +91,11 @@ impl TypeVar {
             Inner::Alias(tv) => return tv.merge_with_type(loc, t),
             Inner::Any => t.clone(),
             Inner::Constrained(set) => {
-                if set.contains(t) {
+                if set.contains(t.base()) {
                     t.clone()
                 } else {
-                    todo!("error type {t} \\not\\in {set:?}");
+                    return Err(TypeErrorType::UnsatisfiedConstraint(set.clone(), t.clone())
+                        .location(loc.clone()));
                 }
             }
             Inner::Concrete(ct) => unify_types(loc, ct, t)?,
@@ -128,21 +145,27 @@ impl TypeVar {
                 Ok(self.clone())
             }
             (Inner::Concrete(ft), Inner::Constrained(possible)) => {
-                if possible.contains(ft) {
+                if possible.contains(ft.base()) {
                     drop((s, o));
                     *RefCell::borrow_mut(&other.inner) = Inner::Alias(self.clone());
                     Ok(self.clone())
                 } else {
-                    todo!("error type {ft} \\not\\in {possible:?}");
+                    Err(
+                        TypeErrorType::UnsatisfiedConstraint(possible.clone(), ft.clone())
+                            .location(loc.clone()),
+                    )
                 }
             }
             (Inner::Constrained(possible), Inner::Concrete(ft)) => {
-                if possible.contains(ft) {
+                if possible.contains(ft.base()) {
                     drop((s, o));
                     *RefCell::borrow_mut(&self.inner) = Inner::Alias(other.clone());
                     Ok(other.clone())
                 } else {
-                    todo!("error type {ft} \\not\\in {possible:?}");
+                    Err(
+                        TypeErrorType::UnsatisfiedConstraint(possible.clone(), ft.clone())
+                            .location(loc.clone()),
+                    )
                 }
             }
             (Inner::Constrained(set1), Inner::Constrained(set2)) => {
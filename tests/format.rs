@@ -0,0 +1,44 @@
+//! Keeping comments in place when formatting
+
+use std::path::Path;
+
+use edd::parse::format::format_source;
+
+fn format(source: &str) -> String {
+    format_source(Path::new("test.ð"), source.to_owned()).unwrap()
+}
+
+#[test]
+fn comments_inside_statements_go_before_them() {
+    let source = "fn f(a: i16, // first
+     b: i16) i16 {
+    a + b
+}
+
+fn main() unit {
+    let x = 1 + // one
+        2;
+    puti16(// the arg
+        x);
+    let y = f(1,
+        // second
+        2); // trailing
+}
+";
+    let formatted = "// first
+fn f(a: i16, b: i16) i16 {
+    a + b
+}
+
+fn main() unit {
+    // one
+    let x = 1 + 2;
+    // the arg
+    puti16(x);
+    // second
+    let y = f(1, 2); // trailing
+}
+";
+    assert_eq!(format(source), formatted);
+    assert_eq!(format(formatted), formatted);
+}
@@ -0,0 +1,173 @@
+//! Driving the language server over stdio the way an editor would
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+const URI: &str = "file:///nonexistent/doc.%C3%B0";
+
+const BROKEN: &str = "include(\"<std>\");
+
+fn add(a: i16, b: i16) i16 {
+    a + b
+}
+
+fn main() unit {
+    let total: bool = add(1, 2);
+}
+";
+
+const FIXED: &str = "include(\"<std>\");
+
+fn add(a: i16, b: i16) i16 {
+    a + b
+}
+
+fn main() unit {
+    let total = add(1, 2);
+    puti16(total);
+}
+";
+
+struct Client {
+    server: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_edd-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("the server starts");
+        let input = server.stdin.take().unwrap();
+        let output = BufReader::new(server.stdout.take().unwrap());
+        Client {
+            server,
+            input,
+            output,
+            next_id: 1,
+        }
+    }
+
+    fn send(&mut self, msg: Value) {
+        let body = msg.to_string();
+        write!(self.input, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.input.flush().unwrap();
+    }
+    fn receive(&mut self) -> Value {
+        let mut len = None;
+        loop {
+            let mut line = String::new();
+            assert_ne!(
+                self.output.read_line(&mut line).unwrap(),
+                0,
+                "the server hung up"
+            );
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                len = Some(value.trim().parse().unwrap());
+            }
+        }
+        let mut body = vec![0; len.expect("a Content-Length header")];
+        self.output.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+    /// Sends a request and gives the result of the response to it
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        let response = self.receive();
+        assert_eq!(
+            response["id"], id,
+            "expected the response to {method}: {response}"
+        );
+        response["result"].clone()
+    }
+    /// Waits for the diagnostics published for `uri`
+    fn diagnostics(&mut self, uri: &str) -> Vec<Value> {
+        let msg = self.receive();
+        assert_eq!(msg["method"], "textDocument/publishDiagnostics", "{msg}");
+        assert_eq!(msg["params"]["uri"], uri);
+        msg["params"]["diagnostics"].as_array().unwrap().clone()
+    }
+
+    fn at(&mut self, method: &str, line: u32, character: u32) -> Value {
+        let params = json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        });
+        self.request(method, params)
+    }
+}
+
+#[test]
+fn session() {
+    let mut client = Client::start();
+
+    let init = client.request("initialize", json!({ "capabilities": {} }));
+    assert_eq!(init["capabilities"]["hoverProvider"], true);
+    assert_eq!(init["capabilities"]["definitionProvider"], true);
+    assert!(init["capabilities"]["completionProvider"].is_object());
+    client.notify("initialized", json!({}));
+
+    client.notify(
+        "textDocument/didOpen",
+        json!({ "textDocument": { "uri": URI, "languageId": "edd", "version": 1, "text": BROKEN } }),
+    );
+    let diags = client.diagnostics(URI);
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!(diags[0]["range"]["start"]["line"], 7);
+    assert_eq!(diags[0]["severity"], 1);
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": FIXED }],
+        }),
+    );
+    assert_eq!(client.diagnostics(URI), Vec::<Value>::new());
+
+    // `total` in `puti16(total)`
+    let hover = client.at("textDocument/hover", 8, 12);
+    assert_eq!(hover["contents"]["value"], "```edd\ni16\n```");
+    assert_eq!(
+        hover["range"]["start"],
+        json!({ "line": 8, "character": 11 })
+    );
+
+    // `add` in `add(1, 2)`
+    let definition = client.at("textDocument/definition", 7, 17);
+    assert_eq!(definition["uri"], URI);
+    assert_eq!(definition["range"]["start"]["line"], 2);
+
+    let completion = client.at("textDocument/completion", 8, 4);
+    let labels: Vec<_> = completion
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    for label in ["total", "add", "main", "puti16"] {
+        assert!(labels.contains(&label), "{label} missing from {labels:?}");
+    }
+
+    assert_eq!(client.request("shutdown", Value::Null), Value::Null);
+    client.notify("exit", Value::Null);
+    assert!(client.server.wait().unwrap().success());
+}
@@ -0,0 +1,131 @@
+//! Compiling from source providers other than the disk
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use edd::{
+    compile_str,
+    diagnostic::Emitter,
+    parse::{
+        source::{Disk, InMemory, Overlay, SourceProvider},
+        ParseError,
+    },
+    CompileError, CompileOptions,
+};
+
+fn parse_errors(result: Result<edd::flat::Program, CompileError>) -> Vec<ParseError> {
+    match result {
+        Err(CompileError::Parse(errors)) => errors,
+        Err(e) => panic!("expected parse errors, got: {e}"),
+        Ok(_) => panic!("expected parse errors, but it compiled"),
+    }
+}
+
+#[test]
+fn in_memory_resolves_dots() {
+    let mut files = InMemory::new().with_file("/p/./lib/../a.ð", "fn main() unit {}");
+
+    let canonical = files.canonicalize(Path::new("/p/lib/x/../../a.ð")).unwrap();
+    assert_eq!(canonical, PathBuf::from("/p/a.ð"));
+    assert_eq!(
+        files.read(Path::new("/p/a.ð")).unwrap(),
+        "fn main() unit {}"
+    );
+
+    assert!(files.remove("/p/lib/../a.ð").is_some());
+    let e = files.read(Path::new("/p/a.ð")).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn overlay_prefers_top() {
+    let top = InMemory::new().with_file("/a.ð", "top");
+    let bottom = InMemory::new()
+        .with_file("/a.ð", "bottom")
+        .with_file("/b.ð", "only bottom");
+    let files = Overlay::new(top, bottom);
+
+    assert_eq!(files.read(Path::new("/a.ð")).unwrap(), "top");
+    assert_eq!(files.read(Path::new("/b.ð")).unwrap(), "only bottom");
+    assert!(files.canonicalize(Path::new("/b.ð")).is_ok());
+    let e = files.canonicalize(Path::new("/c.ð")).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn compile_str_imports_from_memory() {
+    // the root file is not among the files, and the module it imports includes a file above it
+    let files = InMemory::new()
+        .with_file(
+            "/p/lib/geo.ðs",
+            "include(\"../common.ðs\");\npub fn twice(n: i16) i16 { n * SCALE }",
+        )
+        .with_file("/p/common.ðs", "const SCALE: i16 = 2;");
+    let source = "include(\"<std>\");
+import \"lib/geo.ðs\" as geo;
+
+fn main() unit {
+    puti16(geo::twice(21));
+}";
+    let options = CompileOptions::default().read_sources(files);
+
+    compile_str(Path::new("/p/main.ð"), source, options).unwrap();
+}
+
+#[test]
+fn compile_str_reports_missing_files() {
+    let source = "import \"nowhere.ðs\" as nowhere;\nfn main() unit {}";
+    let options = CompileOptions::default().read_sources(InMemory::new());
+
+    let errors = parse_errors(compile_str(Path::new("/p/main.ð"), source, options));
+    assert!(
+        matches!(&errors[..], [ParseError::Io(Some(_), path, _)] if **path == *"/p/nowhere.ðs")
+    );
+}
+
+#[test]
+fn libraries_in_include_paths_come_before_bundled() {
+    // the bundled `<std>` has no `answer`
+    let files = InMemory::new().with_file("/libs/std.ðs", "pub const answer: i16 = 42;");
+    let source = "import \"<std>\" as std;\nfn main() unit {\n    let n = std::answer;\n}";
+
+    let options = CompileOptions::default()
+        .include_path("/libs".into())
+        .read_sources(files);
+    compile_str(Path::new("/p/main.ð"), source, options).unwrap();
+
+    let options = CompileOptions::default().read_sources(InMemory::new());
+    let errors = parse_errors(compile_str(Path::new("/p/main.ð"), source, options));
+    assert!(matches!(&errors[..], [ParseError::NotPublic(_, _, item)] if &**item == "answer"));
+}
+
+#[test]
+fn overlay_falls_back_to_disk() {
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    // an unsaved change to shapes.ðs, while std.ðs it imports is only on disk
+    let shapes = "import \"std.ðs\" as std;\npub fn greet() unit {\n    std::puts(\"hi\\n\");\n}";
+    let files = InMemory::new().with_file(tests.join("shapes.ðs"), shapes);
+    let source = "import \"shapes.ðs\" as shapes;\nfn main() unit {\n    shapes::greet();\n}";
+
+    let options = CompileOptions::default().read_sources(Overlay::new(files, Disk));
+    compile_str(&tests.join("unsaved.ð"), source, options).unwrap();
+
+    // without the change, shapes.ðs has no `greet`
+    let options = CompileOptions::default();
+    let errors = parse_errors(compile_str(&tests.join("unsaved.ð"), source, options));
+    assert!(matches!(&errors[..], [ParseError::NotPublic(_, _, item)] if &**item == "greet"));
+}
+
+#[test]
+fn emitter_reads_from_the_provider() {
+    let files = InMemory::new().with_file("/p/lib.ðs", "pub fn f() bool {\n    1\n}");
+    let source = "import \"lib.ðs\" as lib;\nfn main() unit {}";
+    let options = CompileOptions::default().read_sources(files.clone());
+
+    let e = compile_str(Path::new("/p/main.ð"), source, options).unwrap_err();
+    let mut emitter = Emitter::new(false, files);
+    let rendered: Vec<_> = e.diagnostics().iter().map(|d| emitter.render(d)).collect();
+    assert!(rendered.iter().any(|r| r.contains("1 | pub fn f() bool {")), "{rendered:?}");
+}